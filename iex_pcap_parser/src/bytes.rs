@@ -0,0 +1,57 @@
+//! Fixed-width integers read from byte buffers in either byte order. IEX-TP is little-endian; the
+//! big-endian readers are for decoding other feeds and capture formats with the same primitives.
+//! Reads panic if the integer runs past the end of the buffer.
+
+use std::convert::TryInto;
+
+#[cfg(test)]
+mod tests {
+    use std::convert::TryInto;
+
+    const BYTES: [u8; 10] = [0xff, 0x01, 0x23, 0x45, 0x67, 0x89, 0xab, 0xcd, 0xef, 0x80];
+
+    fn at<const N: usize>(offset: usize) -> [u8; N] {
+        BYTES[offset..offset + N].try_into().unwrap()
+    }
+
+    #[test]
+    fn test_read_le() {
+        for offset in 0..=BYTES.len() - 8 {
+            assert_eq!(crate::bytes::read_u16_le(&BYTES, offset), u16::from_le_bytes(at(offset)));
+            assert_eq!(crate::bytes::read_u32_le(&BYTES, offset), u32::from_le_bytes(at(offset)));
+            assert_eq!(crate::bytes::read_u64_le(&BYTES, offset), u64::from_le_bytes(at(offset)));
+        }
+        assert_eq!(crate::bytes::read_u16_le(&BYTES, 1), 0x2301);
+    }
+
+    #[test]
+    fn test_read_be() {
+        for offset in 0..=BYTES.len() - 8 {
+            assert_eq!(crate::bytes::read_u16_be(&BYTES, offset), u16::from_be_bytes(at(offset)));
+            assert_eq!(crate::bytes::read_u32_be(&BYTES, offset), u32::from_be_bytes(at(offset)));
+            assert_eq!(crate::bytes::read_u64_be(&BYTES, offset), u64::from_be_bytes(at(offset)));
+        }
+        assert_eq!(crate::bytes::read_u16_be(&BYTES, 1), 0x0123);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_read_past_end() {
+        crate::bytes::read_u32_le(&BYTES, BYTES.len() - 3);
+    }
+}
+
+macro_rules! read_fn {
+    ( $name:ident, $type:ty, $from:ident ) => {
+        pub fn $name(bytes: &[u8], offset: usize) -> $type {
+            <$type>::$from(bytes[offset..offset + std::mem::size_of::<$type>()].try_into().unwrap())
+        }
+    };
+}
+
+read_fn!(read_u16_le, u16, from_le_bytes);
+read_fn!(read_u32_le, u32, from_le_bytes);
+read_fn!(read_u64_le, u64, from_le_bytes);
+read_fn!(read_u16_be, u16, from_be_bytes);
+read_fn!(read_u32_be, u32, from_be_bytes);
+read_fn!(read_u64_be, u64, from_be_bytes);
@@ -0,0 +1,3363 @@
+#[macro_use]
+extern crate bitflags;
+extern crate bzip2;
+extern crate chrono;
+extern crate etherparse;
+extern crate flate2;
+extern crate libdt;
+extern crate libh5;
+#[cfg(feature = "native-pcap")]
+extern crate pcap;
+extern crate zstd;
+
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::collections::HashSet;
+#[cfg(feature = "native-pcap")]
+use std::env;
+use std::ffi;
+use std::fs;
+use std::io;
+use std::io::Read;
+#[cfg(feature = "native-pcap")]
+use std::io::Write;
+use std::path;
+
+use flate2::read::GzDecoder;
+use log::{debug, trace, info, warn};
+#[cfg(feature = "native-pcap")]
+use pcap::Capture;
+
+pub mod bytes;
+pub mod csv;
+mod pcapfile;
+mod pcapng;
+
+#[cfg(test)]
+mod tests {
+    /// A 38-byte trade report for `symbol`, which is right-padded with spaces.
+    fn trade_report(symbol: &str) -> Vec<u8> {
+        let mut bytes = vec![b'T', 0];
+        bytes.extend_from_slice(&1_562_160_000_000_000_000u64.to_le_bytes());
+        bytes.extend(format!("{:<8}", symbol).bytes());
+        bytes.extend_from_slice(&100u32.to_le_bytes());
+        bytes.extend_from_slice(&1_990_000u64.to_le_bytes());
+        bytes.extend_from_slice(&42u64.to_le_bytes());
+        bytes
+    }
+
+    /// A classic pcap file holding a single ethernet frame.
+    fn plain_pcap() -> Vec<u8> {
+        let frame = [0xffu8; 60];
+        let mut bytes = Vec::new();
+        // Magic, version 2.4, timezone, sigfigs, snaplen, ethernet link type.
+        for field in &[0xa1b2_c3d4u32, 0x0004_0002, 0, 0, 65535, 1] {
+            bytes.extend_from_slice(&field.to_le_bytes());
+        }
+        // Seconds, microseconds, captured length, original length.
+        for field in &[1_562_160_000u32, 0, frame.len() as u32, frame.len() as u32] {
+            bytes.extend_from_slice(&field.to_le_bytes());
+        }
+        bytes.extend_from_slice(&frame);
+        bytes
+    }
+
+    #[test]
+    fn test_decompressors_match_plain_pcap() {
+        use std::io::Read;
+
+        let plain = plain_pcap();
+        let mut gz = Vec::new();
+        flate2::read::GzEncoder::new(&plain[..], flate2::Compression::default())
+            .read_to_end(&mut gz).unwrap();
+        let mut bz2 = Vec::new();
+        bzip2::read::BzEncoder::new(&plain[..], bzip2::Compression::default())
+            .read_to_end(&mut bz2).unwrap();
+        let zst = zstd::stream::encode_all(&plain[..], 3).unwrap();
+
+        for (extension, compressed) in vec![("gz", gz), ("bz2", bz2), ("zst", zst)] {
+            let decompressor = crate::decompressor_for_extension(std::ffi::OsStr::new(extension)).unwrap();
+            let mut data = Vec::new();
+            decompressor.decoder(Box::new(std::io::Cursor::new(compressed))).unwrap()
+                .read_to_end(&mut data).unwrap();
+            assert_eq!(data, plain, "{} capture differs from the plain pcap", extension);
+        }
+        assert!(crate::decompressor_for_extension(std::ffi::OsStr::new("pcap")).is_none());
+    }
+
+    #[cfg(all(feature = "native-pcap", not(feature = "pure-pcap")))]
+    #[test]
+    fn test_temp_pcap_removed() {
+        use std::io::Read;
+
+        let dir = std::env::temp_dir().join(format!("iex_pcap_parser_temp_dir_{}", std::process::id()));
+        let temp_dir = dir.join("tmp");
+        std::fs::create_dir_all(&temp_dir).unwrap();
+        let gz = |name: &str, plain: &[u8]| {
+            let mut compressed = Vec::new();
+            flate2::read::GzEncoder::new(plain, flate2::Compression::default())
+                .read_to_end(&mut compressed).unwrap();
+            let path = dir.join(name).to_str().unwrap().to_string();
+            std::fs::write(&path, compressed).unwrap();
+            path
+        };
+
+        let capture = gz("day.pcap.gz", &plain_pcap());
+        let packets = crate::packets_from_capture_with_temp_dir(&capture, &temp_dir).unwrap();
+        assert_eq!(packets.count(), 1);
+        assert_eq!(std::fs::read_dir(&temp_dir).unwrap().count(), 0);
+        // Also when libpcap can't open the decompressed file.
+        let garbage = gz("garbage.pcap.gz", &[0xff; 64]);
+        assert!(crate::packets_from_capture_with_temp_dir(&garbage, &temp_dir).is_err());
+        assert_eq!(std::fs::read_dir(&temp_dir).unwrap().count(), 0);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(feature = "native-pcap")]
+    #[test]
+    fn test_native_and_pure_pcap_agree() {
+        let frames = vec![
+            iex_tp_frame(crate::DEEP_MESSAGE_PROTOCOL_ID, &[trade_report("AAPL")]),
+            vec![0xff; 60],
+            iex_tp_frame(crate::DEEP_MESSAGE_PROTOCOL_ID, &[trade_report("SPY"), trade_report("SPY")]),
+        ];
+        let bytes = crate::pcapfile::tests::capture(crate::pcapfile::MAGIC, false, &frames, 250);
+        let path = std::env::temp_dir().join(format!("iex_pcap_parser_readers_{}.pcap", std::process::id()));
+        std::fs::write(&path, &bytes).unwrap();
+
+        let mut native = crate::Packets::new(pcap::Capture::from_file(&path).unwrap().into());
+        let mut pure = crate::Packets::from_bytes(bytes).unwrap();
+        let mut count = 0;
+        loop {
+            match (native.next_frame(), pure.next_frame()) {
+                (Some(Ok(native)), Some(Ok(pure))) => assert_eq!(native, pure),
+                (None, None) => break,
+                _ => panic!("readers disagree after {} packets", count),
+            }
+            count += 1;
+        }
+        assert_eq!(count, frames.len());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_parse_message_trade_report() {
+        let response = crate::parse_message(&trade_report("AAPL"), 0, 1, libdt::UtcNs::default(), crate::ParseMode::Lenient).unwrap();
+        assert_eq!(response.parsed_message.symbol(), Some("AAPL".to_string()));
+        match response.parsed_message.body {
+            crate::IexDeepMessageImpl::TradeReport(trade) => {
+                assert_eq!(trade.size, 100);
+                assert_eq!(trade.price, 1_990_000);
+                assert_eq!(trade.trade_id, 42);
+            },
+            _ => panic!("expected a trade report"),
+        }
+    }
+
+    #[test]
+    fn test_decode_message() {
+        let message = crate::decode_message(&trade_report("AAPL")).unwrap();
+        assert_eq!((message.message_type, message.packet_number, message.message_sequence_number), (b'T', 0, 0));
+        assert_eq!(message.symbol(), Some("AAPL".to_string()));
+        match crate::decode_message(&trade_report("AAPL")[..37]) {
+            Err(crate::ParseError::TruncatedMessage { have: 37, need: 38 }) => {},
+            _ => panic!("expected a truncated message"),
+        }
+    }
+
+    fn security_directory(flags: u8) -> Vec<u8> {
+        let mut bytes = vec![b'D', flags];
+        bytes.extend_from_slice(&1_562_160_000_000_000_000u64.to_le_bytes());
+        bytes.extend(format!("{:<8}", "ZIEXT").bytes());
+        bytes.extend_from_slice(&100u32.to_le_bytes());
+        bytes.extend_from_slice(&100_000u64.to_le_bytes());
+        bytes.push(1);
+        bytes
+    }
+
+    #[test]
+    fn test_official_price() {
+        let official_price = |price_type: u8| {
+            let mut bytes = vec![b'X', price_type];
+            bytes.extend_from_slice(&1_562_176_800_000_000_000u64.to_le_bytes());
+            bytes.extend(b"SPY     ");
+            bytes.extend_from_slice(&2_967_500u64.to_le_bytes());
+            let response = crate::parse_message(&bytes, 5, 9, libdt::UtcNs::default(), crate::ParseMode::Lenient).unwrap();
+            response.parsed_message
+        };
+        let closing = official_price(b'M');
+        assert!(closing.to_serialized_tick().is_none());
+        let tick = closing.to_official_price().unwrap();
+        assert_eq!((tick.message_type, tick.message_subtype), (libh5::OFFICIAL_PRICE, libh5::OFFICIAL_CLOSING_PRICE));
+        assert_eq!((tick.timestamp, tick.size, tick.packet_number, tick.message_sequence_number),
+                   (1_562_176_800_000_000_000, 0, 5, 9));
+        assert_eq!(libh5::format_price(tick.price, tick.price_multiplier), "296.7500");
+        assert_eq!(official_price(b'Q').to_official_price().unwrap().message_subtype, libh5::OFFICIAL_OPENING_PRICE);
+    }
+
+    #[test]
+    fn test_price_range() {
+        let trade = |price: u64| {
+            let mut bytes = trade_report("AAPL");
+            bytes[22..30].copy_from_slice(&price.to_le_bytes());
+            crate::parse_message(&bytes, 0, 1, libdt::UtcNs::default(), crate::ParseMode::Lenient).unwrap().parsed_message
+        };
+        assert!(!crate::PriceRange::default().excludes(&trade(u64::MAX)));
+        let range = crate::PriceRange::default().with_min(0.0001).with_max(100_000.0);
+        // $0.0001, $199 and $100,000 are in the range, bounds included.
+        for &price in &[1, 1_990_000, 1_000_000_000] {
+            assert!(!range.excludes(&trade(price)), "price {}", price);
+        }
+        // An absurd price, as from reading the wrong offset, and $0.
+        for &price in &[1_000_000_001, 0x4141_4141_4141_4141, 0] {
+            assert!(range.excludes(&trade(price)), "price {}", price);
+        }
+        assert!(!crate::PriceRange::default().with_max(100_000.0).excludes(&trade(0)));
+
+        // Only trades are checked.
+        let mut bytes = vec![b'8', 1];
+        bytes.extend_from_slice(&1_562_160_000_000_000_000u64.to_le_bytes());
+        bytes.extend(b"AAPL    ");
+        bytes.extend_from_slice(&100u32.to_le_bytes());
+        bytes.extend_from_slice(&u64::MAX.to_le_bytes());
+        let level = crate::parse_message(&bytes, 0, 1, libdt::UtcNs::default(), crate::ParseMode::Lenient).unwrap().parsed_message;
+        assert!(!range.excludes(&level));
+    }
+
+    #[test]
+    fn test_zero_trade_filter() {
+        let trade = |size: u32, price: u64| {
+            let mut bytes = trade_report("AAPL");
+            bytes[18..22].copy_from_slice(&size.to_le_bytes());
+            bytes[22..30].copy_from_slice(&price.to_le_bytes());
+            crate::parse_message(&bytes, 0, 1, libdt::UtcNs::default(), crate::ParseMode::Lenient).unwrap().parsed_message
+        };
+        let zero_price = trade(100, 0);
+        assert_eq!(zero_price.to_serialized_tick().unwrap().price, 0);
+        assert_eq!(crate::ZeroTradeFilter::default().drops(&zero_price), None);
+        let drop_price = crate::ZeroTradeFilter::default().with_drop_zero_price(true);
+        assert_eq!(drop_price.drops(&zero_price), Some(crate::ZeroTrade::Price));
+        assert_eq!(drop_price.drops(&trade(0, 1_990_000)), None);
+        assert_eq!(drop_price.drops(&trade(100, 1_990_000)), None);
+
+        let drop_both = drop_price.with_drop_zero_size(true);
+        assert_eq!(drop_both.drops(&trade(0, 1_990_000)), Some(crate::ZeroTrade::Size));
+        assert_eq!(drop_both.drops(&trade(0, 0)), Some(crate::ZeroTrade::Size));
+
+        // An emptied price level is kept.
+        let mut bytes = vec![b'8', 1];
+        bytes.extend_from_slice(&1_562_160_000_000_000_000u64.to_le_bytes());
+        bytes.extend(b"AAPL    ");
+        bytes.extend_from_slice(&0u32.to_le_bytes());
+        bytes.extend_from_slice(&0u64.to_le_bytes());
+        let level = crate::parse_message(&bytes, 0, 1, libdt::UtcNs::default(), crate::ParseMode::Lenient).unwrap().parsed_message;
+        assert_eq!(drop_both.drops(&level), None);
+        assert!(level.to_serialized_tick().is_some());
+    }
+
+    #[test]
+    fn test_auction_information() {
+        // SPY's closing auction on 2019-07-03, scheduled for 13:00 New York time on that half day.
+        let mut bytes = vec![b'A', b'C'];
+        bytes.extend_from_slice(&1_562_173_140_000_000_000u64.to_le_bytes());
+        bytes.extend(b"SPY     ");
+        bytes.extend_from_slice(&1_000u32.to_le_bytes());
+        bytes.extend_from_slice(&2_967_500u64.to_le_bytes());
+        bytes.extend_from_slice(&2_967_000u64.to_le_bytes());
+        bytes.extend_from_slice(&200u32.to_le_bytes());
+        bytes.extend(&[b'B', 0]);
+        bytes.extend_from_slice(&1_562_173_200u32.to_le_bytes());
+        for price in &[2_967_000u64, 2_967_500, 2_670_700, 3_264_200] {
+            bytes.extend_from_slice(&price.to_le_bytes());
+        }
+        let message = crate::parse_message(&bytes, 5, 9, libdt::UtcNs::default(), crate::ParseMode::Lenient).unwrap().parsed_message;
+        match &message.body {
+            crate::IexDeepMessageImpl::AuctionInformation(auction) => {
+                assert_eq!(auction.auction_type, crate::AuctionType::Closing);
+                assert_eq!(auction.imbalance_side, crate::ImbalanceSide::BuySideImbalance);
+                assert_eq!(auction.scheduled_auction_timestamp().to_rfc3339(), "2019-07-03T17:00:00.000000000Z");
+            },
+            _ => panic!("expected an auction information message"),
+        }
+        let auction = message.to_auction().unwrap();
+        assert_eq!((auction.auction_type, auction.scheduled_auction_time, auction.upper_auction_collar),
+                   (b'C', 1_562_173_200_000_000_000, 3_264_200));
+        assert_eq!((auction.packet_number, auction.message_sequence_number), (5, 9));
+        assert!(message.to_serialized_tick().is_none());
+
+        bytes[42] = b'X';
+        assert!(crate::parse_message(&bytes, 5, 9, libdt::UtcNs::default(), crate::ParseMode::Lenient).is_err());
+    }
+
+    #[test]
+    fn test_auction_information_layouts() {
+        let mut bytes = vec![b'A', b'O'];
+        bytes.extend_from_slice(&1_562_160_500_000_000_000u64.to_le_bytes());
+        bytes.extend(b"SPY     ");
+        bytes.extend_from_slice(&1_000u32.to_le_bytes());
+        bytes.extend_from_slice(&2_967_500u64.to_le_bytes());
+        bytes.extend_from_slice(&2_967_000u64.to_le_bytes());
+        bytes.extend_from_slice(&200u32.to_le_bytes());
+        bytes.extend(&[b'S', 0]);
+        bytes.extend_from_slice(&1_562_160_600u32.to_le_bytes());
+        let prefix_length = bytes.len();
+        for price in &[2_967_000u64, 2_967_500, 2_670_700, 3_264_200] {
+            bytes.extend_from_slice(&price.to_le_bytes());
+        }
+        let parse = |bytes: &[u8], mode| crate::parse_message(bytes, 0, 1, libdt::UtcNs::default(), mode)
+            .map(|response| match response.parsed_message.body {
+                crate::IexDeepMessageImpl::AuctionInformation(auction) => auction,
+                _ => panic!("expected an auction information message"),
+            });
+
+        let full = parse(&bytes, crate::ParseMode::Lenient).unwrap();
+        assert_eq!((full.layout, full.upper_auction_collar), (crate::AuctionLayout::Full, 3_264_200));
+        assert_eq!(parse(&bytes, crate::ParseMode::Strict).unwrap().layout, crate::AuctionLayout::Full);
+
+        // An older, shorter layout keeps its common fields, in lenient mode only.
+        let prefix = parse(&bytes[..prefix_length], crate::ParseMode::Lenient).unwrap();
+        assert_eq!(prefix.layout, crate::AuctionLayout::Prefix);
+        assert_eq!((prefix.paired_shares, prefix.imbalance_shares, prefix.scheduled_auction_time),
+                   (1_000, 200, 1_562_160_600));
+        assert_eq!((prefix.auction_book_clearing_price, prefix.upper_auction_collar), (0, 0));
+        assert_eq!(parse(&bytes[..60], crate::ParseMode::Lenient).unwrap().layout, crate::AuctionLayout::Prefix);
+        match parse(&bytes[..prefix_length], crate::ParseMode::Strict) {
+            Err(crate::ParseError::TruncatedMessage { have: 48, need: 80 }) => {},
+            _ => panic!("expected a truncated message"),
+        }
+        // Shorter than the prefix, it's skipped and counted as truncated.
+        match parse(&bytes[..prefix_length - 1], crate::ParseMode::Lenient) {
+            Err(crate::ParseError::TruncatedMessage { have: 47, need: 80 }) => {},
+            _ => panic!("expected a truncated message"),
+        }
+
+        let packet = |messages: &[Vec<u8>]| {
+            let frame = iex_tp_frame(crate::DEEP_MESSAGE_PROTOCOL_ID, messages);
+            crate::parse_packet(&frame, 0, libdt::UtcNs::default(), &crate::ParseOptions::default())
+        };
+        let mut stats = crate::ParseStats::default();
+        stats.record(&packet(&[bytes.clone(), bytes[..prefix_length].to_vec(), bytes[..40].to_vec()]));
+        assert_eq!((stats.messages_parsed, stats.auctions_prefix_layout, stats.messages_truncated), (2, 1, 1));
+    }
+
+    #[test]
+    fn test_symbol_normalizer() {
+        let symbol: crate::MessageSymbol = ['B', 'R', 'K', '.', 'a', ' ', ' ', ' '];
+        let normalizer = crate::SymbolNormalizer::default();
+        assert_eq!(normalizer.normalize(&symbol), "BRK.a");
+        assert_eq!(normalizer.with_padding(true).normalize(&symbol), "BRK.a   ");
+        let dashed = normalizer.with_case(crate::SymbolCase::Upper).with_class_separator('-');
+        assert_eq!(dashed.normalize(&symbol), "BRK-A");
+        assert_eq!(dashed.with_padding(true).normalize(&symbol), "BRK-A   ");
+        let lower = normalizer.with_case(crate::SymbolCase::Lower).with_class_separator('/');
+        assert_eq!(lower.normalize(&symbol), "brk/a");
+    }
+
+    #[test]
+    fn test_symbol_of_every_message_type() {
+        // Type, a valid subtype and the length of each message type with a symbol.
+        let types = [(b'D', 0, 31), (b'H', b'H', 22), (b'O', b'O', 18), (b'P', 0, 19), (b'E', b'O', 18),
+                     (b'8', 0, 30), (b'5', 1, 30), (b'T', 0, 38), (b'X', b'Q', 26), (b'B', 0, 38),
+                     (b'A', b'C', 80)];
+        for &(message_type, subtype, length) in &types {
+            let mut bytes = vec![message_type, subtype];
+            bytes.extend_from_slice(&1_562_160_000_000_000_000u64.to_le_bytes());
+            bytes.extend(format!("{:<8}", "ZIEXT").bytes());
+            bytes.resize(length, 0);
+            if message_type == b'P' {
+                bytes[18] = b' ';
+            }
+            if message_type == b'A' {
+                bytes[42] = b'N';
+            }
+            let message = crate::parse_message(&bytes, 0, 1, libdt::UtcNs::default(), crate::ParseMode::Lenient).unwrap().parsed_message;
+            assert_eq!(message.symbol(), Some("ZIEXT".to_string()), "message type {}", message_type as char);
+        }
+
+        let mut system_event = vec![b'S', b'O'];
+        system_event.extend_from_slice(&1_562_160_000_000_000_000u64.to_le_bytes());
+        assert_eq!(crate::parse_message(&system_event, 0, 1, libdt::UtcNs::default(), crate::ParseMode::Lenient).unwrap().parsed_message.symbol(), None);
+    }
+
+    fn trading_status(status: u8, reason: &str) -> Vec<u8> {
+        let mut bytes = vec![b'H', status];
+        bytes.extend_from_slice(&1_562_160_000_000_000_000u64.to_le_bytes());
+        bytes.extend(format!("{:<8}", "AAPL").bytes());
+        bytes.extend(format!("{:<4}", reason).bytes());
+        bytes
+    }
+
+    #[test]
+    fn test_halt_reason() {
+        let reason = |status, reason| match crate::parse_message(&trading_status(status, reason), 0, 1, libdt::UtcNs::default(), crate::ParseMode::Lenient).unwrap().parsed_message.body {
+            crate::IexDeepMessageImpl::TradingStatus(message) => message.halt_reason(),
+            _ => panic!("expected a trading status message"),
+        };
+        assert_eq!(reason(b'H', "T1"), Some(crate::HaltReason::HaltNewsPending));
+        assert_eq!(reason(b'P', "LUDP"), Some(crate::HaltReason::LimitUpLimitDownPause));
+        assert_eq!(reason(b'O', "IPO2"), Some(crate::HaltReason::IpoOrderAcceptancePeriod));
+        assert_eq!(reason(b'H', "NA"), Some(crate::HaltReason::NotAvailable));
+        assert_eq!(reason(b'H', "XY"), Some(crate::HaltReason::Unknown("XY".to_string())));
+        assert_eq!(reason(b'T', ""), None);
+    }
+
+    #[test]
+    fn test_unknown_trading_status() {
+        // 'X' isn't a trading status, as one added to the protocol after this parser wouldn't be.
+        let frame = iex_tp_frame(crate::DEEP_MESSAGE_PROTOCOL_ID, &[trading_status(b'X', ""), trade_report("AAPL")]);
+        let parse = |mode| crate::parse_packet(&frame, 0, libdt::UtcNs::default(),
+                                               &crate::ParseOptions { mode, ..Default::default() });
+        let lenient = parse(crate::ParseMode::Lenient).unwrap();
+        assert_eq!(lenient.messages.len(), 1);
+        assert!(matches!(lenient.errors[..],
+                         [crate::ParseError::InvalidEnumByte { field: "trading_status", byte: b'X' }]));
+        let strict = parse(crate::ParseMode::Strict);
+        assert!(matches!(strict, Err(crate::ParseError::InvalidEnumByte { field: "trading_status", byte: b'X' })));
+
+        let mut stats = crate::ParseStats::default();
+        stats.record(&Ok(lenient));
+        stats.record(&strict);
+        assert_eq!(stats.messages_invalid_enum, 2);
+    }
+
+    #[test]
+    fn test_end_of_messages() {
+        let system_event = |event| {
+            let mut bytes = vec![b'S', event];
+            bytes.extend_from_slice(&1_562_184_000_000_000_000u64.to_le_bytes());
+            bytes
+        };
+        let packet = |messages: &[Vec<u8>]| {
+            let frame = iex_tp_frame(crate::DEEP_MESSAGE_PROTOCOL_ID, messages);
+            crate::parse_packet(&frame, 0, libdt::UtcNs::default(), &crate::ParseOptions::default())
+        };
+
+        let mut stats = crate::ParseStats::default();
+        stats.record(&packet(&[trade_report("AAPL"), system_event(b'E')]));
+        assert!(!stats.end_of_messages);
+        let end = packet(&[system_event(b'C')]).unwrap();
+        assert!(end.messages[0].is_end_of_messages());
+        stats.record(&Ok(end));
+        assert!(stats.end_of_messages);
+
+        let mut merged = crate::ParseStats::default();
+        merged.merge(&stats);
+        assert!(merged.end_of_messages);
+    }
+
+    #[test]
+    fn test_parse_security_directory_flags() {
+        let flags = |subtype| match crate::parse_message(&security_directory(subtype), 0, 1, libdt::UtcNs::default(), crate::ParseMode::Lenient).unwrap().parsed_message.body {
+            crate::IexDeepMessageImpl::SecurityDirectory(directory) => directory.flags,
+            _ => panic!("expected a security directory message"),
+        };
+
+        let test_etp = flags(0xa0);
+        assert!(test_etp.is_test_security());
+        assert!(!test_etp.is_when_issued());
+        assert!(test_etp.is_etp());
+
+        let when_issued = flags(0x40);
+        assert!(!when_issued.is_test_security());
+        assert!(when_issued.is_when_issued());
+        assert!(!when_issued.is_etp());
+
+        assert!(flags(0x0).is_empty());
+    }
+
+    #[test]
+    fn test_parse_message_errors() {
+        let bytes = trade_report("AAPL");
+        match crate::parse_message(&bytes[..20], 0, 1, libdt::UtcNs::default(), crate::ParseMode::Lenient) {
+            Err(crate::ParseError::TruncatedMessage { have: 20, need: 38 }) => {},
+            _ => panic!("expected a truncated message"),
+        }
+        match crate::parse_message(&bytes[..4], 0, 1, libdt::UtcNs::default(), crate::ParseMode::Lenient) {
+            Err(crate::ParseError::TruncatedMessage { have: 4, need: 10 }) => {},
+            _ => panic!("expected a truncated message"),
+        }
+
+        let mut unknown = bytes.clone();
+        unknown[0] = b'Z';
+        match crate::parse_message(&unknown, 0, 1, libdt::UtcNs::default(), crate::ParseMode::Lenient) {
+            Err(crate::ParseError::UnknownMessageType(b'Z')) => {},
+            _ => panic!("expected an unknown message type"),
+        }
+
+        let mut system_event = bytes.clone();
+        system_event[0] = b'S';
+        system_event[1] = b'?';
+        match crate::parse_message(&system_event, 0, 1, libdt::UtcNs::default(), crate::ParseMode::Lenient) {
+            Err(crate::ParseError::InvalidEnumByte { field: "system_event", byte: b'?' }) => {},
+            _ => panic!("expected an invalid system event"),
+        }
+    }
+
+    #[test]
+    fn test_parse_header() {
+        // A DEEP packet of channel 1, session 1150681088, with 2 messages in 76 bytes, sent at
+        // 2019-07-03T13:30:00Z.
+        let header: [u8; 40] = [
+            0x01, 0x00, 0x04, 0x80, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x96, 0x44, 0x4c, 0x00, 0x02, 0x00,
+            0x8c, 0xa0, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x71, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x70, 0x1c, 0xfd, 0xc9, 0xe8, 0xad, 0x15,
+        ];
+        let mut payload = header.to_vec();
+        payload.resize(crate::IexTpHeader::LENGTH + 76, 0);
+        let decoded = crate::parse_header(&payload, crate::ParseMode::Strict).unwrap();
+        assert_eq!((decoded.version, decoded.message_protocol_id, decoded.channel_id, decoded.session_id),
+                   (1, crate::DEEP_MESSAGE_PROTOCOL_ID, 1, 1_150_681_088));
+        assert_eq!((decoded.payload_length, decoded.message_count, decoded.stream_offset,
+                    decoded.first_message_sequence_number), (76, 2, 41_100, 369));
+        assert_eq!(decoded.send_time.to_rfc3339(), "2019-07-03T13:30:00.000000000Z");
+        assert_eq!(decoded.send_datetime().timestamp(), 1_562_160_600);
+        assert!(crate::describe_header(&decoded).ends_with("Send time: 2019-07-03T13:30:00.000000000Z"));
+
+        match crate::parse_header(&header[..39], crate::ParseMode::Lenient) {
+            Err(crate::ParseError::TruncatedHeader { have: 39, need: 40 }) => {},
+            _ => panic!("expected a truncated header"),
+        }
+        match crate::parse_header(&header, crate::ParseMode::Lenient) {
+            Err(crate::ParseError::PayloadLengthMismatch { header: 76, actual: 0 }) => {},
+            _ => panic!("expected a payload length mismatch"),
+        }
+    }
+
+    #[test]
+    fn test_capture_timestamp_ns() {
+        assert_eq!(crate::capture_timestamp_ns(1_562_160_000, 123_456).as_nanos(), 1_562_160_000_123_456_000);
+    }
+
+    /// An ethernet frame carrying an IEX-TP packet of `message_protocol_id` with `messages`.
+    fn iex_tp_frame(message_protocol_id: u16, messages: &[Vec<u8>]) -> Vec<u8> {
+        iex_tp_frame_on(0, 0, message_protocol_id, messages)
+    }
+
+    fn iex_tp_frame_on(channel_id: u32, session_id: u32, message_protocol_id: u16, messages: &[Vec<u8>]) -> Vec<u8> {
+        let mut payload = vec![1, 0];
+        payload.extend_from_slice(&message_protocol_id.to_le_bytes());
+        payload.extend_from_slice(&channel_id.to_le_bytes());
+        payload.extend_from_slice(&session_id.to_le_bytes());
+        let mut body = Vec::new();
+        for message in messages {
+            body.extend_from_slice(&(message.len() as u16).to_le_bytes());
+            body.extend_from_slice(message);
+        }
+        payload.extend_from_slice(&(body.len() as u16).to_le_bytes());
+        payload.extend_from_slice(&(messages.len() as u16).to_le_bytes());
+        payload.extend_from_slice(&[0; 24]);
+        payload.extend_from_slice(&body);
+        let mut frame = Vec::new();
+        etherparse::PacketBuilder::ethernet2([0; 6], [1, 0, 0x5e, 0x57, 0x15, 4])
+            .ipv4([10, 0, 0, 1], [233, 215, 21, 4], 32)
+            .udp(10378, 10378)
+            .write(&mut frame, &payload)
+            .unwrap();
+        frame
+    }
+
+    #[test]
+    fn test_parse_packet_routes_by_protocol() {
+        let frames = vec![
+            iex_tp_frame(crate::DEEP_MESSAGE_PROTOCOL_ID, &[trade_report("AAPL")]),
+            iex_tp_frame(crate::TOPS_MESSAGE_PROTOCOL_ID, &[trade_report("MSFT")]),
+            iex_tp_frame(crate::DEEP_MESSAGE_PROTOCOL_ID, &[trade_report("SPY"), trade_report("SPY")]),
+            iex_tp_frame(0x1234, &[]),
+        ];
+        let packets: Vec<crate::DeepPacket> = frames.iter().enumerate()
+            .map(|(i, frame)| crate::parse_packet(frame, i as u64, libdt::UtcNs::default(), &crate::ParseOptions::default()).unwrap())
+            .collect();
+        let routed: Vec<(bool, usize)> = packets.iter()
+            .map(|packet| (packet.is_deep(), packet.messages.len()))
+            .collect();
+        assert_eq!(routed, vec![(true, 1), (false, 0), (true, 2), (false, 0)]);
+
+        let mut frame = iex_tp_frame(crate::DEEP_MESSAGE_PROTOCOL_ID, &[]);
+        let version = frame.len() - 40;
+        frame[version] = 2;
+        match crate::parse_packet(&frame, 0, libdt::UtcNs::default(), &crate::ParseOptions::default()) {
+            Err(crate::ParseError::UnsupportedVersion(2)) => {},
+            _ => panic!("expected an unsupported version"),
+        }
+    }
+
+    #[test]
+    fn test_channel_filter() {
+        let frames = vec![
+            iex_tp_frame_on(1, 42, crate::DEEP_MESSAGE_PROTOCOL_ID, &[trade_report("AAPL")]),
+            iex_tp_frame_on(2, 42, crate::DEEP_MESSAGE_PROTOCOL_ID, &[trade_report("MSFT")]),
+            iex_tp_frame_on(1, 43, crate::DEEP_MESSAGE_PROTOCOL_ID, &[trade_report("SPY")]),
+        ];
+        let kept = |filter: crate::ChannelFilter| -> Vec<(u32, u32, usize, bool)> {
+            frames.iter().enumerate()
+                .map(|(i, frame)| crate::parse_packet(frame, i as u64, libdt::UtcNs::default(),
+                                                      &crate::ParseOptions { filter, ..Default::default() }).unwrap())
+                .map(|p| (p.header.channel_id, p.header.session_id, p.messages.len(), p.filtered_out))
+                .collect()
+        };
+        assert_eq!(kept(crate::ChannelFilter::default()),
+                   vec![(1, 42, 1, false), (2, 42, 1, false), (1, 43, 1, false)]);
+        assert_eq!(kept(crate::ChannelFilter { channel_id: Some(1), session_id: None }),
+                   vec![(1, 42, 1, false), (2, 42, 0, true), (1, 43, 1, false)]);
+        assert_eq!(kept(crate::ChannelFilter { channel_id: Some(1), session_id: Some(42) }),
+                   vec![(1, 42, 1, false), (2, 42, 0, true), (1, 43, 0, true)]);
+    }
+
+    #[test]
+    fn test_channel_summary() {
+        let frames = [
+            iex_tp_frame_on(1, 42, crate::DEEP_MESSAGE_PROTOCOL_ID, &[trade_report("AAPL"), trade_report("MSFT")]),
+            iex_tp_frame_on(2, 42, crate::DEEP_MESSAGE_PROTOCOL_ID, &[trade_report("SPY")]),
+            iex_tp_frame_on(1, 42, crate::DEEP_MESSAGE_PROTOCOL_ID, &[trade_report("AAPL")]),
+            iex_tp_frame_on(3, 42, crate::TOPS_MESSAGE_PROTOCOL_ID, &[trade_report("IWM")]),
+        ];
+        let mut summary = crate::ChannelSummary::default();
+        for (i, frame) in frames.iter().enumerate() {
+            summary.record(&crate::parse_packet(frame, i as u64, libdt::UtcNs::default(), &crate::ParseOptions::default()).unwrap());
+        }
+        assert_eq!(summary.iter().collect::<Vec<_>>(), vec![(1, 3, 2), (2, 1, 1)]);
+    }
+
+    #[test]
+    fn test_global_sequence() {
+        // Every trade report is sent at the same time, so only the channel and sequence number
+        // tell them apart.
+        let frames = vec![
+            sequenced(iex_tp_frame_on(2, 42, crate::DEEP_MESSAGE_PROTOCOL_ID, &[trade_report("MSFT"), trade_report("SPY")]), 1, 2),
+            sequenced(iex_tp_frame_on(1, 42, crate::DEEP_MESSAGE_PROTOCOL_ID, &[trade_report("AAPL")]), 7, 1),
+        ];
+        let ordered = |frames: &[Vec<u8>]| -> Vec<(u32, u64, String)> {
+            let mut messages: Vec<crate::IexDeepMessage> = frames.iter()
+                .flat_map(|frame| crate::parse_packet(frame, 0, libdt::UtcNs::default(), &crate::ParseOptions::default()).unwrap().messages)
+                .collect();
+            messages.sort_by_key(crate::IexDeepMessage::global_sequence);
+            messages.iter().map(|m| (m.channel_id, m.message_sequence_number, m.symbol().unwrap())).collect()
+        };
+        let expected = vec![(1, 7, "AAPL".to_string()), (2, 1, "MSFT".to_string()), (2, 2, "SPY".to_string())];
+        assert_eq!(ordered(&frames), expected);
+        let reversed: Vec<Vec<u8>> = frames.iter().rev().cloned().collect();
+        assert_eq!(ordered(&reversed), expected);
+    }
+
+    #[test]
+    fn test_parse_body_length_overruns_buffer() {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(&38u16.to_le_bytes());
+        bytes.extend_from_slice(&trade_report("AAPL"));
+        bytes.extend_from_slice(&38u16.to_le_bytes());
+        bytes.extend_from_slice(&trade_report("MSFT")[..20]);
+        let (messages, errors, raw_failures, next) = crate::parse_body(&bytes, 0, 1, 2, libdt::UtcNs::default(),
+                                                                       &crate::ParseOptions::default());
+        assert_eq!((messages.len(), next), (1, 2));
+        assert_eq!(raw_failures, vec![crate::RawMessage { packet_number: 0, message_sequence_number: 2, bytes: trade_report("MSFT")[..20].to_vec() }]);
+        match errors.as_slice() {
+            [crate::ParseError::TruncatedMessage { have: 20, need: 38 }] => {},
+            _ => panic!("expected a truncated message"),
+        }
+
+        // A length field that is itself cut short, and one with nothing after it.
+        for tail in &[vec![38u8], vec![38u8, 0]] {
+            let mut bytes = Vec::new();
+            bytes.extend_from_slice(&38u16.to_le_bytes());
+            bytes.extend_from_slice(&trade_report("AAPL"));
+            bytes.extend_from_slice(tail);
+            let (messages, _, _, _) = crate::parse_body(&bytes, 0, 1, 2, libdt::UtcNs::default(), &crate::ParseOptions::default());
+            assert_eq!(messages.len(), 1);
+        }
+    }
+
+    #[test]
+    fn test_parse_body_zero_length_message() {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(&38u16.to_le_bytes());
+        bytes.extend_from_slice(&trade_report("AAPL"));
+        bytes.extend_from_slice(&0u16.to_le_bytes());
+        bytes.extend_from_slice(&38u16.to_le_bytes());
+        bytes.extend_from_slice(&trade_report("MSFT"));
+
+        // Leniently the 0-length message is skipped, without a sequence number, and MSFT recovered.
+        let (messages, errors, raw_failures, next) = crate::parse_body(&bytes, 0, 1, 3, libdt::UtcNs::default(),
+                                                                       &crate::ParseOptions::default());
+        let symbols: Vec<Option<String>> = messages.iter().map(|m| m.symbol()).collect();
+        assert_eq!(symbols, vec![Some("AAPL".to_string()), Some("MSFT".to_string())]);
+        assert_eq!(messages[1].message_sequence_number, 2);
+        assert!(matches!(errors.as_slice(), [crate::ParseError::ZeroLengthMessage { offset: 40 }]));
+        assert!(raw_failures.is_empty());
+        assert_eq!(next, 3);
+
+        // Strictly it's an error that stops decoding.
+        let strict = crate::ParseOptions { mode: crate::ParseMode::Strict, ..Default::default() };
+        let (messages, errors, _, _) = crate::parse_body(&bytes, 0, 1, 3, libdt::UtcNs::default(), &strict);
+        assert_eq!(messages.len(), 1);
+        assert!(matches!(errors.as_slice(), [crate::ParseError::ZeroLengthMessage { offset: 40 }]));
+
+        let mut counts = crate::MessageCounts::default();
+        crate::count_body(&bytes, &mut counts);
+        assert_eq!(counts.count(b'T'), 2);
+    }
+
+    #[test]
+    fn test_parse_body_message_count() {
+        let mut bytes = Vec::new();
+        for symbol in &["AAPL", "MSFT", "SPY"] {
+            bytes.extend_from_slice(&38u16.to_le_bytes());
+            bytes.extend_from_slice(&trade_report(symbol));
+        }
+        // The declared count only sizes the result; every message in the payload is decoded.
+        for &message_count in &[0, 3, 2, u16::MAX] {
+            let (messages, errors, _, next) = crate::parse_body(&bytes, 0, 1, message_count, libdt::UtcNs::default(),
+                                                                &crate::ParseOptions::default());
+            assert_eq!((messages.len(), errors.len(), next), (3, 0, 4));
+            assert!(messages.capacity() <= bytes.len() / crate::MIN_MESSAGE_BLOCK_LENGTH);
+            assert!(messages.capacity() >= usize::from(message_count).min(3));
+        }
+    }
+
+    #[test]
+    fn test_message_lengths() {
+        let mut system_event = vec![b'S', b'O'];
+        system_event.extend_from_slice(&1_562_160_000_000_000_000u64.to_le_bytes());
+        let messages = [system_event, security_directory(0x80), trading_status(b'H', "T1"), trade_report("AAPL")];
+        let frame = iex_tp_frame(crate::DEEP_MESSAGE_PROTOCOL_ID, &messages);
+        // The body follows 42 bytes of ethernet, IPv4 and UDP headers and the 40 byte IEX-TP header.
+        let body = &frame[82..];
+        let mut offset = 0;
+        for message in &messages {
+            let framed = usize::from(u16::from_le_bytes([body[offset], body[offset + 1]]));
+            let bytes = &body[offset + 2..offset + 2 + framed];
+            assert_eq!(crate::message_length(bytes[0]), Some(framed), "message type {}", bytes[0] as char);
+            let response = crate::parse_message(bytes, 0, 1, libdt::UtcNs::default(), crate::ParseMode::Strict).unwrap();
+            assert_eq!(response.consumed_bytes, message.len());
+            offset += 2 + framed;
+        }
+        assert_eq!(offset, body.len());
+        assert_eq!(crate::message_length(b'Z'), None);
+
+        // Bytes past a message's length are ignored, unless in strict mode.
+        let mut padded = trade_report("AAPL");
+        padded.extend(&[0; 4]);
+        let response = crate::parse_message(&padded, 0, 1, libdt::UtcNs::default(), crate::ParseMode::Lenient).unwrap();
+        assert_eq!(response.consumed_bytes, 38);
+        match crate::parse_message(&padded, 0, 1, libdt::UtcNs::default(), crate::ParseMode::Strict) {
+            Err(crate::ParseError::MessageLengthMismatch { message_type: b'T', framed: 42, expected: 38 }) => {},
+            _ => panic!("expected a message length mismatch"),
+        }
+    }
+
+    #[test]
+    fn test_parse_message_checks_length_per_type() {
+        let mut price_level_update = trade_report("AAPL");
+        price_level_update[0] = b'8';
+        price_level_update[1] = 1;
+        assert!(crate::parse_message(&price_level_update[..30], 0, 1, libdt::UtcNs::default(), crate::ParseMode::Lenient).is_ok());
+        match crate::parse_message(&price_level_update[..29], 0, 1, libdt::UtcNs::default(), crate::ParseMode::Lenient) {
+            Err(crate::ParseError::TruncatedMessage { have: 29, need: 30 }) => {},
+            _ => panic!("expected a truncated message"),
+        }
+    }
+
+    #[test]
+    fn test_parse_body_accumulates_errors() {
+        let mut bytes = Vec::new();
+        for message in &[trade_report("AAPL"), vec![b'Z'; 12], trade_report("MSFT")] {
+            bytes.extend_from_slice(&(message.len() as u16).to_le_bytes());
+            bytes.extend_from_slice(message);
+        }
+        let (messages, errors, raw_failures, next) = crate::parse_body(&bytes, 7, 1, 2, libdt::UtcNs::from_nanos(1_562_160_000_000_123_000),
+                                                                       &crate::ParseOptions::default());
+        assert_eq!((messages.len(), next), (2, 4));
+        assert_eq!(raw_failures, vec![crate::RawMessage { packet_number: 7, message_sequence_number: 2, bytes: vec![b'Z'; 12] }]);
+        assert_eq!(messages[1].message_sequence_number, 3);
+        assert_eq!(messages[1].capture_timestamp_ns.as_nanos(), 1_562_160_000_000_123_000);
+        assert_eq!(errors.len(), 1);
+    }
+
+    #[test]
+    fn test_max_packets() {
+        let frames: Vec<Vec<u8>> = (0..3)
+            .map(|_| iex_tp_frame(crate::DEEP_MESSAGE_PROTOCOL_ID, &[trade_report("AAPL")]))
+            .collect();
+        let packets = |max_packets| {
+            let capture = crate::pcapng::EnhancedPackets::new(crate::pcapng::tests::capture(&frames)).unwrap();
+            crate::Packets::from_pcapng(capture).with_max_packets(max_packets)
+        };
+
+        let mut limited = packets(Some(2));
+        assert_eq!(limited.by_ref().count(), 2);
+        assert!(limited.limit_reached());
+        let mut unlimited = packets(None);
+        assert_eq!(unlimited.by_ref().count(), 3);
+        assert!(!unlimited.limit_reached());
+        let mut above = packets(Some(5));
+        assert_eq!(above.by_ref().count(), 3);
+        assert!(!above.limit_reached());
+        assert_eq!(packets(Some(0)).count(), 0);
+    }
+
+    #[test]
+    fn test_count_messages() {
+        let mut directory = vec![b'D', 0x80];
+        directory.extend_from_slice(&1_562_160_000_000_000_000u64.to_le_bytes());
+        directory.extend(format!("{:<8}", "ZIEXT").bytes());
+        directory.extend_from_slice(&[0; 13]);
+        let frames = vec![
+            iex_tp_frame(crate::DEEP_MESSAGE_PROTOCOL_ID, &[trade_report("AAPL"), directory.clone()]),
+            iex_tp_frame(crate::TOPS_MESSAGE_PROTOCOL_ID, &[trade_report("MSFT")]),
+            iex_tp_frame(crate::DEEP_MESSAGE_PROTOCOL_ID, &[trade_report("SPY"), trade_report("SPY")]),
+            vec![0xff; 60],
+        ];
+        let packets = || {
+            let capture = crate::pcapng::EnhancedPackets::new(crate::pcapng::tests::capture(&frames)).unwrap();
+            crate::Packets::from_pcapng(capture)
+        };
+
+        let counts = packets().count_messages();
+        // The same as decoding every message.
+        let mut decoded = std::collections::HashMap::new();
+        for packet in packets().filter_map(Result::ok) {
+            for message in packet.messages {
+                *decoded.entry(message.message_type).or_insert(0) += 1;
+            }
+        }
+        assert_eq!(counts.iter().collect::<std::collections::HashMap<u8, u64>>(), decoded);
+        assert_eq!((counts.count(b'T'), counts.count(b'D'), counts.total()), (3, 1, 4));
+        assert_eq!((counts.packets, counts.packets_skipped, counts.truncated), (4, 2, 0));
+
+        let mut limited = packets().with_max_packets(Some(1));
+        assert_eq!(limited.count_messages().total(), 2);
+        assert!(limited.limit_reached());
+        let mut merged = counts.clone();
+        merged.merge(&counts);
+        assert_eq!((merged.total(), merged.packets), (8, 8));
+    }
+
+    #[test]
+    fn test_replay() {
+        // Captured 0, 100, 50 and 300 milliseconds in, replayed 1000 times faster.
+        let captured = [0, 100, 50, 300];
+        let mut packets: Vec<Result<crate::DeepPacket, crate::ParseError>> = ["A", "B", "C", "D"].iter().zip(&captured)
+            .map(|(symbol, ms)| {
+                let frame = iex_tp_frame(crate::DEEP_MESSAGE_PROTOCOL_ID, &[trade_report(symbol)]);
+                let captured = libdt::UtcNs::from_nanos(1_562_160_000_000_000_000 + ms * 1_000_000);
+                crate::parse_packet(&frame, 0, captured, &crate::ParseOptions::default())
+            })
+            .collect();
+        packets.insert(1, Err(crate::ParseError::UnsupportedVersion(2)));
+
+        let start = std::time::Instant::now();
+        let mut emitted = Vec::new();
+        let replayed = crate::Replay::default().with_speed(1000.0).run(packets.into_iter(), |message| {
+            emitted.push((message.symbol().unwrap(), start.elapsed()));
+        });
+
+        assert_eq!(replayed, 4);
+        assert_eq!(emitted.iter().map(|(symbol, _)| symbol.as_str()).collect::<Vec<_>>(), vec!["A", "B", "C", "D"]);
+        assert!(emitted.windows(2).all(|pair| pair[0].1 <= pair[1].1));
+        assert!(emitted[3].1 >= std::time::Duration::from_micros(300));
+    }
+
+    /// Sets the first message sequence number and message count of an IEX-TP frame.
+    fn sequenced(mut frame: Vec<u8>, first: u64, count: u16) -> Vec<u8> {
+        frame[42 + 14..42 + 16].copy_from_slice(&count.to_le_bytes());
+        frame[42 + 24..42 + 32].copy_from_slice(&first.to_le_bytes());
+        frame
+    }
+
+    #[test]
+    fn test_parse_modes() {
+        let deep = |messages: &[Vec<u8>], first| sequenced(iex_tp_frame(crate::DEEP_MESSAGE_PROTOCOL_ID, messages), first, messages.len() as u16);
+        let mut long_payload = deep(&[trade_report("AAPL")], 13);
+        long_payload[42 + 12] -= 1;
+        let frames = vec![
+            deep(&[trade_report("AAPL"), trade_report("SPY")], 1),
+            deep(&[vec![b'Z'; 12], trade_report("MSFT")], 3),
+            // Messages 5 to 9 are missing.
+            deep(&[trade_report("AAPL")], 10),
+            // 0x01 isn't a security directory flag.
+            deep(&[security_directory(0x81)], 11),
+            deep(&[trade_report("AAPL"), vec![0; 0]], 12),
+            long_payload,
+        ];
+        let packets = |mode| {
+            let capture = crate::pcapng::EnhancedPackets::new(crate::pcapng::tests::capture(&frames)).unwrap();
+            crate::Packets::from_pcapng(capture).with_mode(mode).collect::<Vec<_>>()
+        };
+
+        let lenient = packets(crate::ParseMode::Lenient);
+        assert_eq!(lenient.len(), 6);
+        let lenient: Vec<crate::DeepPacket> = lenient.into_iter().map(Result::unwrap).collect();
+        assert_eq!(lenient.iter().map(|p| p.messages.len()).collect::<Vec<_>>(), vec![2, 1, 1, 1, 1, 1]);
+        assert_eq!(lenient.iter().map(|p| p.errors.len()).collect::<Vec<_>>(), vec![0, 1, 0, 0, 1, 0]);
+        match &lenient[3].messages[0].body {
+            crate::IexDeepMessageImpl::SecurityDirectory(m) => assert!(m.flags.is_test_security()),
+            _ => panic!("expected a security directory message"),
+        }
+
+        let strict = packets(crate::ParseMode::Strict);
+        assert_eq!(strict.len(), 6);
+        assert!(strict[0].is_ok());
+        assert!(matches!(strict[1], Err(crate::ParseError::UnknownMessageType(b'Z'))));
+        assert!(matches!(strict[2], Err(crate::ParseError::SequenceGap { expected: 3, first: 10, .. })));
+        assert!(matches!(strict[3], Err(crate::ParseError::InvalidEnumByte { field: "flags", byte: 0x81 })));
+        assert!(matches!(strict[4], Err(crate::ParseError::ZeroLengthMessage { .. })));
+        assert!(matches!(strict[5], Err(crate::ParseError::PayloadLengthMismatch { .. })));
+    }
+
+    #[test]
+    fn test_short_payload() {
+        // The header declares a second trade report the packet doesn't have.
+        let mut frame = iex_tp_frame(crate::DEEP_MESSAGE_PROTOCOL_ID, &[trade_report("AAPL")]);
+        let declared = u16::from_le_bytes([frame[42 + 12], frame[42 + 13]]);
+        let actual = usize::from(declared);
+        frame[42 + 12..42 + 14].copy_from_slice(&(2 * declared).to_le_bytes());
+        for &mode in &[crate::ParseMode::Lenient, crate::ParseMode::Strict] {
+            let packet = crate::parse_packet(&frame, 0, libdt::UtcNs::default(),
+                                             &crate::ParseOptions { mode, ..Default::default() });
+            match packet {
+                Err(crate::ParseError::PayloadLengthMismatch { header, actual: have }) => {
+                    assert_eq!((header, have), (2 * declared, actual));
+                },
+                other => panic!("expected a payload length mismatch, got {:?}", other.map(|p| p.messages.len())),
+            }
+        }
+
+        let capture = crate::pcapng::EnhancedPackets::new(crate::pcapng::tests::capture(&[frame])).unwrap();
+        let mut stats = crate::ParseStats::default();
+        for packet in crate::Packets::from_pcapng(capture) {
+            stats.record(&packet);
+        }
+        assert_eq!((stats.packets_total, stats.headers_malformed, stats.messages_parsed), (1, 1, 0));
+    }
+
+    #[test]
+    fn test_next_message_sequence_number() {
+        // The first packet's header claims three messages, but its body only holds two.
+        let frames = vec![
+            sequenced(iex_tp_frame(crate::DEEP_MESSAGE_PROTOCOL_ID, &[trade_report("AAPL"), trade_report("SPY")]), 1, 3),
+            sequenced(iex_tp_frame(crate::DEEP_MESSAGE_PROTOCOL_ID, &[trade_report("MSFT")]), 4, 1),
+        ];
+        let packets = |mode, headers_only| {
+            let capture = crate::pcapng::EnhancedPackets::new(crate::pcapng::tests::capture(&frames)).unwrap();
+            crate::Packets::from_pcapng(capture).with_mode(mode).with_headers_only(headers_only).collect::<Vec<_>>()
+        };
+
+        let lenient = packets(crate::ParseMode::Lenient, false);
+        let next: Vec<u64> = lenient.iter().map(|p| p.as_ref().unwrap().next_message_sequence_number).collect();
+        assert_eq!(next, vec![3, 5]);
+        let mut stats = crate::ParseStats::default();
+        lenient.iter().for_each(|p| stats.record(p));
+        assert_eq!(stats.sequence_gaps, 1);
+
+        let strict = packets(crate::ParseMode::Strict, false);
+        assert!(strict[0].is_ok());
+        assert!(matches!(strict[1], Err(crate::ParseError::SequenceGap { expected: 3, first: 4, .. })));
+
+        // Without the body, the header's message count is all there is to go by.
+        let headers = packets(crate::ParseMode::Strict, true);
+        let next: Vec<u64> = headers.iter().map(|p| p.as_ref().unwrap().next_message_sequence_number).collect();
+        assert_eq!(next, vec![4, 5]);
+    }
+
+    #[test]
+    fn test_validate_packets() {
+        /// A DEEP frame starting `stream_offset` bytes into its stream.
+        fn at_offset(stream_offset: u64, messages: &[Vec<u8>]) -> Vec<u8> {
+            let mut frame = iex_tp_frame(crate::DEEP_MESSAGE_PROTOCOL_ID, messages);
+            frame[42 + 16..42 + 24].copy_from_slice(&stream_offset.to_le_bytes());
+            frame
+        }
+        // Each trade report takes 40 bytes of payload with its length.
+        let good = vec![
+            at_offset(0, &[trade_report("AAPL"), trade_report("SPY")]),
+            at_offset(80, &[trade_report("AAPL")]),
+            at_offset(120, &[]),
+            iex_tp_frame_on(1, 0, crate::DEEP_MESSAGE_PROTOCOL_ID, &[trade_report("MSFT")]),
+        ];
+        let validate = |frames: &[Vec<u8>]| {
+            let capture = crate::pcapng::EnhancedPackets::new(crate::pcapng::tests::capture(frames)).unwrap();
+            crate::validate_packets(crate::Packets::from_pcapng(capture).with_headers_only(true))
+        };
+        let report = validate(&good);
+        assert!(report.is_valid(), "{:?}", report.anomalies);
+        assert_eq!((report.packets, report.deep_packets, report.messages, report.payload_bytes), (4, 4, 4, 160));
+
+        let mut corrupted = good.clone();
+        // A bit flip in the version, a skipped stream offset, a TOPS packet and a frame cut short
+        // inside the header.
+        corrupted[1][42] = 3;
+        corrupted[2][42 + 16..42 + 24].copy_from_slice(&160u64.to_le_bytes());
+        corrupted.insert(3, iex_tp_frame(crate::TOPS_MESSAGE_PROTOCOL_ID, &[]));
+        corrupted.push(good[0][..42 + 20].to_vec());
+        let report = validate(&corrupted);
+        assert!(!report.is_valid());
+        assert_eq!((report.packets, report.deep_packets, report.missing_bytes), (6, 3, 80));
+        let anomalies: Vec<(u64, &str)> = report.anomalies.iter().map(|a| match a {
+            crate::Anomaly::Malformed { packet_number, .. } => (*packet_number, "malformed"),
+            crate::Anomaly::UnexpectedProtocol { packet_number, .. } => (*packet_number, "protocol"),
+            crate::Anomaly::StreamGap { packet_number, .. } => (*packet_number, "gap"),
+            crate::Anomaly::Unreadable { packet_number, .. } => (*packet_number, "unreadable"),
+        }).collect();
+        assert_eq!(anomalies, vec![(1, "malformed"), (2, "gap"), (3, "protocol"), (5, "malformed")]);
+    }
+
+    #[test]
+    fn test_parse_stats() {
+        let mut not_iex = iex_tp_frame(crate::DEEP_MESSAGE_PROTOCOL_ID, &[]);
+        not_iex[42] = 2;
+        let mut short_header = iex_tp_frame(crate::DEEP_MESSAGE_PROTOCOL_ID, &[]);
+        short_header.truncate(42 + 20);
+        let frames = vec![
+            sequenced(iex_tp_frame(crate::DEEP_MESSAGE_PROTOCOL_ID, &[trade_report("AAPL"), trade_report("SPY")]), 1, 2),
+            not_iex,
+            vec![0xff; 10],
+            // Messages 3 and 4 are missing.
+            sequenced(iex_tp_frame(crate::DEEP_MESSAGE_PROTOCOL_ID, &[vec![b'Z'; 12], trade_report("MSFT")]), 5, 2),
+            short_header,
+            sequenced(iex_tp_frame(crate::DEEP_MESSAGE_PROTOCOL_ID, &[trade_report("AAPL")[..20].to_vec()]), 7, 1),
+            // Another session starts its own sequence, which a 0-length message doesn't take part in.
+            sequenced(iex_tp_frame_on(0, 1, crate::DEEP_MESSAGE_PROTOCOL_ID, &[trade_report("AAPL"), Vec::new()]), 100, 1),
+        ];
+        let capture = crate::pcapng::EnhancedPackets::new(crate::pcapng::tests::capture(&frames)).unwrap();
+        let mut stats = crate::ParseStats::default();
+        for packet in crate::Packets::from_pcapng(capture) {
+            stats.record(&packet);
+        }
+        assert_eq!(stats.packets_total, 7);
+        assert_eq!(stats.packets_skipped_non_iex, 1);
+        assert_eq!(stats.headers_malformed, 2);
+        assert_eq!(stats.messages_parsed, 4);
+        assert_eq!(stats.messages_unknown, 1);
+        assert_eq!(stats.messages_truncated, 1);
+        assert_eq!(stats.messages_zero_length, 1);
+        assert_eq!(stats.sequence_gaps, 1);
+
+        #[cfg(feature = "native-pcap")]
+        stats.record(&Err(crate::ParseError::Capture(pcap::Error::TimeoutExpired)));
+        stats.record(&Err(crate::ParseError::TruncatedBlock { offset: 0 }));
+        assert_eq!(stats.packets_total, 7);
+    }
+
+    #[test]
+    fn test_headers_only() {
+        let mut frame = iex_tp_frame_on(1, 42, crate::DEEP_MESSAGE_PROTOCOL_ID, &[trade_report("AAPL")]);
+        // The IEX-TP header follows 42 bytes of ethernet, IPv4 and UDP headers.
+        frame[42 + 16..42 + 24].copy_from_slice(&4096u64.to_le_bytes());
+        frame[42 + 32..42 + 40].copy_from_slice(&1_562_160_600_000_000_123u64.to_le_bytes());
+        let packet = crate::parse_packet(&frame, 0, libdt::UtcNs::default(),
+                                         &crate::ParseOptions { headers_only: true, ..Default::default() }).unwrap();
+        assert!(packet.messages.is_empty() && packet.errors.is_empty());
+        let description = crate::describe_header(&packet.header);
+        for line in &["Message Protocol ID: 0x8004", "Channel ID: 1", "Session ID: 42", "Stream offset: 4096",
+                      "Send time: 2019-07-03T13:30:00.000000123Z"] {
+            assert!(description.lines().any(|l| l == *line), "{:?} not in {}", line, description);
+        }
+        let packet = crate::parse_packet(&frame, 0, libdt::UtcNs::default(), &crate::ParseOptions::default()).unwrap();
+        assert_eq!(packet.messages.len(), 1);
+
+        // A corrupt first sequence number doesn't overflow the next one.
+        frame[42 + 24..42 + 32].copy_from_slice(&u64::MAX.to_le_bytes());
+        let packet = crate::parse_packet(&frame, 0, libdt::UtcNs::default(),
+                                         &crate::ParseOptions { headers_only: true, ..Default::default() }).unwrap();
+        assert_eq!(packet.next_message_sequence_number, u64::MAX);
+        let packet = crate::parse_packet(&frame, 0, libdt::UtcNs::default(), &crate::ParseOptions::default()).unwrap();
+        assert_eq!(packet.next_message_sequence_number, u64::MAX);
+    }
+
+    #[test]
+    fn test_try_for_each_decoded() {
+        let mut bytes = Vec::new();
+        for message in &[trade_report("AAPL"), vec![b'Z'; 12], trade_report("MSFT"), trade_report("AAPL")] {
+            bytes.extend_from_slice(&(message.len() as u16).to_le_bytes());
+            bytes.extend_from_slice(message);
+        }
+        let decode = || {
+            let (messages, errors, _, _) = crate::parse_body(&bytes, 0, 1, 2, libdt::UtcNs::default(), &crate::ParseOptions::default());
+            messages.into_iter().map(Ok).chain(errors.into_iter().map(Err))
+        };
+
+        let mut trades = std::collections::HashMap::new();
+        let result: Result<(), ()> = crate::try_for_each_decoded(decode(), |message| {
+            *trades.entry(message.symbol().unwrap()).or_insert(0) += 1;
+            Ok(())
+        });
+        assert!(result.is_ok());
+        assert_eq!(trades.get("AAPL"), Some(&2));
+        assert_eq!(trades.get("MSFT"), Some(&1));
+
+        let mut seen = 0;
+        let result = crate::try_for_each_decoded(decode(), |message| {
+            seen += 1;
+            match message.symbol() {
+                Some(ref symbol) if symbol == "MSFT" => Err(message.message_sequence_number),
+                _ => Ok(()),
+            }
+        });
+        assert_eq!((result, seen), (Err(3), 2));
+    }
+
+    #[test]
+    fn test_parse_body_skip_auctions() {
+        let mut auction = vec![0; 80];
+        auction[..2].copy_from_slice(b"AC");
+        auction[10..18].copy_from_slice(b"SPY     ");
+        auction[42] = b'N';
+        let mut bytes = Vec::new();
+        for message in &[trade_report("AAPL"), auction, trade_report("MSFT")] {
+            bytes.extend_from_slice(&(message.len() as u16).to_le_bytes());
+            bytes.extend_from_slice(message);
+        }
+        let summarize = |skip_auctions| {
+            let options = crate::ParseOptions { skip_auctions, mode: crate::ParseMode::Strict, ..Default::default() };
+            let (messages, errors, _, next) = crate::parse_body(&bytes, 0, 1, 3, libdt::UtcNs::default(), &options);
+            assert!(errors.is_empty());
+            assert_eq!(next, 4);
+            messages.iter().map(|m| (m.message_type, m.message_sequence_number)).collect::<Vec<_>>()
+        };
+        assert_eq!(summarize(false), vec![(b'T', 1), (b'A', 2), (b'T', 3)]);
+        assert_eq!(summarize(true), vec![(b'T', 1), (b'T', 3)]);
+    }
+
+    #[test]
+    fn test_parse_body_trades_only() {
+        let mut price_level_update = vec![b'8', 1];
+        price_level_update.extend_from_slice(&[0; 28]);
+        price_level_update[10..18].copy_from_slice(b"AAPL    ");
+        let mut trade_break = trade_report("AAPL");
+        trade_break[0] = b'B';
+        let mut bytes = Vec::new();
+        for message in &[trade_report("AAPL"), price_level_update.clone(), trade_report("MSFT"),
+                         vec![b'Z'; 12], price_level_update, trade_break] {
+            bytes.extend_from_slice(&(message.len() as u16).to_le_bytes());
+            bytes.extend_from_slice(message);
+        }
+        let summarize = |trades_only| {
+            let (messages, errors, _, next) = crate::parse_body(&bytes, 0, 1, 2, libdt::UtcNs::default(),
+                                                                &crate::ParseOptions { trades_only, ..Default::default() });
+            // Skipped messages still count towards the next sequence number.
+            assert_eq!(next, 7);
+            let types: Vec<(u8, u64)> = messages.iter()
+                .map(|m| (m.message_type, m.message_sequence_number))
+                .collect();
+            (types, errors.len())
+        };
+        let (all, all_errors) = summarize(false);
+        let (trades, trade_errors) = summarize(true);
+        assert_eq!(all, vec![(b'T', 1), (b'8', 2), (b'T', 3), (b'8', 5), (b'B', 6)]);
+        let full_run_trades: Vec<(u8, u64)> = all.into_iter().filter(|(t, _)| *t == b'T' || *t == b'B').collect();
+        assert_eq!(trades, full_run_trades);
+        // The unknown message is skipped unparsed rather than reported.
+        assert_eq!((all_errors, trade_errors), (1, 0));
+    }
+
+    #[test]
+    fn test_stream_gaps() {
+        let header = |channel_id, session_id, stream_offset, payload_length| crate::IexTpHeader {
+            version: 1,
+            reserved: 0,
+            message_protocol_id: crate::DEEP_MESSAGE_PROTOCOL_ID,
+            channel_id,
+            session_id,
+            payload_length,
+            message_count: 1,
+            stream_offset,
+            first_message_sequence_number: 1,
+            send_time: libdt::UtcNs::default(),
+        };
+        let mut gaps = crate::StreamGaps::default();
+        assert_eq!(gaps.check(&header(1, 42, 1000, 100)), None);
+        assert_eq!(gaps.check(&header(1, 42, 1100, 50)), None);
+        // Another session starts at its own offset.
+        assert_eq!(gaps.check(&header(1, 43, 0, 10)), None);
+        // 1150..1200 was skipped.
+        assert_eq!(gaps.check(&header(1, 42, 1200, 50)), Some(50));
+        // A retransmission of something already seen isn't a gap, and doesn't move the offset back.
+        assert_eq!(gaps.check(&header(1, 42, 1100, 50)), None);
+        assert_eq!(gaps.check(&header(1, 42, 1250, 50)), None);
+        assert_eq!(gaps.check(&header(1, 43, 20, 10)), Some(10));
+        assert_eq!((gaps.gaps, gaps.missing_bytes), (2, 60));
+        // A corrupt offset whose payload would end past u64::MAX changes nothing.
+        assert_eq!(gaps.check(&header(1, 42, u64::MAX - 10, 50)), None);
+        assert_eq!(gaps.check(&header(1, 42, 1300, 50)), None);
+        assert_eq!((gaps.gaps, gaps.missing_bytes), (2, 60));
+    }
+}
+
+pub struct IexTpHeader {
+    pub version: u8,
+    pub reserved: u8,
+    pub message_protocol_id: u16,
+    pub channel_id: u32,
+    pub session_id: u32,
+    pub payload_length: u16,
+    pub message_count: u16,
+    pub stream_offset: u64,
+    pub first_message_sequence_number: u64,
+    pub send_time: libdt::UtcNs,
+}
+
+impl IexTpHeader {
+    /// Bytes of the header on the wire, ahead of its messages.
+    pub const LENGTH: usize = 40;
+
+    /// When IEX sent the packet, to compare with its capture timestamp.
+    pub fn send_datetime(&self) -> chrono::DateTime<chrono::Utc> {
+        self.send_time.to_datetime()
+    }
+}
+
+pub type MessageSymbol = [char; 8];
+
+/// Letter case `SymbolNormalizer` puts symbols in.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum SymbolCase {
+    #[default]
+    Keep,
+    Upper,
+    Lower,
+}
+
+/// Turns a `MessageSymbol` into the name it goes by in datasets and output keys. By default only
+/// the space padding is trimmed.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SymbolNormalizer {
+    padded: bool,
+    case: SymbolCase,
+    class_separator: char,
+}
+
+impl Default for SymbolNormalizer {
+    fn default() -> SymbolNormalizer {
+        SymbolNormalizer {
+            padded: false,
+            case: SymbolCase::Keep,
+            class_separator: '.',
+        }
+    }
+}
+
+impl SymbolNormalizer {
+    /// Keeps symbols padded with spaces to 8 characters, as they are in DEEP messages.
+    pub fn with_padding(mut self, padded: bool) -> SymbolNormalizer {
+        self.padded = padded;
+        self
+    }
+
+    pub fn with_case(mut self, case: SymbolCase) -> SymbolNormalizer {
+        self.case = case;
+        self
+    }
+
+    /// Separates the share class, e.g. of preferred shares like `BRK.A`, with `separator` instead
+    /// of a period.
+    pub fn with_class_separator(mut self, separator: char) -> SymbolNormalizer {
+        self.class_separator = separator;
+        self
+    }
+
+    pub fn normalize(&self, symbol: &MessageSymbol) -> String {
+        let symbol: String = symbol.iter()
+            .map(|&c| if c == '.' { self.class_separator } else { c })
+            .collect();
+        let symbol = match self.case {
+            SymbolCase::Keep => symbol,
+            SymbolCase::Upper => symbol.to_uppercase(),
+            SymbolCase::Lower => symbol.to_lowercase(),
+        };
+        let symbol = symbol.trim_end();
+        if self.padded {
+            format!("{:<8}", symbol)
+        } else {
+            symbol.to_string()
+        }
+    }
+}
+
+/// Why a trade report is left out by a `ZeroTradeFilter`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ZeroTrade {
+    Size,
+    Price,
+}
+
+/// Which trade reports of 0 size or 0 price to leave out of ticks, for analytics like VWAP and bars
+/// that they'd skew. By default all are kept. Price level updates of size 0 remove a price level
+/// and are never left out.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ZeroTradeFilter {
+    drop_zero_size: bool,
+    drop_zero_price: bool,
+}
+
+impl ZeroTradeFilter {
+    pub fn with_drop_zero_size(mut self, drop_zero_size: bool) -> ZeroTradeFilter {
+        self.drop_zero_size = drop_zero_size;
+        self
+    }
+
+    pub fn with_drop_zero_price(mut self, drop_zero_price: bool) -> ZeroTradeFilter {
+        self.drop_zero_price = drop_zero_price;
+        self
+    }
+
+    /// Why `message` is left out, if it is. A trade of both 0 size and 0 price counts as 0 size
+    /// when both are dropped.
+    pub fn drops(&self, message: &IexDeepMessage) -> Option<ZeroTrade> {
+        match &message.body {
+            IexDeepMessageImpl::TradeReport(m) if self.drop_zero_size && m.size == 0 => Some(ZeroTrade::Size),
+            IexDeepMessageImpl::TradeReport(m) if self.drop_zero_price && m.price == 0 => Some(ZeroTrade::Price),
+            _ => None,
+        }
+    }
+}
+
+/// A band of plausible trade prices, in dollars, to leave out trade reports priced outside it,
+/// which usually point at decoding bugs or bad ticks. Both bounds are inclusive, and there are none
+/// by default.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct PriceRange {
+    min: Option<f64>,
+    max: Option<f64>,
+}
+
+impl PriceRange {
+    pub fn with_min(mut self, min: f64) -> PriceRange {
+        self.min = Some(min);
+        self
+    }
+
+    pub fn with_max(mut self, max: f64) -> PriceRange {
+        self.max = Some(max);
+        self
+    }
+
+    /// Whether `message` is a trade report whose decoded price is outside the range.
+    pub fn excludes(&self, message: &IexDeepMessage) -> bool {
+        match &message.body {
+            IexDeepMessageImpl::TradeReport(m) => {
+                let price = m.price as f64 / get_price_multiplier_for_timestamp(message.timestamp) as f64;
+                self.min.is_some_and(|min| price < min) || self.max.is_some_and(|max| price > max)
+            },
+            _ => false,
+        }
+    }
+}
+
+fn get_price_multiplier_for_timestamp(_timestamp: u64) -> u64 {
+    10000
+}
+
+pub struct IexDeepMessage {
+    pub message_type: u8,
+    pub message_subtype: u8,
+    pub timestamp: u64,
+    pub body: IexDeepMessageImpl,
+    pub packet_number: u64,
+    /// The IEX-TP channel of the packet carrying this message.
+    pub channel_id: u32,
+    pub message_sequence_number: u64,
+    /// When the packet carrying this message was captured, as opposed to when IEX sent it.
+    pub capture_timestamp_ns: libdt::UtcNs,
+}
+
+/// Orders messages of several channels into one deterministic sequence: by send time, then by
+/// channel id, then by message sequence number, which is unique within a channel and session.
+/// Fields compare in declaration order.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct GlobalSequence {
+    pub timestamp: u64,
+    pub channel_id: u32,
+    pub message_sequence_number: u64,
+}
+
+impl IexDeepMessage {
+    pub fn global_sequence(&self) -> GlobalSequence {
+        GlobalSequence {
+            timestamp: self.timestamp,
+            channel_id: self.channel_id,
+            message_sequence_number: self.message_sequence_number,
+        }
+    }
+
+    /// Whether this is the system event marking the end of the session's messages.
+    pub fn is_end_of_messages(&self) -> bool {
+        matches!(&self.body, IexDeepMessageImpl::SystemEvent(m) if m.system_event == SystemEvent::EndOfMessages)
+    }
+
+    /// Trade reports and price level updates as ticks. Callers leaving out trades of 0 size or
+    /// price check them with `ZeroTradeFilter::drops` first.
+    pub fn to_serialized_tick(&self) -> Option<libh5::Tick> {
+        match &self.body {
+            IexDeepMessageImpl::TradeReport(m) => {
+                Some(libh5::Tick {
+                    message_type: self.message_type,
+                    message_subtype: self.message_subtype,
+                    timestamp: self.timestamp,
+                    size: m.size,
+                    price: m.price,
+                    price_multiplier: get_price_multiplier_for_timestamp(self.timestamp),
+                    packet_number: self.packet_number,
+                    message_sequence_number: self.message_sequence_number,
+                    capture_timestamp: self.capture_timestamp_ns.as_nanos(),
+                })
+            },
+            IexDeepMessageImpl::PriceLevelUpdate(m) => {
+                Some(libh5::Tick {
+                    message_type: self.message_type,
+                    message_subtype: self.message_subtype,
+                    timestamp: self.timestamp,
+                    size: m.size,
+                    price: m.price,
+                    price_multiplier: get_price_multiplier_for_timestamp(self.timestamp),
+                    packet_number: self.packet_number,
+                    message_sequence_number: self.message_sequence_number,
+                    capture_timestamp: self.capture_timestamp_ns.as_nanos(),
+                })
+            },
+            _ => None,
+        }
+    }
+
+    /// An official opening or closing price as a tick without a size, with its `PriceType` as the
+    /// subtype.
+    pub fn to_official_price(&self) -> Option<libh5::Tick> {
+        match &self.body {
+            IexDeepMessageImpl::OfficialPrice(m) => Some(libh5::Tick {
+                message_type: self.message_type,
+                message_subtype: m.price_type.clone() as u8,
+                timestamp: self.timestamp,
+                size: 0,
+                price: m.official_price,
+                price_multiplier: get_price_multiplier_for_timestamp(self.timestamp),
+                packet_number: self.packet_number,
+                message_sequence_number: self.message_sequence_number,
+                capture_timestamp: self.capture_timestamp_ns.as_nanos(),
+            }),
+            _ => None,
+        }
+    }
+
+    /// An auction information message with its scheduled time in nanoseconds, not yet checked
+    /// against the trade date.
+    pub fn to_auction(&self) -> Option<libh5::Auction> {
+        match &self.body {
+            IexDeepMessageImpl::AuctionInformation(m) => Some(libh5::Auction {
+                timestamp: self.timestamp,
+                auction_type: m.auction_type.clone() as u8,
+                paired_shares: m.paired_shares,
+                reference_price: m.reference_price,
+                indicative_clearing_price: m.indicative_clearing_price,
+                imbalance_shares: m.imbalance_shares,
+                imbalance_side: m.imbalance_side.clone() as u8,
+                extension_number: m.extension_number,
+                scheduled_auction_time: m.scheduled_auction_timestamp().as_nanos(),
+                scheduled_off_trade_date: false,
+                auction_book_clearing_price: m.auction_book_clearing_price,
+                collar_reference_price: m.collar_reference_price,
+                lower_auction_collar: m.lower_auction_collar,
+                upper_auction_collar: m.upper_auction_collar,
+                price_multiplier: get_price_multiplier_for_timestamp(self.timestamp),
+                packet_number: self.packet_number,
+                message_sequence_number: self.message_sequence_number,
+            }),
+            _ => None,
+        }
+    }
+
+    /// The symbol of any message but a system event, without its space padding.
+    pub fn symbol(&self) -> Option<String> {
+        self.normalized_symbol(&SymbolNormalizer::default())
+    }
+
+    pub fn normalized_symbol(&self, normalizer: &SymbolNormalizer) -> Option<String> {
+        self.message_symbol().map(|symbol| normalizer.normalize(symbol))
+    }
+
+    /// The symbol as sent, padded with spaces, of any message but a system event.
+    pub fn message_symbol(&self) -> Option<&MessageSymbol> {
+        let symbol = match &self.body {
+            IexDeepMessageImpl::SystemEvent(_) => return None,
+            IexDeepMessageImpl::SecurityDirectory(m) => &m.symbol,
+            IexDeepMessageImpl::TradingStatus(m) => &m.symbol,
+            IexDeepMessageImpl::OperationalHaltStatus(m) => &m.symbol,
+            IexDeepMessageImpl::ShortSalePriceTestStatus(m) => &m.symbol,
+            IexDeepMessageImpl::SecurityEvent(m) => &m.symbol,
+            IexDeepMessageImpl::PriceLevelUpdate(m) => &m.symbol,
+            IexDeepMessageImpl::TradeReport(m) => &m.symbol,
+            IexDeepMessageImpl::OfficialPrice(m) => &m.symbol,
+            IexDeepMessageImpl::TradeBreak(m) => &m.symbol,
+            IexDeepMessageImpl::AuctionInformation(m) => &m.symbol,
+        };
+        Some(symbol)
+    }
+}
+
+// TODO(sherry): codegen the impls
+
+#[derive(Clone, PartialEq, Debug)]
+#[repr(u8)]
+pub enum SystemEvent {
+    StartOfMessages             = 'O' as u8,
+    StartOfSystemHours          = 'S' as u8,
+    StartOfRegularMarketHours   = 'R' as u8,
+    EndOfRegularMarketHours     = 'M' as u8,
+    EndOfSystemHours            = 'E' as u8,
+    EndOfMessages               = 'C' as u8,
+}
+
+impl SystemEvent {
+    pub fn from_u8(byte: u8) -> Option<SystemEvent> {
+        match byte as char {
+            'O' => Some(SystemEvent::StartOfMessages),
+            'S' => Some(SystemEvent::StartOfSystemHours),
+            'R' => Some(SystemEvent::StartOfRegularMarketHours),
+            'M' => Some(SystemEvent::EndOfRegularMarketHours),
+            'E' => Some(SystemEvent::EndOfSystemHours),
+            'C' => Some(SystemEvent::EndOfMessages),
+            _ => None,
+        }
+    }
+}
+
+bitflags! {
+    pub struct SecurityDirectoryFlags: u8 {
+        const TEST_SECURITY = 0x80;
+        const WHEN_ISSUED = 0x40;
+        /// Exchange traded product
+        const ETP = 0x20;
+    }
+}
+
+impl SecurityDirectoryFlags {
+    pub fn is_test_security(&self) -> bool {
+        self.contains(SecurityDirectoryFlags::TEST_SECURITY)
+    }
+
+    pub fn is_when_issued(&self) -> bool {
+        self.contains(SecurityDirectoryFlags::WHEN_ISSUED)
+    }
+
+    pub fn is_etp(&self) -> bool {
+        self.contains(SecurityDirectoryFlags::ETP)
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+#[repr(u8)]
+pub enum LimitUpLimitDownTier {
+    NotApplicable = 0x0,
+    Tier1NmsStock = 0x1,
+    Tier2NmsStock = 0x2,
+}
+
+impl LimitUpLimitDownTier {
+    pub fn from_u8(byte: u8) -> Option<LimitUpLimitDownTier> {
+        match byte {
+            0x0 => Some(LimitUpLimitDownTier::NotApplicable),
+            0x1 => Some(LimitUpLimitDownTier::Tier1NmsStock),
+            0x2 => Some(LimitUpLimitDownTier::Tier2NmsStock),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+#[repr(u8)]
+pub enum TradingStatus {
+    Halted                                  = 'H' as u8,
+    HaltReleasedIntoOrderAcceptancePeriod   = 'O' as u8,
+    PausedAndOrderAcceptancePeriod          = 'P' as u8,
+    Trading                                 = 'T' as u8,
+}
+
+impl TradingStatus {
+    pub fn from_u8(byte: u8) -> Option<TradingStatus> {
+        match byte as char {
+            'H' => Some(TradingStatus::Halted),
+            'O' => Some(TradingStatus::HaltReleasedIntoOrderAcceptancePeriod),
+            'P' => Some(TradingStatus::PausedAndOrderAcceptancePeriod),
+            'T' => Some(TradingStatus::Trading),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+#[repr(u8)]
+pub enum OperationalHaltStatus {
+    Halted      = 'O' as u8,
+    NotHalted   = 'N' as u8,
+}
+
+impl OperationalHaltStatus {
+    pub fn from_u8(byte: u8) -> Option<OperationalHaltStatus> {
+        match byte as char {
+            'O' => Some(OperationalHaltStatus::Halted),
+            'N' => Some(OperationalHaltStatus::NotHalted),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+#[repr(u8)]
+pub enum ShortSalePriceTestStatus {
+    NotInEffect = 0x0,
+    InEffect    = 0x1,
+}
+
+impl ShortSalePriceTestStatus {
+    pub fn from_u8(byte: u8) -> Option<ShortSalePriceTestStatus> {
+        match byte {
+            0x0 => Some(ShortSalePriceTestStatus::NotInEffect),
+            0x1 => Some(ShortSalePriceTestStatus::InEffect),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+#[repr(u8)]
+pub enum Detail {
+    NoPriceTestInPlace  = ' ' as u8,
+    Activated           = 'A' as u8,
+    Continued           = 'C' as u8,
+    Deactivated         = 'D' as u8,
+    NotAvailable        = 'N' as u8,
+}
+
+impl Detail {
+    pub fn from_u8(byte: u8) -> Option<Detail> {
+        match byte as char {
+            ' ' => Some(Detail::NoPriceTestInPlace),
+            'A' => Some(Detail::Activated),
+            'C' => Some(Detail::Continued),
+            'D' => Some(Detail::Deactivated),
+            'N' => Some(Detail::NotAvailable),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+#[repr(u8)]
+pub enum SecurityEvent {
+    OpeningProcessComplete = 'O' as u8,
+    ClosingProcessComplete = 'C' as u8,
+}
+
+impl SecurityEvent {
+    pub fn from_u8(byte: u8) -> Option<SecurityEvent> {
+        match byte as char {
+            'O' => Some(SecurityEvent::OpeningProcessComplete),
+            'C' => Some(SecurityEvent::ClosingProcessComplete),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+#[repr(u8)]
+pub enum PriceLevelUpdateEventFlags {
+    OrderBookIsProcessingAnEvent = 0x0,
+    EventProcessingComplete = 0x1,
+}
+
+impl PriceLevelUpdateEventFlags {
+    pub fn from_u8(byte: u8) -> Option<PriceLevelUpdateEventFlags> {
+        match byte {
+            0x0 => Some(PriceLevelUpdateEventFlags::OrderBookIsProcessingAnEvent),
+            0x1 => Some(PriceLevelUpdateEventFlags::EventProcessingComplete),
+            _ => None,
+        }
+    }
+}
+
+// TODO(sherry): these are not mutually exclusive
+// #[derive(hdf5::H5Type, Clone, PartialEq, Debug)]
+// #[repr(u8)]
+// enum SaleConditionFlags {
+//     IntermarketSweep        = 'F' as u8,
+//     ExtendedHours           = 'T' as u8,
+//     OddLot                  = 'I' as u8,
+//     TradeThroughExempt      = '8' as u8,
+//     SinglePriceCrossTrade   = 'X' as u8,
+// }
+// 
+// impl SaleConditionFlags {
+//     fn from_u8(byte: u8) -> Option<SaleConditionFlags> {
+//         match byte as char {
+//             'F' => Some(SaleConditionFlags::IntermarketSweep),
+//             'T' => Some(SaleConditionFlags::ExtendedHours),
+//             'I' => Some(SaleConditionFlags::OddLot),
+//             '8' => Some(SaleConditionFlags::TradeThroughExempt),
+//             'X' => Some(SaleConditionFlags::SinglePriceCrossTrade),
+//             _ => None,
+//         }
+//     }
+// }
+
+#[derive(Clone, PartialEq, Debug)]
+#[repr(u8)]
+pub enum PriceType {
+    OfficialOpeningPrice = 'Q' as u8,
+    OfficialClosingPrice = 'M' as u8,
+}
+
+impl PriceType {
+    pub fn from_u8(byte: u8) -> Option<PriceType> {
+        match byte as char {
+            'Q' => Some(PriceType::OfficialOpeningPrice),
+            'M' => Some(PriceType::OfficialClosingPrice),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+#[repr(u8)]
+pub enum AuctionType {
+    Opening     = 'O' as u8,
+    Closing     = 'C' as u8,
+    Ipo         = 'I' as u8,
+    Halt        = 'H' as u8,
+    Volatility  = 'V' as u8,
+}
+
+impl AuctionType {
+    pub fn from_u8(byte: u8) -> Option<AuctionType> {
+        match byte as char {
+            'O' => Some(AuctionType::Opening),
+            'C' => Some(AuctionType::Closing),
+            'I' => Some(AuctionType::Ipo),
+            'H' => Some(AuctionType::Halt),
+            'V' => Some(AuctionType::Volatility),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+#[repr(u8)]
+pub enum ImbalanceSide {
+    BuySideImbalance    = 'B' as u8,
+    SellSideImbalance   = 'S' as u8,
+    NoImbalance         = 'N' as u8,
+}
+
+impl ImbalanceSide {
+    pub fn from_u8(byte: u8) -> Option<ImbalanceSide> {
+        match byte as char {
+            'B' => Some(ImbalanceSide::BuySideImbalance),
+            'S' => Some(ImbalanceSide::SellSideImbalance),
+            'N' => Some(ImbalanceSide::NoImbalance),
+            _ => None,
+        }
+    }
+}
+
+pub struct SystemEventMessage {
+    pub system_event: SystemEvent,
+}
+
+pub struct SecurityDirectoryMessage {
+    pub symbol: MessageSymbol,
+    pub round_lot_size: u32,
+    pub adjusted_poc_price: u64,
+    pub luld_tier: LimitUpLimitDownTier,
+    pub flags: SecurityDirectoryFlags,
+}
+
+pub struct TradingStatusMessage {
+    pub symbol: MessageSymbol,
+    pub reason: [char; 4],
+    pub trading_status: TradingStatus,
+}
+
+impl TradingStatusMessage {
+    /// `reason` decoded, or `None` if it's blank, as it is when trading resumes.
+    pub fn halt_reason(&self) -> Option<HaltReason> {
+        HaltReason::from_chars(&self.reason)
+    }
+}
+
+/// Why a symbol's trading is halted or paused, per the reason codes of the DEEP spec.
+#[derive(Clone, PartialEq, Debug)]
+pub enum HaltReason {
+    /// T1
+    HaltNewsPending,
+    /// T2
+    HaltNewsDissemination,
+    /// IPO1
+    IpoNotYetTrading,
+    /// IPO2
+    IpoOrderAcceptancePeriod,
+    /// IPO3
+    IpoPreLaunchPeriod,
+    /// IPOD
+    IpoDeferred,
+    /// MCB1
+    MarketWideCircuitBreakerLevel1,
+    /// MCB2
+    MarketWideCircuitBreakerLevel2,
+    /// MCB3
+    MarketWideCircuitBreakerLevel3,
+    /// LUDP
+    LimitUpLimitDownPause,
+    /// NA
+    NotAvailable,
+    /// Any other code, with trailing spaces trimmed.
+    Unknown(String),
+}
+
+impl HaltReason {
+    /// Reason codes are left-aligned and padded with spaces; blank ones give `None`.
+    pub fn from_chars(reason: &[char; 4]) -> Option<HaltReason> {
+        let code: String = reason.iter().collect();
+        let reason = match code.trim_end() {
+            "" => return None,
+            "T1" => HaltReason::HaltNewsPending,
+            "T2" => HaltReason::HaltNewsDissemination,
+            "IPO1" => HaltReason::IpoNotYetTrading,
+            "IPO2" => HaltReason::IpoOrderAcceptancePeriod,
+            "IPO3" => HaltReason::IpoPreLaunchPeriod,
+            "IPOD" => HaltReason::IpoDeferred,
+            "MCB1" => HaltReason::MarketWideCircuitBreakerLevel1,
+            "MCB2" => HaltReason::MarketWideCircuitBreakerLevel2,
+            "MCB3" => HaltReason::MarketWideCircuitBreakerLevel3,
+            "LUDP" => HaltReason::LimitUpLimitDownPause,
+            "NA" => HaltReason::NotAvailable,
+            other => HaltReason::Unknown(other.to_string()),
+        };
+        Some(reason)
+    }
+}
+
+pub struct OperationalHaltStatusMessage {
+    pub symbol: MessageSymbol,
+    pub operational_halt_status: OperationalHaltStatus,
+}
+
+pub struct ShortSalePriceTestStatusMessage {
+    pub symbol: MessageSymbol,
+    pub detail: Detail,
+    pub short_sale_price_test_status: ShortSalePriceTestStatus,
+}
+
+pub struct SecurityEventMessage {
+    pub symbol: MessageSymbol,
+    pub security_event: SecurityEvent,
+}
+
+pub struct PriceLevelUpdateMessage {
+    pub symbol: MessageSymbol,
+    pub size: u32,
+    pub price: u64,
+    pub event_flags: PriceLevelUpdateEventFlags,
+}
+
+pub struct TradeReportMessage {
+    pub symbol: MessageSymbol,
+    pub size: u32,
+    pub price: u64,
+    pub trade_id: u64,
+    pub sale_condition_flags: u8,
+}
+
+pub struct OfficialPriceMessage {
+    pub symbol: MessageSymbol,
+    pub official_price: u64,
+    pub price_type: PriceType,
+}
+
+pub struct TradeBreakMessage {
+    pub symbol: MessageSymbol,
+    pub size: u32,
+    pub price: u64,
+    pub trade_id: u64,
+    pub sale_condition_flags: u8,
+}
+
+pub struct AuctionInformationMessage {
+    pub symbol: MessageSymbol,
+    pub paired_shares: u32,
+    pub reference_price: u64,
+    pub indicative_clearing_price: u64,
+    pub imbalance_shares: u32,
+    pub imbalance_side: ImbalanceSide,
+    pub extension_number: u8,
+    pub scheduled_auction_time: u32,
+    pub auction_book_clearing_price: u64,
+    pub collar_reference_price: u64,
+    pub lower_auction_collar: u64,
+    pub upper_auction_collar: u64,
+    pub auction_type: AuctionType,
+    pub layout: AuctionLayout,
+}
+
+/// Which layout an auction information message was sent in.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AuctionLayout {
+    /// All 80 bytes of the current DEEP spec.
+    Full,
+    /// Ends after `scheduled_auction_time`, as from an older protocol version. Only read in lenient
+    /// mode; the fields after it are 0.
+    Prefix,
+}
+
+impl AuctionInformationMessage {
+    /// `scheduled_auction_time`, which IEX sends in whole seconds since the epoch.
+    pub fn scheduled_auction_timestamp(&self) -> libdt::UtcNs {
+        libdt::UtcNs::from_secs(self.scheduled_auction_time as u64)
+    }
+}
+
+pub enum IexDeepMessageImpl {
+    SystemEvent(SystemEventMessage),
+    SecurityDirectory(SecurityDirectoryMessage),
+    TradingStatus(TradingStatusMessage),
+    OperationalHaltStatus(OperationalHaltStatusMessage),
+    ShortSalePriceTestStatus(ShortSalePriceTestStatusMessage),
+    SecurityEvent(SecurityEventMessage),
+
+    /// Trading message formats
+    PriceLevelUpdate(PriceLevelUpdateMessage),
+    TradeReport(TradeReportMessage),
+    OfficialPrice(OfficialPriceMessage),
+    TradeBreak(TradeBreakMessage),
+
+    /// Auction message formats
+    AuctionInformation(AuctionInformationMessage),
+}
+
+struct ParseMessageResponse {
+    parsed_message: IexDeepMessage,
+    consumed_bytes: usize,
+}
+
+/// Every message starts with its type, subtype and 8-byte timestamp.
+const MESSAGE_HEADER_LENGTH: usize = 10;
+/// The fewest bytes of a payload a message can take: its 2-byte length and its header.
+const MIN_MESSAGE_BLOCK_LENGTH: usize = 2 + MESSAGE_HEADER_LENGTH;
+
+/// On-wire length of each message type per the DEEP spec, which is all `parse_message` reads of
+/// it. The message structs' Rust layout has nothing to do with it.
+const MESSAGE_LENGTHS: [(u8, usize); 12] = [
+    (b'S', MESSAGE_HEADER_LENGTH),
+    (b'D', 31),
+    (b'H', 22),
+    (b'O', 18),
+    (b'P', 19),
+    (b'E', 18),
+    (b'8', 30),
+    (b'5', 30),
+    (b'T', 38),
+    (b'X', 26),
+    (b'B', 38),
+    (b'A', 80),
+];
+
+/// Bytes of an auction information message up to and including `scheduled_auction_time`, which
+/// shorter layouts of it share. See `AuctionLayout::Prefix`.
+const AUCTION_PREFIX_LENGTH: usize = 48;
+
+fn message_length(message_type: u8) -> Option<usize> {
+    MESSAGE_LENGTHS.iter().find(|(t, _)| *t == message_type).map(|&(_, length)| length)
+}
+
+fn parse_symbol(bytes: &[u8]) -> MessageSymbol {
+    [
+        bytes[10] as char, bytes[11] as char,
+        bytes[12] as char, bytes[13] as char,
+        bytes[14] as char, bytes[15] as char,
+        bytes[16] as char, bytes[17] as char,
+    ]
+}
+
+/// How anomalies in a capture are treated.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum ParseMode {
+    /// A message that fails to decode is left out and its error kept in `DeepPacket::errors`; the
+    /// rest of its packet is still decoded. So is a 0-length message, which is skipped without
+    /// taking a sequence number, so any messages after it are recovered. Sequence gaps are left
+    /// for the caller to notice.
+    #[default]
+    Lenient,
+    /// The first anomaly of a packet makes the whole packet an error, including sequence gaps,
+    /// payload lengths that disagree with the header, unknown security directory flags and 0-length
+    /// messages, so none of a packet's messages are recovered once it has one.
+    Strict,
+}
+
+/// Decodes a single DEEP message, without the length that precedes it in a packet. It's decoded
+/// leniently, with packet and sequence number 0 and no capture timestamp.
+///
+/// A trade report of 100 AAPL shares at $199:
+///
+/// ```
+/// use iex_pcap_parser::{decode_message, IexDeepMessageImpl};
+///
+/// let mut bytes = vec![b'T', 0];
+/// bytes.extend_from_slice(&1_562_160_600_000_000_000u64.to_le_bytes());
+/// bytes.extend_from_slice(b"AAPL    ");
+/// bytes.extend_from_slice(&100u32.to_le_bytes());
+/// bytes.extend_from_slice(&1_990_000u64.to_le_bytes());
+/// bytes.extend_from_slice(&42u64.to_le_bytes());
+///
+/// let message = decode_message(&bytes).unwrap();
+/// assert_eq!(message.symbol().as_deref(), Some("AAPL"));
+/// match message.body {
+///     IexDeepMessageImpl::TradeReport(trade) => assert_eq!((trade.size, trade.price), (100, 1_990_000)),
+///     _ => unreachable!(),
+/// }
+/// ```
+///
+/// The start of messages system event, which is only a header:
+///
+/// ```
+/// use iex_pcap_parser::{decode_message, IexDeepMessageImpl, SystemEvent};
+///
+/// let mut bytes = vec![b'S', b'O'];
+/// bytes.extend_from_slice(&1_562_140_800_000_000_000u64.to_le_bytes());
+///
+/// match decode_message(&bytes).unwrap().body {
+///     IexDeepMessageImpl::SystemEvent(event) => assert_eq!(event.system_event, SystemEvent::StartOfMessages),
+///     _ => unreachable!(),
+/// }
+/// ```
+pub fn decode_message(bytes: &[u8]) -> Result<IexDeepMessage, ParseError> {
+    parse_message(bytes, 0, 0, libdt::UtcNs::default(), ParseMode::Lenient).map(|response| response.parsed_message)
+}
+
+fn parse_message(bytes: &[u8], packet_num: u64, message_seq_num: u64, capture_timestamp_ns: libdt::UtcNs,
+                 mode: ParseMode) -> Result<ParseMessageResponse, ParseError> {
+    if bytes.len() < MESSAGE_HEADER_LENGTH {
+        return Err(ParseError::TruncatedMessage { have: bytes.len(), need: MESSAGE_HEADER_LENGTH });
+    }
+    let message_type = bytes[0];
+    let need = message_length(message_type).ok_or(ParseError::UnknownMessageType(message_type))?;
+    // Lenient mode reads the common prefix of an auction information message of an older layout.
+    let auction_prefix = mode == ParseMode::Lenient && message_type == b'A'
+        && (AUCTION_PREFIX_LENGTH..need).contains(&bytes.len());
+    if bytes.len() < need && !auction_prefix {
+        return Err(ParseError::TruncatedMessage { have: bytes.len(), need });
+    }
+    // Lenient mode reads the fields it knows of a longer message, as a newer version of the spec
+    // could append some.
+    if mode == ParseMode::Strict && bytes.len() > need {
+        return Err(ParseError::MessageLengthMismatch { message_type, framed: bytes.len(), expected: need });
+    }
+    let message_subtype = bytes[1];
+    let timestamp = bytes::read_u64_le(bytes, 2);
+    let invalid_subtype = |field| ParseError::InvalidEnumByte { field, byte: message_subtype };
+    let body = match message_type as char {
+        'S' => {
+            let message = SystemEventMessage {
+                system_event: SystemEvent::from_u8(message_subtype)
+                    .ok_or_else(|| invalid_subtype("system_event"))?,
+            };
+            IexDeepMessageImpl::SystemEvent(message)
+        },
+        'D' => {
+            let message = SecurityDirectoryMessage {
+                flags: match mode {
+                    ParseMode::Lenient => SecurityDirectoryFlags::from_bits_truncate(message_subtype),
+                    ParseMode::Strict => SecurityDirectoryFlags::from_bits(message_subtype)
+                        .ok_or_else(|| invalid_subtype("flags"))?,
+                },
+                symbol: parse_symbol(bytes),
+                round_lot_size: bytes::read_u32_le(bytes, 18),
+                adjusted_poc_price: bytes::read_u64_le(bytes, 22),
+                luld_tier: LimitUpLimitDownTier::from_u8(bytes[30])
+                    .ok_or(ParseError::InvalidEnumByte { field: "luld_tier", byte: bytes[30] })?,
+            };
+            IexDeepMessageImpl::SecurityDirectory(message)
+        },
+        'H' => {
+            let message = TradingStatusMessage {
+                trading_status: TradingStatus::from_u8(message_subtype)
+                    .ok_or_else(|| invalid_subtype("trading_status"))?,
+                symbol: parse_symbol(bytes),
+                reason: [
+                    bytes[18] as char, bytes[19] as char,
+                    bytes[20] as char, bytes[21] as char,
+                ],
+            };
+            IexDeepMessageImpl::TradingStatus(message)
+        },
+        'O' => {
+            let message = OperationalHaltStatusMessage {
+                operational_halt_status: OperationalHaltStatus::from_u8(message_subtype)
+                    .ok_or_else(|| invalid_subtype("operational_halt_status"))?,
+                symbol: parse_symbol(bytes),
+            };
+            IexDeepMessageImpl::OperationalHaltStatus(message)
+        },
+        'P' => {
+            let message = ShortSalePriceTestStatusMessage {
+                short_sale_price_test_status: ShortSalePriceTestStatus::from_u8(message_subtype)
+                    .ok_or_else(|| invalid_subtype("short_sale_price_test_status"))?,
+                symbol: parse_symbol(bytes),
+                detail: Detail::from_u8(bytes[18])
+                    .ok_or(ParseError::InvalidEnumByte { field: "detail", byte: bytes[18] })?,
+            };
+            IexDeepMessageImpl::ShortSalePriceTestStatus(message)
+        },
+        'E' => {
+            let message = SecurityEventMessage {
+                security_event: SecurityEvent::from_u8(message_subtype)
+                    .ok_or_else(|| invalid_subtype("security_event"))?,
+                symbol: parse_symbol(bytes),
+            };
+            IexDeepMessageImpl::SecurityEvent(message)
+        },
+        '8' | '5' => {
+            let message = PriceLevelUpdateMessage {
+                event_flags: PriceLevelUpdateEventFlags::from_u8(message_subtype)
+                    .ok_or_else(|| invalid_subtype("event_flags"))?,
+                symbol: parse_symbol(bytes),
+                size: bytes::read_u32_le(bytes, 18),
+                price: bytes::read_u64_le(bytes, 22),
+            };
+            IexDeepMessageImpl::PriceLevelUpdate(message)
+        },
+        'T' => {
+            // SaleConditionFlags::from_u8(message_subtype).map(|sale_condition_flags| {
+            let message = TradeReportMessage {
+                symbol: parse_symbol(bytes),
+                size: bytes::read_u32_le(bytes, 18),
+                price: bytes::read_u64_le(bytes, 22),
+                trade_id: bytes::read_u64_le(bytes, 30),
+                sale_condition_flags: message_subtype,
+            };
+            IexDeepMessageImpl::TradeReport(message)
+        },
+        'X' => {
+            let message = OfficialPriceMessage {
+                price_type: PriceType::from_u8(message_subtype)
+                    .ok_or_else(|| invalid_subtype("price_type"))?,
+                symbol: parse_symbol(bytes),
+                official_price: bytes::read_u64_le(bytes, 18),
+            };
+            IexDeepMessageImpl::OfficialPrice(message)
+        },
+        'B' => {
+            // SaleConditionFlags::from_u8(message_subtype).map(|sale_condition_flags| {
+            let message = TradeBreakMessage {
+                symbol: parse_symbol(bytes),
+                size: bytes::read_u32_le(bytes, 18),
+                price: bytes::read_u64_le(bytes, 22),
+                trade_id: bytes::read_u64_le(bytes, 30),
+                sale_condition_flags: message_subtype,
+            };
+            IexDeepMessageImpl::TradeBreak(message)
+        },
+        'A' => {
+            let collar_price = |offset| if auction_prefix { 0 } else { bytes::read_u64_le(bytes, offset) };
+            let message = AuctionInformationMessage {
+                auction_type: AuctionType::from_u8(message_subtype)
+                    .ok_or_else(|| invalid_subtype("auction_type"))?,
+                symbol: parse_symbol(bytes),
+                paired_shares: bytes::read_u32_le(bytes, 18),
+                reference_price: bytes::read_u64_le(bytes, 22),
+                indicative_clearing_price: bytes::read_u64_le(bytes, 30),
+                imbalance_shares: bytes::read_u32_le(bytes, 38),
+                imbalance_side: ImbalanceSide::from_u8(bytes[42])
+                    .ok_or(ParseError::InvalidEnumByte { field: "imbalance_side", byte: bytes[42] })?,
+                extension_number: bytes[43],
+                scheduled_auction_time: bytes::read_u32_le(bytes, 44),
+                auction_book_clearing_price: collar_price(48),
+                collar_reference_price: collar_price(56),
+                lower_auction_collar: collar_price(64),
+                upper_auction_collar: collar_price(72),
+                layout: if auction_prefix { AuctionLayout::Prefix } else { AuctionLayout::Full },
+            };
+            IexDeepMessageImpl::AuctionInformation(message)
+        },
+        _ => return Err(ParseError::UnknownMessageType(message_type)),
+    };
+    Ok(ParseMessageResponse {
+        parsed_message: IexDeepMessage {
+            message_type,
+            message_subtype,
+            timestamp,
+            body,
+            packet_number: packet_num,
+            // Filled in from the header by parse_packet.
+            channel_id: 0,
+            message_sequence_number: message_seq_num,
+            capture_timestamp_ns,
+        },
+        consumed_bytes: need,
+    })
+}
+
+/// Trade reports and the trade breaks that cancel them.
+fn is_trade_message_type(message_type: u8) -> bool {
+    message_type == b'T' || message_type == b'B'
+}
+
+/// Messages that failed to parse are skipped; their errors are returned alongside the rest, with
+/// the bytes of those messages. In strict mode decoding stops at the first error, and a dangling
+/// byte or 0-length message is one; leniently a 0-length message is an error, but decoding goes on
+/// after it. With `trades_only`, messages other than trades are skipped by their type byte without
+/// being parsed, as are auction information messages with `skip_auctions`. Also returns the
+/// sequence number after the last message decoded or skipped, where the next packet of the channel
+/// and session should start. `message_count` is the header's, which only sizes the result up
+/// front; the messages actually in `bytes` are decoded whether there are more or fewer.
+fn parse_body(bytes: &[u8], packet_num: u64, message_seq_num_start: u64, message_count: u16,
+              capture_timestamp_ns: libdt::UtcNs, options: &ParseOptions)
+              -> (Vec<IexDeepMessage>, Vec<ParseError>, Vec<RawMessage>, u64) {
+    let ParseOptions { trades_only, skip_auctions, mode, .. } = *options;
+    // Most messages are skipped with trades_only, and a count the payload can't hold isn't trusted.
+    let capacity = if trades_only { 0 } else { usize::from(message_count).min(bytes.len() / MIN_MESSAGE_BLOCK_LENGTH) };
+    let mut messages = Vec::with_capacity(capacity);
+    let mut errors = Vec::new();
+    let mut raw_failures = Vec::new();
+    let raw = |message_seq_num, bytes: &[u8]| RawMessage {
+        packet_number: packet_num,
+        message_sequence_number: message_seq_num,
+        bytes: bytes.to_vec(),
+    };
+    let mut offset = 0;
+    let mut message_seq_num = message_seq_num_start;
+    while offset < bytes.len() && (mode == ParseMode::Lenient || errors.is_empty()) {
+        if offset + 2 > bytes.len() {
+            warn!("dangling byte after the last message at offset {} of {}. breaking", offset, bytes.len());
+            if mode == ParseMode::Strict {
+                errors.push(ParseError::TruncatedMessage { have: bytes.len() - offset, need: 2 });
+            }
+            break;
+        }
+        let message_length = bytes::read_u16_le(bytes, offset) as usize;
+        offset += 2;
+        if message_length == 0 {
+            // The loop stops at the first error in strict mode.
+            let action = if mode == ParseMode::Strict { "stopping" } else { "skipping" };
+            warn!("encountered 0-length message at offset {}. {}", offset - 2, action);
+            errors.push(ParseError::ZeroLengthMessage { offset: offset - 2 });
+            continue;
+        }
+        if offset + message_length > bytes.len() {
+            warn!("message {} at offset {} claims {} bytes but only {} are left. breaking",
+                  message_seq_num, offset, message_length, bytes.len() - offset);
+            errors.push(ParseError::TruncatedMessage { have: bytes.len() - offset, need: message_length });
+            raw_failures.push(raw(message_seq_num, &bytes[offset..]));
+            break;
+        }
+        if (trades_only && !is_trade_message_type(bytes[offset])) || (skip_auctions && bytes[offset] == b'A') {
+            offset += message_length;
+            message_seq_num = message_seq_num.saturating_add(1);
+            continue;
+        }
+        match parse_message(&bytes[offset..offset + message_length], packet_num, message_seq_num, capture_timestamp_ns, mode) {
+            Ok(response) => {
+                messages.push(response.parsed_message);
+                trace!("consumed bytes: {}", response.consumed_bytes);
+            },
+            Err(e) => {
+                debug!("Failed to parse message {} in packet {} at offset {}: {:?}",
+                       message_seq_num, packet_num, offset, e);
+                errors.push(e);
+                raw_failures.push(raw(message_seq_num, &bytes[offset..offset + message_length]));
+            },
+        }
+        offset += message_length;
+        message_seq_num = message_seq_num.saturating_add(1);
+    }
+    (messages, errors, raw_failures, message_seq_num)
+}
+
+/// Tallies the messages of a DEEP packet body by their type byte without decoding them, skipping
+/// and stopping where `parse_body` would in lenient mode.
+fn count_body(bytes: &[u8], counts: &mut MessageCounts) {
+    let mut offset = 0;
+    while offset + 2 <= bytes.len() {
+        let message_length = bytes::read_u16_le(bytes, offset) as usize;
+        offset += 2;
+        if message_length == 0 {
+            continue;
+        }
+        if offset + message_length > bytes.len() {
+            counts.truncated += 1;
+            break;
+        }
+        counts.by_type[usize::from(bytes[offset])] += 1;
+        offset += message_length;
+    }
+}
+
+/// Decodes the IEX-TP header at the start of `bytes`, which is the whole UDP payload, for reading
+/// packets off transports other than pcap. Its messages follow after `IexTpHeader::LENGTH` bytes.
+/// `bytes` must hold at least the header's `payload_length` bytes after the header, or the body
+/// would be decoded from whatever is left of a truncated packet; in strict mode it must hold
+/// exactly that many.
+pub fn parse_header(bytes: &[u8], mode: ParseMode) -> Result<IexTpHeader, ParseError> {
+    let iex_header_length = IexTpHeader::LENGTH;
+    if bytes.len() < iex_header_length {
+        return Err(ParseError::TruncatedHeader { have: bytes.len(), need: iex_header_length });
+    }
+    if bytes[0] != IEX_TP_VERSION {
+        return Err(ParseError::UnsupportedVersion(bytes[0]));
+    }
+    let payload_length = bytes::read_u16_le(bytes, 12);
+    let actual = bytes.len() - iex_header_length;
+    if usize::from(payload_length) > actual || (mode == ParseMode::Strict && usize::from(payload_length) != actual) {
+        return Err(ParseError::PayloadLengthMismatch {
+            header: payload_length,
+            actual,
+        });
+    }
+
+    Ok(IexTpHeader {
+        version: bytes[0],
+        reserved: bytes[1],
+        message_protocol_id: bytes::read_u16_le(bytes, 2),
+        channel_id: bytes::read_u32_le(bytes, 4),
+        session_id: bytes::read_u32_le(bytes, 8),
+        payload_length,
+        message_count: bytes::read_u16_le(bytes, 14),
+        stream_offset: bytes::read_u64_le(bytes, 16),
+        first_message_sequence_number: bytes::read_u64_le(bytes, 24),
+        send_time: libdt::UtcNs::from_nanos(bytes::read_u64_le(bytes, 32)),
+    })
+}
+
+/// The header's fields, one per line.
+pub fn describe_header(iex_header: &IexTpHeader) -> String {
+    format!("Version: {}\nMessage Protocol ID: {:#06x}\nChannel ID: {}\nSession ID: {}\nPayload length: {}\n\
+             Message count: {}\nStream offset: {}\nFirst msg seq num: {}\nSend time: {}",
+            iex_header.version, iex_header.message_protocol_id, iex_header.channel_id, iex_header.session_id,
+            iex_header.payload_length, iex_header.message_count, iex_header.stream_offset,
+            iex_header.first_message_sequence_number,
+            iex_header.send_datetime().to_rfc3339_opts(chrono::SecondsFormat::Nanos, true))
+}
+
+/// Logs `describe_header` at info level.
+pub fn debug_header(iex_header: &IexTpHeader) {
+    for line in describe_header(iex_header).lines() {
+        info!("{}", line);
+    }
+    info!("");
+}
+
+#[derive(Debug)]
+pub enum LoadPcapError {
+    NoFileExtension,
+    WrongFileExtension,
+    FileError(io::Error),
+    DecompressError(io::Error),
+    #[cfg(feature = "native-pcap")]
+    PcapError(pcap::Error),
+    /// Neither a pcap nor a pcapng capture, going by its first bytes. Only returned by the
+    /// built-in readers.
+    UnknownFormat,
+}
+
+/// Only IEX-TP multicast traffic is of interest when capturing live.
+pub const IEX_MULTICAST_FILTER: &str = "udp and dst net 233.215.21.0/24";
+/// How long reading from a live capture blocks before giving up with `pcap::Error::TimeoutExpired`,
+/// so callers get a chance to do periodic work or stop.
+pub const LIVE_READ_TIMEOUT_MS: i32 = 1000;
+
+#[cfg(feature = "native-pcap")]
+fn load_capture_from_pcap<P: AsRef<path::Path>>(path: P) -> Result<pcap::Capture<pcap::Offline>, LoadPcapError> {
+    Capture::from_file(path).or_else(|e| Err(LoadPcapError::PcapError(e)))
+}
+
+/// A compression format captures can be archived in. Compressed captures are decompressed into a
+/// temp pcap for libpcap, which only reads plain files, or into memory for the built-in readers.
+trait Decompressor {
+    fn decoder(&self, compressed: Box<dyn io::Read>) -> io::Result<Box<dyn io::Read>>;
+}
+
+struct Gz;
+
+impl Decompressor for Gz {
+    fn decoder(&self, compressed: Box<dyn io::Read>) -> io::Result<Box<dyn io::Read>> {
+        Ok(Box::new(GzDecoder::new(compressed)))
+    }
+}
+
+struct Bz2;
+
+impl Decompressor for Bz2 {
+    fn decoder(&self, compressed: Box<dyn io::Read>) -> io::Result<Box<dyn io::Read>> {
+        Ok(Box::new(bzip2::read::BzDecoder::new(compressed)))
+    }
+}
+
+struct Zstd;
+
+impl Decompressor for Zstd {
+    fn decoder(&self, compressed: Box<dyn io::Read>) -> io::Result<Box<dyn io::Read>> {
+        Ok(Box::new(zstd::stream::read::Decoder::new(compressed)?))
+    }
+}
+
+fn decompressor_for_extension(extension: &ffi::OsStr) -> Option<Box<dyn Decompressor>> {
+    match extension.to_str() {
+        Some("gz") => Some(Box::new(Gz)),
+        Some("bz2") => Some(Box::new(Bz2)),
+        Some("zst") => Some(Box::new(Zstd)),
+        _ => None,
+    }
+}
+
+/// Deletes the file when dropped.
+#[cfg(feature = "native-pcap")]
+struct TempFile(path::PathBuf);
+
+#[cfg(feature = "native-pcap")]
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        if let Err(e) = fs::remove_file(&self.0) {
+            warn!("Failed to remove temp file {:?}: {}", self.0, e);
+        }
+    }
+}
+
+/// The temp pcap is deleted as soon as libpcap has opened it, or failed to. The open capture keeps
+/// reading it until it's closed, after which its space is freed.
+#[cfg(feature = "native-pcap")]
+fn load_capture_from_compressed(path: &str, decompressor: &dyn Decompressor, temp_dir: &path::Path)
+    -> Result<pcap::Capture<pcap::Offline>, LoadPcapError> {
+    let f = match fs::File::open(path) {
+        Ok(f) => f,
+        Err(e) => {
+            return Err(LoadPcapError::FileError(e));
+        },
+    };
+    let mut data = Vec::new();
+    if let Err(e) = decompressor.decoder(Box::new(io::BufReader::new(f)))
+        .and_then(|mut decoder| decoder.read_to_end(&mut data)) {
+        return Err(LoadPcapError::DecompressError(e));
+    }
+    // Named apart from any capture that might be in the temp dir too, and from other runs'.
+    let temp_path = {
+        let stem = path::Path::new(path).file_stem().unwrap().to_string_lossy();
+        temp_dir.join(format!("iex_pcap_parser_{}_{}", std::process::id(), stem))
+    };
+    let mut pcap_file = match fs::File::create(&temp_path) {
+        Ok(f) => f,
+        Err(e) => {
+            return Err(LoadPcapError::FileError(e));
+        },
+    };
+    let temp_file = TempFile(temp_path);
+    if let Err(e) = pcap_file.write_all(&data) {
+        return Err(LoadPcapError::FileError(e));
+    }
+    info!("Decompressed {} to temp file {:?}", path, temp_file.0);
+    load_capture_from_pcap(&temp_file.0)
+}
+
+// Capturing from a device requires root or CAP_NET_RAW (see README).
+#[cfg(feature = "native-pcap")]
+pub fn load_capture_from_device(iface: &str) -> Result<pcap::Capture<pcap::Active>, LoadPcapError> {
+    let mut capture = Capture::from_device(iface)
+        .and_then(|c| c.promisc(true).timeout(LIVE_READ_TIMEOUT_MS).open())
+        .map_err(LoadPcapError::PcapError)?;
+    capture.filter(IEX_MULTICAST_FILTER).map_err(LoadPcapError::PcapError)?;
+    Ok(capture)
+}
+
+// TODO(sherry): avoid uncompressing into temp pcap and read directly from the compressed file
+#[cfg(feature = "native-pcap")]
+pub fn load_capture_from_file(file: &str) -> Result<pcap::Capture<pcap::Offline>, LoadPcapError> {
+    load_capture_from_file_with_temp_dir(file, &env::temp_dir())
+}
+
+/// Like `load_capture_from_file`, decompressing compressed captures into `temp_dir`.
+#[cfg(feature = "native-pcap")]
+pub fn load_capture_from_file_with_temp_dir(file: &str, temp_dir: &path::Path)
+    -> Result<pcap::Capture<pcap::Offline>, LoadPcapError> {
+    let path = path::Path::new(file);
+    if let Some(extension) = path.extension() {
+        if extension == ffi::OsStr::new("pcap") || extension == ffi::OsStr::new("pcapng") {
+            load_capture_from_pcap(file)
+        } else if let Some(decompressor) = decompressor_for_extension(extension) {
+            load_capture_from_compressed(file, decompressor.as_ref(), temp_dir)
+        } else {
+            Err(LoadPcapError::WrongFileExtension)
+        }
+    } else {
+        Err(LoadPcapError::NoFileExtension)
+    }
+}
+
+/// The bytes of a `.pcap` or `.pcapng` capture, decompressed if it's `.gz`, `.bz2` or `.zst`.
+#[cfg(any(feature = "pure-pcap", not(feature = "native-pcap")))]
+fn read_capture(file: &str) -> Result<Vec<u8>, LoadPcapError> {
+    let extension = path::Path::new(file).extension().ok_or(LoadPcapError::NoFileExtension)?;
+    let decompressor = if extension == ffi::OsStr::new("pcap") || extension == ffi::OsStr::new("pcapng") {
+        None
+    } else {
+        Some(decompressor_for_extension(extension).ok_or(LoadPcapError::WrongFileExtension)?)
+    };
+    let f = fs::File::open(file).map_err(LoadPcapError::FileError)?;
+    let mut data = Vec::new();
+    match decompressor {
+        Some(decompressor) => decompressor.decoder(Box::new(io::BufReader::new(f)))
+            .and_then(|mut decoder| decoder.read_to_end(&mut data))
+            .map_err(LoadPcapError::DecompressError)?,
+        None => io::BufReader::new(f).read_to_end(&mut data).map_err(LoadPcapError::FileError)?,
+    };
+    Ok(data)
+}
+
+#[derive(Debug)]
+pub enum ParseError {
+    /// Reading the next packet from the capture failed.
+    #[cfg(feature = "native-pcap")]
+    Capture(pcap::Error),
+    Ethernet(etherparse::ReadError),
+    TruncatedHeader { have: usize, need: usize },
+    UnsupportedVersion(u8),
+    /// A message is shorter than its type requires.
+    TruncatedMessage { have: usize, need: usize },
+    /// A message's length prefix at `offset` into the packet body is 0.
+    ZeroLengthMessage { offset: usize },
+    UnknownMessageType(u8),
+    /// A message is framed longer than its type's length per the spec. Only checked in strict mode.
+    MessageLengthMismatch { message_type: u8, framed: usize, expected: usize },
+    /// `field` holds a byte that doesn't map to any of its enum's values.
+    InvalidEnumByte { field: &'static str, byte: u8 },
+    /// A pcap record or pcapng block starting at `offset` into the capture is cut short.
+    TruncatedBlock { offset: usize },
+    /// The header's payload length isn't the number of bytes after it. Fewer bytes than declared,
+    /// as in a truncated capture, are always an error; more only in strict mode.
+    PayloadLengthMismatch { header: u16, actual: usize },
+    /// The packet's first message sequence number skips past where its channel and session left
+    /// off. Only reported in strict mode.
+    SequenceGap { channel_id: u32, session_id: u32, expected: u64, first: u64 },
+}
+
+impl ParseError {
+    /// Whether the capture couldn't be read any further, rather than a packet of it decoded.
+    pub fn is_unreadable_capture(&self) -> bool {
+        match self {
+            #[cfg(feature = "native-pcap")]
+            ParseError::Capture(_) => true,
+            ParseError::TruncatedBlock { .. } => true,
+            _ => false,
+        }
+    }
+}
+
+const IEX_TP_VERSION: u8 = 0x1;
+pub const TOPS_MESSAGE_PROTOCOL_ID: u16 = 0x8003;
+pub const DEEP_MESSAGE_PROTOCOL_ID: u16 = 0x8004;
+
+/// How `Packets` decodes each packet, as set by its `with_*` methods.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+struct ParseOptions {
+    filter: ChannelFilter,
+    trades_only: bool,
+    skip_auctions: bool,
+    headers_only: bool,
+    mode: ParseMode,
+}
+
+fn parse_packet(data: &[u8], packet_num: u64, capture_timestamp_ns: libdt::UtcNs, options: &ParseOptions)
+                -> Result<DeepPacket, ParseError> {
+    let mode = options.mode;
+    let packet = etherparse::SlicedPacket::from_ethernet(data).map_err(ParseError::Ethernet)?;
+    let iex_header_length = IexTpHeader::LENGTH;
+    let iex_header = parse_header(packet.payload, mode)?;
+
+    let filtered_out = !options.filter.matches(&iex_header);
+    // Saturating, as a corrupt header's sequence number can be anything.
+    let unparsed = (Vec::new(), Vec::new(), Vec::new(),
+                    iex_header.first_message_sequence_number.saturating_add(u64::from(iex_header.message_count)));
+    let (mut messages, mut errors, raw_failures, next_message_sequence_number) = match iex_header.message_protocol_id {
+        _ if filtered_out || options.headers_only => unparsed,
+        DEEP_MESSAGE_PROTOCOL_ID => parse_body(&packet.payload[iex_header_length..], packet_num, iex_header.first_message_sequence_number,
+                                              iex_header.message_count, capture_timestamp_ns, options),
+        // TODO(sherry): decode TOPS
+        _ => unparsed,
+    };
+    if mode == ParseMode::Strict && !errors.is_empty() {
+        return Err(errors.swap_remove(0));
+    }
+    for message in &mut messages {
+        message.channel_id = iex_header.channel_id;
+    }
+    Ok(DeepPacket {
+        header: iex_header,
+        messages,
+        errors,
+        raw_failures,
+        next_message_sequence_number,
+        capture_len: data.len() as u32,
+        capture_timestamp_ns,
+        filtered_out,
+    })
+}
+
+/// Selects packets by the channel and session in their IEX-TP header. Unset ids match any.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ChannelFilter {
+    pub channel_id: Option<u32>,
+    pub session_id: Option<u32>,
+}
+
+impl ChannelFilter {
+    pub fn matches(&self, header: &IexTpHeader) -> bool {
+        (self.channel_id.is_none() || self.channel_id == Some(header.channel_id))
+            && (self.session_id.is_none() || self.session_id == Some(header.session_id))
+    }
+}
+
+/// A message's bytes as they were in the capture, without its length prefix.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RawMessage {
+    /// Number of the packet the message arrived in, from 0 in capture order.
+    pub packet_number: u64,
+    pub message_sequence_number: u64,
+    pub bytes: Vec<u8>,
+}
+
+/// Packets of protocols other than DEEP, such as TOPS in a mixed capture, come without messages,
+/// as do packets left out by a `ChannelFilter`.
+pub struct DeepPacket {
+    pub header: IexTpHeader,
+    pub messages: Vec<IexDeepMessage>,
+    /// Messages of the packet that couldn't be decoded and were left out of `messages`.
+    pub errors: Vec<ParseError>,
+    /// The bytes of the messages in `errors` that had any, for decoding them again offline. A
+    /// message cut short by the end of the packet has as many bytes as were left.
+    pub raw_failures: Vec<RawMessage>,
+    /// The sequence number the next packet of this channel and session should start at: after the
+    /// last message of the body that was read, or as the header says if the body wasn't parsed.
+    pub next_message_sequence_number: u64,
+    /// Number of bytes of the packet that were stored in the capture.
+    pub capture_len: u32,
+    pub capture_timestamp_ns: libdt::UtcNs,
+    /// Whether the packet's channel or session didn't match the filter, so its body wasn't parsed.
+    pub filtered_out: bool,
+}
+
+impl DeepPacket {
+    pub fn is_deep(&self) -> bool {
+        self.header.message_protocol_id == DEEP_MESSAGE_PROTOCOL_ID
+    }
+}
+
+/// Detects packets missing from a capture by checking that each packet's stream offset continues
+/// where the previous packet of its channel and session ended.
+#[derive(Default)]
+pub struct StreamGaps {
+    /// The stream offset each (channel, session) should continue at.
+    expected: HashMap<(u32, u32), u64>,
+    pub gaps: u64,
+    pub missing_bytes: u64,
+}
+
+impl StreamGaps {
+    /// Returns how many bytes of the stream are missing right before this packet, if any. A header
+    /// whose payload would end past `u64::MAX` is malformed and ignored.
+    pub fn check(&mut self, header: &IexTpHeader) -> Option<u64> {
+        let end = header.stream_offset.checked_add(u64::from(header.payload_length))?;
+        let expected = self.expected.entry((header.channel_id, header.session_id))
+            .or_insert(header.stream_offset);
+        let missing = header.stream_offset.saturating_sub(*expected);
+        *expected = (*expected).max(end);
+        if missing == 0 {
+            return None;
+        }
+        self.gaps += 1;
+        self.missing_bytes += missing;
+        Some(missing)
+    }
+}
+
+/// The message sequence number each (channel, session) should continue at.
+#[derive(Debug, Default)]
+struct SequenceNumbers {
+    next: HashMap<(u32, u32), u64>,
+}
+
+impl SequenceNumbers {
+    /// Returns the sequence number this packet should have started at if messages are missing
+    /// right before it. The channel and session continue at `next`, as returned by `parse_body`.
+    fn check(&mut self, header: &IexTpHeader, next: u64) -> Option<u64> {
+        let expected = self.next.entry((header.channel_id, header.session_id))
+            .or_insert(header.first_message_sequence_number);
+        let gap = Some(*expected).filter(|&expected| header.first_message_sequence_number > expected);
+        *expected = (*expected).max(next);
+        gap
+    }
+}
+
+/// Counts of what was read, from packets down to messages, as one snapshot of a capture's health.
+#[derive(Debug, Default)]
+pub struct ParseStats {
+    pub packets_total: u64,
+    /// Packets that aren't IEX-TP version 1, such as other traffic on the capture's interface.
+    pub packets_skipped_non_iex: u64,
+    /// Packets whose ethernet, IP or UDP headers are invalid or whose IEX-TP header is cut short.
+    pub headers_malformed: u64,
+    pub messages_parsed: u64,
+    pub messages_unknown: u64,
+    /// Messages with a byte that isn't one of its enum's values, such as an unknown trading
+    /// status, which usually means the protocol gained a value this parser doesn't know yet.
+    pub messages_invalid_enum: u64,
+    pub messages_truncated: u64,
+    /// Messages whose length is 0, skipped in lenient mode.
+    pub messages_zero_length: u64,
+    /// Jumps in the message sequence numbers of a channel and session.
+    pub sequence_gaps: u64,
+    /// Whether an end of messages system event was read. Without one a capture was probably cut
+    /// short. Never set when messages other than trades are skipped.
+    pub end_of_messages: bool,
+    /// Auction information messages read in `AuctionLayout::Prefix`.
+    pub auctions_prefix_layout: u64,
+    sequence_numbers: SequenceNumbers,
+}
+
+impl ParseStats {
+    /// Counts one item of `Packets`. Errors reading the capture itself, such as live timeouts,
+    /// aren't packets and aren't counted. In strict mode a packet's first anomaly is its error, so
+    /// that's all that's counted of it.
+    pub fn record(&mut self, packet: &Result<DeepPacket, ParseError>) {
+        let packet = match packet {
+            Ok(packet) => packet,
+            Err(e) if e.is_unreadable_capture() => return,
+            Err(e) => {
+                self.packets_total += 1;
+                match e {
+                    ParseError::UnsupportedVersion(_) => self.packets_skipped_non_iex += 1,
+                    ParseError::Ethernet(_) | ParseError::TruncatedHeader { .. }
+                    | ParseError::PayloadLengthMismatch { .. } => self.headers_malformed += 1,
+                    ParseError::SequenceGap { .. } => self.sequence_gaps += 1,
+                    e => self.record_message_error(e),
+                }
+                return;
+            },
+        };
+        self.packets_total += 1;
+        self.messages_parsed += packet.messages.len() as u64;
+        self.end_of_messages |= packet.messages.iter().any(IexDeepMessage::is_end_of_messages);
+        self.auctions_prefix_layout += packet.messages.iter()
+            .filter(|m| matches!(&m.body, IexDeepMessageImpl::AuctionInformation(a) if a.layout == AuctionLayout::Prefix))
+            .count() as u64;
+        for e in &packet.errors {
+            self.record_message_error(e);
+        }
+        if self.sequence_numbers.check(&packet.header, packet.next_message_sequence_number).is_some() {
+            self.sequence_gaps += 1;
+        }
+    }
+
+    /// Adds the counts of another capture. Sequence gaps between the captures aren't counted.
+    pub fn merge(&mut self, other: &ParseStats) {
+        self.packets_total += other.packets_total;
+        self.packets_skipped_non_iex += other.packets_skipped_non_iex;
+        self.headers_malformed += other.headers_malformed;
+        self.messages_parsed += other.messages_parsed;
+        self.messages_unknown += other.messages_unknown;
+        self.messages_invalid_enum += other.messages_invalid_enum;
+        self.messages_truncated += other.messages_truncated;
+        self.messages_zero_length += other.messages_zero_length;
+        self.sequence_gaps += other.sequence_gaps;
+        self.end_of_messages |= other.end_of_messages;
+        self.auctions_prefix_layout += other.auctions_prefix_layout;
+    }
+
+    fn record_message_error(&mut self, e: &ParseError) {
+        match e {
+            ParseError::UnknownMessageType(_) => self.messages_unknown += 1,
+            ParseError::InvalidEnumByte { .. } => self.messages_invalid_enum += 1,
+            ParseError::TruncatedMessage { .. } => self.messages_truncated += 1,
+            ParseError::ZeroLengthMessage { .. } => self.messages_zero_length += 1,
+            _ => (),
+        }
+    }
+}
+
+/// Messages of a capture by type, as counted by `Packets::count_messages` from their type bytes.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MessageCounts {
+    pub packets: u64,
+    /// Packets that weren't DEEP, didn't match the filter, or had a malformed header.
+    pub packets_skipped: u64,
+    /// Messages cut short by the end of their packet, which have no type counted.
+    pub truncated: u64,
+    by_type: [u64; 256],
+}
+
+impl Default for MessageCounts {
+    fn default() -> MessageCounts {
+        MessageCounts {
+            packets: 0,
+            packets_skipped: 0,
+            truncated: 0,
+            by_type: [0; 256],
+        }
+    }
+}
+
+impl MessageCounts {
+    pub fn count(&self, message_type: u8) -> u64 {
+        self.by_type[usize::from(message_type)]
+    }
+
+    /// Messages of every type.
+    pub fn total(&self) -> u64 {
+        self.by_type.iter().sum()
+    }
+
+    /// `(message_type, count)` of the types seen, by type byte.
+    pub fn iter(&self) -> impl Iterator<Item = (u8, u64)> + '_ {
+        (0..=u8::MAX).map(move |message_type| (message_type, self.count(message_type))).filter(|(_, count)| *count > 0)
+    }
+
+    /// Adds the counts of another capture.
+    pub fn merge(&mut self, other: &MessageCounts) {
+        self.packets += other.packets;
+        self.packets_skipped += other.packets_skipped;
+        self.truncated += other.truncated;
+        for (count, other) in self.by_type.iter_mut().zip(other.by_type.iter()) {
+            *count += other;
+        }
+    }
+}
+
+/// DEEP messages and distinct symbols by channel, to tell which channels carry which symbols.
+#[derive(Debug, Default)]
+pub struct ChannelSummary {
+    channels: BTreeMap<u32, (u64, HashSet<MessageSymbol>)>,
+}
+
+impl ChannelSummary {
+    /// Counts the messages of a DEEP packet the filter kept under its header's channel.
+    pub fn record(&mut self, packet: &DeepPacket) {
+        if packet.filtered_out || !packet.is_deep() {
+            return;
+        }
+        let (messages, symbols) = self.channels.entry(packet.header.channel_id).or_default();
+        *messages += packet.messages.len() as u64;
+        symbols.extend(packet.messages.iter().filter_map(IexDeepMessage::message_symbol));
+    }
+
+    /// `(channel_id, messages, distinct symbols)` of the channels seen, by channel.
+    pub fn iter(&self) -> impl Iterator<Item = (u32, u64, usize)> + '_ {
+        self.channels.iter().map(|(channel_id, (messages, symbols))| (*channel_id, *messages, symbols.len()))
+    }
+}
+
+/// libpcap timestamps are microsecond precision unless a capture is opened with nanosecond
+/// precision, which `pcap` doesn't expose.
+fn capture_timestamp_ns(seconds: i64, microseconds: i64) -> libdt::UtcNs {
+    libdt::UtcNs::from_nanos(seconds as u64 * libdt::NS_PER_SEC + microseconds as u64 * 1000)
+}
+
+/// A packet of the built-in readers as `Packets::next_frame` returns it.
+fn built_in_frame(packet: Option<Result<pcapng::Packet<'_>, pcapng::TruncatedBlock>>)
+                  -> Option<Result<(&[u8], libdt::UtcNs), ParseError>> {
+    packet.map(|packet| {
+        packet.map(|packet| (packet.data, packet.timestamp_ns))
+            .map_err(|e| ParseError::TruncatedBlock { offset: e.offset })
+    })
+}
+
+/// Iterates over the packets of a capture, decoding the DEEP messages in each. Live captures yield
+/// `ParseError::Capture(pcap::Error::TimeoutExpired)` whenever no packet arrived in time; the
+/// iterator can keep being polled after that.
+pub struct Packets {
+    source: PacketSource,
+    packet_number: u64,
+    done: bool,
+    options: ParseOptions,
+    max_packets: Option<u64>,
+    /// Only tracked in strict mode, where gaps are errors.
+    sequence_numbers: SequenceNumbers,
+}
+
+enum PacketSource {
+    #[cfg(feature = "native-pcap")]
+    Capture(pcap::Capture<dyn pcap::Activated>),
+    /// The built-in readers, used without libpcap, and for pcapng captures an old libpcap can't
+    /// open.
+    PcapNg(pcapng::EnhancedPackets),
+    Pcap(pcapfile::Records),
+}
+
+impl Packets {
+    #[cfg(feature = "native-pcap")]
+    pub fn new(capture: pcap::Capture<dyn pcap::Activated>) -> Packets {
+        Packets::from_source(PacketSource::Capture(capture))
+    }
+
+    fn from_pcapng(packets: pcapng::EnhancedPackets) -> Packets {
+        Packets::from_source(PacketSource::PcapNg(packets))
+    }
+
+    /// Reads a pcap or pcapng capture already in memory with the built-in readers.
+    pub fn from_bytes(data: Vec<u8>) -> Result<Packets, LoadPcapError> {
+        if pcapng::is_pcapng(&data) {
+            return pcapng::EnhancedPackets::new(data).map(Packets::from_pcapng).ok_or(LoadPcapError::UnknownFormat);
+        }
+        pcapfile::Records::new(data).map(|records| Packets::from_source(PacketSource::Pcap(records)))
+            .ok_or(LoadPcapError::UnknownFormat)
+    }
+
+    fn from_source(source: PacketSource) -> Packets {
+        Packets {
+            source,
+            packet_number: 0,
+            done: false,
+            options: ParseOptions::default(),
+            max_packets: None,
+            sequence_numbers: SequenceNumbers::default(),
+        }
+    }
+
+    /// Only decodes the messages of packets matching `filter`.
+    pub fn with_filter(mut self, filter: ChannelFilter) -> Packets {
+        self.options.filter = filter;
+        self
+    }
+
+    /// Only decodes trade reports and trade breaks, skipping every other message unparsed.
+    pub fn with_trades_only(mut self, trades_only: bool) -> Packets {
+        self.options.trades_only = trades_only;
+        self
+    }
+
+    /// Skips auction information messages unparsed, for runs that don't keep them.
+    pub fn with_skip_auctions(mut self, skip_auctions: bool) -> Packets {
+        self.options.skip_auctions = skip_auctions;
+        self
+    }
+
+    /// Only parses the IEX-TP header of each packet, leaving `messages` empty.
+    pub fn with_headers_only(mut self, headers_only: bool) -> Packets {
+        self.options.headers_only = headers_only;
+        self
+    }
+
+    /// Stops after `max_packets` packets, if given.
+    pub fn with_max_packets(mut self, max_packets: Option<u64>) -> Packets {
+        self.max_packets = max_packets;
+        self
+    }
+
+    /// Whether anomalies make a packet an error; see `ParseMode`.
+    pub fn with_mode(mut self, mode: ParseMode) -> Packets {
+        self.options.mode = mode;
+        self
+    }
+
+    /// Whether iteration stopped, or will stop, because of the `with_max_packets` limit.
+    pub fn limit_reached(&self) -> bool {
+        self.max_packets == Some(self.packet_number)
+    }
+
+    /// Counts the messages of the remaining packets by type without decoding them. No packet,
+    /// message or tick is built, so it's much faster than iterating and takes constant memory.
+    /// Honors the filter and `max_packets`, but not `trades_only` or the mode. Stops at the first
+    /// capture error, like iterating does.
+    pub fn count_messages(&mut self) -> MessageCounts {
+        let mut counts = MessageCounts::default();
+        let filter = self.options.filter;
+        while let Some(frame) = self.next_frame() {
+            let data = match frame {
+                Ok((data, _)) => data,
+                #[cfg(feature = "native-pcap")]
+                Err(ParseError::Capture(pcap::Error::TimeoutExpired)) => continue,
+                Err(e) => {
+                    warn!("Stopped counting: {:?}", e);
+                    break;
+                },
+            };
+            counts.packets += 1;
+            let payload = match etherparse::SlicedPacket::from_ethernet(data) {
+                Ok(packet) => packet.payload,
+                Err(_) => {
+                    counts.packets_skipped += 1;
+                    continue;
+                },
+            };
+            match parse_header(payload, ParseMode::Lenient) {
+                Ok(header) if header.message_protocol_id == DEEP_MESSAGE_PROTOCOL_ID && filter.matches(&header) => {
+                    count_body(&payload[IexTpHeader::LENGTH..], &mut counts);
+                },
+                _ => counts.packets_skipped += 1,
+            }
+        }
+        counts
+    }
+
+    /// The next frame of the capture and when it was captured, counting it towards `max_packets`.
+    fn next_frame(&mut self) -> Option<Result<(&[u8], libdt::UtcNs), ParseError>> {
+        if self.done || self.limit_reached() {
+            return None;
+        }
+        let frame = match &mut self.source {
+            #[cfg(feature = "native-pcap")]
+            PacketSource::Capture(capture) => match capture.next() {
+                Ok(raw_packet) => {
+                    let ts = raw_packet.header.ts;
+                    // time_t and suseconds_t are only i64 on some platforms.
+                    #[allow(clippy::useless_conversion)]
+                    Some(Ok((raw_packet.data, capture_timestamp_ns(i64::from(ts.tv_sec), i64::from(ts.tv_usec)))))
+                },
+                Err(pcap::Error::NoMorePackets) => None,
+                Err(e) => Some(Err(ParseError::Capture(e))),
+            },
+            PacketSource::PcapNg(packets) => built_in_frame(packets.next_packet()),
+            PacketSource::Pcap(records) => built_in_frame(records.next_packet()),
+        };
+        match frame {
+            Some(Ok(frame)) => {
+                self.packet_number += 1;
+                Some(Ok(frame))
+            },
+            // Live captures can be polled again after timing out.
+            #[cfg(feature = "native-pcap")]
+            Some(Err(e @ ParseError::Capture(pcap::Error::TimeoutExpired))) => Some(Err(e)),
+            Some(Err(e)) => {
+                self.done = true;
+                Some(Err(e))
+            },
+            None => {
+                self.done = true;
+                None
+            },
+        }
+    }
+}
+
+impl Iterator for Packets {
+    type Item = Result<DeepPacket, ParseError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let packet_number = self.packet_number;
+        let options = self.options;
+        let (data, timestamp_ns) = match self.next_frame()? {
+            Ok(frame) => frame,
+            Err(e) => return Some(Err(e)),
+        };
+        let packet = parse_packet(data, packet_number, timestamp_ns, &options);
+        if let (ParseMode::Strict, Ok(packet)) = (options.mode, &packet) {
+            if let Some(expected) = self.sequence_numbers.check(&packet.header, packet.next_message_sequence_number) {
+                return Some(Err(ParseError::SequenceGap {
+                    channel_id: packet.header.channel_id,
+                    session_id: packet.header.session_id,
+                    expected,
+                    first: packet.header.first_message_sequence_number,
+                }));
+            }
+        }
+        Some(packet)
+    }
+}
+
+/// Iterates over every DEEP message of a capture, one packet at a time.
+pub struct Messages {
+    packets: Packets,
+    pending: std::vec::IntoIter<Result<IexDeepMessage, ParseError>>,
+}
+
+impl Messages {
+    pub fn new(packets: Packets) -> Messages {
+        Messages {
+            packets,
+            pending: Vec::new().into_iter(),
+        }
+    }
+}
+
+impl Iterator for Messages {
+    type Item = Result<IexDeepMessage, ParseError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(message) = self.pending.next() {
+                return Some(message);
+            }
+            match self.packets.next()? {
+                Ok(packet) => {
+                    self.pending = packet.messages.into_iter().map(Ok)
+                        .chain(packet.errors.into_iter().map(Err))
+                        .collect::<Vec<_>>()
+                        .into_iter();
+                },
+                Err(e) => return Some(Err(e)),
+            }
+        }
+    }
+}
+
+/// Something `validate_capture` found wrong with a packet. Packets are numbered from 0 in capture
+/// order.
+#[derive(Debug)]
+pub enum Anomaly {
+    /// The packet has no valid IEX-TP header, or isn't IEX-TP at all.
+    Malformed { packet_number: u64, error: ParseError },
+    /// The packet is IEX-TP, but of a protocol other than DEEP.
+    UnexpectedProtocol { packet_number: u64, message_protocol_id: u16 },
+    /// Bytes of the channel and session's stream are missing right before the packet.
+    StreamGap { packet_number: u64, channel_id: u32, session_id: u32, missing_bytes: u64 },
+    /// The capture couldn't be read past this packet.
+    Unreadable { packet_number: u64, error: ParseError },
+}
+
+#[derive(Debug, Default)]
+pub struct ValidationReport {
+    pub packets: u64,
+    /// Packets with a valid DEEP header.
+    pub deep_packets: u64,
+    /// Messages the DEEP headers say their packets hold, without decoding any.
+    pub messages: u64,
+    pub payload_bytes: u64,
+    pub missing_bytes: u64,
+    pub anomalies: Vec<Anomaly>,
+}
+
+impl ValidationReport {
+    pub fn is_valid(&self) -> bool {
+        self.anomalies.is_empty()
+    }
+}
+
+/// Checks that every packet of a capture has a valid IEX-TP header of the DEEP protocol, and that
+/// each channel and session's stream offsets are contiguous. Only headers are parsed, so this is
+/// much faster than decoding the capture.
+pub fn validate_capture(file: &str) -> Result<ValidationReport, LoadPcapError> {
+    packets_from_capture(file).map(|packets| validate_packets(packets.with_headers_only(true)))
+}
+
+fn validate_packets(packets: Packets) -> ValidationReport {
+    let mut report = ValidationReport::default();
+    let mut stream_gaps = StreamGaps::default();
+    for packet in packets {
+        let packet_number = report.packets;
+        let packet = match packet {
+            Ok(packet) => packet,
+            Err(error) if error.is_unreadable_capture() => {
+                report.anomalies.push(Anomaly::Unreadable { packet_number, error });
+                break;
+            },
+            Err(error) => {
+                report.packets += 1;
+                report.anomalies.push(Anomaly::Malformed { packet_number, error });
+                continue;
+            },
+        };
+        report.packets += 1;
+        let header = &packet.header;
+        if !packet.is_deep() {
+            report.anomalies.push(Anomaly::UnexpectedProtocol {
+                packet_number,
+                message_protocol_id: header.message_protocol_id,
+            });
+            continue;
+        }
+        report.deep_packets += 1;
+        report.messages += u64::from(header.message_count);
+        report.payload_bytes += u64::from(header.payload_length);
+        if let Some(missing_bytes) = stream_gaps.check(header) {
+            report.missing_bytes += missing_bytes;
+            report.anomalies.push(Anomaly::StreamGap {
+                packet_number,
+                channel_id: header.channel_id,
+                session_id: header.session_id,
+                missing_bytes,
+            });
+        }
+    }
+    report
+}
+
+/// Opens a capture with libpcap, unless built with the `pure-pcap` feature. libpcap only reads
+/// plain files, so compressed captures are decompressed into a temp pcap in the system's temp dir.
+pub fn packets_from_capture(file: &str) -> Result<Packets, LoadPcapError> {
+    packets_from_capture_with_temp_dir(file, &std::env::temp_dir())
+}
+
+/// Like `packets_from_capture`, decompressing captures for libpcap into `temp_dir`. The temp pcap
+/// is deleted once opened, so it takes up space until the returned `Packets` is dropped.
+#[cfg(all(feature = "native-pcap", not(feature = "pure-pcap")))]
+pub fn packets_from_capture_with_temp_dir(file: &str, temp_dir: &path::Path) -> Result<Packets, LoadPcapError> {
+    match load_capture_from_file_with_temp_dir(file, temp_dir) {
+        Ok(capture) => Ok(Packets::new(capture.into())),
+        // libpcap only reads pcapng since 1.1.0; older ones get the built-in reader instead.
+        Err(LoadPcapError::PcapError(e)) => {
+            let data = fs::read(file).map_err(LoadPcapError::FileError)?;
+            match pcapng::EnhancedPackets::new(data) {
+                Some(packets) => {
+                    warn!("libpcap can't read {} ({}); reading it as pcapng", file, e);
+                    Ok(Packets::from_pcapng(packets))
+                },
+                None => Err(LoadPcapError::PcapError(e)),
+            }
+        },
+        Err(e) => Err(e),
+    }
+}
+
+/// Reads a capture into memory and opens it with the built-in readers, without libpcap, so
+/// `temp_dir` isn't used.
+#[cfg(any(feature = "pure-pcap", not(feature = "native-pcap")))]
+pub fn packets_from_capture_with_temp_dir(file: &str, _temp_dir: &path::Path) -> Result<Packets, LoadPcapError> {
+    Packets::from_bytes(read_capture(file)?)
+}
+
+/// Lazily decodes every message of a `.pcap` or `.pcapng` capture. Classic pcaps may be compressed
+/// as `.gz`, `.bz2` or `.zst`.
+///
+/// Total traded volume for a symbol:
+///
+/// ```no_run
+/// use iex_pcap_parser::{IexDeepMessageImpl, messages_from_capture};
+///
+/// let mut volume = 0u64;
+/// for message in messages_from_capture("20190703_IEXTP1_DEEP1.0.pcap.gz").unwrap() {
+///     let message = message.unwrap();
+///     if let IexDeepMessageImpl::TradeReport(trade) = &message.body {
+///         if message.symbol().as_deref() == Some("AAPL") {
+///             volume += u64::from(trade.size);
+///         }
+///     }
+/// }
+/// println!("AAPL traded {} shares", volume);
+/// ```
+pub fn messages_from_capture(file: &str) -> Result<Messages, LoadPcapError> {
+    packets_from_capture(file).map(Messages::new)
+}
+
+#[derive(Debug)]
+pub enum TryForEachError<E> {
+    Load(LoadPcapError),
+    /// The first error returned by the handler, which stopped the iteration.
+    Handler(E),
+}
+
+/// Passes the messages that decoded to `f`, logging and skipping the others.
+fn try_for_each_decoded<I, F, E>(messages: I, mut f: F) -> Result<(), E>
+    where I: Iterator<Item = Result<IexDeepMessage, ParseError>>,
+          F: FnMut(&IexDeepMessage) -> Result<(), E> {
+    for message in messages {
+        match message {
+            Ok(message) => f(&message)?,
+            Err(e) => warn!("Skipping undecodable message or packet: {:?}", e),
+        }
+    }
+    Ok(())
+}
+
+/// Calls `f` with every message of a capture, for sinks other than the h5 files written by the
+/// binary. Messages and packets that fail to decode are logged and skipped.
+///
+/// Trades per symbol:
+///
+/// ```no_run
+/// use std::collections::HashMap;
+/// use iex_pcap_parser::{IexDeepMessageImpl, for_each_message};
+///
+/// let mut trades: HashMap<String, u64> = HashMap::new();
+/// for_each_message("20190703_IEXTP1_DEEP1.0.pcap.gz", |message| {
+///     if let IexDeepMessageImpl::TradeReport(_) = &message.body {
+///         *trades.entry(message.symbol().unwrap()).or_insert(0) += 1;
+///     }
+/// }).unwrap();
+/// ```
+pub fn for_each_message<F: FnMut(&IexDeepMessage)>(file: &str, mut f: F) -> Result<(), LoadPcapError> {
+    let messages = messages_from_capture(file)?;
+    try_for_each_decoded(messages, |message| -> Result<(), std::convert::Infallible> {
+        f(message);
+        Ok(())
+    }).unwrap_or_else(|e| match e {});
+    Ok(())
+}
+
+/// Like `for_each_message`, but stops at the first error `f` returns.
+pub fn try_for_each_message<F, E>(file: &str, f: F) -> Result<(), TryForEachError<E>>
+    where F: FnMut(&IexDeepMessage) -> Result<(), E> {
+    let messages = messages_from_capture(file).map_err(TryForEachError::Load)?;
+    try_for_each_decoded(messages, f).map_err(TryForEachError::Handler)
+}
+
+/// Which timestamp of each packet `Replay` reproduces the spacing of.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ReplayClock {
+    /// When the packet was captured, so network jitter is replayed too.
+    Capture,
+    /// The send time in the IEX-TP header.
+    SendTime,
+}
+
+/// Replays packets with the spacing they were captured or sent with, scaled by a speed, turning a
+/// capture into a synthetic live feed.
+///
+/// Each packet is due its offset from the first packet, divided by the speed, after the replay
+/// started. Scheduling against the start instead of the previous packet keeps late wakeups from
+/// adding up, but each sleep can still overshoot by the OS timer slack and scheduling latency:
+/// tens of microseconds on an idle Linux box, a millisecond or more elsewhere or under load.
+/// Packets closer together than that come out in bursts. They're never reordered; a packet
+/// timestamped before the first one, as can happen across channels, is passed on right away.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Replay {
+    speed: f64,
+    clock: ReplayClock,
+}
+
+impl Default for Replay {
+    fn default() -> Replay {
+        Replay {
+            speed: 1.0,
+            clock: ReplayClock::Capture,
+        }
+    }
+}
+
+impl Replay {
+    /// How many times faster than real time to replay. Panics unless it's positive and finite.
+    pub fn with_speed(mut self, speed: f64) -> Replay {
+        assert!(speed > 0.0 && speed.is_finite(), "replay speed must be positive, not {}", speed);
+        self.speed = speed;
+        self
+    }
+
+    pub fn with_clock(mut self, clock: ReplayClock) -> Replay {
+        self.clock = clock;
+        self
+    }
+
+    /// Passes the messages of each packet to `f` once the packet is due. Packets that fail to
+    /// decode are logged and skipped. Returns the number of packets replayed.
+    pub fn run<I, F>(&self, packets: I, mut f: F) -> u64
+        where I: Iterator<Item = Result<DeepPacket, ParseError>>,
+              F: FnMut(&IexDeepMessage) {
+        let mut origin = None;
+        let mut replayed = 0;
+        for packet in packets {
+            let packet = match packet {
+                Ok(packet) => packet,
+                Err(e) => {
+                    warn!("Skipping undecodable packet: {:?}", e);
+                    continue;
+                },
+            };
+            let timestamp = match self.clock {
+                ReplayClock::Capture => packet.capture_timestamp_ns,
+                ReplayClock::SendTime => packet.header.send_time,
+            };
+            let (first, start) = *origin.get_or_insert((timestamp, std::time::Instant::now()));
+            if timestamp > first {
+                let due = start + (timestamp - first).div_f64(self.speed);
+                let now = std::time::Instant::now();
+                if due > now {
+                    std::thread::sleep(due - now);
+                }
+            }
+            for message in &packet.messages {
+                f(message);
+            }
+            replayed += 1;
+        }
+        replayed
+    }
+}
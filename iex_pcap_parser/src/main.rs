@@ -1,984 +1,4217 @@
-extern crate etherparse;
-extern crate flate2;
+extern crate chrono;
+extern crate ctrlc;
 extern crate hdf5;
+extern crate iex_pcap_parser;
+extern crate indicatif;
 extern crate libdt;
+extern crate libfs;
 extern crate libh5;
 extern crate libiex;
+#[cfg(feature = "native-pcap")]
 extern crate pcap;
 extern crate pretty_env_logger;
 
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
 use std::env;
-use std::ffi;
+use std::fmt;
 use std::fs;
 use std::io;
-use std::io::{Read, Write};
+use std::io::{IsTerminal, Write};
 use std::path;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time;
+
+use iex_pcap_parser::ParseError;
+use log::{debug, error, info, warn};
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{BTreeSet, HashMap, HashSet};
+
+    fn tick(timestamp: u64) -> libh5::Tick {
+        libh5::Tick {
+            message_type: b'T',
+            message_subtype: 0,
+            timestamp,
+            size: 100,
+            price: 1234500,
+            price_multiplier: 10000,
+            packet_number: 0,
+            message_sequence_number: timestamp,
+            capture_timestamp: timestamp,
+        }
+    }
+
+    fn padded(symbol: &str) -> iex_pcap_parser::MessageSymbol {
+        let mut padded = [' '; 8];
+        for (c, s) in padded.iter_mut().zip(symbol.chars()) {
+            *c = s;
+        }
+        padded
+    }
+
+    fn message(message_type: u8, body: iex_pcap_parser::IexDeepMessageImpl) -> iex_pcap_parser::IexDeepMessage {
+        iex_pcap_parser::IexDeepMessage {
+            message_type,
+            message_subtype: 0,
+            timestamp: 0,
+            body,
+            packet_number: 0,
+            channel_id: 0,
+            message_sequence_number: 0,
+            capture_timestamp_ns: libdt::UtcNs::default(),
+        }
+    }
+
+    fn trade(symbol: &str, trade_id: u64) -> iex_pcap_parser::IexDeepMessage {
+        message(b'T', iex_pcap_parser::IexDeepMessageImpl::TradeReport(iex_pcap_parser::TradeReportMessage {
+            symbol: padded(symbol),
+            size: 100,
+            price: 1234500,
+            trade_id,
+            sale_condition_flags: 0,
+        }))
+    }
+
+    fn packet(stream_offset: u64, messages: Vec<iex_pcap_parser::IexDeepMessage>) -> iex_pcap_parser::DeepPacket {
+        iex_pcap_parser::DeepPacket {
+            header: iex_pcap_parser::IexTpHeader {
+                version: 1,
+                reserved: 0,
+                message_protocol_id: iex_pcap_parser::DEEP_MESSAGE_PROTOCOL_ID,
+                channel_id: 1,
+                session_id: 1,
+                payload_length: 0,
+                message_count: messages.len() as u16,
+                stream_offset,
+                first_message_sequence_number: 1,
+                send_time: libdt::UtcNs::default(),
+            },
+            next_message_sequence_number: 1 + messages.len() as u64,
+            messages,
+            errors: Vec::new(),
+            raw_failures: Vec::new(),
+            capture_len: 0,
+            capture_timestamp_ns: libdt::UtcNs::default(),
+            filtered_out: false,
+        }
+    }
+
+    #[test]
+    fn test_split_by_symbol() {
+        let mut dir = std::env::temp_dir();
+        dir.push(format!("iex_pcap_parser_split_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let mut stonks_ticks = HashMap::new();
+        stonks_ticks.insert("AAPL    ".to_string(), vec![tick(1)]);
+        // At the open, since bars start from the local midnight before a tick.
+        let open = 1_562_160_600_000_000_000;
+        stonks_ticks.insert("SPY     ".to_string(), vec![tick(open + 2), tick(open + 3)]);
+        let mut official_prices = HashMap::new();
+        official_prices.insert("SPY     ".to_string(), vec![libh5::Tick { message_type: libh5::OFFICIAL_PRICE, ..tick(4) }]);
+        let trade_date = chrono::NaiveDate::from_ymd_opt(2019, 7, 3).unwrap();
+        let sources = strings(&["20190703_IEXTP1_DEEP1.0.pcap.gz"]);
+        let output = crate::OutputOptions {
+            write_options: &libh5::WriteOptions::default(),
+            sorted: false,
+            index_by_time: false,
+            interrupted: false,
+            channel_id: None,
+            bars: Some(std::time::Duration::from_secs(60)),
+            sources: &sources,
+        };
+        let mut paths = crate::write_split_by_symbol(&dir, &trade_date, &stonks_ticks, &official_prices,
+                                                     &HashMap::new(), &output);
+        paths.sort();
+
+        assert_eq!(paths, vec![dir.join("20190703_AAPL.h5"), dir.join("20190703_SPY.h5")]);
+        let spy = paths[1].to_str().unwrap();
+        assert_eq!(libfs::symbol_and_date_from_h5(spy), Ok(("SPY".to_string(), trade_date)));
+        assert_eq!(libh5::load_ticks_from_file("SPY     ", spy).len(), 2);
+        let file = hdf5::File::open(spy, "r").unwrap();
+        assert_eq!(libh5::read_official_prices(&file, "SPY     ").unwrap().len(), 1);
+        let (bars, interval_ns) = libh5::read_bars(&file, "SPY     ").unwrap().unwrap();
+        assert_eq!((bars.len(), bars[0].trade_count, interval_ns), (1, 2, 60_000_000_000));
+        assert_eq!(libh5::read_source_files(&file).unwrap(), vec!["20190703_IEXTP1_DEEP1.0.pcap.gz"]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_remove_test_securities() {
+        let directory = |symbol: &str, flags| iex_pcap_parser::IexDeepMessage {
+            message_type: b'D',
+            message_subtype: flags,
+            timestamp: 0,
+            body: iex_pcap_parser::IexDeepMessageImpl::SecurityDirectory(
+                iex_pcap_parser::SecurityDirectoryMessage {
+                    symbol: {
+                        let mut padded = [' '; 8];
+                        for (c, s) in padded.iter_mut().zip(symbol.chars()) {
+                            *c = s;
+                        }
+                        padded
+                    },
+                    round_lot_size: 100,
+                    adjusted_poc_price: 0,
+                    luld_tier: iex_pcap_parser::LimitUpLimitDownTier::NotApplicable,
+                    flags: iex_pcap_parser::SecurityDirectoryFlags::from_bits_truncate(flags),
+                }),
+            packet_number: 0,
+            channel_id: 0,
+            message_sequence_number: 0,
+            capture_timestamp_ns: libdt::UtcNs::default(),
+        };
+        let padded = iex_pcap_parser::SymbolNormalizer::default().with_padding(true);
+        assert_eq!(crate::test_security_symbol(&directory("ZIEXT", 0x80), &padded), Some("ZIEXT   ".to_string()));
+        assert_eq!(crate::test_security_symbol(&directory("ZIEXT", 0x80), &padded.with_case(iex_pcap_parser::SymbolCase::Lower)),
+                   Some("ziext   ".to_string()));
+        assert_eq!(crate::test_security_symbol(&directory("AAPL", 0x0), &padded), None);
+
+        // Ticks that arrived before the directory message are dropped too.
+        let mut stonks_ticks = HashMap::new();
+        stonks_ticks.insert("AAPL    ".to_string(), vec![tick(1)]);
+        stonks_ticks.insert("ZIEXT   ".to_string(), vec![tick(2), tick(3)]);
+        let test_symbols: HashSet<String> = vec!["ZIEXT   ".to_string()].into_iter().collect();
+        assert_eq!(crate::remove_test_securities(&mut stonks_ticks, &test_symbols), 2);
+        assert_eq!(stonks_ticks.keys().collect::<Vec<_>>(), vec!["AAPL    "]);
+    }
+
+    #[test]
+    fn test_export_csv() {
+        let mut dir = std::env::temp_dir();
+        dir.push(format!("iex_pcap_parser_export_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let h5 = dir.join("20190703.h5").to_str().unwrap().to_string();
+        {
+            let file = hdf5::File::open(&h5, "w").unwrap();
+            libh5::append_ticks(&file, "AAPL    ", &[tick(1), tick(2)]).unwrap();
+            libh5::append_ticks(&file, "SPY     ", &[tick(3)]).unwrap();
+        }
+
+        let args = crate::parse_export_args(&[
+            "--in".to_string(), h5,
+            "--symbol".to_string(), "AAPL".to_string(),
+            "--out".to_string(), dir.join("aapl.csv").to_str().unwrap().to_string(),
+        ]).unwrap();
+        let mut stdout = Vec::new();
+        assert_eq!(crate::export_csv(&args, &mut stdout).unwrap(), 2);
+        assert!(stdout.is_empty());
+        let csv = std::fs::read_to_string(&args.output).unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0], iex_pcap_parser::csv::HEADER);
+        assert!(lines[1].starts_with("1970-01-01T00:00:00.000000001Z,1,T,0,123.4500,100,"));
+
+        // Piped, the same CSV goes to stdout.
+        let piped = crate::ExportArgs { output: "-".to_string(), ..args };
+        assert_eq!(crate::export_csv(&piped, &mut stdout).unwrap(), 2);
+        assert_eq!(String::from_utf8(stdout).unwrap(), csv);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_diff_h5() {
+        let mut dir = std::env::temp_dir();
+        dir.push(format!("iex_pcap_parser_diff_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = |name: &str| dir.join(name).to_str().unwrap().to_string();
+        let write = |name: &str, symbols: &[(&str, Vec<libh5::Tick>)]| {
+            let file = hdf5::File::open(path(name), "w").unwrap();
+            for (symbol, ticks) in symbols {
+                libh5::append_ticks(&file, symbol, ticks).unwrap();
+            }
+        };
+        write("old.h5", &[("AAPL    ", vec![tick(1), tick(2)]), ("SPY     ", vec![tick(3)])]);
+        write("same.h5", &[("AAPL    ", vec![tick(1), tick(2)]), ("SPY     ", vec![tick(3)])]);
+        write("new.h5", &[("AAPL    ", vec![tick(1), libh5::Tick { price: 1234600, ..tick(2) }]),
+                          ("SPY     ", vec![tick(3), tick(4)]), ("MSFT    ", vec![tick(5)])]);
+
+        let diff = |b: &str, extra: &[&str]| {
+            let mut args = strings(&["--a", &path("old.h5"), "--b", &path(b)]);
+            args.extend(strings(extra));
+            crate::diff_h5(&crate::parse_diff_args(&args).unwrap()).unwrap()
+        };
+        assert_eq!(diff("same.h5", &["--deep"]).count, 0);
+        let shallow = diff("new.h5", &[]);
+        assert_eq!(shallow.reported, vec![
+            format!("MSFT is only in {}", path("new.h5")),
+            format!("SPY has 1 ticks in {} but 2 in {}", path("old.h5"), path("new.h5")),
+        ]);
+        let deep = diff("new.h5", &["--deep"]);
+        assert_eq!(deep.count, 3);
+        assert_eq!(deep.reported[1], "AAPL tick 1: price 1234500 != 1234600");
+        let limited = diff("new.h5", &["--deep", "--limit", "1"]);
+        assert_eq!((limited.count, limited.reported.len()), (3, 1));
+        assert!(crate::parse_diff_args(&strings(&["--a", "old.h5"])).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_h5_info() {
+        let mut dir = std::env::temp_dir();
+        dir.push(format!("iex_pcap_parser_info_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let h5 = dir.join("20190703.h5").to_str().unwrap().to_string();
+        {
+            let file = hdf5::File::open(&h5, "w").unwrap();
+            libh5::write_versions(&file, "0.1.0", &libh5::WriteOptions::default()).unwrap();
+            libh5::append_ticks(&file, "SPY     ", &[tick(3)]).unwrap();
+            libh5::append_ticks(&file, "AAPL    ", &[tick(1), tick(2)]).unwrap();
+            libh5::write_official_prices(&file, "AAPL    ", &[tick(4)]).unwrap();
+        }
+
+        let args = crate::parse_info_args(&strings(&["--in", &h5])).unwrap();
+        let lines = crate::h5_info(&args).unwrap();
+        assert_eq!(lines[0], format!("{}: schema version 1, written by 0.1.0", h5));
+        let symbols: Vec<&str> = lines[1..].iter().map(|line| line.split(':').next().unwrap()).collect();
+        assert_eq!(symbols, vec!["AAPL", "SPY"]);
+        assert_eq!(lines[1], "AAPL: 2 ticks, 1970-01-01T00:00:00.000000001Z to 1970-01-01T00:00:00.000000002Z, \
+                              price multiplier 10000");
 
-use flate2::read::GzDecoder;
-use log::{trace, info, warn};
-use pcap::Capture;
-
-/// 40 bytes
-struct IexTpHeader {
-    version: u8,
-    reserved: u8,
-    message_protocol_id: u16,
-    channel_id: u32,
-    session_id: u32,
-    payload_length: u16,
-    message_count: u16,
-    stream_offset: u64,
-    first_message_sequence_number: u64,
-    send_time: libdt::UtcNs,
-}
-
-macro_rules! _index_offset {
-    ( $arr:expr, $offset:expr, $type:ty, $index:expr ) => {
         {
-            (($arr[$offset + $index] as $type) << (8*($index)))
+            let file = hdf5::File::open(&h5, "r+").unwrap();
+            libh5::write_attr(&file, libh5::SCHEMA_VERSION_ATTR, &99u32).unwrap();
+        }
+        assert!(crate::h5_info(&args).is_err());
+        assert!(crate::parse_info_args(&strings(&["--in"])).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_verify_h5() {
+        let h5 = std::env::temp_dir().join(format!("iex_pcap_parser_verify_{}.h5", std::process::id()));
+        let config = crate::parse_args(&strings(&["iex_pcap_parser", "--checksum", "day.pcap"])).unwrap();
+        assert!(config.write_options.checksum);
+        {
+            let file = crate::open_output(&h5, &config.write_options, false);
+            crate::write_dataset(&file, "AAPL    ", &[tick(1), tick(2)], &config.write_options, false, false);
+        }
+
+        let args = crate::parse_verify_args(&strings(&["--in", h5.to_str().unwrap()])).unwrap();
+        let report = crate::verify_h5(&args).unwrap();
+        assert_eq!((report.datasets, report.checksummed), (1, 1));
+        assert!(report.corrupt.is_empty());
+        assert!(crate::parse_verify_args(&strings(&["--in"])).is_err());
+        std::fs::remove_file(&h5).unwrap();
+    }
+
+    #[test]
+    fn test_tick_field_diffs() {
+        assert!(crate::tick_field_diffs(&tick(1), &tick(1)).is_empty());
+        let other = libh5::Tick { size: 200, price_multiplier: 100, ..tick(1) };
+        assert_eq!(crate::tick_field_diffs(&tick(1), &other), vec!["size 100 != 200", "price_multiplier 10000 != 100"]);
+    }
+
+    fn strings(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn test_merge() {
+        let mut dir = std::env::temp_dir();
+        dir.push(format!("iex_pcap_parser_merge_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = |name: &str| dir.join(name).to_str().unwrap().to_string();
+        for (name, symbol, ticks, sources) in vec![
+            ("20190703_AAPL.h5", "AAPL    ", vec![tick(1), tick(2)], vec!["1.pcap"]),
+            ("20190703_SPY.h5", "SPY     ", vec![tick(3)], vec!["1.pcap", "2.pcap"]),
+        ] {
+            let file = hdf5::File::open(path(name), "w").unwrap();
+            libh5::append_ticks(&file, symbol, &ticks).unwrap();
+            libh5::write_source_files(&file, &sources).unwrap();
+        }
+        {
+            let file = hdf5::File::open(path("20190703_AAPL.h5"), "r+").unwrap();
+            libh5::write_attr(&libh5::tick_dataset(&file, "AAPL    ").unwrap(), libh5::SORTED_ATTR, &true).unwrap();
+            libh5::write_time_index(&file, "AAPL    ", &[tick(1), tick(2)]).unwrap();
+            libh5::write_official_prices(&file, "AAPL    ", &[tick(4)]).unwrap();
+            libh5::write_untraded_symbols(&file, &["IWM     ", "SPY     "]).unwrap();
+        }
+
+        let args = crate::parse_merge_args(&strings(&[
+            "--out", &path("20190703.h5"), &path("20190703_AAPL.h5"), &path("20190703_SPY.h5"),
+        ])).unwrap();
+        assert_eq!(crate::merge_h5(&args).unwrap(), 2);
+        assert_eq!(libh5::load_symbol_index(&args.output).unwrap(),
+                   vec![("AAPL    ".to_string(), 2), ("SPY     ".to_string(), 1)]);
+        let output = hdf5::File::open(&args.output, "r").unwrap();
+        assert_eq!(libh5::read_attr::<u32>(&output, libh5::SCHEMA_VERSION_ATTR).unwrap(), Some(libh5::SCHEMA_VERSION));
+        assert_eq!(libh5::read_source_files(&output).unwrap(), vec!["1.pcap", "2.pcap"]);
+        assert_eq!(libh5::group_symbols(&output, libh5::TIME_INDEX_GROUP).unwrap(), vec!["AAPL    "]);
+        assert!(libh5::read_official_prices(&output, "AAPL    ").unwrap() == vec![tick(4)]);
+        // SPY traded in its own input.
+        assert_eq!(libh5::read_untraded_symbols(&output).unwrap(), vec!["IWM     "]);
+
+        // The same symbol twice only merges with --concat.
+        let args = crate::parse_merge_args(&strings(&[
+            "--out", &path("20190703_twice.h5"), &path("20190703_SPY.h5"), &path("20190703_SPY.h5"),
+        ])).unwrap();
+        assert!(crate::merge_h5(&args).is_err());
+        assert!(!std::path::Path::new(&args.output).exists());
+        let concat = crate::MergeArgs { concat: true, ..args };
+        assert_eq!(crate::merge_h5(&concat).unwrap(), 1);
+        // An existing output is only replaced with --overwrite.
+        assert!(crate::merge_h5(&concat).is_err());
+        let args = crate::parse_merge_args(&strings(&[
+            "--out", &path("20190703_twice.h5"), "--concat", "--overwrite", &path("20190703_SPY.h5"), &path("20190703_SPY.h5"),
+        ])).unwrap();
+        assert_eq!(crate::merge_h5(&args).unwrap(), 1);
+        assert_eq!(libh5::load_ticks_from_file("SPY     ", &args.output).len(), 2);
+
+        // Bars are made again from every input's ticks, even if only one input had bars.
+        let open = 1_562_160_600_000_000_000;
+        let minute = std::time::Duration::from_secs(60);
+        for (name, ticks) in [("20190703_QQQ.h5", [tick(open)]),
+                              ("20190703_QQQ2.h5", [tick(open + 60 * libdt::NS_PER_SEC)])] {
+            let file = hdf5::File::open(path(name), "w").unwrap();
+            libh5::append_ticks(&file, "QQQ     ", &ticks).unwrap();
+        }
+        {
+            let file = hdf5::File::open(path("20190703_QQQ.h5"), "r+").unwrap();
+            let bars = crate::symbol_bars(&[tick(open)], minute);
+            libh5::write_bars(&file, "QQQ     ", &bars, minute.as_nanos() as u64).unwrap();
+        }
+        let args = crate::parse_merge_args(&strings(&[
+            "--out", &path("20190703_qqq.h5"), "--concat", &path("20190703_QQQ.h5"), &path("20190703_QQQ2.h5"),
+        ])).unwrap();
+        assert_eq!(crate::merge_h5(&args).unwrap(), 1);
+        let output = hdf5::File::open(&args.output, "r").unwrap();
+        let (bars, interval_ns) = libh5::read_bars(&output, "QQQ     ").unwrap().unwrap();
+        assert_eq!(interval_ns, minute.as_nanos() as u64);
+        assert_eq!(bars.iter().map(|bar| bar.timestamp).collect::<Vec<_>>(), vec![open, open + 60 * libdt::NS_PER_SEC]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_collect() {
+        let directory = |symbol: &str| message(b'D', iex_pcap_parser::IexDeepMessageImpl::SecurityDirectory(
+            iex_pcap_parser::SecurityDirectoryMessage {
+                symbol: padded(symbol),
+                round_lot_size: 100,
+                adjusted_poc_price: 0,
+                luld_tier: iex_pcap_parser::LimitUpLimitDownTier::NotApplicable,
+                flags: iex_pcap_parser::SecurityDirectoryFlags::from_bits_truncate(0x80),
+            }));
+
+        let mut config = crate::ParserConfig::new(crate::Input::File("20190703.pcap".to_string()));
+        config.skip_test_securities = true;
+        let mut packets = vec![
+            Ok(packet(0, vec![trade("AAPL", 1), directory("ZIEXT")])),
+            Err(iex_pcap_parser::ParseError::UnsupportedVersion(2)),
+            Ok(packet(0, vec![trade("ZIEXT", 2), trade("AAPL", 3)])),
+        ].into_iter();
+        let running = std::sync::atomic::AtomicBool::new(true);
+        let collected = crate::collect(&config, &mut packets, &crate::TimeWindow::default(), &running, None, None);
+
+        assert_eq!(collected.packet_counter, 3);
+        assert_eq!(collected.tick_counter, 4);
+        assert_eq!(collected.tradeable_counter, 3);
+        assert_eq!(collected.stonks_ticks["AAPL    "].len(), 2);
+        assert_eq!(collected.stonks_ticks["ZIEXT   "].len(), 1);
+        assert!(collected.test_symbols.contains("ZIEXT   "));
+        assert_eq!(collected.channels, vec![(1, 1)].into_iter().collect::<BTreeSet<_>>());
+        assert_eq!(collected.parse_stats.packets_total, 3);
+        assert_eq!(collected.parse_stats.packets_skipped_non_iex, 1);
+        assert_eq!(collected.parse_stats.messages_parsed, 4);
+
+        // Auctions are only kept with --include-auctions.
+        let auction = || message(b'A', iex_pcap_parser::IexDeepMessageImpl::AuctionInformation(
+            iex_pcap_parser::AuctionInformationMessage {
+                symbol: padded("SPY"),
+                paired_shares: 1000,
+                reference_price: 2967500,
+                indicative_clearing_price: 2967000,
+                imbalance_shares: 200,
+                imbalance_side: iex_pcap_parser::ImbalanceSide::BuySideImbalance,
+                extension_number: 0,
+                scheduled_auction_time: 1_562_173_200,
+                auction_book_clearing_price: 2967000,
+                collar_reference_price: 2967500,
+                lower_auction_collar: 2670700,
+                upper_auction_collar: 3264200,
+                auction_type: iex_pcap_parser::AuctionType::Closing,
+                layout: iex_pcap_parser::AuctionLayout::Full,
+            }));
+        let mut config = crate::ParserConfig::new(crate::Input::File("20190703.pcap".to_string()));
+        for &include_auctions in &[false, true] {
+            config.include_auctions = include_auctions;
+            let collected = crate::collect(&config, &mut vec![Ok(packet(0, vec![auction()]))].into_iter(),
+                                           &crate::TimeWindow::default(), &running, None, None);
+            assert_eq!(collected.auctions.contains_key("SPY     "), include_auctions);
+        }
+
+        // Clearing the running flag stops before the next packet.
+        running.store(false, std::sync::atomic::Ordering::SeqCst);
+        let collected = crate::collect(&config, &mut vec![Ok(packet(0, vec![trade("AAPL", 4)]))].into_iter(),
+                                       &crate::TimeWindow::default(), &running, None, None);
+        assert_eq!(collected.packet_counter, 0);
+    }
+
+    #[test]
+    fn test_global_order() {
+        // The packet number tells which input each tick came from.
+        let at = |input, timestamp, message_sequence_number| libh5::Tick {
+            packet_number: input,
+            message_sequence_number,
+            ..tick(timestamp)
+        };
+        let channel_1 = (Some(1), vec![at(1, 10, 1), at(1, 20, 2), at(1, 20, 3)]);
+        let channel_2 = (Some(2), vec![at(2, 10, 1), at(2, 20, 1), at(2, 30, 2)]);
+        let unknown = (None, vec![at(0, 20, 1)]);
+        let order = |inputs: Vec<crate::InputTicks>| -> Vec<(u64, u64, u64)> {
+            crate::global_order(inputs).iter()
+                .map(|t| (t.timestamp, t.packet_number, t.message_sequence_number))
+                .collect()
+        };
+        let merged = order(vec![channel_1.clone(), channel_2.clone(), unknown.clone()]);
+        assert_eq!(merged, vec![(10, 1, 1), (10, 2, 1), (20, 1, 2), (20, 1, 3), (20, 2, 1), (20, 0, 1), (30, 2, 2)]);
+        assert_eq!(order(vec![unknown, channel_2, channel_1]), merged);
+    }
+
+    #[test]
+    fn test_single_channel() {
+        let channels: BTreeSet<(u32, u32)> = vec![(1, 42), (1, 43), (2, 42)].into_iter().collect();
+        let filter = |channel_id| iex_pcap_parser::ChannelFilter { channel_id, session_id: None };
+        assert_eq!(crate::single_channel(&channels, &filter(None)), None);
+        assert_eq!(crate::single_channel(&channels, &filter(Some(2))), Some(2));
+        let one: BTreeSet<(u32, u32)> = vec![(1, 42), (1, 43)].into_iter().collect();
+        assert_eq!(crate::single_channel(&one, &filter(None)), Some(1));
+    }
+
+    #[test]
+    fn test_merge_rejects_mixed_dates() {
+        let args = crate::parse_merge_args(&strings(&[
+            "--out", "out.h5", "20190703_AAPL.h5", "20190705_AAPL.h5",
+        ])).unwrap();
+        assert!(crate::merge_h5(&args).is_err());
+        assert!(crate::parse_merge_args(&strings(&["--out", "out.h5"])).is_err());
+        // h5 files can't be streamed.
+        assert!(crate::parse_merge_args(&strings(&["--out", "-", "20190703_AAPL.h5"])).is_err());
+    }
+
+    #[test]
+    fn test_combined_subcommands() {
+        let mut dir = std::env::temp_dir();
+        dir.push(format!("iex_pcap_parser_combined_{}", std::process::id()));
+        for sub in &["old", "new", "more", "split"] {
+            std::fs::create_dir_all(dir.join(sub)).unwrap();
+        }
+        let path = |name: &str| dir.join(name).to_str().unwrap().to_string();
+        let write = |name: &str, rows: &[(&str, libh5::Tick)]| {
+            let file = hdf5::File::open(path(name), "w").unwrap();
+            let rows: Vec<libh5::TickWithSymbol> = rows.iter()
+                .map(|(symbol, tick)| libh5::TickWithSymbol::new(symbol, tick).unwrap())
+                .collect();
+            libh5::write_combined_ticks(&file, &rows, &libh5::WriteOptions::default()).unwrap();
+        };
+        let (old, new, more) = (path("old/20190703.h5"), path("new/20190703.h5"), path("more/20190703.h5"));
+        write("old/20190703.h5", &[("AAPL    ", tick(1)), ("SPY     ", tick(2)), ("AAPL    ", tick(3))]);
+        write("new/20190703.h5", &[("AAPL    ", tick(1)), ("SPY     ", libh5::Tick { price: 1234600, ..tick(2) })]);
+        write("more/20190703.h5", &[("IWM     ", tick(4))]);
+        {
+            let file = hdf5::File::open(path("split/20190703.h5"), "w").unwrap();
+            libh5::append_ticks(&file, "AAPL    ", &[tick(1), tick(3)]).unwrap();
+            libh5::append_ticks(&file, "SPY     ", &[tick(2)]).unwrap();
+        }
+
+        let diff = |a: &str, b: &str| {
+            crate::diff_h5(&crate::parse_diff_args(&strings(&["--a", a, "--b", b, "--deep"])).unwrap()).unwrap()
+        };
+        assert_eq!(diff(&old, &new).reported, vec![
+            format!("AAPL has 2 ticks in {} but 1 in {}", old, new),
+            "SPY tick 0: price 1234500 != 1234600".to_string(),
+        ]);
+        // The same ticks combined or in a dataset per symbol.
+        assert_eq!(diff(&old, &path("split/20190703.h5")).count, 0);
+
+        let export = crate::ExportArgs { input: old.clone(), symbol: "AAPL".to_string(), output: "-".to_string() };
+        assert_eq!(crate::export_csv(&export, Vec::new()).unwrap(), 2);
+
+        let output = path("20190703.h5");
+        let merge = |inputs: &[&str]| {
+            let mut args = strings(&["--out", &output]);
+            args.extend(strings(inputs));
+            crate::merge_h5(&crate::parse_merge_args(&args).unwrap())
+        };
+        assert_eq!(merge(&[&old, &more]).unwrap(), 3);
+        {
+            let file = hdf5::File::open(&output, "r").unwrap();
+            let rows: Vec<(String, u64)> = libh5::read_combined_ticks(&file).unwrap().iter()
+                .map(|row| (row.symbol().to_string(), row.timestamp))
+                .collect();
+            assert_eq!(rows, vec![("AAPL    ".to_string(), 1), ("AAPL    ".to_string(), 3),
+                                  ("IWM     ".to_string(), 4), ("SPY     ".to_string(), 2)]);
+            assert!(!file.link_exists(libh5::SYMBOL_INDEX_DATASET));
+        }
+        let info = crate::h5_info(&crate::InfoArgs { input: output.clone() }).unwrap();
+        assert_eq!(info.len(), 4);
+        assert_eq!(info[1], "AAPL: 2 ticks, 1970-01-01T00:00:00.000000001Z to 1970-01-01T00:00:00.000000003Z, \
+                             price multiplier 10000");
+        std::fs::remove_file(&output).unwrap();
+
+        // Combined and per-symbol inputs aren't merged.
+        assert!(merge(&[&old, &path("split/20190703.h5")]).is_err());
+        assert!(!std::path::Path::new(&output).exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_ticks_off_trade_date() {
+        // Noon in New York on 2019-07-02 and 2019-07-03.
+        let july_2 = 1_562_083_200_000_000_000;
+        let july_3 = 1_562_169_600_000_000_000;
+        let trade_date = chrono::NaiveDate::from_ymd_opt(2019, 7, 3).unwrap();
+        let mut stonks_ticks = HashMap::new();
+        stonks_ticks.insert("AAPL".to_string(), vec![tick(july_3), tick(july_3 + 1)]);
+        stonks_ticks.insert("SPY".to_string(), vec![tick(july_2), tick(july_3), tick(july_2 + 1)]);
+        assert_eq!(crate::ticks_off_trade_date(&stonks_ticks, &trade_date),
+                   vec![("SPY".to_string(), 2, chrono::NaiveDate::from_ymd_opt(2019, 7, 2))]);
+
+        let args: Vec<String> = vec!["iex_pcap_parser", "--strict", "day.pcap"]
+            .into_iter().map(String::from).collect();
+        assert!(crate::parse_args(&args).unwrap().strict);
+    }
+
+    #[test]
+    fn test_timestamps_out_of_order() {
+        let sequenced = |message_sequence_number, timestamp| libh5::Tick { message_sequence_number, ..tick(timestamp) };
+        let mut stonks_ticks = HashMap::new();
+        stonks_ticks.insert("AAPL".to_string(), vec![sequenced(1, 100), sequenced(2, 100), sequenced(3, 101)]);
+        // Arrival order doesn't matter, only sequence number order.
+        stonks_ticks.insert("SPY".to_string(), vec![sequenced(5, 300), sequenced(4, 200), sequenced(6, 250),
+                                                    sequenced(7, 240), sequenced(8, 400)]);
+        assert_eq!(crate::timestamps_out_of_order(&stonks_ticks),
+                   vec![("SPY".to_string(), 6, 300, 250), ("SPY".to_string(), 7, 250, 240)]);
+
+        assert!(crate::parse_args(&strings(&["iex_pcap_parser", "--check-monotonic", "day.pcap"])).unwrap().check_monotonic);
+        assert!(crate::parse_args(&strings(&["iex_pcap_parser", "--check-monotonic", "--count-only", "day.pcap"])).is_err());
+        assert!(crate::parse_args(&strings(&["iex_pcap_parser", "--check-monotonic", "--live", "eth0"])).is_err());
+    }
+
+    #[test]
+    fn test_combined_ticks() {
+        let mut stonks_ticks = HashMap::new();
+        stonks_ticks.insert("SPY     ".to_string(), vec![tick(2), tick(5)]);
+        stonks_ticks.insert("AAPL    ".to_string(), vec![tick(3), tick(1)]);
+        let rows = |sorted| -> Vec<(String, u64)> {
+            crate::combined_ticks(&stonks_ticks, sorted).iter()
+                .map(|row| (row.symbol().trim_end().to_string(), row.to_tick().timestamp))
+                .collect()
+        };
+        let expected = |rows: &[(&str, u64)]| -> Vec<(String, u64)> {
+            rows.iter().map(|(symbol, timestamp)| (symbol.to_string(), *timestamp)).collect()
+        };
+        assert_eq!(rows(false), expected(&[("AAPL", 3), ("AAPL", 1), ("SPY", 2), ("SPY", 5)]));
+        assert_eq!(rows(true), expected(&[("AAPL", 1), ("SPY", 2), ("AAPL", 3), ("SPY", 5)]));
+
+        assert!(crate::parse_args(&strings(&["iex_pcap_parser", "--combined", "day.pcap"])).unwrap().combined);
+        for flag in &["--split-by-symbol", "--index-by-time", "--fixed-point", "--resume", "--count-only"] {
+            assert!(crate::parse_args(&strings(&["iex_pcap_parser", "--combined", flag, "day.pcap"])).is_err());
+        }
+    }
+
+    #[test]
+    fn test_flag_auctions_off_trade_date() {
+        let auction = |scheduled_auction_time| libh5::Auction {
+            timestamp: 1_562_173_140_000_000_000,
+            auction_type: b'C',
+            paired_shares: 0,
+            reference_price: 0,
+            indicative_clearing_price: 0,
+            imbalance_shares: 0,
+            imbalance_side: b'N',
+            extension_number: 0,
+            scheduled_auction_time,
+            scheduled_off_trade_date: false,
+            auction_book_clearing_price: 0,
+            collar_reference_price: 0,
+            lower_auction_collar: 0,
+            upper_auction_collar: 0,
+            price_multiplier: 10000,
+            packet_number: 0,
+            message_sequence_number: 0,
+        };
+        // 13:00 in New York on 2019-07-03, and a day later.
+        let close = 1_562_173_200_000_000_000;
+        let mut auctions = HashMap::new();
+        auctions.insert("SPY".to_string(), vec![auction(close), auction(close + libdt::NS_PER_DAY)]);
+        let trade_date = chrono::NaiveDate::from_ymd_opt(2019, 7, 3).unwrap();
+        assert_eq!(crate::flag_auctions_off_trade_date(&mut auctions, &trade_date), 1);
+        let flags: Vec<bool> = auctions["SPY"].iter().map(|a| a.scheduled_off_trade_date).collect();
+        assert_eq!(flags, vec![false, true]);
+    }
+
+    #[test]
+    fn test_tick_yield() {
+        let tick_yield = crate::TickYield { written: 45, tradeable: 50, messages: 200 };
+        assert_eq!(tick_yield.tradeable_percent(), 25.0);
+        assert_eq!(tick_yield.written_percent(), 90.0);
+        assert_eq!(tick_yield.to_string(), "wrote 45 ticks from 50 tradeable messages (25.00% of all 200 messages)");
+        let empty = crate::TickYield { written: 0, tradeable: 0, messages: 0 };
+        assert_eq!(empty.tradeable_percent(), 0.0);
+        assert_eq!(empty.written_percent(), 0.0);
+    }
+
+    #[test]
+    fn test_remove_unwritable_symbols() {
+        let mut stonks_ticks = HashMap::new();
+        for symbol in &["AAPL    ", "AAPL", "        ", "A/B     ", "_symbol_index", "SPY     "] {
+            stonks_ticks.insert(symbol.to_string(), vec![tick(1), tick(2)]);
+        }
+        let mut dataset_names = HashMap::new();
+        let removed = crate::remove_unwritable_symbols(&mut stonks_ticks, &mut dataset_names);
+        assert_eq!(removed, vec![
+            ("        ".to_string(), 2),
+            ("A/B     ".to_string(), 2),
+            ("AAPL    ".to_string(), 2),
+            ("_symbol_index".to_string(), 2),
+        ]);
+        let mut kept: Vec<&String> = stonks_ticks.keys().collect();
+        kept.sort();
+        assert_eq!(kept, vec!["AAPL", "SPY     "]);
+
+        // Later flushes keep the symbols kept before and drop ones that clash with them.
+        stonks_ticks.insert("SPY     ".to_string(), vec![tick(3)]);
+        stonks_ticks.insert("SPY".to_string(), vec![tick(3)]);
+        let removed = crate::remove_unwritable_symbols(&mut stonks_ticks, &mut dataset_names);
+        assert_eq!(removed, vec![("SPY".to_string(), 1)]);
+    }
+
+    #[test]
+    fn test_trade_breaks() {
+        let mut stonks_ticks = HashMap::new();
+        stonks_ticks.insert("AAPL".to_string(), vec![tick(1), tick(2), tick(3)]);
+        stonks_ticks.insert("SPY".to_string(), vec![tick(4)]);
+        let mut trade_breaks = crate::TradeBreaks::default();
+        trade_breaks.record_trade(100, "AAPL", 0);
+        trade_breaks.record_trade(101, "AAPL", 1);
+        trade_breaks.record_trade(102, "AAPL", 2);
+        trade_breaks.record_trade(103, "SPY", 0);
+        assert!(trade_breaks.record_break(101));
+        assert!(trade_breaks.record_break(103));
+        assert!(!trade_breaks.record_break(999));
+        assert_eq!(trade_breaks.remove_broken(&mut stonks_ticks), 2);
+        let aapl: Vec<u64> = stonks_ticks["AAPL"].iter().map(|t| t.timestamp).collect();
+        assert_eq!(aapl, vec![1, 3]);
+        assert!(stonks_ticks["SPY"].is_empty());
+
+        assert!(crate::parse_args(&strings(&["iex_pcap_parser", "--apply-breaks", "--live", "eth0"])).is_err());
+    }
+
+    #[test]
+    fn test_split_by_symbol_args() {
+        let args: Vec<String> = vec!["iex_pcap_parser", "--split-by-symbol", "day.pcap"]
+            .into_iter().map(String::from).collect();
+        assert!(crate::parse_args(&args).unwrap().split_by_symbol);
+
+        let args: Vec<String> = vec!["iex_pcap_parser", "--split-by-symbol", "--live", "eth0"]
+            .into_iter().map(String::from).collect();
+        assert!(crate::parse_args(&args).is_err());
+    }
+
+    #[test]
+    fn test_symbol_normalizer_args() {
+        let normalizer = |flags: &[&str]| {
+            let mut args = vec!["iex_pcap_parser"];
+            args.extend_from_slice(flags);
+            args.push("day.pcap");
+            crate::parse_args(&strings(&args)).map(|args| args.symbol_normalizer)
+        };
+        let padded = iex_pcap_parser::SymbolNormalizer::default().with_padding(true);
+        assert_eq!(normalizer(&[]), Ok(padded));
+        assert_eq!(normalizer(&["--symbol-case", "lower", "--class-separator", "-"]),
+                   Ok(padded.with_case(iex_pcap_parser::SymbolCase::Lower).with_class_separator('-')));
+        assert!(normalizer(&["--symbol-case", "title"]).is_err());
+        assert!(normalizer(&["--class-separator", "--"]).is_err());
+        assert!(normalizer(&["--class-separator", "/"]).is_err());
+    }
+
+    #[test]
+    fn test_symbol_collisions() {
+        let aapl = ['A', 'A', 'P', 'L', ' ', ' ', ' ', ' '];
+        // Corrupt padding that trims all the same.
+        let tabbed = ['A', 'A', 'P', 'L', '\t', ' ', ' ', ' '];
+        let padded = iex_pcap_parser::SymbolNormalizer::default().with_padding(true);
+
+        let mut names = crate::SymbolNames::default();
+        assert_eq!(names.name(&aapl, &padded, crate::SymbolCollisions::Merge), "AAPL    ");
+        assert_eq!(names.name(&tabbed, &padded, crate::SymbolCollisions::Merge), "AAPL    ");
+        assert_eq!(names.name(&tabbed, &padded, crate::SymbolCollisions::Merge), "AAPL    ");
+        assert_eq!(names.collisions, vec![("AAPL\t   ".to_string(), "AAPL    ".to_string())]);
+
+        let mut names = crate::SymbolNames::default();
+        assert_eq!(names.name(&aapl, &padded, crate::SymbolCollisions::Suffix), "AAPL    ");
+        assert_eq!(names.name(&tabbed, &padded, crate::SymbolCollisions::Suffix), "AAPL~2  ");
+        assert_eq!(names.name(&aapl, &padded, crate::SymbolCollisions::Suffix), "AAPL    ");
+        assert_eq!(names.collisions.len(), 1);
+        let mut names = crate::SymbolNames::default();
+        names.name(&aapl, &iex_pcap_parser::SymbolNormalizer::default(), crate::SymbolCollisions::Suffix);
+        assert_eq!(names.name(&tabbed, &iex_pcap_parser::SymbolNormalizer::default(), crate::SymbolCollisions::Suffix), "AAPL~2");
+
+        // Full-width symbols lose their end to the suffix, without taking another symbol's name.
+        let upper = iex_pcap_parser::SymbolNormalizer::default().with_padding(true)
+            .with_case(iex_pcap_parser::SymbolCase::Upper);
+        let mut names = crate::SymbolNames::default();
+        for (symbol, name) in &[("ABCDEF~2", "ABCDEF~2"), ("ABCDEFGH", "ABCDEFGH"), ("abcdefgh", "ABCDEF~3"),
+                                ("AbcdefgH", "ABCDEF~4")] {
+            assert_eq!(names.name(&self::padded(symbol), &upper, crate::SymbolCollisions::Suffix), *name);
+        }
+
+        let collisions = |flags: &[&str]| {
+            let mut args = vec!["iex_pcap_parser"];
+            args.extend_from_slice(flags);
+            args.push("day.pcap");
+            crate::parse_args(&strings(&args)).map(|args| args.symbol_collisions)
+        };
+        assert_eq!(collisions(&[]), Ok(crate::SymbolCollisions::Merge));
+        assert_eq!(collisions(&["--symbol-collisions", "suffix"]), Ok(crate::SymbolCollisions::Suffix));
+        assert!(collisions(&["--symbol-collisions", "panic"]).is_err());
+    }
+
+    #[test]
+    fn test_capture_raw_args() {
+        assert!(!crate::parse_args(&strings(&["iex_pcap_parser", "day.pcap"])).unwrap().capture_raw);
+        assert!(crate::parse_args(&strings(&["iex_pcap_parser", "--capture-raw", "day.pcap"])).unwrap().capture_raw);
+        assert!(crate::parse_args(&strings(&["iex_pcap_parser", "--capture-raw", "--live", "eth0"])).unwrap().capture_raw);
+        assert!(crate::parse_args(&strings(&["iex_pcap_parser", "--capture-raw", "--split-by-symbol",
+                                             "day.pcap"])).is_err());
+    }
+
+    #[test]
+    fn test_bars_args() {
+        let bars = |interval| crate::parse_args(&strings(&["iex_pcap_parser", "--bars", interval, "day.pcap"]))
+            .map(|args| args.bars);
+        assert_eq!(crate::parse_args(&strings(&["iex_pcap_parser", "day.pcap"])).unwrap().bars, None);
+        assert_eq!(bars("1m"), Ok(Some(std::time::Duration::from_secs(60))));
+        assert_eq!(bars("30s"), Ok(Some(std::time::Duration::from_secs(30))));
+        assert_eq!(bars("4h"), Ok(Some(std::time::Duration::from_secs(4 * 3600))));
+        // Intervals that don't divide a day would start at a different time each day.
+        assert!(bars("7h").is_err());
+        assert!(bars("0m").is_err());
+        assert!(bars("1d").is_err());
+        assert!(bars("m").is_err());
+        assert!(crate::parse_args(&strings(&["iex_pcap_parser", "--bars", "1m", "--live", "eth0"])).is_err());
+    }
+
+    #[test]
+    fn test_symbol_vwaps() {
+        assert!(!crate::parse_args(&strings(&["iex_pcap_parser", "day.pcap"])).unwrap().vwap);
+        assert!(crate::parse_args(&strings(&["iex_pcap_parser", "--vwap", "day.pcap"])).unwrap().vwap);
+        assert!(crate::parse_args(&strings(&["iex_pcap_parser", "--vwap", "--live", "eth0"])).is_err());
+
+        let mut stonks_ticks = HashMap::new();
+        stonks_ticks.insert("SPY     ".to_string(), vec![tick(1), libh5::Tick { price: 1244500, size: 300, ..tick(2) }]);
+        stonks_ticks.insert("AAPL    ".to_string(), vec![tick(3)]);
+        // Only quotes, so no VWAP.
+        stonks_ticks.insert("IBM     ".to_string(), vec![libh5::Tick { message_type: b'8', ..tick(4) }]);
+        let vwaps = crate::symbol_vwaps(&stonks_ticks);
+        assert_eq!(vwaps.iter().map(|(symbol, _)| symbol.as_str()).collect::<Vec<_>>(), vec!["AAPL    ", "SPY     "]);
+        assert_eq!(vwaps[1].1.volume, 400);
+        assert!((vwaps[1].1.vwap.unwrap() - 124.2).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_chunk_size_args() {
+        let args = crate::parse_args(&strings(&["iex_pcap_parser", "day.pcap"])).unwrap();
+        assert_eq!(args.write_options, libh5::WriteOptions::default());
+        let args = crate::parse_args(&strings(&["iex_pcap_parser", "--chunk-size", "512", "day.pcap"])).unwrap();
+        assert_eq!(args.write_options, libh5::WriteOptions { chunk_size: 512, layout: libh5::Layout::Chunked, ..Default::default() });
+        let args = crate::parse_args(&strings(&["iex_pcap_parser", "--fixed-point", "day.pcap"])).unwrap();
+        assert!(args.write_options.fixed_point);
+        let args = crate::parse_args(&strings(&["iex_pcap_parser", "--group-symbols", "2", "day.pcap"])).unwrap();
+        assert_eq!(args.write_options.symbol_grouping, libh5::SymbolGrouping::FirstTwoLetters);
+        for letters in &["0", "3", "a"] {
+            assert!(crate::parse_args(&strings(&["iex_pcap_parser", "--group-symbols", letters, "day.pcap"])).is_err());
+        }
+        assert!(crate::parse_args(&strings(&["iex_pcap_parser", "--chunk-size", "0", "day.pcap"])).is_err());
+        assert!(crate::parse_args(&strings(&["iex_pcap_parser", "--chunk-size", "day.pcap"])).is_err());
+    }
+
+    #[test]
+    fn test_channel_args() {
+        let args = crate::parse_args(&strings(&["iex_pcap_parser", "--channel", "1", "--session", "42", "day.pcap"])).unwrap();
+        assert_eq!(args.filter, iex_pcap_parser::ChannelFilter { channel_id: Some(1), session_id: Some(42) });
+        let args = crate::parse_args(&strings(&["iex_pcap_parser", "day.pcap"])).unwrap();
+        assert_eq!(args.filter, iex_pcap_parser::ChannelFilter::default());
+        assert!(crate::parse_args(&strings(&["iex_pcap_parser", "--channel", "x", "day.pcap"])).is_err());
+        assert!(crate::parse_args(&strings(&["iex_pcap_parser", "--trades-only", "day.pcap"])).unwrap().trades_only);
+        assert!(crate::parse_args(&strings(&["iex_pcap_parser", "--trades-only", "--skip-test-securities", "day.pcap"])).is_err());
+        assert!(!crate::parse_args(&strings(&["iex_pcap_parser", "day.pcap"])).unwrap().include_auctions);
+        assert!(crate::parse_args(&strings(&["iex_pcap_parser", "--include-auctions", "day.pcap"])).unwrap().include_auctions);
+        assert!(crate::parse_args(&strings(&["iex_pcap_parser", "--trades-only", "--include-auctions", "day.pcap"])).is_err());
+    }
+
+    #[test]
+    fn test_time_window() {
+        let trade_date = chrono::NaiveDate::from_ymd_opt(2019, 3, 10).unwrap();
+        let args = crate::parse_args(&strings(&["iex_pcap_parser", "--start", "01:30", "--end", "03:30", "day.pcap"])).unwrap();
+        assert_eq!(args.dst, libdt::DstPolicy::Reject);
+        let window = crate::TimeWindow::new(args.start, args.end, &trade_date, args.dst).unwrap();
+        let (start, end) = (window.start.unwrap(), window.end.unwrap());
+        // 01:30 EST, whatever the host's time zone.
+        assert_eq!(start, libdt::UtcNs::from_secs(1_552_199_400));
+        // Clocks skip 02:00 to 03:00 Eastern on this date.
+        assert_eq!(end - start, std::time::Duration::from_secs(3600));
+        assert!(window.contains(start.as_nanos()));
+        assert!(!window.contains(end.as_nanos()));
+        assert!(!window.contains(start.as_nanos() - 1));
+
+        let args = crate::parse_args(&strings(&["iex_pcap_parser", "--start", "2019-03-10T02:30:00", "day.pcap"])).unwrap();
+        assert!(crate::TimeWindow::new(args.start, args.end, &trade_date, args.dst).is_err());
+        let args = crate::parse_args(&strings(&["iex_pcap_parser", "--start", "02:30", "--dst", "resolve", "day.pcap"])).unwrap();
+        let window = crate::TimeWindow::new(args.start, args.end, &trade_date, args.dst).unwrap();
+        assert_eq!(window.start, crate::TimeBound::parse("03:30").unwrap().resolve(&trade_date, args.dst).ok());
+
+        assert!(crate::TimeWindow::new(args.end, args.start, &trade_date, args.dst).unwrap().contains(0));
+        assert!(crate::parse_args(&strings(&["iex_pcap_parser", "--start", "9am", "day.pcap"])).is_err());
+        assert!(crate::parse_args(&strings(&["iex_pcap_parser", "--dst", "later", "day.pcap"])).is_err());
+        let args = crate::parse_args(&strings(&["iex_pcap_parser", "--start", "10:00", "--end", "09:30", "day.pcap"])).unwrap();
+        assert!(crate::TimeWindow::new(args.start, args.end, &trade_date, args.dst).is_err());
+    }
+
+    /// The ticks of a run with --start and --end, those off the trade date and the start of their
+    /// daily bars, which all depend on converting to and from Eastern time.
+    fn eastern_output() -> String {
+        let config = crate::parse_args(&strings(&["iex_pcap_parser", "--start", "09:30", "--end", "23:45",
+                                                  "20190703.pcap"])).unwrap();
+        let trade_date = chrono::NaiveDate::from_ymd_opt(2019, 7, 3).unwrap();
+        let window = crate::TimeWindow::new(config.start, config.end, &trade_date, config.dst).unwrap();
+        // 09:31:20, 23:30 and 23:50 Eastern on 2019-07-03, the last two on 2019-07-04 in UTC.
+        let trades = [1_562_160_680, 1_562_211_000, 1_562_212_200].iter()
+            .map(|&secs| iex_pcap_parser::IexDeepMessage { timestamp: secs * libdt::NS_PER_SEC, ..trade("AAPL", secs) })
+            .collect();
+        let running = std::sync::atomic::AtomicBool::new(true);
+        let collected = crate::collect(&config, &mut vec![Ok(packet(0, trades))].into_iter(), &window, &running,
+                                       None, None);
+        let ticks = &collected.stonks_ticks["AAPL    "];
+        let bars = crate::symbol_bars(ticks, std::time::Duration::from_secs(24 * 60 * 60));
+        format!("ticks {:?}, off trade date {:?}, bars {:?}",
+                ticks.iter().map(|t| t.timestamp / libdt::NS_PER_SEC).collect::<Vec<_>>(),
+                crate::ticks_off_trade_date(&collected.stonks_ticks, &trade_date),
+                bars.iter().map(|b| b.timestamp / libdt::NS_PER_SEC).collect::<Vec<_>>())
+    }
+
+    /// Run by `test_host_time_zone` with a different TZ each time.
+    #[test]
+    #[ignore]
+    fn print_eastern_output() {
+        println!("eastern output: {}", eastern_output());
+    }
+
+    #[test]
+    fn test_host_time_zone() {
+        assert_eq!(eastern_output(), "ticks [1562160680, 1562211000], off trade date [], bars [1562126400]");
+        // chrono reads TZ once per thread, so each zone gets a process of its own.
+        for tz in &["America/New_York", "UTC", "Asia/Tokyo"] {
+            let output = std::process::Command::new(std::env::current_exe().unwrap())
+                .args(["--exact", "tests::print_eastern_output", "--ignored", "--nocapture"])
+                .env("TZ", tz)
+                .output()
+                .unwrap();
+            assert!(output.status.success());
+            let stdout = String::from_utf8(output.stdout).unwrap();
+            assert!(stdout.contains(&format!("eastern output: {}\n", eastern_output())), "{}: {}", tz, stdout);
+        }
+    }
+
+    #[test]
+    fn test_dump_headers_args() {
+        assert!(crate::parse_args(&strings(&["iex_pcap_parser", "--dump-headers", "day.pcap"])).unwrap().dump_headers);
+        assert!(!crate::parse_args(&strings(&["iex_pcap_parser", "day.pcap"])).unwrap().dump_headers);
+    }
+
+    #[test]
+    fn test_replay_args() {
+        let replay = |flags: &[&str]| {
+            let mut args = vec!["iex_pcap_parser"];
+            args.extend_from_slice(flags);
+            args.push("day.pcap");
+            crate::parse_args(&strings(&args)).map(|args| args.replay)
+        };
+        assert_eq!(replay(&[]), Ok(None));
+        assert_eq!(replay(&["--replay"]), Ok(Some(iex_pcap_parser::Replay::default())));
+        assert_eq!(replay(&["--replay", "--speed", "10", "--replay-clock", "send"]),
+                   Ok(Some(iex_pcap_parser::Replay::default().with_speed(10.0)
+                           .with_clock(iex_pcap_parser::ReplayClock::SendTime))));
+        assert!(replay(&["--replay", "--speed", "0"]).is_err());
+        assert!(replay(&["--replay", "--speed", "inf"]).is_err());
+        assert!(replay(&["--speed", "10"]).is_err());
+        assert!(replay(&["--replay", "--split-by-symbol"]).is_err());
+        assert!(crate::parse_args(&strings(&["iex_pcap_parser", "--replay", "--live", "eth0"])).is_err());
+    }
+
+    #[test]
+    fn test_count_only_args() {
+        assert!(!crate::parse_args(&strings(&["iex_pcap_parser", "day.pcap"])).unwrap().count_only);
+        assert!(crate::parse_args(&strings(&["iex_pcap_parser", "--count-only", "--channel", "1", "day.pcap"]))
+            .unwrap().count_only);
+        assert!(crate::parse_args(&strings(&["iex_pcap_parser", "--count-only", "--sort", "day.pcap"])).is_err());
+        assert!(crate::parse_args(&strings(&["iex_pcap_parser", "--count-only", "--start", "09:30", "day.pcap"])).is_err());
+        assert!(crate::parse_args(&strings(&["iex_pcap_parser", "--count-only", "--replay", "day.pcap"])).is_err());
+        assert!(crate::parse_args(&strings(&["iex_pcap_parser", "--count-only", "--live", "eth0"])).is_err());
+    }
+
+    #[test]
+    fn test_flush_ticks() {
+        let path = std::env::temp_dir().join(format!("iex_pcap_parser_flush_{}.h5", std::process::id()));
+        let running = std::sync::atomic::AtomicBool::new(true);
+        let mut written = Vec::new();
+        for &flush_ticks in &[1, 3, crate::DEFAULT_FLUSH_TICKS] {
+            let mut config = crate::ParserConfig::new(crate::Input::Live("eth0".to_string()));
+            config.flush_ticks = flush_ticks;
+            let mut packets = (0..10)
+                .map(|i| Ok(packet(i, vec![iex_pcap_parser::IexDeepMessage {
+                    timestamp: i,
+                    ..trade(if i % 3 == 0 { "SPY" } else { "AAPL" }, i)
+                }])))
+                .collect::<Vec<_>>()
+                .into_iter();
+            let file = crate::open_output(&path, &config.write_options, false);
+            let mut collected = crate::collect(&config, &mut packets, &crate::TimeWindow::default(), &running,
+                                               Some(&file), None);
+            // Buffers are appended as soon as one fills up, so none is left full.
+            assert!(collected.stonks_ticks.values().all(|ticks| ticks.len() < flush_ticks));
+            crate::flush_ticks(&file, &mut collected.stonks_ticks, &config.write_options);
+            let datasets: Vec<Vec<libh5::Tick>> = ["AAPL    ", "SPY     "].iter()
+                .map(|symbol| libh5::read_ticks(&file.dataset(symbol).unwrap()).unwrap())
+                .collect();
+            written.push(datasets);
+        }
+        assert_eq!(written[0][0].len(), 6);
+        assert_eq!(written[0][1].len(), 4);
+        assert!(written.iter().all(|datasets| *datasets == written[0]));
+        std::fs::remove_file(&path).unwrap();
+
+        let args = crate::parse_args(&strings(&["iex_pcap_parser", "--live", "eth0"])).unwrap();
+        assert_eq!(args.flush_ticks, crate::DEFAULT_FLUSH_TICKS);
+        let args = crate::parse_args(&strings(&["iex_pcap_parser", "--flush-ticks", "1000", "--live", "eth0"])).unwrap();
+        assert_eq!(args.flush_ticks, 1000);
+        assert!(crate::parse_args(&strings(&["iex_pcap_parser", "--flush-ticks", "0", "--live", "eth0"])).is_err());
+        assert!(crate::parse_args(&strings(&["iex_pcap_parser", "--flush-ticks", "1000", "day.pcap"])).is_err());
+    }
+
+    #[test]
+    fn test_stop_at_end_of_messages() {
+        let running = std::sync::atomic::AtomicBool::new(true);
+        let collect = |stop| {
+            let mut config = crate::ParserConfig::new(crate::Input::File("day.pcap".to_string()));
+            config.stop_at_end_of_messages = stop;
+            let end_of_messages = message(b'S', iex_pcap_parser::IexDeepMessageImpl::SystemEvent(
+                iex_pcap_parser::SystemEventMessage { system_event: iex_pcap_parser::SystemEvent::EndOfMessages }));
+            let packets = vec![packet(0, vec![trade("AAPL", 1)]), packet(1, vec![end_of_messages]),
+                               packet(2, vec![trade("AAPL", 2)])];
+            let mut packets = packets.into_iter().map(Ok);
+            crate::collect(&config, &mut packets, &crate::TimeWindow::default(), &running, None, None)
+        };
+
+        let read_all = collect(false);
+        assert!(read_all.parse_stats.end_of_messages);
+        assert_eq!((read_all.packet_counter, read_all.stonks_ticks["AAPL    "].len()), (3, 2));
+        let stopped = collect(true);
+        assert!(stopped.parse_stats.end_of_messages);
+        assert_eq!((stopped.packet_counter, stopped.stonks_ticks["AAPL    "].len()), (2, 1));
+
+        assert!(crate::parse_args(&strings(&["iex_pcap_parser", "--stop-at-end-of-messages", "day.pcap"])).unwrap()
+            .stop_at_end_of_messages);
+        for flag in &["--trades-only", "--dump-headers", "--count-only"] {
+            assert!(crate::parse_args(&strings(&["iex_pcap_parser", "--stop-at-end-of-messages", flag, "day.pcap"]))
+                .is_err());
+        }
+    }
+
+    #[test]
+    fn test_drop_zero_trades() {
+        let zero_price = message(b'T', iex_pcap_parser::IexDeepMessageImpl::TradeReport(iex_pcap_parser::TradeReportMessage {
+            symbol: padded("AAPL"),
+            size: 100,
+            price: 0,
+            trade_id: 2,
+            sale_condition_flags: 0,
+        }));
+        let mut packets = Some(packet(0, vec![trade("AAPL", 1), zero_price])).into_iter().map(Ok);
+        let config = crate::parse_args(&strings(&["iex_pcap_parser", "--drop-zero-price", "day.pcap"])).unwrap();
+        let running = std::sync::atomic::AtomicBool::new(true);
+        let collected = crate::collect(&config, &mut packets, &crate::TimeWindow::default(), &running, None, None);
+        assert_eq!(collected.stonks_ticks["AAPL    "].len(), 1);
+        assert_eq!(collected.stonks_ticks["AAPL    "][0].price, 1234500);
+        assert_eq!((collected.zero_size_trades, collected.zero_price_trades), (0, 1));
+
+        let args = crate::parse_args(&strings(&["iex_pcap_parser", "--drop-zero-size", "day.pcap"])).unwrap();
+        assert_eq!(args.zero_trades, iex_pcap_parser::ZeroTradeFilter::default().with_drop_zero_size(true));
+        let args = crate::parse_args(&strings(&["iex_pcap_parser", "day.pcap"])).unwrap();
+        assert_eq!(args.zero_trades, iex_pcap_parser::ZeroTradeFilter::default());
+    }
+
+    #[test]
+    fn test_channel_summary() {
+        let on_channel = |channel_id, packet: iex_pcap_parser::DeepPacket| iex_pcap_parser::DeepPacket {
+            header: iex_pcap_parser::IexTpHeader { channel_id, ..packet.header },
+            ..packet
+        };
+        let config = crate::parse_args(&strings(&["iex_pcap_parser", "--channel-summary", "day.pcap"])).unwrap();
+        let mut packets = vec![
+            Ok(on_channel(1, packet(0, vec![trade("AAPL", 1), trade("MSFT", 2), trade("AAPL", 3)]))),
+            Ok(on_channel(2, packet(0, vec![trade("SPY", 4)]))),
+        ].into_iter();
+        let running = std::sync::atomic::AtomicBool::new(true);
+        let collected = crate::collect(&config, &mut packets, &crate::TimeWindow::default(), &running, None, None);
+        assert_eq!(collected.channel_summary.iter().collect::<Vec<_>>(), vec![(1, 3, 2), (2, 1, 1)]);
+
+        assert!(crate::parse_args(&strings(&["iex_pcap_parser", "--channel-summary", "--count-only", "day.pcap"])).is_err());
+        assert!(crate::parse_args(&strings(&["iex_pcap_parser", "--channel-summary", "--resume", "day.pcap"])).is_err());
+    }
+
+    #[test]
+    fn test_price_range() {
+        let priced = |price, trade_id| message(b'T', iex_pcap_parser::IexDeepMessageImpl::TradeReport(
+            iex_pcap_parser::TradeReportMessage {
+                symbol: padded("AAPL"),
+                size: 100,
+                price,
+                trade_id,
+                sale_condition_flags: 0,
+            }));
+        let config = crate::parse_args(&strings(&["iex_pcap_parser", "--min-price", "0.0001", "--max-price", "100000",
+                                                  "day.pcap"])).unwrap();
+        // $199, then a price read from the wrong offset.
+        let mut packets = Some(packet(0, vec![priced(1_990_000, 1), priced(0x4141_4141_4141_4141, 2)])).into_iter().map(Ok);
+        let running = std::sync::atomic::AtomicBool::new(true);
+        let collected = crate::collect(&config, &mut packets, &crate::TimeWindow::default(), &running, None, None);
+        assert_eq!(collected.stonks_ticks["AAPL    "].iter().map(|t| t.price).collect::<Vec<u64>>(), vec![1_990_000]);
+        assert_eq!((collected.out_of_range_trades, collected.tradeable_counter), (1, 1));
+
+        let args = crate::parse_args(&strings(&["iex_pcap_parser", "day.pcap"])).unwrap();
+        assert_eq!(args.price_range, iex_pcap_parser::PriceRange::default());
+        for range in &[["--min-price", "-1"], ["--max-price", "lots"]] {
+            assert!(crate::parse_args(&strings(&["iex_pcap_parser", range[0], range[1], "day.pcap"])).is_err());
+        }
+        assert!(crate::parse_args(&strings(&["iex_pcap_parser", "--min-price", "10", "--max-price", "1", "day.pcap"])).is_err());
+    }
+
+    #[test]
+    fn test_max_errors() {
+        let errors = || vec![
+            Err(iex_pcap_parser::ParseError::TruncatedHeader { have: 12, need: 40 }),
+            Ok(packet(1, vec![trade("AAPL", 1)])),
+            Err(iex_pcap_parser::ParseError::UnsupportedVersion(2)),
+            Err(iex_pcap_parser::ParseError::UnknownMessageType(b'Z')),
+            Ok(packet(4, vec![trade("AAPL", 2)])),
+        ].into_iter();
+        let running = std::sync::atomic::AtomicBool::new(true);
+        let config = crate::parse_args(&strings(&["iex_pcap_parser", "--strict", "--max-errors", "2", "day.pcap"])).unwrap();
+        let mut packets = errors();
+        let collected = crate::collect(&config, &mut packets, &crate::TimeWindow::default(), &running, None, None);
+        // Reading stops at the second error, before the packets after it.
+        assert_eq!(collected.strict_errors.iter().map(|e| e.packet).collect::<Vec<u64>>(), vec![0, 2]);
+        assert_eq!(collected.packet_counter, 3);
+        assert_eq!(collected.stonks_ticks["AAPL    "].len(), 1);
+        assert_eq!(packets.count(), 2);
+
+        // Lenient runs skip malformed headers without counting them against it.
+        let config = crate::parse_args(&strings(&["iex_pcap_parser", "--max-errors", "1", "day.pcap"])).unwrap();
+        let mut packets = errors().take(3);
+        let collected = crate::collect(&config, &mut packets, &crate::TimeWindow::default(), &running, None, None);
+        assert!(collected.strict_errors.is_empty());
+        assert_eq!(collected.packet_counter, 3);
+
+        assert!(crate::parse_args(&strings(&["iex_pcap_parser", "--strict", "--max-errors", "0", "day.pcap"])).is_err());
+        assert!(crate::parse_args(&strings(&["iex_pcap_parser", "--strict", "day.pcap", "--max-errors"])).is_err());
+    }
+
+    #[test]
+    fn test_untraded_symbols() {
+        let directory = |symbol: &str, flags| message(b'D', iex_pcap_parser::IexDeepMessageImpl::SecurityDirectory(
+            iex_pcap_parser::SecurityDirectoryMessage {
+                symbol: padded(symbol),
+                round_lot_size: 100,
+                adjusted_poc_price: 0,
+                luld_tier: iex_pcap_parser::LimitUpLimitDownTier::NotApplicable,
+                flags: iex_pcap_parser::SecurityDirectoryFlags::from_bits_truncate(flags),
+            }));
+        let config = crate::parse_args(&strings(&["iex_pcap_parser", "--skip-test-securities", "--untraded-symbols",
+                                                  "day.pcap"])).unwrap();
+        let mut packets = vec![
+            Ok(packet(0, vec![directory("AAPL", 0), directory("IWM", 0), directory("SPY", 0), directory("ZIEXT", 0x80)])),
+            Ok(packet(1, vec![trade("AAPL", 1), trade("SPY", 2), trade("TSLA", 3)])),
+        ].into_iter();
+        let running = std::sync::atomic::AtomicBool::new(true);
+        let collected = crate::collect(&config, &mut packets, &crate::TimeWindow::default(), &running, None, None);
+        assert_eq!(collected.directory_symbols.len(), 4);
+        // Neither the test security nor the symbol missing from the directory is listed.
+        assert_eq!(crate::untraded_symbols(&collected.directory_symbols, &collected.traded_symbols, &collected.test_symbols),
+                   vec!["IWM     "]);
+
+        assert!(crate::parse_args(&strings(&["iex_pcap_parser", "--untraded-symbols", "--trades-only", "day.pcap"])).is_err());
+        assert!(crate::parse_args(&strings(&["iex_pcap_parser", "--untraded-symbols", "--split-by-symbol", "day.pcap"])).is_err());
+        assert!(crate::parse_args(&strings(&["iex_pcap_parser", "--untraded-symbols", "--count-only", "day.pcap"])).is_err());
+        assert!(crate::parse_args(&strings(&["iex_pcap_parser", "--untraded-symbols", "--replay", "day.pcap"])).is_err());
+    }
+
+    #[test]
+    fn test_temp_dir_args() {
+        assert_eq!(crate::parse_args(&strings(&["iex_pcap_parser", "day.pcap.gz"])).unwrap().temp_dir, None);
+        assert_eq!(crate::parse_args(&strings(&["iex_pcap_parser", "--temp-dir", "/scratch", "day.pcap.gz"])).unwrap().temp_dir,
+                   Some("/scratch".to_string()));
+        assert!(crate::parse_args(&strings(&["iex_pcap_parser", "day.pcap.gz", "--temp-dir"])).is_err());
+        assert!(crate::parse_args(&strings(&["iex_pcap_parser", "--temp-dir", "/scratch", "--live", "eth0"])).is_err());
+    }
+
+    #[test]
+    fn test_resume_args() {
+        assert!(crate::parse_args(&strings(&["iex_pcap_parser", "--resume", "captures"])).unwrap().resume);
+        assert!(!crate::parse_args(&strings(&["iex_pcap_parser", "captures"])).unwrap().resume);
+        for flag in &["--sort", "--split-by-symbol", "--apply-breaks", "--vwap", "--count-only", "--replay"] {
+            assert!(crate::parse_args(&strings(&["iex_pcap_parser", "--resume", flag, "captures"])).is_err());
+        }
+        assert!(crate::parse_args(&strings(&["iex_pcap_parser", "--resume", "--max-packets", "10", "captures"])).is_err());
+        assert!(crate::parse_args(&strings(&["iex_pcap_parser", "--resume", "--symbol-collisions", "suffix", "captures"])).is_err());
+        assert!(crate::parse_args(&strings(&["iex_pcap_parser", "--resume", "--live", "eth0"])).is_err());
+    }
+
+    #[test]
+    fn test_resume_manifest() {
+        let mut manifest = crate::ResumeManifest::default();
+        assert_eq!(crate::ResumeManifest::parse(&manifest.to_string()), Ok(crate::ResumeManifest::default()));
+        manifest.captures = strings(&["captures/20190703_IEXTP1_DEEP1.0_1.pcap", "captures/20190703_IEXTP1_DEEP1.0_2.pcap"]);
+        manifest.rows.insert("AAPL    ".to_string(), 12);
+        manifest.rows.insert("_official_prices/AAPL    ".to_string(), 2);
+        manifest.test_symbols.insert("ZIEXT   ".to_string());
+        manifest.channels.insert((1, 150));
+        let text = manifest.to_string();
+        assert!(text.starts_with("iex_pcap_parser resume 1\ncapture\tcaptures/20190703_IEXTP1_DEEP1.0_1.pcap\n"));
+        assert!(text.contains("\nrows\t12\tAAPL    \n"));
+        assert_eq!(crate::ResumeManifest::parse(&text), Ok(manifest));
+
+        assert!(crate::ResumeManifest::parse("").is_err());
+        assert!(crate::ResumeManifest::parse("iex_pcap_parser resume 2\n").is_err());
+        assert!(crate::ResumeManifest::parse("iex_pcap_parser resume 1\nrows\tmany\tAAPL    \n").is_err());
+        assert!(crate::ResumeManifest::parse("iex_pcap_parser resume 1\nchannel\t1\n").is_err());
+        assert!(crate::ResumeManifest::parse("iex_pcap_parser resume 1\ncaptured\tday.pcap\n").is_err());
+    }
+
+    #[test]
+    fn test_resume_after_interruption() {
+        let mut dir = std::env::temp_dir();
+        dir.push(format!("iex_pcap_parser_resume_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let output = dir.join("20190703.h5").to_str().unwrap().to_string();
+        let manifest_path = crate::ResumeManifest::path(&output);
+        let mut config = crate::ParserConfig::new(crate::Input::File(dir.to_str().unwrap().to_string()));
+        config.resume = true;
+        let trade_date = chrono::NaiveDate::from_ymd_opt(2019, 7, 3).unwrap();
+        let collected = |ticks: Vec<(&str, Vec<libh5::Tick>)>| {
+            crate::Collected {
+                stonks_ticks: ticks.into_iter().map(|(symbol, ticks)| (symbol.to_string(), ticks)).collect(),
+                channels: vec![(1, 150)].into_iter().collect(),
+                ..Default::default()
+            }
+        };
+
+        // The first capture is written and recorded before the run is stopped partway through
+        // writing the second.
+        {
+            let (file, mut manifest) = crate::open_resumable_output(&output, &manifest_path, &config);
+            let mut first = collected(vec![("AAPL    ", vec![tick(1), tick(2)]), ("ZIEXT   ", vec![tick(3)])]);
+            first.test_symbols.insert("ZIEXT   ".to_string());
+            first.official_prices.insert("AAPL    ".to_string(), vec![tick(4)]);
+            crate::write_resumed_capture(&file, &config, &mut manifest, &manifest_path, "1.pcap", first, &trade_date);
+            libh5::append_ticks(&file, "AAPL    ", &[tick(5)]).unwrap();
+            libh5::append_ticks(&file, "SPY     ", &[tick(6)]).unwrap();
+            libh5::append_official_prices(&file, "AAPL    ", &[tick(7)]).unwrap();
+        }
+        let file = hdf5::File::open(&output, "r").unwrap();
+        assert_eq!(libh5::read_attr::<bool>(&file, libh5::INCOMPLETE_ATTR).unwrap(), Some(true));
+        drop(file);
+
+        {
+            let (file, mut manifest) = crate::open_resumable_output(&output, &manifest_path, &config);
+            assert_eq!(manifest.captures, strings(&["1.pcap"]));
+            assert_eq!(libh5::symbols(&file).unwrap(), vec!["AAPL    "]);
+            assert_eq!(file.dataset("AAPL    ").unwrap().size(), 2);
+            assert_eq!(libh5::read_official_prices(&file, "AAPL    ").unwrap().len(), 1);
+            // The test security is known from the first capture.
+            let second = collected(vec![("AAPL    ", vec![tick(8)]), ("SPY     ", vec![tick(9)]), ("ZIEXT   ", vec![tick(10)])]);
+            crate::write_resumed_capture(&file, &config, &mut manifest, &manifest_path, "captures/2.pcap", second, &trade_date);
+            crate::finish_resumable_output(&file, &manifest, &manifest_path, &config.filter);
+        }
+        assert!(!manifest_path.exists());
+        let file = hdf5::File::open(&output, "r").unwrap();
+        assert_eq!(libh5::read_attr::<bool>(&file, libh5::INCOMPLETE_ATTR).unwrap(), Some(false));
+        assert_eq!(libh5::read_attr::<u32>(&file, libh5::CHANNEL_ID_ATTR).unwrap(), Some(1));
+        assert_eq!(libh5::read_source_files(&file).unwrap(), vec!["1.pcap", "2.pcap"]);
+        let timestamps: Vec<u64> = libh5::read_ticks(&file.dataset("AAPL    ").unwrap()).unwrap().iter()
+            .map(|t| t.timestamp)
+            .collect();
+        assert_eq!(timestamps, vec![1, 2, 8]);
+        drop(file);
+        let index: Vec<(String, u64)> = libh5::load_symbol_index(&output).unwrap().into_iter()
+            .map(|(symbol, count)| (symbol.trim_end().to_string(), count))
+            .collect();
+        assert_eq!(index, vec![("AAPL".to_string(), 3), ("SPY".to_string(), 1)]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_max_packets_args() {
+        assert_eq!(crate::parse_args(&strings(&["iex_pcap_parser", "--max-packets", "1000", "day.pcap"])).unwrap().max_packets, Some(1000));
+        assert_eq!(crate::parse_args(&strings(&["iex_pcap_parser", "day.pcap"])).unwrap().max_packets, None);
+        assert!(crate::parse_args(&strings(&["iex_pcap_parser", "--max-packets", "-1", "day.pcap"])).is_err());
+        assert!(crate::parse_args(&strings(&["iex_pcap_parser", "--max-packets", "10", "--live", "eth0"])).is_err());
+    }
+
+    #[test]
+    fn test_captures_in_directory() {
+        let mut dir = std::env::temp_dir();
+        dir.push(format!("iex_pcap_parser_captures_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = |name: &str| dir.join(name).to_str().unwrap().to_string();
+        for name in &["20190703_IEXTP1_DEEP1.0_2.pcap", "20190703_IEXTP1_DEEP1.0_1.pcap.gz"] {
+            std::fs::write(path(name), b"").unwrap();
+        }
+        let captures = crate::captures_for_input(dir.to_str().unwrap(), false).unwrap();
+        assert_eq!(captures, vec![path("20190703_IEXTP1_DEEP1.0_1.pcap.gz"), path("20190703_IEXTP1_DEEP1.0_2.pcap")]);
+        assert_eq!(crate::trade_date_of_captures(&captures), Ok(chrono::NaiveDate::from_ymd_opt(2019, 7, 3).unwrap()));
+
+        std::fs::write(path("20190705_IEXTP1_DEEP1.0.pcap"), b"").unwrap();
+        let captures = crate::captures_for_input(dir.to_str().unwrap(), false).unwrap();
+        assert!(crate::trade_date_of_captures(&captures).is_err());
+
+        assert_eq!(crate::captures_for_input("day.pcap", false), Ok(strings(&["day.pcap"])));
+        assert!(crate::captures_for_input("day.pcap", true).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+        std::fs::create_dir_all(&dir).unwrap();
+        assert!(crate::captures_for_input(dir.to_str().unwrap(), false).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_name_template() {
+        let trade_date = chrono::NaiveDate::from_ymd_opt(2019, 7, 3).unwrap();
+        assert_eq!(crate::NameTemplate::default().render(&trade_date, Some(1)), "20190703.h5");
+        let template = crate::NameTemplate::parse("{date}_{product}_ch{channel}.h5").unwrap();
+        assert_eq!(template.render(&trade_date, Some(1)), "20190703_DEEP_ch1.h5");
+        assert!(crate::NameTemplate::parse("iex/{product}_{date}.h5").is_err());
+        assert!(crate::NameTemplate::parse("{date}_{venue}.h5").is_err());
+        assert!(crate::NameTemplate::parse("{date.h5").is_err());
+        assert!(crate::NameTemplate::parse("date}.h5").is_err());
+
+        let args = crate::parse_args(&strings(&["iex_pcap_parser", "--name-template", "{product}_{date}.h5", "day.pcap"])).unwrap();
+        assert_eq!(args.name_template.render(&trade_date, None), "DEEP_20190703.h5");
+        assert!(crate::parse_args(&strings(&["iex_pcap_parser", "--name-template", "{date}_ch{channel}.h5", "day.pcap"])).is_err());
+        assert!(crate::parse_args(&strings(&["iex_pcap_parser", "--name-template", "{date}_ch{channel}.h5",
+                                             "--channel", "1", "day.pcap"])).is_ok());
+        assert!(crate::parse_args(&strings(&["iex_pcap_parser", "--name-template", "{product}_{date}.h5",
+                                             "--split-by-symbol", "day.pcap"])).is_err());
+    }
+
+    #[test]
+    fn test_existing_outputs() {
+        let mut dir = std::env::temp_dir();
+        dir.push(format!("iex_pcap_parser_clobber_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let trade_date = chrono::NaiveDate::from_ymd_opt(2019, 7, 3).unwrap();
+        std::fs::write(dir.join("20190702_AAPL.h5"), b"").unwrap();
+        assert!(crate::existing_outputs(&dir, &trade_date, "20190703.h5", false).unwrap().is_empty());
+        assert!(crate::existing_outputs(&dir, &trade_date, "20190703.h5", true).unwrap().is_empty());
+
+        // A second run of the same day finds the first one's output.
+        std::fs::write(dir.join("20190703.h5"), b"").unwrap();
+        std::fs::write(dir.join("20190703_SPY.h5"), b"").unwrap();
+        assert_eq!(crate::existing_outputs(&dir, &trade_date, "20190703.h5", false).unwrap(), vec![dir.join("20190703.h5")]);
+        assert!(crate::existing_outputs(&dir, &trade_date, "20190703_DEEP_ch1.h5", false).unwrap().is_empty());
+        assert_eq!(crate::existing_outputs(&dir, &trade_date, "20190703.h5", true).unwrap(), vec![dir.join("20190703_SPY.h5")]);
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert!(!crate::parse_args(&strings(&["iex_pcap_parser", "day.pcap"])).unwrap().overwrite);
+        assert!(crate::parse_args(&strings(&["iex_pcap_parser", "--overwrite", "day.pcap"])).unwrap().overwrite);
+        assert!(!crate::parse_args(&strings(&["iex_pcap_parser", "--no-clobber", "day.pcap"])).unwrap().overwrite);
+        assert!(crate::parse_args(&strings(&["iex_pcap_parser", "--overwrite", "--no-clobber", "day.pcap"])).is_err());
+    }
+
+    #[test]
+    fn test_index_by_time_args() {
+        let args = crate::parse_args(&strings(&["iex_pcap_parser", "--index-by-time", "day.pcap"])).unwrap();
+        assert!(args.index_by_time && args.sort);
+        assert!(crate::parse_args(&strings(&["iex_pcap_parser", "--index-by-time", "--live", "eth0"])).is_err());
+    }
+}
+
+
+/// How often ticks buffered from a live capture get appended to the h5 file.
+const LIVE_FLUSH_INTERVAL: time::Duration = time::Duration::from_secs(60);
+/// Ticks a symbol of a live capture can have buffered before they're appended early, 64K or a few
+/// MB per symbol.
+const DEFAULT_FLUSH_TICKS: usize = 65_536;
+
+enum Input {
+    File(String),
+    Live(String),
+}
+
+/// Everything a run of the parser is configured with. parse_args builds it from the command line,
+/// starting from the defaults of `ParserConfig::new`.
+struct ParserConfig {
+    input: Input,
+    progress: bool,
+    sort: bool,
+    index_by_time: bool,
+    split_by_symbol: bool,
+    skip_test_securities: bool,
+    strict: bool,
+    /// A strict run reads on past packets that fail to parse until this many have, then reports
+    /// them all, rather than stopping at the first.
+    max_errors: Option<usize>,
+    write_options: libh5::WriteOptions,
+    filter: iex_pcap_parser::ChannelFilter,
+    trades_only: bool,
+    /// Auction information messages are only decoded and written with this set.
+    include_auctions: bool,
+    apply_breaks: bool,
+    start: Option<TimeBound>,
+    end: Option<TimeBound>,
+    dst: libdt::DstPolicy,
+    max_packets: Option<u64>,
+    recursive: bool,
+    overwrite: bool,
+    dump_headers: bool,
+    name_template: NameTemplate,
+    capture_raw: bool,
+    symbol_normalizer: iex_pcap_parser::SymbolNormalizer,
+    symbol_collisions: SymbolCollisions,
+    bars: Option<time::Duration>,
+    vwap: bool,
+    /// Ticks are written to stdout with their original timing instead of to an h5 file.
+    replay: Option<iex_pcap_parser::Replay>,
+    /// Messages are only counted by type, without decoding them or writing anything.
+    count_only: bool,
+    /// Where compressed captures are decompressed for libpcap, instead of the system's temp dir.
+    temp_dir: Option<String>,
+    /// Each capture is written out once read and recorded in a manifest, so an interrupted run
+    /// continues where it stopped.
+    resume: bool,
+    /// Live ticks get appended once any symbol has this many buffered, besides every
+    /// `LIVE_FLUSH_INTERVAL`.
+    flush_ticks: usize,
+    /// Reading stops after the end of messages system event, which ends the session.
+    stop_at_end_of_messages: bool,
+    zero_trades: iex_pcap_parser::ZeroTradeFilter,
+    price_range: iex_pcap_parser::PriceRange,
+    /// The security directory's symbols without trades are written to the output.
+    untraded_symbols: bool,
+    /// Messages and distinct symbols are counted by channel and logged in the summary.
+    channel_summary: bool,
+    /// Each symbol's timestamps are checked to never go back in sequence number order.
+    check_monotonic: bool,
+    /// Every symbol's ticks go in one dataset with a symbol column instead of a dataset each.
+    combined: bool,
+    log_level: log::LevelFilter,
+}
+
+impl ParserConfig {
+    fn new(input: Input) -> ParserConfig {
+        ParserConfig {
+            input,
+            progress: false,
+            sort: false,
+            index_by_time: false,
+            split_by_symbol: false,
+            skip_test_securities: false,
+            strict: false,
+            max_errors: None,
+            write_options: libh5::WriteOptions::default(),
+            filter: iex_pcap_parser::ChannelFilter::default(),
+            trades_only: false,
+            include_auctions: false,
+            apply_breaks: false,
+            start: None,
+            end: None,
+            dst: libdt::DstPolicy::Reject,
+            max_packets: None,
+            recursive: false,
+            overwrite: false,
+            dump_headers: false,
+            name_template: NameTemplate::default(),
+            capture_raw: false,
+            symbol_normalizer: iex_pcap_parser::SymbolNormalizer::default().with_padding(true),
+            symbol_collisions: SymbolCollisions::Merge,
+            bars: None,
+            vwap: false,
+            replay: None,
+            count_only: false,
+            temp_dir: None,
+            resume: false,
+            flush_ticks: DEFAULT_FLUSH_TICKS,
+            stop_at_end_of_messages: false,
+            zero_trades: iex_pcap_parser::ZeroTradeFilter::default(),
+            price_range: iex_pcap_parser::PriceRange::default(),
+            untraded_symbols: false,
+            channel_summary: false,
+            check_monotonic: false,
+            combined: false,
+            log_level: log::LevelFilter::Info,
+        }
+    }
+}
+
+/// A --start or --end time, in US Eastern time whatever the host's time zone. Times of day are on
+/// the trade date, which isn't known until the input is opened.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum TimeBound {
+    TimeOfDay(chrono::NaiveTime),
+    DateTime(chrono::NaiveDateTime),
+}
+
+impl TimeBound {
+    fn parse(s: &str) -> Result<TimeBound, String> {
+        if let Ok(datetime) = chrono::NaiveDateTime::parse_from_str(s, "%Y-%m-%dT%H:%M:%S") {
+            return Ok(TimeBound::DateTime(datetime));
+        }
+        chrono::NaiveTime::parse_from_str(s, "%H:%M:%S")
+            .or_else(|_| chrono::NaiveTime::parse_from_str(s, "%H:%M"))
+            .map(TimeBound::TimeOfDay)
+            .map_err(|_| format!("invalid time {:?}, expected HH:MM[:SS] or YYYY-mm-ddTHH:MM:SS", s))
+    }
+
+    fn resolve(&self, trade_date: &chrono::NaiveDate, dst: libdt::DstPolicy) -> Result<libdt::UtcNs, String> {
+        let datetime = match self {
+            TimeBound::TimeOfDay(time) => trade_date.and_time(*time),
+            TimeBound::DateTime(datetime) => *datetime,
+        };
+        libdt::utc_ns_for_naive_datetime_in(&datetime, &libdt::UsEastern, dst)
+            .ok_or_else(|| format!("{} doesn't exist or is ambiguous in US Eastern time; \
+                                    --dst resolve picks one", datetime))
+    }
+}
+
+/// Ticks are kept if their timestamp is in [start, end).
+#[derive(Debug, Default, PartialEq)]
+struct TimeWindow {
+    start: Option<libdt::UtcNs>,
+    end: Option<libdt::UtcNs>,
+}
+
+impl TimeWindow {
+    fn new(start: Option<TimeBound>, end: Option<TimeBound>, trade_date: &chrono::NaiveDate,
+           dst: libdt::DstPolicy) -> Result<TimeWindow, String> {
+        let window = TimeWindow {
+            start: start.map(|b| b.resolve(trade_date, dst)).transpose()?,
+            end: end.map(|b| b.resolve(trade_date, dst)).transpose()?,
+        };
+        if let (Some(start), Some(end)) = (window.start, window.end) {
+            if start >= end {
+                return Err(format!("--start {} isn't before --end {}", start, end));
+            }
+        }
+        Ok(window)
+    }
+
+    fn contains(&self, timestamp: u64) -> bool {
+        let timestamp = libdt::UtcNs::from_nanos(timestamp);
+        self.start.is_none_or(|start| start <= timestamp) && self.end.is_none_or(|end| timestamp < end)
+    }
+}
+
+/// Only DEEP is decoded, so that's the product every output is named after.
+const PRODUCT: &str = "DEEP";
+const NAME_PLACEHOLDERS: [&str; 3] = ["date", "product", "channel"];
+
+/// Name of the combined output file, with `{date}` as YYYYmmdd, `{product}` and `{channel}`
+/// filled in.
+#[derive(Clone, Debug, PartialEq)]
+struct NameTemplate(String);
+
+impl Default for NameTemplate {
+    fn default() -> NameTemplate {
+        NameTemplate("{date}.h5".to_string())
+    }
+}
+
+impl NameTemplate {
+    fn parse(template: &str) -> Result<NameTemplate, String> {
+        if template.contains('/') {
+            return Err(format!("name template {:?} must be a file name, not a path", template));
+        }
+        let mut rest = template;
+        while let Some(open) = rest.find(['{', '}']) {
+            if rest[open..].starts_with('}') {
+                return Err(format!("unmatched }} in name template {:?}", template));
+            }
+            let close = match rest[open..].find('}') {
+                Some(i) => open + i,
+                None => return Err(format!("unclosed {{ in name template {:?}", template)),
+            };
+            let placeholder = &rest[open + 1..close];
+            if !NAME_PLACEHOLDERS.contains(&placeholder) {
+                return Err(format!("unknown placeholder {{{}}} in name template {:?}, expected one of {}",
+                                   placeholder, template, NAME_PLACEHOLDERS.join(", ")));
+            }
+            rest = &rest[close + 1..];
+        }
+        Ok(NameTemplate(template.to_string()))
+    }
+
+    fn uses(&self, placeholder: &str) -> bool {
+        self.0.contains(&format!("{{{}}}", placeholder))
+    }
+
+    /// `{channel}` is left empty without a `channel`; `parse_args` requires --channel for it.
+    fn render(&self, trade_date: &chrono::NaiveDate, channel: Option<u32>) -> String {
+        self.0.replace("{date}", &trade_date.format("%Y%m%d").to_string())
+            .replace("{product}", PRODUCT)
+            .replace("{channel}", &channel.map_or(String::new(), |c| c.to_string()))
+    }
+}
+
+/// Each -v raises the log level above the default of info, each -q lowers it.
+fn log_level_for_verbosity(verbosity: i32) -> log::LevelFilter {
+    match verbosity {
+        v if v <= -3 => log::LevelFilter::Off,
+        -2 => log::LevelFilter::Error,
+        -1 => log::LevelFilter::Warn,
+        0 => log::LevelFilter::Info,
+        1 => log::LevelFilter::Debug,
+        _ => log::LevelFilter::Trace,
+    }
+}
+
+/// A --bars interval: a whole number of seconds, minutes or hours, like 30s, 1m or 1h. It has to
+/// divide a day, so bars start at the same times every day.
+fn parse_bar_interval(interval: &str) -> Result<time::Duration, String> {
+    let error = || format!("--bars needs an interval that divides a day, like 30s, 1m or 1h, not {}", interval);
+    let unit_secs = match interval.chars().last() {
+        Some('s') => 1,
+        Some('m') => libdt::SEC_PER_MIN,
+        Some('h') => libdt::SEC_PER_MIN * libdt::MIN_PER_HOUR,
+        _ => return Err(error()),
+    };
+    let count: u64 = interval[..interval.len() - 1].parse().map_err(|_| error())?;
+    match count.checked_mul(unit_secs * libdt::NS_PER_SEC) {
+        Some(ns) if libdt::NS_PER_DAY.checked_rem(ns) == Some(0) => Ok(time::Duration::from_nanos(ns)),
+        _ => Err(error()),
+    }
+}
+
+fn usage(program: &str) -> String {
+    format!("Usage: {} [-v|-vv|-q|-qq] [--progress] [--sort] [--index-by-time] [--split-by-symbol] [--skip-test-securities] [--strict [--max-errors <n>]] [--chunked] [--chunk-size <ticks>] [--fixed-point] [--checksum] [--group-symbols 1|2] [--channel <id>] [--session <id>] [--trades-only] [--include-auctions] [--apply-breaks] [--start <time>] [--end <time>] [--dst reject|resolve] [--max-packets <n>] [--recursive] [--overwrite|--no-clobber] [--dump-headers] [--name-template <template>] [--capture-raw] [--symbol-case keep|upper|lower] [--class-separator <char>] [--symbol-collisions merge|suffix] [--bars <interval>] [--vwap] [--replay [--speed <x>] [--replay-clock capture|send]] [--count-only] [--temp-dir <dir>] [--resume] [--stop-at-end-of-messages] [--drop-zero-size] [--drop-zero-price] [--min-price <dollars>] [--max-price <dollars>] [--untraded-symbols] [--channel-summary] [--check-monotonic] [--combined] <file.pcap[.gz|.bz2|.zst]|file.pcapng|dir> | --live <iface> [--flush-ticks <n>]", program)
+}
+
+fn parse_args(vargs: &[String]) -> Result<ParserConfig, String> {
+    let mut input = None;
+    let mut config = ParserConfig::new(Input::File(String::new()));
+    let mut overwrite = None;
+    let mut speed = None;
+    let mut replay_clock = None;
+    let mut min_price = None;
+    let mut max_price = None;
+    let mut flush_ticks = None;
+    let mut verbosity = 0;
+    let mut iter = vargs.iter().skip(1);
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--live" => match iter.next() {
+                Some(iface) => input = Some(Input::Live(iface.clone())),
+                None => return Err("--live needs an interface".to_string()),
+            },
+            "--progress" => config.progress = true,
+            "--sort" => config.sort = true,
+            "--index-by-time" => config.index_by_time = true,
+            "--split-by-symbol" => config.split_by_symbol = true,
+            "--skip-test-securities" => config.skip_test_securities = true,
+            "--strict" => config.strict = true,
+            "--channel" => match iter.next().map(|n| n.parse()) {
+                Some(Ok(id)) => config.filter.channel_id = Some(id),
+                _ => return Err("--channel needs a channel id".to_string()),
+            },
+            "--session" => match iter.next().map(|n| n.parse()) {
+                Some(Ok(id)) => config.filter.session_id = Some(id),
+                _ => return Err("--session needs a session id".to_string()),
+            },
+            "--trades-only" => config.trades_only = true,
+            "--include-auctions" => config.include_auctions = true,
+            "--recursive" => config.recursive = true,
+            "--dump-headers" => config.dump_headers = true,
+            "--capture-raw" => config.capture_raw = true,
+            "--vwap" => config.vwap = true,
+            "--count-only" => config.count_only = true,
+            "--stop-at-end-of-messages" => config.stop_at_end_of_messages = true,
+            "--drop-zero-size" => config.zero_trades = config.zero_trades.with_drop_zero_size(true),
+            "--drop-zero-price" => config.zero_trades = config.zero_trades.with_drop_zero_price(true),
+            "--min-price" => match iter.next().map(|n| n.parse::<f64>()) {
+                Some(Ok(x)) if x >= 0.0 && x.is_finite() => min_price = Some(x),
+                _ => return Err("--min-price needs a price in dollars".to_string()),
+            },
+            "--max-price" => match iter.next().map(|n| n.parse::<f64>()) {
+                Some(Ok(x)) if x >= 0.0 && x.is_finite() => max_price = Some(x),
+                _ => return Err("--max-price needs a price in dollars".to_string()),
+            },
+            "--untraded-symbols" => config.untraded_symbols = true,
+            "--channel-summary" => config.channel_summary = true,
+            "--check-monotonic" => config.check_monotonic = true,
+            "--combined" => config.combined = true,
+            "--temp-dir" => match iter.next() {
+                Some(dir) => config.temp_dir = Some(dir.clone()),
+                None => return Err("--temp-dir needs a directory".to_string()),
+            },
+            "--resume" => config.resume = true,
+            "--flush-ticks" => match iter.next().map(|n| n.parse()) {
+                Some(Ok(n)) if n > 0 => flush_ticks = Some(n),
+                _ => return Err("--flush-ticks needs a positive number of ticks".to_string()),
+            },
+            "--replay" => config.replay = Some(iex_pcap_parser::Replay::default()),
+            "--speed" => match iter.next().map(|n| n.parse::<f64>()) {
+                Some(Ok(x)) if x > 0.0 && x.is_finite() => speed = Some(x),
+                _ => return Err("--speed needs a positive number".to_string()),
+            },
+            "--replay-clock" => match iter.next().map(String::as_str) {
+                Some("capture") => replay_clock = Some(iex_pcap_parser::ReplayClock::Capture),
+                Some("send") => replay_clock = Some(iex_pcap_parser::ReplayClock::SendTime),
+                _ => return Err("--replay-clock needs capture or send".to_string()),
+            },
+            "--bars" => match iter.next() {
+                Some(interval) => config.bars = Some(parse_bar_interval(interval)?),
+                None => return Err("--bars needs an interval".to_string()),
+            },
+            "--symbol-case" => {
+                let case = match iter.next().map(String::as_str) {
+                    Some("keep") => iex_pcap_parser::SymbolCase::Keep,
+                    Some("upper") => iex_pcap_parser::SymbolCase::Upper,
+                    Some("lower") => iex_pcap_parser::SymbolCase::Lower,
+                    _ => return Err("--symbol-case needs keep, upper or lower".to_string()),
+                };
+                config.symbol_normalizer = config.symbol_normalizer.with_case(case);
+            },
+            "--class-separator" => {
+                let mut chars = iter.next().map(|s| s.chars());
+                match chars.as_mut().map(|c| (c.next(), c.next())) {
+                    Some((Some(separator), None)) if separator != ' ' && separator != '/' => {
+                        config.symbol_normalizer = config.symbol_normalizer.with_class_separator(separator);
+                    },
+                    _ => return Err("--class-separator needs a single character other than space or /".to_string()),
+                }
+            },
+            "--symbol-collisions" => match iter.next().map(String::as_str) {
+                Some("merge") => config.symbol_collisions = SymbolCollisions::Merge,
+                Some("suffix") => config.symbol_collisions = SymbolCollisions::Suffix,
+                _ => return Err("--symbol-collisions needs merge or suffix".to_string()),
+            },
+            "--name-template" => match iter.next() {
+                Some(template) => config.name_template = NameTemplate::parse(template)?,
+                None => return Err("--name-template needs a template".to_string()),
+            },
+            "--overwrite" | "--no-clobber" => {
+                let flag_overwrite = arg == "--overwrite";
+                if overwrite.is_some_and(|o| o != flag_overwrite) {
+                    return Err("--overwrite and --no-clobber can't be used together".to_string());
+                }
+                overwrite = Some(flag_overwrite);
+            },
+            "--apply-breaks" => config.apply_breaks = true,
+            "--start" => match iter.next() {
+                Some(time) => config.start = Some(TimeBound::parse(time)?),
+                None => return Err("--start needs a time".to_string()),
+            },
+            "--end" => match iter.next() {
+                Some(time) => config.end = Some(TimeBound::parse(time)?),
+                None => return Err("--end needs a time".to_string()),
+            },
+            "--dst" => match iter.next().map(String::as_str) {
+                Some("reject") => config.dst = libdt::DstPolicy::Reject,
+                Some("resolve") => config.dst = libdt::DstPolicy::Resolve,
+                _ => return Err("--dst needs reject or resolve".to_string()),
+            },
+            "--max-errors" => match iter.next().map(|n| n.parse()) {
+                Some(Ok(n)) if n > 0 => config.max_errors = Some(n),
+                _ => return Err("--max-errors needs a number of errors above 0".to_string()),
+            },
+            "--max-packets" => match iter.next().map(|n| n.parse()) {
+                Some(Ok(n)) => config.max_packets = Some(n),
+                _ => return Err("--max-packets needs a number of packets".to_string()),
+            },
+            "--chunked" => config.write_options.layout = libh5::Layout::Chunked,
+            "--fixed-point" => config.write_options.fixed_point = true,
+            "--checksum" => config.write_options.checksum = true,
+            "--group-symbols" => match iter.next().map(|n| n.parse().ok().and_then(libh5::SymbolGrouping::from_prefix_len)) {
+                Some(Some(grouping)) if grouping != libh5::SymbolGrouping::Flat => {
+                    config.write_options.symbol_grouping = grouping;
+                },
+                _ => return Err("--group-symbols needs 1 or 2 letters".to_string()),
+            },
+            "--chunk-size" => match iter.next().map(|n| n.parse()) {
+                Some(Ok(n)) => {
+                    config.write_options.chunk_size = n;
+                    config.write_options.layout = libh5::Layout::Chunked;
+                },
+                _ => return Err("--chunk-size needs a number of ticks".to_string()),
+            },
+            flag if flag.len() > 1 && flag.starts_with('-') && flag[1..].chars().all(|c| c == 'v') => {
+                verbosity += flag.len() as i32 - 1;
+            },
+            flag if flag.len() > 1 && flag.starts_with('-') && flag[1..].chars().all(|c| c == 'q') => {
+                verbosity -= flag.len() as i32 - 1;
+            },
+            flag if flag.starts_with("--") => return Err(format!("unknown flag {}", flag)),
+            file => input = Some(Input::File(file.to_string())),
+        }
+    }
+    config.write_options.validate()?;
+    if let (Some(min), Some(max)) = (min_price, max_price) {
+        if min > max {
+            return Err(format!("--min-price {} is above --max-price {}", min, max));
+        }
+    }
+    if let Some(min) = min_price {
+        config.price_range = config.price_range.with_min(min);
+    }
+    if let Some(max) = max_price {
+        config.price_range = config.price_range.with_max(max);
+    }
+    match config.replay.as_mut() {
+        Some(replay) => {
+            if let Some(speed) = speed {
+                *replay = replay.with_speed(speed);
+            }
+            if let Some(clock) = replay_clock {
+                *replay = replay.with_clock(clock);
+            }
+        },
+        None if speed.is_some() => return Err("--speed needs --replay".to_string()),
+        None if replay_clock.is_some() => return Err("--replay-clock needs --replay".to_string()),
+        None => (),
+    }
+    let no_h5 = match (config.replay.is_some(), config.count_only) {
+        (true, true) => return Err("--replay and --count-only can't be used together".to_string()),
+        (true, false) => Some("--replay"),
+        (false, true) => Some("--count-only"),
+        (false, false) => None,
+    };
+    if let Some(mode) = no_h5 {
+        let h5_only = [
+            (config.sort, "--sort"),
+            (config.index_by_time, "--index-by-time"),
+            (config.split_by_symbol, "--split-by-symbol"),
+            (config.skip_test_securities, "--skip-test-securities"),
+            (config.apply_breaks, "--apply-breaks"),
+            (config.capture_raw, "--capture-raw"),
+            (config.dump_headers, "--dump-headers"),
+            (config.bars.is_some(), "--bars"),
+            (config.vwap, "--vwap"),
+            (config.combined, "--combined"),
+            (config.untraded_symbols, "--untraded-symbols"),
+        ];
+        if let Some((_, flag)) = h5_only.iter().find(|(set, _)| *set) {
+            return Err(format!("{} can't be used with {}, which writes no h5 file", flag, mode));
+        }
+    }
+    // Counting doesn't decode messages, so there are no timestamps to compare.
+    if config.count_only && (config.start.is_some() || config.end.is_some()) {
+        return Err("--start and --end can't be used with --count-only".to_string());
+    }
+    if config.name_template.uses("channel") && config.filter.channel_id.is_none() {
+        return Err("{channel} in --name-template needs --channel".to_string());
+    }
+    if config.split_by_symbol && config.name_template != NameTemplate::default() {
+        return Err("--name-template can't be used with --split-by-symbol".to_string());
+    }
+    if config.split_by_symbol && config.capture_raw {
+        return Err("--capture-raw can't be used with --split-by-symbol, whose files hold a single symbol".to_string());
+    }
+    if config.combined {
+        // Those all need a dataset per symbol.
+        let per_symbol = [
+            (config.split_by_symbol, "--split-by-symbol"),
+            (config.index_by_time, "--index-by-time"),
+            (config.write_options.fixed_point, "--fixed-point"),
+            (config.write_options.symbol_grouping != libh5::SymbolGrouping::Flat, "--group-symbols"),
+            (config.resume, "--resume"),
+            (matches!(input, Some(Input::Live(_))), "--live"),
+        ];
+        if let Some((_, flag)) = per_symbol.iter().find(|(set, _)| *set) {
+            return Err(format!("--combined can't be used with {}", flag));
+        }
+    }
+    if config.trades_only && config.skip_test_securities {
+        return Err("--skip-test-securities needs security directory messages, which --trades-only skips".to_string());
+    }
+    if config.trades_only && config.include_auctions {
+        return Err("--include-auctions needs auction information messages, which --trades-only skips".to_string());
+    }
+    if config.trades_only && config.untraded_symbols {
+        return Err("--untraded-symbols needs security directory messages, which --trades-only skips".to_string());
+    }
+    if config.split_by_symbol && config.untraded_symbols {
+        return Err("--untraded-symbols can't be used with --split-by-symbol, whose files hold a single symbol".to_string());
+    }
+    if config.channel_summary && config.count_only {
+        return Err("--channel-summary can't be used with --count-only, which doesn't decode messages".to_string());
+    }
+    if config.channel_summary && config.replay.is_some() {
+        return Err("--channel-summary can't be used with --replay".to_string());
+    }
+    if config.check_monotonic && (config.count_only || config.replay.is_some()) {
+        return Err("--check-monotonic needs the ticks written, so can't be used with --count-only or --replay".to_string());
+    }
+    if config.stop_at_end_of_messages {
+        let undecoded = [
+            (config.trades_only, "--trades-only, which skips system events"),
+            (config.dump_headers, "--dump-headers, which doesn't decode messages"),
+            (config.count_only, "--count-only, which doesn't decode messages"),
+            (config.replay.is_some(), "--replay"),
+        ];
+        if let Some((_, flag)) = undecoded.iter().find(|(set, _)| *set) {
+            return Err(format!("--stop-at-end-of-messages can't be used with {}", flag));
+        }
+    }
+    if config.resume {
+        // Captures are written out one at a time, so nothing that needs all of the day's ticks at
+        // once can be used.
+        let whole_day = [
+            (config.replay.is_some(), "--replay"),
+            (config.count_only, "--count-only"),
+            (config.dump_headers, "--dump-headers"),
+            (config.sort, "--sort"),
+            (config.index_by_time, "--index-by-time"),
+            (config.split_by_symbol, "--split-by-symbol"),
+            (config.apply_breaks, "--apply-breaks"),
+            (config.capture_raw, "--capture-raw"),
+            (config.untraded_symbols, "--untraded-symbols"),
+            (config.channel_summary, "--channel-summary"),
+            (config.check_monotonic, "--check-monotonic"),
+            (config.bars.is_some(), "--bars"),
+            (config.vwap, "--vwap"),
+            (config.max_packets.is_some(), "--max-packets"),
+            (config.symbol_collisions == SymbolCollisions::Suffix, "--symbol-collisions suffix"),
+            (matches!(input, Some(Input::Live(_))), "--live"),
+        ];
+        if let Some((_, flag)) = whole_day.iter().find(|(set, _)| *set) {
+            return Err(format!("{} can't be used with --resume", flag));
+        }
+    }
+    if let Some(Input::Live(_)) = input {
+        if config.sort {
+            return Err("--sort can't be used with --live".to_string());
+        }
+        if config.index_by_time {
+            return Err("--index-by-time can't be used with --live".to_string());
+        }
+        if config.apply_breaks {
+            return Err("--apply-breaks can't be used with --live".to_string());
+        }
+        if config.split_by_symbol {
+            return Err("--split-by-symbol can't be used with --live".to_string());
+        }
+        if config.max_packets.is_some() {
+            return Err("--max-packets can't be used with --live".to_string());
+        }
+        if config.recursive {
+            return Err("--recursive can't be used with --live".to_string());
+        }
+        if config.bars.is_some() {
+            return Err("--bars can't be used with --live".to_string());
+        }
+        if config.vwap {
+            return Err("--vwap can't be used with --live".to_string());
+        }
+        if config.replay.is_some() {
+            return Err("--replay can't be used with --live".to_string());
+        }
+        if config.count_only {
+            return Err("--count-only can't be used with --live".to_string());
+        }
+        if config.check_monotonic {
+            return Err("--check-monotonic can't be used with --live".to_string());
+        }
+        if config.temp_dir.is_some() {
+            return Err("--temp-dir can't be used with --live".to_string());
+        }
+    }
+    match (flush_ticks, &input) {
+        (Some(n), Some(Input::Live(_))) => config.flush_ticks = n,
+        (Some(_), _) => return Err("--flush-ticks needs --live".to_string()),
+        (None, _) => (),
+    }
+    config.input = input.ok_or_else(|| "missing input".to_string())?;
+    // The index relies on ticks being in timestamp order.
+    config.sort |= config.index_by_time;
+    config.overwrite = overwrite.unwrap_or(false);
+    config.log_level = log_level_for_verbosity(verbosity);
+    Ok(config)
+}
+
+const PCAP_GLOBAL_HEADER_LEN: u64 = 24;
+const PCAP_RECORD_HEADER_LEN: u64 = 16;
+/// Redrawing on every packet would slow down parsing noticeably.
+const PROGRESS_UPDATE_INTERVAL: u64 = 4096;
+
+/// Progress is measured in bytes read when the size of the capture is known upfront, and in
+/// packets otherwise (compressed input, live capture).
+struct Progress {
+    bar: indicatif::ProgressBar,
+    by_bytes: bool,
+}
+
+impl Progress {
+    fn new(input: &Input) -> Progress {
+        let total_bytes = match input {
+            Input::File(file) if file.ends_with(".pcap") => fs::metadata(file).ok().map(|m| m.len()),
+            _ => None,
+        };
+        match total_bytes {
+            Some(total_bytes) => {
+                let style = indicatif::ProgressStyle::with_template(
+                    "{wide_bar} {bytes}/{total_bytes} [{elapsed_precise}, ETA {eta}]")
+                    .expect("valid progress template");
+                Progress {
+                    bar: indicatif::ProgressBar::new(total_bytes).with_style(style),
+                    by_bytes: true,
+                }
+            },
+            None => {
+                let style = indicatif::ProgressStyle::with_template(
+                    "{spinner} {pos} packets [{elapsed_precise}]")
+                    .expect("valid progress template");
+                Progress {
+                    bar: indicatif::ProgressBar::new_spinner().with_style(style),
+                    by_bytes: false,
+                }
+            },
+        }
+    }
+
+    fn update(&self, packets: u64, bytes: u64) {
+        if packets % PROGRESS_UPDATE_INTERVAL == 0 {
+            self.bar.set_position(if self.by_bytes { bytes } else { packets });
+        }
+    }
+}
+
+/// The captures to read for an input argument: the file itself, or the captures in a directory.
+fn captures_for_input(input: &str, recursive: bool) -> Result<Vec<String>, String> {
+    if !path::Path::new(input).is_dir() {
+        if recursive {
+            return Err(format!("--recursive needs a directory, not {}", input));
+        }
+        return Ok(vec![input.to_string()]);
+    }
+    let captures: Vec<String> = libiex::deep_pcaps_in_dir(input, recursive)
+        .map_err(|e| format!("Failed to list {}: {}", input, e))?
+        .iter()
+        .filter_map(|p| p.to_str().map(String::from))
+        .collect();
+    if captures.is_empty() {
+        return Err(format!("no captures in {}", input));
+    }
+    Ok(captures)
+}
+
+/// The trade date that all captures' file names agree on.
+fn trade_date_of_captures(captures: &[String]) -> Result<chrono::NaiveDate, String> {
+    let mut trade_date = None;
+    for capture in captures {
+        let date = libiex::trade_date_from_deep_pcap(capture)
+            .map_err(|e| format!("No trade date in {}: {:?}", capture, e))?;
+        match trade_date {
+            Some((first, first_date)) if first_date != date => {
+                return Err(format!("{} is from {} but {} is from {}", capture, date, first, first_date));
+            },
+            Some(_) => {},
+            None => trade_date = Some((capture, date)),
+        }
+    }
+    trade_date.map(|(_, date)| date).ok_or_else(|| "no captures".to_string())
+}
+
+/// --strict makes any anomaly in a packet fatal instead of skipping and counting it.
+fn parse_mode(args: &ParserConfig) -> iex_pcap_parser::ParseMode {
+    if args.strict {
+        iex_pcap_parser::ParseMode::Strict
+    } else {
+        iex_pcap_parser::ParseMode::Lenient
+    }
+}
+
+fn open_capture(pcap: &str, args: &ParserConfig, max_packets: Option<u64>) -> iex_pcap_parser::Packets {
+    info!("reading {}", pcap);
+    let temp_dir = args.temp_dir.as_ref().map_or_else(env::temp_dir, path::PathBuf::from);
+    match iex_pcap_parser::packets_from_capture_with_temp_dir(pcap, &temp_dir) {
+        Ok(packets) => packets
+            .with_mode(parse_mode(args))
+            .with_filter(args.filter)
+            .with_trades_only(args.trades_only)
+            .with_skip_auctions(!args.include_auctions)
+            .with_headers_only(args.dump_headers)
+            .with_max_packets(max_packets),
+        Err(e) => panic!("Failed to load {} with error: {:?}", pcap, e),
+    }
+}
+
+/// The symbol of a security directory message that marks a test security, normalized like the
+/// dataset names it's compared against.
+fn test_security_symbol(message: &iex_pcap_parser::IexDeepMessage, normalizer: &iex_pcap_parser::SymbolNormalizer)
+    -> Option<String> {
+    match &message.body {
+        iex_pcap_parser::IexDeepMessageImpl::SecurityDirectory(m) if m.flags.is_test_security() => {
+            message.normalized_symbol(normalizer)
+        },
+        _ => None,
+    }
+}
+
+/// Drops the ticks of test securities, returning how many were dropped.
+fn remove_test_securities<T>(stonks_ticks: &mut HashMap<String, Vec<T>>,
+                          test_symbols: &HashSet<String>) -> usize {
+    let mut removed = 0;
+    for symbol in test_symbols {
+        if let Some(ticks) = stonks_ticks.remove(symbol) {
+            removed += ticks.len();
         }
-    };
+    }
+    removed
 }
 
-macro_rules! bytes_u16 {
-    ( $arr:expr, $offset:expr ) => {
-        {
-            _index_offset!($arr, $offset, u16, 0) +
-            _index_offset!($arr, $offset, u16, 1)
+/// Symbols with ticks whose timestamps don't fall on `trade_date`, sorted by symbol, along with how
+/// many there are and the date of the first one. A capture whose file name doesn't match its
+/// contents would otherwise be written under the wrong date.
+fn ticks_off_trade_date(stonks_ticks: &HashMap<String, Vec<libh5::Tick>>,
+                        trade_date: &chrono::NaiveDate) -> Vec<(String, usize, Option<chrono::NaiveDate>)> {
+    let mut mismatches = Vec::new();
+    for (symbol, ticks) in stonks_ticks {
+        let mut dates = ticks.iter()
+            .map(|t| libdt::naive_date_for_utc_ns_in(libdt::UtcNs::from_nanos(t.timestamp), &libdt::UsEastern))
+            .filter(|date| *date != Some(*trade_date));
+        if let Some(first) = dates.next() {
+            mismatches.push((symbol.clone(), dates.count() + 1, first));
         }
-    };
+    }
+    mismatches.sort();
+    mismatches
 }
 
-macro_rules! bytes_u32 {
-    ( $arr:expr, $offset:expr ) => {
-        {
-            _index_offset!($arr, $offset, u32, 0) +
-            _index_offset!($arr, $offset, u32, 1) +
-            _index_offset!($arr, $offset, u32, 2) +
-            _index_offset!($arr, $offset, u32, 3)
+/// How many ticks out of timestamp order `--check-monotonic` logs.
+const OUT_OF_ORDER_SHOWN: usize = 5;
+
+/// Ticks whose timestamp is before that of their symbol's previous tick in sequence number order,
+/// as `(symbol, message_sequence_number, previous timestamp, timestamp)` sorted by symbol and
+/// sequence number. IEX sends messages in timestamp order, so these are clock anomalies or
+/// timestamps read from the wrong offset.
+fn timestamps_out_of_order(stonks_ticks: &HashMap<String, Vec<libh5::Tick>>) -> Vec<(String, u64, u64, u64)> {
+    let mut out_of_order = Vec::new();
+    for (symbol, ticks) in stonks_ticks {
+        let mut ticks: Vec<&libh5::Tick> = ticks.iter().collect();
+        ticks.sort_by_key(|t| t.message_sequence_number);
+        for pair in ticks.windows(2) {
+            if pair[1].timestamp < pair[0].timestamp {
+                out_of_order.push((symbol.clone(), pair[1].message_sequence_number, pair[0].timestamp,
+                                   pair[1].timestamp));
+            }
+        }
+    }
+    out_of_order.sort();
+    out_of_order
+}
+
+/// Flags the auctions scheduled for a date other than `trade_date`, returning how many there are.
+fn flag_auctions_off_trade_date(auctions: &mut HashMap<String, Vec<libh5::Auction>>,
+                                trade_date: &chrono::NaiveDate) -> usize {
+    let mut flagged = 0;
+    for auction in auctions.values_mut().flatten() {
+        let scheduled = libdt::UtcNs::from_nanos(auction.scheduled_auction_time);
+        auction.scheduled_off_trade_date =
+            libdt::naive_date_for_utc_ns_in(scheduled, &libdt::UsEastern) != Some(*trade_date);
+        if auction.scheduled_off_trade_date {
+            flagged += 1;
+        }
+    }
+    flagged
+}
+
+/// How many messages became written ticks. Only trades and price level updates are tradeable, so
+/// a sudden drop in their share of all messages usually means they failed to parse.
+struct TickYield {
+    written: usize,
+    tradeable: usize,
+    messages: usize,
+}
+
+fn percent(part: usize, whole: usize) -> f64 {
+    if whole == 0 {
+        0.0
+    } else {
+        100.0 * part as f64 / whole as f64
+    }
+}
+
+impl TickYield {
+    fn tradeable_percent(&self) -> f64 {
+        percent(self.tradeable, self.messages)
+    }
+
+    /// Below 100% when ticks were dropped, e.g. those of test securities.
+    fn written_percent(&self) -> f64 {
+        percent(self.written, self.tradeable)
+    }
+}
+
+impl fmt::Display for TickYield {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "wrote {} ticks from {} tradeable messages ({:.2}% of all {} messages)",
+               self.written, self.tradeable, self.tradeable_percent(), self.messages)
+    }
+}
+
+/// Drops symbols that can't be written, returning each with how many ticks it had: those that are
+/// blank, contain the HDF5 path separator, clash with the index datasets, or differ only in padding
+/// from a symbol in `dataset_names`, which maps trimmed names to the symbols kept so far. The
+/// trimmed name is the one split output files are named after.
+fn remove_unwritable_symbols<T>(stonks_ticks: &mut HashMap<String, Vec<T>>,
+                             dataset_names: &mut HashMap<String, String>) -> Vec<(String, usize)> {
+    let mut symbols: Vec<String> = stonks_ticks.keys().cloned().collect();
+    symbols.sort();
+    let mut removed = Vec::new();
+    for symbol in symbols {
+        let name = symbol.trim_end().to_string();
+        let writable = !name.trim().is_empty()
+            && !name.contains('/')
+            && name != libh5::SYMBOL_INDEX_DATASET
+            && name != libh5::TIME_INDEX_GROUP
+            && name != libh5::RAW_MESSAGES_DATASET
+            && name != libh5::OFFICIAL_PRICES_GROUP
+            && name != libh5::BARS_GROUP
+            && name != libh5::AUCTIONS_GROUP;
+        let kept = match dataset_names.get(&name) {
+            Some(kept) => *kept == symbol,
+            None if writable => {
+                dataset_names.insert(name, symbol.clone());
+                true
+            },
+            None => false,
+        };
+        if !kept {
+            let ticks = stonks_ticks.remove(&symbol).map_or(0, |t| t.len());
+            removed.push((symbol, ticks));
+        }
+    }
+    removed
+}
+
+/// What to do with distinct symbols that normalize to the same name, which only corrupt data has.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum SymbolCollisions {
+    /// Stores them together under the name.
+    Merge,
+    /// Keeps the later ones apart under the name suffixed with `~2`, `~3` and so on.
+    Suffix,
+}
+
+/// Names symbols as they're collected, noticing distinct symbols that normalize alike.
+#[derive(Default)]
+struct SymbolNames {
+    names: HashMap<iex_pcap_parser::MessageSymbol, String>,
+    // How many distinct symbols normalized to each name.
+    symbols: HashMap<String, usize>,
+    /// Each symbol that collided with an earlier one, with the name it got.
+    collisions: Vec<(String, String)>,
+}
+
+impl SymbolNames {
+    fn name(&mut self, symbol: &iex_pcap_parser::MessageSymbol, normalizer: &iex_pcap_parser::SymbolNormalizer,
+            collisions: SymbolCollisions) -> String {
+        if let Some(name) = self.names.get(symbol) {
+            return name.clone();
+        }
+        let normalized = normalizer.normalize(symbol);
+        let seen = self.symbols.entry(normalized.clone()).or_insert(0);
+        *seen += 1;
+        let seen = *seen;
+        let name = match (seen, collisions) {
+            (1, _) | (_, SymbolCollisions::Merge) => normalized.clone(),
+            (n, SymbolCollisions::Suffix) => {
+                // A shortened name can be another symbol's, so it's taken like one.
+                let name = (n..).map(|n| suffixed_name(&normalized, n))
+                    .find(|name| !self.symbols.contains_key(name))
+                    .expect("Some suffix is free");
+                self.symbols.insert(name.clone(), 1);
+                name
+            },
+        };
+        if seen > 1 {
+            let raw: String = symbol.iter().collect();
+            warn!("Symbol {:?} normalizes to {:?} like another symbol; storing it as {:?}",
+                  raw, normalized.trim_end(), name.trim_end());
+            self.collisions.push((raw, name.clone()));
+        }
+        self.names.insert(*symbol, name.clone());
+        name
+    }
+}
+
+/// `normalized` ending in `~n`, cut short to fit the 8 bytes symbols are stored in. Padded names
+/// stay padded to the same width.
+fn suffixed_name(normalized: &str, n: usize) -> String {
+    let suffix = format!("~{}", n);
+    let mut name = String::new();
+    for c in normalized.trim_end().chars() {
+        if name.len() + c.len_utf8() + suffix.len() > 8 {
+            break;
+        }
+        name.push(c);
+    }
+    name.push_str(&suffix);
+    format!("{:<width$}", name, width = normalized.len())
+}
+
+/// Where each trade was stored by trade id, so a trade break can remove the trade it cancels. Only
+/// works while ticks stay in memory, so not for live captures.
+#[derive(Default)]
+struct TradeBreaks {
+    trades: HashMap<u64, (String, usize)>,
+    broken: HashMap<String, HashSet<usize>>,
+}
+
+impl TradeBreaks {
+    fn record_trade(&mut self, trade_id: u64, symbol: &str, index: usize) {
+        self.trades.insert(trade_id, (symbol.to_string(), index));
+    }
+
+    /// Returns false if no trade with the id was seen.
+    fn record_break(&mut self, trade_id: u64) -> bool {
+        match self.trades.get(&trade_id) {
+            Some((symbol, index)) => {
+                self.broken.entry(symbol.clone()).or_insert_with(HashSet::new).insert(*index);
+                true
+            },
+            None => false,
         }
+    }
+
+    /// Drops the broken trades, returning how many were dropped. Must be called before the ticks
+    /// are reordered.
+    fn remove_broken(&self, stonks_ticks: &mut HashMap<String, Vec<libh5::Tick>>) -> usize {
+        let mut removed = 0;
+        for (symbol, indices) in &self.broken {
+            if let Some(ticks) = stonks_ticks.get_mut(symbol) {
+                let mut i = 0;
+                ticks.retain(|_| {
+                    i += 1;
+                    !indices.contains(&(i - 1))
+                });
+                removed += indices.len();
+            }
+        }
+        removed
+    }
+}
+
+fn flush_ticks(file: &hdf5::File, stonks_ticks: &mut HashMap<String, Vec<libh5::Tick>>,
+               options: &libh5::WriteOptions) {
+    for (symbol, ticks) in stonks_ticks.iter_mut() {
+        if ticks.is_empty() {
+            continue;
+        }
+        info!("flushing {} ticks for symbol {}", ticks.len(), symbol);
+        if let Err(e) = libh5::append_ticks_with_options(file, symbol, ticks, options) {
+            panic!("Failed to append ticks for {}: {}", symbol, e);
+        }
+        ticks.clear();
+    }
+}
+
+fn open_output<P: AsRef<path::Path>>(path: P, options: &libh5::WriteOptions, interrupted: bool) -> hdf5::File {
+    let file = match hdf5::file::File::open(&path, "w") {
+        Ok(f) => f,
+        Err(e) => panic!("Failed to open hdf5 handle: {}", e),
     };
+    if let Err(e) = libh5::write_versions(&file, env!("CARGO_PKG_VERSION"), options) {
+        panic!("Failed to write versions to {:?}: {}", path.as_ref(), e);
+    }
+    if interrupted {
+        if let Err(e) = libh5::write_attr(&file, libh5::INCOMPLETE_ATTR, &true) {
+            panic!("Failed to mark {:?} as incomplete: {}", path.as_ref(), e);
+        }
+    }
+    file
 }
 
-macro_rules! bytes_u64 {
-    ( $arr:expr, $offset:expr ) => {
-        {
-            _index_offset!($arr, $offset, u64, 0) +
-            _index_offset!($arr, $offset, u64, 1) +
-            _index_offset!($arr, $offset, u64, 2) +
-            _index_offset!($arr, $offset, u64, 3) +
-            _index_offset!($arr, $offset, u64, 4) +
-            _index_offset!($arr, $offset, u64, 5) +
-            _index_offset!($arr, $offset, u64, 6) +
-            _index_offset!($arr, $offset, u64, 7)
+/// Output files of `trade_date` already in `dir`: the combined file `output`, or every per-symbol
+/// file if `split_by_symbol`, since which symbols there will be isn't known before parsing.
+fn existing_outputs(dir: &path::Path, trade_date: &chrono::NaiveDate, output: &str, split_by_symbol: bool)
+    -> io::Result<Vec<path::PathBuf>> {
+    if !split_by_symbol {
+        let path = dir.join(output);
+        return Ok(if path.exists() { vec![path] } else { Vec::new() });
+    }
+    let mut existing = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let date = path.to_str().and_then(|p| libfs::symbol_and_date_from_h5(p).ok()).map(|(_, date)| date);
+        if date == Some(*trade_date) {
+            existing.push(path);
         }
+    }
+    existing.sort();
+    Ok(existing)
+}
+
+/// A time index can only be written for `sorted` ticks.
+fn write_dataset(file: &hdf5::File, symbol: &str, ticks: &[libh5::Tick], options: &libh5::WriteOptions,
+                 sorted: bool, index_by_time: bool) {
+    info!("writing {} ticks for symbol {}", ticks.len(), symbol);
+    let dataset = match libh5::write_ticks(file, symbol, ticks, options) {
+        Ok(x) => x,
+        Err(e) => panic!("Failed to write ticks for {}: {}", symbol, e),
     };
+    if let Err(e) = libh5::write_attr(&dataset, libh5::SORTED_ATTR, &sorted) {
+        panic!("Failed to write sorted attribute for {}: {}", symbol, e);
+    }
+    if sorted && index_by_time {
+        if let Err(e) = libh5::write_time_index(file, symbol, ticks) {
+            panic!("Failed to write time index for {}: {}", symbol, e);
+        }
+    }
 }
 
-type MessageSymbol = [char; 8];
-
-fn get_price_multiplier_for_timestamp(_timestamp: u64) -> u64 {
-    10000
-}
-
-struct IexDeepMessage {
-    message_type: u8,
-    message_subtype: u8,
-    timestamp: u64,
-    body: IexDeepMessageImpl,
-    packet_number: u64,
-    message_sequence_number: u64,
-}
-
-impl IexDeepMessage {
-    fn to_serialized_tick(&self) -> Option<libh5::Tick> {
-        match &self.body {
-            IexDeepMessageImpl::TradeReport(m) => {
-                Some(libh5::Tick {
-                    message_type: self.message_type,
-                    message_subtype: self.message_subtype,
-                    timestamp: self.timestamp,
-                    size: m.size,
-                    price: m.price,
-                    price_multiplier: get_price_multiplier_for_timestamp(self.timestamp),
-                    packet_number: self.packet_number,
-                    message_sequence_number: self.message_sequence_number,
-                })
-            },
-            IexDeepMessageImpl::PriceLevelUpdate(m) => {
-                Some(libh5::Tick {
-                    message_type: self.message_type,
-                    message_subtype: self.message_subtype,
-                    timestamp: self.timestamp,
-                    size: m.size,
-                    price: m.price,
-                    price_multiplier: get_price_multiplier_for_timestamp(self.timestamp),
-                    packet_number: self.packet_number,
-                    message_sequence_number: self.message_sequence_number,
-                })
-            },
-            _ => None,
+/// Every symbol's ticks as rows of `--combined` output: in timestamp order across symbols if
+/// sorted, otherwise symbol by symbol in name order.
+fn combined_ticks(stonks_ticks: &HashMap<String, Vec<libh5::Tick>>, sorted: bool) -> Vec<libh5::TickWithSymbol> {
+    let mut symbols: Vec<&String> = stonks_ticks.keys().collect();
+    symbols.sort();
+    let mut rows = symbols.into_iter()
+        .flat_map(|symbol| stonks_ticks[symbol].iter().map(move |tick| libh5::TickWithSymbol::new(symbol, tick)))
+        .collect::<hdf5::Result<Vec<_>>>()
+        .unwrap_or_else(|e| panic!("Failed to combine ticks: {}", e));
+    if sorted {
+        rows.sort_by_key(|t| (t.timestamp, t.message_sequence_number));
+    }
+    rows
+}
+
+fn write_channel_id(file: &hdf5::File, channel_id: Option<u32>) {
+    if let Some(channel_id) = channel_id {
+        if let Err(e) = libh5::write_attr(file, libh5::CHANNEL_ID_ATTR, &channel_id) {
+            panic!("Failed to write channel id: {}", e);
         }
     }
+}
 
-    fn symbol(&self) -> Option<String> {
-        match &self.body {
-            IexDeepMessageImpl::TradeReport(m) => Some(m.symbol.into_iter().collect()),
-            IexDeepMessageImpl::PriceLevelUpdate(m) => Some(m.symbol.into_iter().collect()),
-            _ => None,
+/// Records the captures ticks were read from; live output has none.
+fn write_source_files(file: &hdf5::File, sources: &[String]) {
+    if sources.is_empty() {
+        return;
+    }
+    if let Err(e) = libh5::write_source_files(file, sources) {
+        panic!("Failed to write source files: {}", e);
+    }
+}
+
+/// A capture's file name without its directory.
+fn source_name(capture: &str) -> String {
+    path::Path::new(capture).file_name()
+        .map_or_else(|| capture.to_string(), |name| name.to_string_lossy().into_owned())
+}
+
+/// The channel all ticks came from, if there was only one: either the one filtered for, or the
+/// only one seen.
+fn single_channel(channels: &BTreeSet<(u32, u32)>, filter: &iex_pcap_parser::ChannelFilter) -> Option<u32> {
+    let ids: BTreeSet<u32> = channels.iter()
+        .map(|(channel, _)| *channel)
+        .filter(|id| filter.channel_id.is_none_or(|f| f == *id))
+        .collect();
+    if ids.len() == 1 { ids.into_iter().next() } else { None }
+}
+
+/// Ticks of a merge input, with the input's channel id if it has one.
+type InputTicks = (Option<u32>, Vec<libh5::Tick>);
+
+/// Concatenates the ticks of several inputs, each from the channel given with it, in their
+/// `GlobalSequence` order: by timestamp, then channel id, then message sequence number. Ticks of
+/// inputs without a known channel come after those of known channels at the same timestamp, and
+/// among themselves keep their input order.
+fn global_order(inputs: Vec<InputTicks>) -> Vec<libh5::Tick> {
+    let mut keyed: Vec<(iex_pcap_parser::GlobalSequence, libh5::Tick)> = inputs.into_iter()
+        .flat_map(|(channel_id, ticks)| ticks.into_iter().map(move |tick| (iex_pcap_parser::GlobalSequence {
+            timestamp: tick.timestamp,
+            channel_id: channel_id.unwrap_or(u32::MAX),
+            message_sequence_number: tick.message_sequence_number,
+        }, tick)))
+        .collect();
+    keyed.sort_by_key(|(key, _)| *key);
+    keyed.into_iter().map(|(_, tick)| tick).collect()
+}
+
+fn write_official_prices(file: &hdf5::File, symbol: &str, official_prices: &HashMap<String, Vec<libh5::Tick>>) {
+    if let Some(prices) = official_prices.get(symbol) {
+        if let Err(e) = libh5::write_official_prices(file, symbol, prices) {
+            panic!("Failed to write official prices for {}: {}", symbol, e);
         }
     }
 }
 
-// TODO(sherry): codegen the impls
+/// VWAP, volume and last price of each symbol with trades, by symbol.
+fn symbol_vwaps(stonks_ticks: &HashMap<String, Vec<libh5::Tick>>) -> Vec<(String, libh5::AccumulatorSnapshot)> {
+    let mut vwaps: Vec<(String, libh5::AccumulatorSnapshot)> = stonks_ticks.iter()
+        .map(|(symbol, ticks)| {
+            let mut accumulator = libh5::Accumulator::default();
+            for tick in ticks {
+                accumulator.update(tick);
+            }
+            (symbol.clone(), accumulator.snapshot())
+        })
+        .filter(|(_, snapshot)| snapshot.vwap.is_some())
+        .collect();
+    vwaps.sort_by(|a, b| a.0.cmp(&b.0));
+    vwaps
+}
 
-#[derive(hdf5::H5Type, Clone, PartialEq, Debug)]
-#[repr(u8)]
-enum SystemEvent {
-    StartOfMessages             = 'O' as u8,
-    StartOfSystemHours          = 'S' as u8,
-    StartOfRegularMarketHours   = 'R' as u8,
-    EndOfRegularMarketHours     = 'M' as u8,
-    EndOfSystemHours            = 'E' as u8,
-    EndOfMessages               = 'C' as u8,
+/// Aggregates the symbol's trades into bars of `interval` on the Eastern clock.
+fn symbol_bars(ticks: &[libh5::Tick], interval: time::Duration) -> Vec<libh5::Bar> {
+    libh5::bars(ticks, |timestamp| {
+        match libdt::bucket_start_in(libdt::UtcNs::from_nanos(timestamp), interval, &libdt::UsEastern) {
+            Some(start) => start.as_nanos(),
+            None => panic!("No Eastern midnight before timestamp {} to start bars from", timestamp),
+        }
+    })
 }
 
-impl SystemEvent {
-    fn from_u8(byte: u8) -> Option<SystemEvent> {
-        match byte as char {
-            'O' => Some(SystemEvent::StartOfMessages),
-            'S' => Some(SystemEvent::StartOfSystemHours),
-            'R' => Some(SystemEvent::StartOfRegularMarketHours),
-            'M' => Some(SystemEvent::EndOfRegularMarketHours),
-            'E' => Some(SystemEvent::EndOfSystemHours),
-            'C' => Some(SystemEvent::EndOfMessages),
-            _ => None,
+fn write_bars(file: &hdf5::File, symbol: &str, ticks: &[libh5::Tick], interval: time::Duration) {
+    let bars = symbol_bars(ticks, interval);
+    if bars.is_empty() {
+        return;
+    }
+    if let Err(e) = libh5::write_bars(file, symbol, &bars, interval.as_nanos() as u64) {
+        panic!("Failed to write bars for {}: {}", symbol, e);
+    }
+}
+
+fn write_auctions(file: &hdf5::File, symbol: &str, auctions: &HashMap<String, Vec<libh5::Auction>>) {
+    if let Some(auctions) = auctions.get(symbol) {
+        if let Err(e) = libh5::write_auctions(file, symbol, auctions) {
+            panic!("Failed to write auctions for {}: {}", symbol, e);
         }
     }
 }
 
-#[derive(hdf5::H5Type, Clone, PartialEq, Debug)]
-#[repr(u8)]
-enum LimitUpLimitDownTier {
-    NotApplicable = 0x0,
-    Tier1NmsStock = 0x1,
-    Tier2NmsStock = 0x2,
+/// How the files of `write_split_by_symbol` are written, besides what goes in them.
+struct OutputOptions<'a> {
+    write_options: &'a libh5::WriteOptions,
+    sorted: bool,
+    index_by_time: bool,
+    /// The files are marked incomplete.
+    interrupted: bool,
+    channel_id: Option<u32>,
+    bars: Option<time::Duration>,
+    sources: &'a [String],
 }
 
-impl LimitUpLimitDownTier {
-    fn from_u8(byte: u8) -> Option<LimitUpLimitDownTier> {
-        match byte {
-            0x0 => Some(LimitUpLimitDownTier::NotApplicable),
-            0x1 => Some(LimitUpLimitDownTier::Tier1NmsStock),
-            0x2 => Some(LimitUpLimitDownTier::Tier2NmsStock),
-            _ => None,
+/// Writes each symbol's ticks, official prices, auctions and bars into its own `YYYYmmdd_SYMBOL.h5` in
+/// `dir`, returning the files written.
+fn write_split_by_symbol(dir: &path::Path, trade_date: &chrono::NaiveDate,
+                         stonks_ticks: &HashMap<String, Vec<libh5::Tick>>,
+                         official_prices: &HashMap<String, Vec<libh5::Tick>>,
+                         auctions: &HashMap<String, Vec<libh5::Auction>>,
+                         output: &OutputOptions) -> Vec<path::PathBuf> {
+    let mut paths = Vec::new();
+    for (symbol, ticks) in stonks_ticks {
+        let path = dir.join(libfs::h5_for_symbol(trade_date, symbol));
+        let file = open_output(&path, output.write_options, output.interrupted);
+        write_channel_id(&file, output.channel_id);
+        write_source_files(&file, output.sources);
+        write_dataset(&file, symbol, ticks, output.write_options, output.sorted, output.index_by_time);
+        write_official_prices(&file, symbol, official_prices);
+        write_auctions(&file, symbol, auctions);
+        if let Some(interval) = output.bars {
+            write_bars(&file, symbol, ticks, interval);
         }
+        paths.push(path);
     }
+    paths
 }
 
-#[derive(hdf5::H5Type, Clone, PartialEq, Debug)]
-#[repr(u8)]
-enum TradingStatus {
-    Halted                                  = 'H' as u8,
-    HaltReleasedIntoOrderAcceptancePeriod   = 'O' as u8,
-    PausedAndOrderAcceptancePeriod          = 'P' as u8,
-    Trading                                 = 'T' as u8,
+const RESUME_MANIFEST_HEADER: &str = "iex_pcap_parser resume 1";
+
+/// Progress of a --resume run, kept next to its output until the run is done. It's text, one
+/// tab-separated record per line after the header:
+///
+/// ```text
+/// capture <path>                  a capture fully written to the output
+/// rows    <count>  <dataset>      the rows a dataset has once those captures are written
+/// test    <symbol>                a test security, whose ticks later captures drop too
+/// channel <channel> <session>     a channel and session seen
+/// ```
+///
+/// Datasets are named by their path in the file, so official prices are `_official_prices/SYMBOL`.
+/// Anything past the recorded rows, or any dataset not recorded, is from a capture that was being
+/// written when the run stopped.
+#[derive(Debug, Default, PartialEq)]
+struct ResumeManifest {
+    captures: Vec<String>,
+    rows: BTreeMap<String, u64>,
+    test_symbols: BTreeSet<String>,
+    channels: BTreeSet<(u32, u32)>,
 }
 
-impl TradingStatus {
-    fn from_u8(byte: u8) -> Option<TradingStatus> {
-        match byte as char {
-            'H' => Some(TradingStatus::Halted),
-            'O' => Some(TradingStatus::HaltReleasedIntoOrderAcceptancePeriod),
-            'P' => Some(TradingStatus::PausedAndOrderAcceptancePeriod),
-            'T' => Some(TradingStatus::Trading),
-            _ => None,
+impl ResumeManifest {
+    fn path(output: &str) -> path::PathBuf {
+        path::PathBuf::from(format!("{}.progress", output))
+    }
+
+    fn parse(text: &str) -> Result<ResumeManifest, String> {
+        let mut lines = text.lines();
+        if lines.next() != Some(RESUME_MANIFEST_HEADER) {
+            return Err(format!("expected a manifest starting with {:?}", RESUME_MANIFEST_HEADER));
+        }
+        let mut manifest = ResumeManifest::default();
+        for line in lines {
+            let fields: Vec<&str> = line.splitn(3, '\t').collect();
+            let invalid = || format!("invalid manifest line {:?}", line);
+            match fields.as_slice() {
+                ["capture", path] => manifest.captures.push(path.to_string()),
+                ["rows", count, dataset] => {
+                    manifest.rows.insert(dataset.to_string(), count.parse().map_err(|_| invalid())?);
+                },
+                ["test", symbol] => {
+                    manifest.test_symbols.insert(symbol.to_string());
+                },
+                ["channel", channel, session] => {
+                    let channel = channel.parse().map_err(|_| invalid())?;
+                    manifest.channels.insert((channel, session.parse().map_err(|_| invalid())?));
+                },
+                _ => return Err(invalid()),
+            }
+        }
+        Ok(manifest)
+    }
+
+    /// The manifest at `path`, or none if there isn't one.
+    fn load(path: &path::Path) -> io::Result<Option<ResumeManifest>> {
+        match fs::read_to_string(path) {
+            Ok(text) => ResumeManifest::parse(&text)
+                .map(Some)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Replaces the manifest at `path` all at once, so a stopped run never leaves half of one.
+    fn save(&self, path: &path::Path) -> io::Result<()> {
+        let mut temp = path.as_os_str().to_owned();
+        temp.push(".tmp");
+        fs::write(&temp, self.to_string())?;
+        fs::rename(&temp, path)
+    }
+
+    /// Records the rows each of the file's datasets has now.
+    fn record_rows(&mut self, file: &hdf5::File) -> hdf5::Result<()> {
+        self.rows.clear();
+        for dataset in resumable_datasets(file)? {
+            let rows = file.dataset(&dataset)?.size() as u64;
+            self.rows.insert(dataset, rows);
+        }
+        Ok(())
+    }
+
+    /// Drops whatever was written to `file` after the manifest was last saved.
+    fn truncate(&self, file: &hdf5::File) -> hdf5::Result<()> {
+        if file.link_exists(libh5::SYMBOL_INDEX_DATASET) {
+            file.unlink(libh5::SYMBOL_INDEX_DATASET)?;
+        }
+        for dataset in resumable_datasets(file)? {
+            match self.rows.get(&dataset) {
+                Some(&rows) => {
+                    let written = file.dataset(&dataset)?;
+                    if written.size() as u64 > rows {
+                        warn!("Dropping {} rows of {} past the last finished capture",
+                              written.size() as u64 - rows, dataset.trim_end());
+                        written.resize(rows as usize)?;
+                    }
+                },
+                None => {
+                    warn!("Dropping {}, which no finished capture wrote", dataset.trim_end());
+                    file.unlink(&dataset)?;
+                },
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for ResumeManifest {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{}", RESUME_MANIFEST_HEADER)?;
+        for capture in &self.captures {
+            writeln!(f, "capture\t{}", capture)?;
+        }
+        for (dataset, rows) in &self.rows {
+            writeln!(f, "rows\t{}\t{}", rows, dataset)?;
+        }
+        for symbol in &self.test_symbols {
+            writeln!(f, "test\t{}", symbol)?;
+        }
+        for (channel, session) in &self.channels {
+            writeln!(f, "channel\t{}\t{}", channel, session)?;
+        }
+        Ok(())
+    }
+}
+
+/// Paths of the datasets a --resume run appends to: ticks, official prices and auctions.
+fn resumable_datasets(file: &hdf5::File) -> hdf5::Result<Vec<String>> {
+    let mut datasets = Vec::new();
+    for symbol in libh5::symbols(file)? {
+        datasets.push(libh5::symbol_dataset_path(file, &symbol)?);
+    }
+    for group in &[libh5::OFFICIAL_PRICES_GROUP, libh5::AUCTIONS_GROUP] {
+        for symbol in libh5::group_symbols(file, group)? {
+            datasets.push(format!("{}/{}", group, symbol));
+        }
+    }
+    Ok(datasets)
+}
+
+/// Appends what was collected from `capture` to the output of a --resume run, and records the
+/// capture as done in the manifest saved at `manifest_path`.
+fn write_resumed_capture(file: &hdf5::File, config: &ParserConfig, manifest: &mut ResumeManifest,
+                         manifest_path: &path::Path, capture: &str, mut collected: Collected,
+                         trade_date: &chrono::NaiveDate) {
+    let c = &mut collected;
+    manifest.test_symbols.extend(c.test_symbols.drain());
+    let test_symbols: HashSet<String> = manifest.test_symbols.iter().cloned().collect();
+    remove_test_securities(&mut c.official_prices, &test_symbols);
+    remove_test_securities(&mut c.auctions, &test_symbols);
+    let test_security_ticks = remove_test_securities(&mut c.stonks_ticks, &test_symbols);
+    if test_security_ticks > 0 {
+        info!("dropped {} ticks of test securities", test_security_ticks);
+    }
+    for (symbol, ticks) in remove_unwritable_symbols(&mut c.stonks_ticks, &mut c.dataset_names) {
+        warn!("Skipping {} ticks of unwritable symbol {:?}", ticks, symbol);
+    }
+    for (symbol, prices) in remove_unwritable_symbols(&mut c.official_prices, &mut c.dataset_names) {
+        warn!("Skipping {} official prices of unwritable symbol {:?}", prices, symbol);
+    }
+    for (symbol, count) in remove_unwritable_symbols(&mut c.auctions, &mut c.dataset_names) {
+        warn!("Skipping {} auctions of unwritable symbol {:?}", count, symbol);
+    }
+    let off_date = flag_auctions_off_trade_date(&mut c.auctions, trade_date);
+    if off_date > 0 {
+        warn!("{} auctions are scheduled for a date other than trade date {}", off_date, trade_date);
+    }
+
+    flush_ticks(file, &mut c.stonks_ticks, &config.write_options);
+    for (symbol, prices) in &c.official_prices {
+        if let Err(e) = libh5::append_official_prices(file, symbol, prices) {
+            panic!("Failed to append official prices for {}: {}", symbol, e);
+        }
+    }
+    for (symbol, auctions) in &c.auctions {
+        if let Err(e) = libh5::append_auctions(file, symbol, auctions) {
+            panic!("Failed to append auctions for {}: {}", symbol, e);
+        }
+    }
+    // On disk before the manifest says so.
+    if let Err(e) = file.flush() {
+        panic!("Failed to flush output: {}", e);
+    }
+
+    manifest.captures.push(capture.to_string());
+    manifest.channels.extend(c.channels.iter().copied());
+    if let Err(e) = manifest.record_rows(file) {
+        panic!("Failed to count rows written: {}", e);
+    }
+    if let Err(e) = manifest.save(manifest_path) {
+        panic!("Failed to write {}: {}", manifest_path.display(), e);
+    }
+}
+
+/// Opens the output of a --resume run: the partly written one its manifest describes, or a new one
+/// marked incomplete along with an empty manifest.
+fn open_resumable_output(output_path: &str, manifest_path: &path::Path, config: &ParserConfig)
+    -> (hdf5::File, ResumeManifest) {
+    let manifest = ResumeManifest::load(manifest_path)
+        .unwrap_or_else(|e| panic!("Failed to read {}: {}", manifest_path.display(), e));
+    match manifest {
+        Some(manifest) => {
+            let file = hdf5::File::open(output_path, "r+")
+                .unwrap_or_else(|e| panic!("Failed to reopen {}: {}", output_path, e));
+            if let Err(e) = manifest.truncate(&file) {
+                panic!("Failed to drop unfinished output from {}: {}", output_path, e);
+            }
+            (file, manifest)
+        },
+        None => {
+            if !config.overwrite && path::Path::new(output_path).exists() {
+                panic!("{} already exists; pass --overwrite to replace it", output_path);
+            }
+            let file = open_output(output_path, &config.write_options, true);
+            let manifest = ResumeManifest::default();
+            if let Err(e) = manifest.save(manifest_path) {
+                panic!("Failed to write {}: {}", manifest_path.display(), e);
+            }
+            (file, manifest)
+        },
+    }
+}
+
+/// Completes the output of a --resume run once every capture is in it, and removes the manifest.
+fn finish_resumable_output(file: &hdf5::File, manifest: &ResumeManifest, manifest_path: &path::Path,
+                           filter: &iex_pcap_parser::ChannelFilter) {
+    write_channel_id(file, single_channel(&manifest.channels, filter));
+    let sources: Vec<String> = manifest.captures.iter().map(|capture| source_name(capture)).collect();
+    write_source_files(file, &sources);
+    let symbols = libh5::symbols(file).unwrap_or_else(|e| panic!("Failed to list symbols: {}", e));
+    if let Err(e) = libh5::write_symbol_index(file, &symbols) {
+        panic!("Failed to write symbol index: {}", e);
+    }
+    if let Err(e) = libh5::write_attr(file, libh5::INCOMPLETE_ATTR, &false) {
+        panic!("Failed to mark output as complete: {}", e);
+    }
+    if let Err(e) = file.flush() {
+        panic!("Failed to flush output: {}", e);
+    }
+    if let Err(e) = fs::remove_file(manifest_path) {
+        panic!("Failed to remove {}: {}", manifest_path.display(), e);
+    }
+}
+
+struct ExportArgs {
+    input: String,
+    symbol: String,
+    output: String,
+}
+
+/// Arguments of the export subcommand, following `export`.
+fn parse_export_args(vargs: &[String]) -> Result<ExportArgs, String> {
+    let mut input = None;
+    let mut symbol = None;
+    let mut output = None;
+    let mut iter = vargs.iter();
+    while let Some(arg) = iter.next() {
+        let value = match arg.as_str() {
+            "--in" => &mut input,
+            "--symbol" => &mut symbol,
+            "--out" => &mut output,
+            flag => return Err(format!("unknown argument {}", flag)),
+        };
+        match iter.next() {
+            Some(v) => *value = Some(v.clone()),
+            None => return Err(format!("{} needs a value", arg)),
+        }
+    }
+    match (input, symbol, output) {
+        (Some(input), Some(symbol), Some(output)) => Ok(ExportArgs { input, symbol, output }),
+        _ => Err("--in, --symbol and --out are required".to_string()),
+    }
+}
+
+/// `--out` of text output that goes to stdout, for piping into another tool.
+const STDOUT_OUTPUT: &str = "-";
+
+/// Writes one symbol's ticks from an h5 file as CSV, to `stdout` for an output of
+/// `STDOUT_OUTPUT`, returning how many were written.
+fn export_csv<W: Write>(args: &ExportArgs, stdout: W) -> io::Result<usize> {
+    // Dataset names keep the space padding symbols have in DEEP messages.
+    let symbol = format!("{:<8}", args.symbol);
+    let ticks = libh5::load_ticks_from_file(&symbol, &args.input);
+    let out: Box<dyn Write> = if args.output == STDOUT_OUTPUT {
+        Box::new(stdout)
+    } else {
+        Box::new(fs::File::create(&args.output)?)
+    };
+    let mut out = io::BufWriter::new(out);
+    iex_pcap_parser::csv::write_ticks(&mut out, &ticks)?;
+    out.flush()?;
+    Ok(ticks.len())
+}
+
+struct MergeArgs {
+    output: String,
+    inputs: Vec<String>,
+    concat: bool,
+    overwrite: bool,
+}
+
+/// Arguments of the merge subcommand, following `merge`.
+fn parse_merge_args(vargs: &[String]) -> Result<MergeArgs, String> {
+    let mut output = None;
+    let mut inputs = Vec::new();
+    let mut concat = false;
+    let mut overwrite = false;
+    let mut iter = vargs.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--out" => match iter.next() {
+                Some(out) => output = Some(out.clone()),
+                None => return Err("--out needs a value".to_string()),
+            },
+            "--concat" => concat = true,
+            "--overwrite" => overwrite = true,
+            flag if flag.starts_with("--") => return Err(format!("unknown flag {}", flag)),
+            input => inputs.push(input.to_string()),
+        }
+    }
+    match output {
+        Some(output) if output == STDOUT_OUTPUT => {
+            Err("merge writes an h5 file, which can't be streamed to stdout; export --out - writes CSV".to_string())
+        },
+        Some(output) if !inputs.is_empty() => Ok(MergeArgs { output, inputs, concat, overwrite }),
+        Some(_) => Err("no inputs to merge".to_string()),
+        None => Err("--out is required".to_string()),
+    }
+}
+
+/// Trade date of an h5 named either `YYYYmmdd.h5` or `YYYYmmdd_SYMBOL.h5`.
+fn trade_date_of_h5(file: &str) -> hdf5::Result<chrono::NaiveDate> {
+    libfs::trade_date_from_h5(file)
+        .or_else(|_| libfs::symbol_and_date_from_h5(file).map(|(_, trade_date)| trade_date))
+        .map_err(|e| hdf5::Error::from(format!("Can't tell the trade date of {}: {:?}", file, e)))
+}
+
+/// Everything `merge_h5` reads of its inputs, before it writes any of it.
+#[derive(Default)]
+struct MergeInputs {
+    // Whether the inputs are `--combined` output, which they must all be or none be.
+    combined: bool,
+    incomplete: bool,
+    channel_ids: BTreeSet<Option<u32>>,
+    sources: Vec<String>,
+    // Each symbol's ticks from every input it's in, with the input's channel.
+    ticks: HashMap<String, Vec<InputTicks>>,
+    sorted: HashMap<String, bool>,
+    official_prices: HashMap<String, Vec<InputTicks>>,
+    auctions: HashMap<String, Vec<Vec<libh5::Auction>>>,
+    // Each input's bars of a symbol, with their interval in nanoseconds.
+    bars: HashMap<String, Vec<(Vec<libh5::Bar>, u64)>>,
+    // Symbols with a time index in any input, which is written again for the merged ticks.
+    time_indexed: HashSet<String>,
+    raw_messages: Vec<libh5::RawMessage>,
+    // Only if an input has them.
+    untraded_symbols: Option<BTreeSet<String>>,
+}
+
+/// Adds an input's `rows` of `symbol` to those of the inputs before it. Only `concat` allows more
+/// than one input to have rows of a symbol, like its ticks.
+fn add_merge_input<T>(merged: &mut HashMap<String, Vec<T>>, symbol: String, rows: T, concat: bool, what: &str)
+    -> hdf5::Result<()> {
+    match merged.get_mut(&symbol) {
+        Some(_) if !concat => Err(hdf5::Error::from(format!(
+            "{} of {} are in more than one input; pass --concat to append", what, symbol.trim_end()))),
+        Some(inputs) => {
+            inputs.push(rows);
+            Ok(())
+        },
+        None => {
+            merged.insert(symbol, vec![rows]);
+            Ok(())
+        },
+    }
+}
+
+/// Copies every symbol of the inputs into a new file, with its official prices, auctions, bars and
+/// time index, and the inputs' raw messages and untraded symbols, returning the number of symbols.
+/// A symbol in several inputs is an error unless `concat` is set, in which case its ticks are
+/// appended in input order, and its bars and time index are made again from them. Inputs written
+/// with `--combined` make combined output, and can't be merged with other inputs. All the inputs
+/// are read before the output is created, and a failed write removes it, so a failed merge leaves
+/// no output behind.
+fn merge_h5(args: &MergeArgs) -> hdf5::Result<usize> {
+    let mut trade_date = None;
+    for input in &args.inputs {
+        let date = trade_date_of_h5(input)?;
+        match trade_date {
+            Some((first, first_date)) if first_date != date => {
+                return Err(hdf5::Error::from(format!("{} is from {} but {} is from {}",
+                                                     input, date, first, first_date)));
+            },
+            Some(_) => {},
+            None => trade_date = Some((input, date)),
+        }
+    }
+
+    if !args.overwrite && path::Path::new(&args.output).exists() {
+        return Err(hdf5::Error::from(format!("{} already exists; pass --overwrite to replace it", args.output)));
+    }
+    let merged = read_merge_inputs(args)?;
+    write_merged(&args.output, merged).inspect_err(|_| {
+        if let Err(e) = fs::remove_file(&args.output) {
+            warn!("Failed to remove {} after a failed merge: {}", args.output, e);
+        }
+    })
+}
+
+fn read_merge_inputs(args: &MergeArgs) -> hdf5::Result<MergeInputs> {
+    let mut merged = MergeInputs::default();
+    for (i, input) in args.inputs.iter().enumerate() {
+        let file = hdf5::File::open(input, "r")?;
+        let combined = file.link_exists(libh5::COMBINED_TICKS_DATASET);
+        if i == 0 {
+            merged.combined = combined;
+        } else if combined != merged.combined {
+            return Err(hdf5::Error::from(format!(
+                "{} and {} can't be merged, as only one has combined ticks", args.inputs[0], input)));
+        }
+        merged.incomplete |= libh5::read_attr::<bool>(&file, libh5::INCOMPLETE_ATTR)?.unwrap_or(false);
+        let channel_id = libh5::read_attr::<u32>(&file, libh5::CHANNEL_ID_ATTR)?;
+        merged.channel_ids.insert(channel_id);
+        for source in libh5::read_source_files(&file)? {
+            if !merged.sources.contains(&source) {
+                merged.sources.push(source);
+            }
+        }
+        let input_ticks = if combined {
+            // Sorted combined rows are in timestamp order across symbols, so each symbol's are too.
+            let dataset = file.dataset(libh5::COMBINED_TICKS_DATASET)?;
+            let sorted = libh5::read_attr::<bool>(&dataset, libh5::SORTED_ATTR)?.unwrap_or(false);
+            libh5::read_combined_symbol_ticks(&file)?.into_iter().map(|(symbol, ticks)| (symbol, sorted, ticks)).collect()
+        } else {
+            let mut input_ticks = Vec::new();
+            for symbol in libh5::symbols(&file)? {
+                let dataset = libh5::tick_dataset(&file, &symbol)?;
+                let sorted = libh5::read_attr::<bool>(&dataset, libh5::SORTED_ATTR)?.unwrap_or(false);
+                input_ticks.push((symbol, sorted, libh5::read_ticks(&dataset)?));
+            }
+            input_ticks
+        };
+        for (symbol, input_sorted, ticks) in input_ticks {
+            match merged.sorted.get_mut(&symbol) {
+                // Concatenations are put in global order, which sorts them by timestamp.
+                Some(symbol_sorted) if args.concat => *symbol_sorted = true,
+                Some(_) => {
+                    return Err(hdf5::Error::from(format!(
+                        "{} is in more than one input; pass --concat to append", symbol.trim_end())));
+                },
+                None => {
+                    merged.sorted.insert(symbol.clone(), input_sorted);
+                },
+            }
+            info!("merging {} ticks for symbol {} from {}", ticks.len(), symbol, input);
+            merged.ticks.entry(symbol).or_default().push((channel_id, ticks));
+        }
+        for symbol in libh5::group_symbols(&file, libh5::OFFICIAL_PRICES_GROUP)? {
+            let prices = libh5::read_official_prices(&file, &symbol)?;
+            add_merge_input(&mut merged.official_prices, symbol, (channel_id, prices), args.concat, "official prices")?;
+        }
+        for symbol in libh5::group_symbols(&file, libh5::AUCTIONS_GROUP)? {
+            let auctions = libh5::read_auctions(&file, &symbol)?;
+            add_merge_input(&mut merged.auctions, symbol, auctions, args.concat, "auctions")?;
+        }
+        for symbol in libh5::group_symbols(&file, libh5::BARS_GROUP)? {
+            if let Some(bars) = libh5::read_bars(&file, &symbol)? {
+                add_merge_input(&mut merged.bars, symbol, bars, args.concat, "bars")?;
+            }
+        }
+        merged.time_indexed.extend(libh5::group_symbols(&file, libh5::TIME_INDEX_GROUP)?);
+        let raw_messages = libh5::read_raw_messages(&file)?;
+        if !raw_messages.is_empty() && !merged.raw_messages.is_empty() && !args.concat {
+            return Err(hdf5::Error::from("raw messages are in more than one input; pass --concat to append"));
+        }
+        merged.raw_messages.extend(raw_messages);
+        if file.link_exists(libh5::UNTRADED_SYMBOLS_DATASET) {
+            merged.untraded_symbols.get_or_insert_with(BTreeSet::new).extend(libh5::read_untraded_symbols(&file)?);
+        }
+    }
+    Ok(merged)
+}
+
+fn write_merged(path: &str, mut merged: MergeInputs) -> hdf5::Result<usize> {
+    let output = hdf5::File::open(path, "w")?;
+    libh5::write_versions(&output, env!("CARGO_PKG_VERSION"), &libh5::WriteOptions::default())?;
+    let mut traded = HashSet::new();
+    let mut combined = HashMap::new();
+    for (symbol, mut inputs) in merged.ticks {
+        let concatenated = inputs.len() > 1;
+        let ticks = if concatenated { global_order(inputs) } else { inputs.remove(0).1 };
+        let sorted = merged.sorted[&symbol];
+        if !merged.combined {
+            libh5::append_ticks(&output, &symbol, &ticks)?;
+            let dataset = libh5::tick_dataset(&output, &symbol)?;
+            libh5::write_attr(&dataset, libh5::SORTED_ATTR, &sorted)?;
+            if sorted && merged.time_indexed.contains(&symbol) {
+                libh5::write_time_index(&output, &symbol, &ticks)?;
+            }
+        }
+        match merged.bars.remove(&symbol) {
+            // Bars of a single input only cover the merged ticks if they all came from it.
+            Some(mut bars) if bars.len() == 1 && !concatenated => {
+                let (bars, interval_ns) = bars.remove(0);
+                libh5::write_bars(&output, &symbol, &bars, interval_ns)?;
+            },
+            Some(bars) => {
+                let interval_ns = bars[0].1;
+                if bars.iter().any(|(_, other)| *other != interval_ns) {
+                    return Err(hdf5::Error::from(format!(
+                        "bars of {} have different intervals in the inputs", symbol.trim_end())));
+                }
+                let bars = symbol_bars(&ticks, time::Duration::from_nanos(interval_ns));
+                if !bars.is_empty() {
+                    libh5::write_bars(&output, &symbol, &bars, interval_ns)?;
+                }
+            },
+            None => {},
+        }
+        if ticks.iter().any(|tick| tick.message_type == libh5::TRADE_REPORT) {
+            traded.insert(symbol.clone());
+        }
+        if merged.combined {
+            combined.insert(symbol, ticks);
         }
     }
+    if merged.combined {
+        // In timestamp order across symbols only if every symbol's ticks are sorted.
+        let sorted = merged.sorted.values().all(|sorted| *sorted);
+        let rows = combined_ticks(&combined, sorted);
+        let dataset = libh5::write_combined_ticks(&output, &rows, &libh5::WriteOptions::default())?;
+        libh5::write_attr(&dataset, libh5::SORTED_ATTR, &sorted)?;
+    }
+    for (symbol, mut inputs) in merged.official_prices {
+        let prices = if inputs.len() == 1 { inputs.remove(0).1 } else { global_order(inputs) };
+        libh5::write_official_prices(&output, &symbol, &prices)?;
+    }
+    for (symbol, inputs) in merged.auctions {
+        let concatenated = inputs.len() > 1;
+        let mut auctions = inputs.concat();
+        if concatenated {
+            auctions.sort_by_key(|auction| auction.timestamp);
+        }
+        libh5::write_auctions(&output, &symbol, &auctions)?;
+    }
+    if !merged.raw_messages.is_empty() {
+        libh5::write_raw_messages(&output, &merged.raw_messages)?;
+    }
+    if let Some(untraded) = &merged.untraded_symbols {
+        // A symbol untraded in one input can have trades in another.
+        let untraded: Vec<&String> = untraded.iter().filter(|symbol| !traded.contains(*symbol)).collect();
+        libh5::write_untraded_symbols(&output, &untraded)?;
+    }
+    if merged.incomplete {
+        libh5::write_attr(&output, libh5::INCOMPLETE_ATTR, &true)?;
+    }
+    if !merged.sources.is_empty() {
+        libh5::write_source_files(&output, &merged.sources)?;
+    }
+    if let (1, Some(Some(channel_id))) = (merged.channel_ids.len(), merged.channel_ids.iter().next()) {
+        libh5::write_attr(&output, libh5::CHANNEL_ID_ATTR, channel_id)?;
+    }
+    // Like the output of --combined, combined merges have no symbol index.
+    let symbols: Vec<&String> = merged.sorted.keys().collect();
+    if !merged.combined {
+        libh5::write_symbol_index(&output, &symbols)?;
+    }
+    Ok(symbols.len())
 }
 
-#[derive(hdf5::H5Type, Clone, PartialEq, Debug)]
-#[repr(u8)]
-enum OperationalHaltStatus {
-    Halted      = 'O' as u8,
-    NotHalted   = 'N' as u8,
+/// How many differences `diff` reports by default.
+const DIFF_LIMIT: usize = 10;
+
+struct DiffArgs {
+    a: String,
+    b: String,
+    deep: bool,
+    limit: usize,
 }
 
-impl OperationalHaltStatus {
-    fn from_u8(byte: u8) -> Option<OperationalHaltStatus> {
-        match byte as char {
-            'O' => Some(OperationalHaltStatus::Halted),
-            'N' => Some(OperationalHaltStatus::NotHalted),
-            _ => None,
+/// Arguments of the diff subcommand, following `diff`.
+fn parse_diff_args(vargs: &[String]) -> Result<DiffArgs, String> {
+    let mut a = None;
+    let mut b = None;
+    let mut deep = false;
+    let mut limit = DIFF_LIMIT;
+    let mut iter = vargs.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--a" => a = iter.next().cloned(),
+            "--b" => b = iter.next().cloned(),
+            "--deep" => deep = true,
+            "--limit" => match iter.next().map(|n| n.parse()) {
+                Some(Ok(n)) => limit = n,
+                _ => return Err("--limit needs a number of differences".to_string()),
+            },
+            flag => return Err(format!("unknown argument {}", flag)),
         }
     }
+    match (a, b) {
+        (Some(a), Some(b)) => Ok(DiffArgs { a, b, deep, limit }),
+        _ => Err("--a and --b are required".to_string()),
+    }
 }
 
-#[derive(hdf5::H5Type, Clone, PartialEq, Debug)]
-#[repr(u8)]
-enum ShortSalePriceTestStatus {
-    NotInEffect = 0x0,
-    InEffect    = 0x1,
+/// Differences found by `diff_h5`, of which only the first `limit` are kept.
+#[derive(Debug, Default)]
+struct H5Diff {
+    reported: Vec<String>,
+    count: usize,
+    limit: usize,
 }
 
-impl ShortSalePriceTestStatus {
-    fn from_u8(byte: u8) -> Option<ShortSalePriceTestStatus> {
-        match byte {
-            0x0 => Some(ShortSalePriceTestStatus::NotInEffect),
-            0x1 => Some(ShortSalePriceTestStatus::InEffect),
-            _ => None,
+impl H5Diff {
+    fn add<F: FnOnce() -> String>(&mut self, describe: F) {
+        if self.reported.len() < self.limit {
+            self.reported.push(describe());
         }
+        self.count += 1;
     }
 }
 
-#[derive(hdf5::H5Type, Clone, PartialEq, Debug)]
-#[repr(u8)]
-enum Detail {
-    NoPriceTestInPlace  = ' ' as u8,
-    Activated           = 'A' as u8,
-    Continued           = 'C' as u8,
-    Deactivated         = 'D' as u8,
-    NotAvailable        = 'N' as u8,
-}
-
-impl Detail {
-    fn from_u8(byte: u8) -> Option<Detail> {
-        match byte as char {
-            ' ' => Some(Detail::NoPriceTestInPlace),
-            'A' => Some(Detail::Activated),
-            'C' => Some(Detail::Continued),
-            'D' => Some(Detail::Deactivated),
-            'N' => Some(Detail::NotAvailable),
-            _ => None,
-        }
-    }
+/// The fields that differ between two ticks, as `name a != b`.
+fn tick_field_diffs(a: &libh5::Tick, b: &libh5::Tick) -> Vec<String> {
+    let fields = [
+        ("message_type", u64::from(a.message_type), u64::from(b.message_type)),
+        ("message_subtype", u64::from(a.message_subtype), u64::from(b.message_subtype)),
+        ("timestamp", a.timestamp, b.timestamp),
+        ("size", u64::from(a.size), u64::from(b.size)),
+        ("price", a.price, b.price),
+        ("price_multiplier", a.price_multiplier, b.price_multiplier),
+        ("packet_number", a.packet_number, b.packet_number),
+        ("message_sequence_number", a.message_sequence_number, b.message_sequence_number),
+        ("capture_timestamp", a.capture_timestamp, b.capture_timestamp),
+    ];
+    fields.iter()
+        .filter(|(_, a, b)| a != b)
+        .map(|(name, a, b)| format!("{} {} != {}", name, a, b))
+        .collect()
 }
 
-#[derive(hdf5::H5Type, Clone, PartialEq, Debug)]
-#[repr(u8)]
-enum SecurityEvent {
-    OpeningProcessComplete = 'O' as u8,
-    ClosingProcessComplete = 'C' as u8,
+/// An h5 file's ticks by symbol, from its dataset per symbol or, in `--combined` output, from the
+/// rows of its `libh5::COMBINED_TICKS_DATASET`.
+enum H5Ticks {
+    PerSymbol(hdf5::File),
+    Combined(BTreeMap<String, Vec<libh5::Tick>>),
 }
 
-impl SecurityEvent {
-    fn from_u8(byte: u8) -> Option<SecurityEvent> {
-        match byte as char {
-            'O' => Some(SecurityEvent::OpeningProcessComplete),
-            'C' => Some(SecurityEvent::ClosingProcessComplete),
-            _ => None,
+impl H5Ticks {
+    fn new(file: hdf5::File) -> hdf5::Result<H5Ticks> {
+        if file.link_exists(libh5::COMBINED_TICKS_DATASET) {
+            Ok(H5Ticks::Combined(libh5::read_combined_symbol_ticks(&file)?))
+        } else {
+            Ok(H5Ticks::PerSymbol(file))
         }
     }
-}
-
-#[derive(hdf5::H5Type, Clone, PartialEq, Debug)]
-#[repr(u8)]
-enum PriceLevelUpdateEventFlags {
-    OrderBookIsProcessingAnEvent = 0x0,
-    EventProcessingComplete = 0x1,
-}
 
-impl PriceLevelUpdateEventFlags {
-    fn from_u8(byte: u8) -> Option<PriceLevelUpdateEventFlags> {
-        match byte {
-            0x0 => Some(PriceLevelUpdateEventFlags::OrderBookIsProcessingAnEvent),
-            0x1 => Some(PriceLevelUpdateEventFlags::EventProcessingComplete),
-            _ => None,
+    fn symbols(&self) -> hdf5::Result<Vec<String>> {
+        match self {
+            H5Ticks::PerSymbol(file) => libh5::symbols(file),
+            H5Ticks::Combined(ticks) => Ok(ticks.keys().cloned().collect()),
         }
     }
-}
 
-// TODO(sherry): these are not mutually exclusive
-// #[derive(hdf5::H5Type, Clone, PartialEq, Debug)]
-// #[repr(u8)]
-// enum SaleConditionFlags {
-//     IntermarketSweep        = 'F' as u8,
-//     ExtendedHours           = 'T' as u8,
-//     OddLot                  = 'I' as u8,
-//     TradeThroughExempt      = '8' as u8,
-//     SinglePriceCrossTrade   = 'X' as u8,
-// }
-// 
-// impl SaleConditionFlags {
-//     fn from_u8(byte: u8) -> Option<SaleConditionFlags> {
-//         match byte as char {
-//             'F' => Some(SaleConditionFlags::IntermarketSweep),
-//             'T' => Some(SaleConditionFlags::ExtendedHours),
-//             'I' => Some(SaleConditionFlags::OddLot),
-//             '8' => Some(SaleConditionFlags::TradeThroughExempt),
-//             'X' => Some(SaleConditionFlags::SinglePriceCrossTrade),
-//             _ => None,
-//         }
-//     }
-// }
-
-#[derive(hdf5::H5Type, Clone, PartialEq, Debug)]
-#[repr(u8)]
-enum PriceType {
-    OfficialOpeningPrice = 'Q' as u8,
-    OfficialClosingPrice = 'M' as u8,
-}
-
-impl PriceType {
-    fn from_u8(byte: u8) -> Option<PriceType> {
-        match byte as char {
-            'Q' => Some(PriceType::OfficialOpeningPrice),
-            'M' => Some(PriceType::OfficialClosingPrice),
-            _ => None,
+    fn count(&self, symbol: &str) -> hdf5::Result<usize> {
+        match self {
+            H5Ticks::PerSymbol(file) => Ok(libh5::tick_dataset(file, symbol)?.size()),
+            H5Ticks::Combined(ticks) => Ok(ticks.get(symbol).map_or(0, Vec::len)),
         }
     }
-}
 
-#[derive(hdf5::H5Type, Clone, PartialEq, Debug)]
-#[repr(u8)]
-enum AuctionType {
-    Opening     = 'O' as u8,
-    Closing     = 'C' as u8,
-    Ipo         = 'I' as u8,
-    Halt        = 'H' as u8,
-    Volatility  = 'V' as u8,
+    fn ticks(&self, symbol: &str) -> hdf5::Result<Vec<libh5::Tick>> {
+        match self {
+            H5Ticks::PerSymbol(file) => libh5::read_ticks(&libh5::tick_dataset(file, symbol)?),
+            H5Ticks::Combined(ticks) => Ok(ticks.get(symbol).cloned().unwrap_or_default()),
+        }
+    }
 }
 
-impl AuctionType {
-    fn from_u8(byte: u8) -> Option<AuctionType> {
-        match byte as char {
-            'O' => Some(AuctionType::Opening),
-            'C' => Some(AuctionType::Closing),
-            'I' => Some(AuctionType::Ipo),
-            'H' => Some(AuctionType::Halt),
-            'V' => Some(AuctionType::Volatility),
-            _ => None,
+/// Compares the symbols of two h5 files and their tick counts, and with `deep` every tick. Either
+/// file can be `--combined` output.
+fn diff_h5(args: &DiffArgs) -> hdf5::Result<H5Diff> {
+    let a = H5Ticks::new(hdf5::File::open(&args.a, "r")?)?;
+    let b = H5Ticks::new(hdf5::File::open(&args.b, "r")?)?;
+    let a_symbols: BTreeSet<String> = a.symbols()?.into_iter().collect();
+    let b_symbols: BTreeSet<String> = b.symbols()?.into_iter().collect();
+    let mut diff = H5Diff { limit: args.limit, ..H5Diff::default() };
+    for symbol in a_symbols.difference(&b_symbols) {
+        diff.add(|| format!("{} is only in {}", symbol.trim_end(), args.a));
+    }
+    for symbol in b_symbols.difference(&a_symbols) {
+        diff.add(|| format!("{} is only in {}", symbol.trim_end(), args.b));
+    }
+    for symbol in a_symbols.intersection(&b_symbols) {
+        let (a_count, b_count) = (a.count(symbol)?, b.count(symbol)?);
+        if a_count != b_count {
+            diff.add(|| format!("{} has {} ticks in {} but {} in {}", symbol.trim_end(),
+                                a_count, args.a, b_count, args.b));
+        }
+        if !args.deep {
+            continue;
+        }
+        let a_ticks = a.ticks(symbol)?;
+        let b_ticks = b.ticks(symbol)?;
+        for (i, (a_tick, b_tick)) in a_ticks.iter().zip(&b_ticks).enumerate() {
+            let fields = tick_field_diffs(a_tick, b_tick);
+            if !fields.is_empty() {
+                diff.add(|| format!("{} tick {}: {}", symbol.trim_end(), i, fields.join(", ")));
+            }
         }
     }
+    Ok(diff)
 }
 
-#[derive(hdf5::H5Type, Clone, PartialEq, Debug)]
-#[repr(u8)]
-enum ImbalanceSide {
-    BuySideImbalance    = 'B' as u8,
-    SellSideImbalance   = 'S' as u8,
-    NoImbalance         = 'N' as u8,
+struct InfoArgs {
+    input: String,
 }
 
-impl ImbalanceSide {
-    fn from_u8(byte: u8) -> Option<ImbalanceSide> {
-        match byte as char {
-            'B' => Some(ImbalanceSide::BuySideImbalance),
-            'S' => Some(ImbalanceSide::SellSideImbalance),
-            'N' => Some(ImbalanceSide::NoImbalance),
-            _ => None,
-        }
+/// Arguments of the info subcommand, following `info`.
+fn parse_info_args(vargs: &[String]) -> Result<InfoArgs, String> {
+    match vargs {
+        [flag, input] if flag == "--in" => Ok(InfoArgs { input: input.clone() }),
+        _ => Err("--in is required".to_string()),
     }
 }
 
-struct SystemEventMessage {
-    system_event: SystemEvent,
+/// A line of `h5_info` for a symbol.
+fn symbol_info(symbol: &str, count: usize, time_range: Option<(u64, u64)>, price_multiplier: Option<u64>) -> String {
+    let time_range = time_range.map_or("unknown time range".to_string(), |(first, last)| {
+        format!("{} to {}", libdt::UtcNs::from_nanos(first).to_rfc3339(), libdt::UtcNs::from_nanos(last).to_rfc3339())
+    });
+    format!("{}: {} ticks, {}, price multiplier {}", symbol.trim_end(), count, time_range,
+            price_multiplier.map_or("unknown".to_string(), |m| m.to_string()))
 }
 
-struct SecurityDirectoryMessage {
-    symbol: MessageSymbol,
-    round_lot_size: u32,
-    adjusted_poc_price: u64,
-    luld_tier: LimitUpLimitDownTier,
-    flags: u8,
+/// One line per symbol of an h5 file, after a line with the file's versions: its tick count, time
+/// range and price multiplier. Fails if the file's schema version isn't one this libh5 reads.
+fn h5_info(args: &InfoArgs) -> hdf5::Result<Vec<String>> {
+    let file = hdf5::File::open(&args.input, "r")?;
+    libh5::check_schema_version(&file)?;
+    let schema_version = libh5::read_attr::<u32>(&file, libh5::SCHEMA_VERSION_ATTR)?
+        .map_or("none".to_string(), |v| v.to_string());
+    let parser_version = libh5::read_attr::<hdf5::types::VarLenUnicode>(&file, libh5::PARSER_VERSION_ATTR)?
+        .map_or("an unknown parser".to_string(), |v| v.to_string());
+    let mut lines = vec![format!("{}: schema version {}, written by {}", args.input, schema_version, parser_version)];
+    match H5Ticks::new(file)? {
+        H5Ticks::PerSymbol(file) => {
+            let mut symbols = libh5::symbols(&file)?;
+            symbols.sort();
+            for symbol in symbols {
+                let dataset = libh5::tick_dataset(&file, &symbol)?;
+                // Recorded on fixed-point datasets, and otherwise in every tick.
+                let price_multiplier = match libh5::read_attr::<u64>(&dataset, libh5::PRICE_MULTIPLIER_ATTR)? {
+                    Some(price_multiplier) => Some(price_multiplier),
+                    None => libh5::read_tick_rows(&dataset, 0, 1)?.first().map(|t| t.price_multiplier),
+                };
+                let time_range = libh5::read_timestamp_range(&dataset)?;
+                lines.push(symbol_info(&symbol, dataset.size(), time_range, price_multiplier));
+            }
+        },
+        // Combined rows have no per-symbol attributes, so it's all read from the ticks.
+        H5Ticks::Combined(ticks) => for (symbol, ticks) in ticks {
+            let first = ticks.iter().map(|t| t.timestamp).min();
+            let last = ticks.iter().map(|t| t.timestamp).max();
+            lines.push(symbol_info(&symbol, ticks.len(), first.zip(last), ticks.first().map(|t| t.price_multiplier)));
+        },
+    }
+    Ok(lines)
 }
 
-struct TradingStatusMessage {
-    symbol: MessageSymbol,
-    reason: [char; 4],
-    trading_status: TradingStatus,
+struct VerifyArgs {
+    input: String,
 }
 
-struct OperationalHaltStatusMessage {
-    symbol: MessageSymbol,
-    operational_halt_status: OperationalHaltStatus,
+/// Arguments of the verify subcommand, following `verify`.
+fn parse_verify_args(vargs: &[String]) -> Result<VerifyArgs, String> {
+    match vargs {
+        [flag, input] if flag == "--in" => Ok(VerifyArgs { input: input.clone() }),
+        _ => Err("--in is required".to_string()),
+    }
 }
 
-struct ShortSalePriceTestStatusMessage {
-    symbol: MessageSymbol,
-    detail: Detail,
-    short_sale_price_test_status: ShortSalePriceTestStatus,
+/// Reads back every dataset of an h5 file, checking the checksums of those that have them.
+fn verify_h5(args: &VerifyArgs) -> hdf5::Result<libh5::VerifyReport> {
+    let file = hdf5::File::open(&args.input, "r")?;
+    libh5::verify_datasets(&file)
 }
 
-struct SecurityEventMessage {
-    symbol: MessageSymbol,
-    security_event: SecurityEvent,
+/// The packets of an input's captures in turn. Captures after the first are opened as the
+/// previous one runs out, with what's left of --max-packets.
+struct Captures<'a> {
+    config: &'a ParserConfig,
+    packets: iex_pcap_parser::Packets,
+    // The captures opened so far, none for a live capture.
+    opened: Vec<String>,
+    pending: VecDeque<String>,
+    read: u64,
 }
 
-struct PriceLevelUpdateMessage {
-    symbol: MessageSymbol,
-    size: u32,
-    price: u64,
-    event_flags: PriceLevelUpdateEventFlags,
-}
+impl<'a> Captures<'a> {
+    fn new(config: &'a ParserConfig, packets: iex_pcap_parser::Packets, opened: Vec<String>,
+           pending: VecDeque<String>) -> Captures<'a> {
+        Captures {
+            config,
+            packets,
+            opened,
+            pending,
+            read: 0,
+        }
+    }
 
-struct TradeReportMessage {
-    symbol: MessageSymbol,
-    size: u32,
-    price: u64,
-    trade_id: u64,
-    sale_condition_flags: u8,
-}
+    /// The file names of the captures read from, for the output's source_files.
+    fn sources(&self) -> Vec<String> {
+        self.opened.iter().map(|capture| source_name(capture)).collect()
+    }
 
-struct OfficialPriceMessage {
-    symbol: MessageSymbol,
-    official_price: u64,
-    price_type: PriceType,
+    /// Whether reading stopped because of --max-packets.
+    fn limit_reached(&self) -> bool {
+        self.packets.limit_reached()
+    }
 }
 
-struct TradeBreakMessage {
-    symbol: MessageSymbol,
-    size: u32,
-    price: u64,
-    trade_id: u64,
-    sale_condition_flags: u8,
-}
+impl<'a> Iterator for Captures<'a> {
+    type Item = Result<iex_pcap_parser::DeepPacket, ParseError>;
 
-struct AuctionInformationMessage {
-    symbol: MessageSymbol,
-    paired_shares: u32,
-    reference_price: u64,
-    indicative_clearing_price: u64,
-    imbalance_shares: u32,
-    imbalance_side: ImbalanceSide,
-    extension_number: u8,
-    scheduled_auction_time: u32,
-    auction_book_clearing_price: u64,
-    collar_reference_price: u64,
-    lower_auction_collar: u64,
-    upper_auction_collar: u64,
-    auction_type: AuctionType,
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.packets.next() {
+                Some(packet) => {
+                    if !matches!(&packet, Err(e) if e.is_unreadable_capture()) {
+                        self.read += 1;
+                    }
+                    return Some(packet);
+                },
+                None => match self.pending.pop_front() {
+                    Some(capture) if !self.packets.limit_reached() => {
+                        let max_packets = self.config.max_packets.map(|max| max - self.read);
+                        self.packets = open_capture(&capture, self.config, max_packets);
+                        self.opened.push(capture);
+                    },
+                    _ => return None,
+                },
+            }
+        }
+    }
 }
 
-enum IexDeepMessageImpl {
-    SystemEvent(SystemEventMessage),
-    SecurityDirectory(SecurityDirectoryMessage),
-    TradingStatus(TradingStatusMessage),
-    OperationalHaltStatus(OperationalHaltStatusMessage),
-    ShortSalePriceTestStatus(ShortSalePriceTestStatusMessage),
-    SecurityEvent(SecurityEventMessage),
-
-    /// Trading message formats
-    PriceLevelUpdate(PriceLevelUpdateMessage),
-    TradeReport(TradeReportMessage),
-    OfficialPrice(OfficialPriceMessage),
-    TradeBreak(TradeBreakMessage),
+/// Everything collect gathers from the packets of a run, for the summary and the output.
+#[derive(Default)]
+struct Collected {
+    stonks_ticks: HashMap<String, Vec<libh5::Tick>>,
+    // Kept apart from ticks, by symbol with the same padding.
+    official_prices: HashMap<String, Vec<libh5::Tick>>,
+    auctions: HashMap<String, Vec<libh5::Auction>>,
+    // Test securities' directory messages can come after some of their ticks, so ticks are only
+    // dropped when they're about to be written.
+    test_symbols: HashSet<String>,
+    // Named like datasets, to tell the security directory's symbols without trades.
+    directory_symbols: HashSet<String>,
+    traded_symbols: HashSet<String>,
+    tick_type_count: HashMap<u8, usize>,
+    packet_counter: u64,
+    tick_counter: usize,
+    tradeable_counter: usize,
+    removed: usize,
+    dataset_names: HashMap<String, String>,
+    symbol_names: SymbolNames,
+    unwritable: HashSet<String>,
+    unwritable_ticks: usize,
+    skipped_counter: usize,
+    other_protocol_counter: usize,
+    filtered_counter: usize,
+    outside_window: usize,
+    zero_size_trades: usize,
+    zero_price_trades: usize,
+    out_of_range_trades: usize,
+    trade_breaks: TradeBreaks,
+    channels: BTreeSet<(u32, u32)>,
+    channel_summary: iex_pcap_parser::ChannelSummary,
+    stream_gaps: iex_pcap_parser::StreamGaps,
+    parse_stats: iex_pcap_parser::ParseStats,
+    // Reading stopped at an error in the capture rather than at its end.
+    unreadable: bool,
+    // The bytes of messages that failed to decode, with --capture-raw.
+    raw_messages: Vec<libh5::RawMessage>,
+    // Packets a strict run with --max-errors failed to parse.
+    strict_errors: Vec<StrictError>,
+}
 
-    /// Auction message formats
-    AuctionInformation(AuctionInformationMessage),
+/// A packet that failed to parse in a strict run with --max-errors.
+struct StrictError {
+    packet: u64,
+    // Where the capture had been read to. A packet that fails to parse doesn't say its length, so
+    // this is the end of the last packet that parsed.
+    offset: u64,
+    error: ParseError,
 }
 
-struct ParseMessageResponse {
-    parsed_message: IexDeepMessage,
-    consumed_bytes: usize,
+/// Aborts a strict run with every packet that failed to parse, if any did.
+fn abort_on_strict_errors(config: &ParserConfig, errors: &[StrictError]) {
+    if errors.is_empty() {
+        return;
+    }
+    for StrictError { packet, offset, error } in errors {
+        error!("Failed to parse packet {} after byte {}: {:?}", packet, offset, error);
+    }
+    if Some(errors.len()) == config.max_errors {
+        panic!("{} packets failed to parse, stopping at --max-errors", errors.len());
+    }
+    panic!("{} packets failed to parse", errors.len());
 }
 
-fn parse_message(bytes: &[u8], packet_num: u64, message_seq_num: u64) -> Option<ParseMessageResponse> {
-    let message_type = bytes[0];
-    let message_subtype = bytes[1];
-    let timestamp = bytes_u64!(bytes, 2);
-    match message_type as char {
-        'S' => {
-            SystemEvent::from_u8(message_subtype).map(|system_event| {
-                let message = SystemEventMessage {
-                    system_event,
-                };
-                let consumed_bytes = std::mem::size_of_val(&message);
-                let body = IexDeepMessageImpl::SystemEvent(message);
-                ParseMessageResponse {
-                    parsed_message: IexDeepMessage {
-                        message_type,
-                        message_subtype,
-                        timestamp,
-                        body,
-                        packet_number: packet_num,
-                        message_sequence_number: message_seq_num,
-                    },
-                    consumed_bytes,
+/// Reads packets until they run out or `running` is cleared. Ticks of a live capture are flushed
+/// to `live_file` as they come in.
+fn collect<I>(config: &ParserConfig, packets: &mut I, window: &TimeWindow, running: &AtomicBool,
+              live_file: Option<&hdf5::File>, progress: Option<&Progress>) -> Collected
+    where I: Iterator<Item = Result<iex_pcap_parser::DeepPacket, ParseError>> {
+    let mut collected = Collected::default();
+    let c = &mut collected;
+    let mut last_flush = time::Instant::now();
+    // A symbol has reached --flush-ticks since the last flush.
+    let mut buffer_full = false;
+    let mut bytes_read = PCAP_GLOBAL_HEADER_LEN;
+    while running.load(Ordering::SeqCst) {
+        if let Some(file) = live_file {
+            if buffer_full || last_flush.elapsed() >= LIVE_FLUSH_INTERVAL {
+                c.removed += remove_test_securities(&mut c.stonks_ticks, &c.test_symbols);
+                for (symbol, ticks) in remove_unwritable_symbols(&mut c.stonks_ticks, &mut c.dataset_names) {
+                    warn!("Skipping {} ticks of unwritable symbol {:?}", ticks, symbol);
+                    c.unwritable.insert(symbol);
+                    c.unwritable_ticks += ticks;
                 }
-            })
-        },
-        'D' => {
-            LimitUpLimitDownTier::from_u8(bytes[30]).map(|luld_tier| {
-                let message = SecurityDirectoryMessage {
-                    flags: message_subtype,
-                    symbol: [
-                        bytes[10] as char, bytes[11] as char,
-                        bytes[12] as char, bytes[13] as char,
-                        bytes[14] as char, bytes[15] as char,
-                        bytes[16] as char, bytes[17] as char,
-                    ],
-                    round_lot_size: bytes_u32!(bytes, 18),
-                    adjusted_poc_price: bytes_u64!(bytes, 22),
-                    luld_tier,
-                };
-                let consumed_bytes = std::mem::size_of_val(&message);
-                let body = IexDeepMessageImpl::SecurityDirectory(message);
-                ParseMessageResponse {
-                    parsed_message: IexDeepMessage {
-                        message_type,
-                        message_subtype,
-                        timestamp,
-                        body,
-                        packet_number: packet_num,
-                        message_sequence_number: message_seq_num,
-                    },
-                    consumed_bytes,
+                flush_ticks(file, &mut c.stonks_ticks, &config.write_options);
+                last_flush = time::Instant::now();
+                buffer_full = false;
+            }
+        }
+
+        let packet = packets.next();
+        if let Some(packet) = &packet {
+            c.parse_stats.record(packet);
+        }
+        let packet = match packet {
+            Some(Ok(packet)) => packet,
+            #[cfg(feature = "native-pcap")]
+            Some(Err(ParseError::Capture(pcap::Error::TimeoutExpired))) => continue,
+            Some(Err(e)) if config.strict && config.max_errors.is_some() && !e.is_unreadable_capture() => {
+                warn!("Failed to parse packet {}: {:?}", c.packet_counter, e);
+                c.strict_errors.push(StrictError { packet: c.packet_counter, offset: bytes_read, error: e });
+                c.packet_counter += 1;
+                if Some(c.strict_errors.len()) == config.max_errors {
+                    break;
                 }
-            })
-        },
-        'H' => {
-            TradingStatus::from_u8(message_subtype).map(|trading_status| {
-                let message = TradingStatusMessage {
-                    trading_status,
-                    symbol: [
-                        bytes[10] as char, bytes[11] as char,
-                        bytes[12] as char, bytes[13] as char,
-                        bytes[14] as char, bytes[15] as char,
-                        bytes[16] as char, bytes[17] as char,
-                    ],
-                    reason: [
-                        bytes[18] as char, bytes[19] as char,
-                        bytes[20] as char, bytes[21] as char,
-                    ],
-                };
-                let consumed_bytes = std::mem::size_of_val(&message);
-                let body = IexDeepMessageImpl::TradingStatus(message);
-                ParseMessageResponse {
-                    parsed_message: IexDeepMessage {
-                        message_type,
-                        message_subtype,
-                        timestamp,
-                        body,
-                        packet_number: packet_num,
-                        message_sequence_number: message_seq_num,
-                    },
-                    consumed_bytes,
+                continue;
+            },
+            Some(Err(e @ ParseError::Ethernet(_)))
+            | Some(Err(e @ ParseError::TruncatedHeader { .. }))
+            | Some(Err(e @ ParseError::UnsupportedVersion(_))) => {
+                if config.strict {
+                    panic!("Failed to parse packet {}: {:?}", c.packet_counter, e);
                 }
-            })
-        },
-        'O' => {
-            OperationalHaltStatus::from_u8(message_subtype).map(|operational_halt_status| {
-                let message = OperationalHaltStatusMessage {
-                    operational_halt_status,
-                    symbol: [
-                        bytes[10] as char, bytes[11] as char,
-                        bytes[12] as char, bytes[13] as char,
-                        bytes[14] as char, bytes[15] as char,
-                        bytes[16] as char, bytes[17] as char,
-                    ],
-                };
-                let consumed_bytes = std::mem::size_of_val(&message);
-                let body = IexDeepMessageImpl::OperationalHaltStatus(message);
-                ParseMessageResponse {
-                    parsed_message: IexDeepMessage {
-                        message_type,
-                        message_subtype,
-                        timestamp,
-                        body,
-                        packet_number: packet_num,
-                        message_sequence_number: message_seq_num,
-                    },
-                    consumed_bytes,
+                debug!("Skipping packet {}: {:?}", c.packet_counter, e);
+                c.packet_counter += 1;
+                continue;
+            },
+            // Its body would be decoded from whatever bytes of a truncated packet are left.
+            Some(Err(ParseError::PayloadLengthMismatch { header, actual })) if !config.strict => {
+                warn!("Skipping packet {}: {} bytes of payload, but its header declares {}", c.packet_counter,
+                      actual, header);
+                c.packet_counter += 1;
+                continue;
+            },
+            Some(Err(e)) if e.is_unreadable_capture() => {
+                warn!("Stopped reading capture: {:?}", e);
+                c.unreadable = true;
+                break;
+            },
+            Some(Err(e)) => panic!("Failed to parse packet {}: {:?}", c.packet_counter, e),
+            None => break,
+        };
+        bytes_read += PCAP_RECORD_HEADER_LEN + u64::from(packet.capture_len);
+        if config.dump_headers {
+            info!("Packet {}", c.packet_counter);
+            iex_pcap_parser::debug_header(&packet.header);
+        }
+        for e in &packet.errors {
+            warn!("Skipping message in packet {}: {:?}", c.packet_counter, e);
+        }
+        c.skipped_counter += packet.errors.len();
+        if config.capture_raw {
+            // Packet numbers restart with each capture in Packets, so the run's count is used.
+            let packet_counter = c.packet_counter;
+            c.raw_messages.extend(packet.raw_failures.iter()
+                .map(|raw| libh5::RawMessage::new(packet_counter, raw.message_sequence_number, &raw.bytes)));
+        }
+        c.channels.insert((packet.header.channel_id, packet.header.session_id));
+        if config.channel_summary {
+            c.channel_summary.record(&packet);
+        }
+        if let Some(missing) = c.stream_gaps.check(&packet.header) {
+            warn!("{} bytes missing before packet {} of channel {} session {}", missing, c.packet_counter,
+                  packet.header.channel_id, packet.header.session_id);
+        }
+        if packet.filtered_out {
+            c.filtered_counter += 1;
+        } else if !packet.is_deep() {
+            debug!("Skipping packet {} of protocol {:#06x}", c.packet_counter, packet.header.message_protocol_id);
+            c.other_protocol_counter += 1;
+        }
+
+        for message in packet.messages {
+            // Dataset names keep the space padding symbols have in DEEP messages.
+            let symbol = message.message_symbol()
+                .map(|symbol| c.symbol_names.name(symbol, &config.symbol_normalizer, config.symbol_collisions));
+            if config.skip_test_securities && test_security_symbol(&message, &config.symbol_normalizer).is_some() {
+                // Named like its ticks, which a symbol collision may have suffixed.
+                let symbol = symbol.clone().expect("Security directory messages have a symbol");
+                if c.test_symbols.insert(symbol.clone()) {
+                    info!("skipping test security {}", symbol.trim_end());
                 }
-            })
-        },
-        'P' => {
-            ShortSalePriceTestStatus::from_u8(message_subtype).and_then(|short_sale_price_test_status| {
-                Detail::from_u8(bytes[18]).map(|detail| {
-                    let message = ShortSalePriceTestStatusMessage {
-                        short_sale_price_test_status,
-                        symbol: [
-                            bytes[10] as char, bytes[11] as char,
-                            bytes[12] as char, bytes[13] as char,
-                            bytes[14] as char, bytes[15] as char,
-                            bytes[16] as char, bytes[17] as char,
-                        ],
-                        detail,
+            }
+            if let iex_pcap_parser::IexDeepMessageImpl::SecurityDirectory(_) = &message.body {
+                c.directory_symbols.insert(symbol.clone().expect("Security directory messages have a symbol"));
+            }
+
+            let zero_trade = config.zero_trades.drops(&message);
+            match zero_trade {
+                Some(iex_pcap_parser::ZeroTrade::Size) => c.zero_size_trades += 1,
+                Some(iex_pcap_parser::ZeroTrade::Price) => c.zero_price_trades += 1,
+                None => {},
+            }
+            let out_of_range = zero_trade.is_none() && config.price_range.excludes(&message);
+            if out_of_range {
+                c.out_of_range_trades += 1;
+                debug!("Dropping trade of {} at {} in packet {}, outside --min-price/--max-price",
+                       symbol.as_deref().map_or("", str::trim_end),
+                       message.to_serialized_tick().map_or(0.0, |tick| tick.price_f64()), c.packet_counter);
+            }
+            if let Some(serialized_tick) = message.to_serialized_tick().filter(|_| zero_trade.is_none() && !out_of_range) {
+                c.tradeable_counter += 1;
+                // Filtered here, so trade breaks only see the indexes of kept ticks.
+                if window.contains(serialized_tick.timestamp) {
+                    let symbol = match &symbol {
+                        Some(symbol) => symbol.clone(),
+                        None => panic!("Trade tick needs to have a symbol"),
                     };
-                    let consumed_bytes = std::mem::size_of_val(&message);
-                    let body = IexDeepMessageImpl::ShortSalePriceTestStatus(message);
-                    ParseMessageResponse {
-                        parsed_message: IexDeepMessage {
-                            message_type,
-                            message_subtype,
-                            timestamp,
-                            body,
-                            packet_number: packet_num,
-                            message_sequence_number: message_seq_num,
-                        },
-                        consumed_bytes,
+                    let entry = c.stonks_ticks.entry(symbol.clone()).or_insert(Vec::new());
+                    (*entry).push(serialized_tick);
+                    buffer_full |= entry.len() >= config.flush_ticks;
+                    if let iex_pcap_parser::IexDeepMessageImpl::TradeReport(trade) = &message.body {
+                        c.traded_symbols.insert(symbol.clone());
+                        if config.apply_breaks {
+                            c.trade_breaks.record_trade(trade.trade_id, &symbol, entry.len() - 1);
+                        }
                     }
-                })
-            })
-        },
-        'E' => {
-            SecurityEvent::from_u8(message_subtype).map(|security_event| {
-                let message = SecurityEventMessage {
-                    security_event,
-                    symbol: [
-                        bytes[10] as char, bytes[11] as char,
-                        bytes[12] as char, bytes[13] as char,
-                        bytes[14] as char, bytes[15] as char,
-                        bytes[16] as char, bytes[17] as char,
-                    ],
-                };
-                let consumed_bytes = std::mem::size_of_val(&message);
-                let body = IexDeepMessageImpl::SecurityEvent(message);
-                ParseMessageResponse {
-                    parsed_message: IexDeepMessage {
-                        message_type,
-                        message_subtype,
-                        timestamp,
-                        body,
-                        packet_number: packet_num,
-                        message_sequence_number: message_seq_num,
-                    },
-                    consumed_bytes,
+                } else {
+                    c.outside_window += 1;
                 }
-            })
-        },
-        '8' | '5' => {
-            PriceLevelUpdateEventFlags::from_u8(message_subtype).map(|event_flags| {
-                let message = PriceLevelUpdateMessage {
-                    event_flags,
-                    symbol: [
-                        bytes[10] as char, bytes[11] as char,
-                        bytes[12] as char, bytes[13] as char,
-                        bytes[14] as char, bytes[15] as char,
-                        bytes[16] as char, bytes[17] as char,
-                    ],
-                    size: bytes_u32!(bytes, 18),
-                    price: bytes_u64!(bytes, 22),
-                };
-                let consumed_bytes = std::mem::size_of_val(&message);
-                let body = IexDeepMessageImpl::PriceLevelUpdate(message);
-                ParseMessageResponse {
-                    parsed_message: IexDeepMessage {
-                        message_type,
-                        message_subtype,
-                        timestamp,
-                        body,
-                        packet_number: packet_num,
-                        message_sequence_number: message_seq_num,
-                    },
-                    consumed_bytes,
+            }
+            if let Some(official_price) = message.to_official_price() {
+                if window.contains(official_price.timestamp) {
+                    let symbol = symbol.clone().expect("Official price needs to have a symbol");
+                    c.official_prices.entry(symbol).or_insert(Vec::new()).push(official_price);
                 }
-            })
-        },
-        'T' => {
-            // SaleConditionFlags::from_u8(message_subtype).map(|sale_condition_flags| {
-            if bytes.len() >= 38 {
-                let message = TradeReportMessage {
-                    symbol: [
-                        bytes[10] as char, bytes[11] as char,
-                        bytes[12] as char, bytes[13] as char,
-                        bytes[14] as char, bytes[15] as char,
-                        bytes[16] as char, bytes[17] as char,
-                    ],
-                    size: bytes_u32!(bytes, 18),
-                    price: bytes_u64!(bytes, 22),
-                    trade_id: bytes_u64!(bytes, 30),
-                    sale_condition_flags: message_subtype,
-                };
-                let consumed_bytes = std::mem::size_of_val(&message);
-                let body = IexDeepMessageImpl::TradeReport(message);
-                Some(ParseMessageResponse {
-                    parsed_message: IexDeepMessage {
-                        message_type,
-                        message_subtype,
-                        timestamp,
-                        body,
-                        packet_number: packet_num,
-                        message_sequence_number: message_seq_num,
-                    },
-                    consumed_bytes,
-                })
-            } else {
-                println!("Not enough bytes to parse trade message: have {}, expected {}",
-                      bytes.len(), 38);
-                None
-            }
-            // })
-        },
-        'X' => {
-            PriceType::from_u8(message_subtype).map(|price_type| {
-                let message = OfficialPriceMessage {
-                    price_type,
-                    symbol: [
-                        bytes[10] as char, bytes[11] as char,
-                        bytes[12] as char, bytes[13] as char,
-                        bytes[14] as char, bytes[15] as char,
-                        bytes[16] as char, bytes[17] as char,
-                    ],
-                    official_price: bytes_u64!(bytes, 18),
-                };
-                let consumed_bytes = std::mem::size_of_val(&message);
-                let body = IexDeepMessageImpl::OfficialPrice(message);
-                ParseMessageResponse {
-                    parsed_message: IexDeepMessage {
-                        message_type,
-                        message_subtype,
-                        timestamp,
-                        body,
-                        packet_number: packet_num,
-                        message_sequence_number: message_seq_num,
-                    },
-                    consumed_bytes,
+            }
+            if let Some(auction) = message.to_auction().filter(|_| config.include_auctions) {
+                if window.contains(auction.timestamp) {
+                    let symbol = symbol.clone().expect("Auction needs to have a symbol");
+                    c.auctions.entry(symbol).or_insert(Vec::new()).push(auction);
                 }
-            })
-        },
-        'B' => {
-            // SaleConditionFlags::from_u8(message_subtype).map(|sale_condition_flags| {
-            if bytes.len() >= 38 {
-                let message = TradeBreakMessage {
-                    symbol: [
-                        bytes[10] as char, bytes[11] as char,
-                        bytes[12] as char, bytes[13] as char,
-                        bytes[14] as char, bytes[15] as char,
-                        bytes[16] as char, bytes[17] as char,
-                    ],
-                    size: bytes_u32!(bytes, 18),
-                    price: bytes_u64!(bytes, 22),
-                    trade_id: bytes_u64!(bytes, 30),
-                    sale_condition_flags: message_subtype,
-                };
-                let consumed_bytes = std::mem::size_of_val(&message);
-                let body = IexDeepMessageImpl::TradeBreak(message);
-                Some(ParseMessageResponse {
-                    parsed_message: IexDeepMessage {
-                        message_type,
-                        message_subtype,
-                        timestamp,
-                        body,
-                        packet_number: packet_num,
-                        message_sequence_number: message_seq_num,
-                    },
-                    consumed_bytes,
-                })
-            } else {
-                println!("Not enough bytes to parse message! Have {}, expected {}",
-                      bytes.len(), 38);
-                None
-            }
-            // })
-        },
-        'A' => {
-            // TODO(sherry): implement
-            None
-        },
-        _ => {
-            warn!("unknown message type '{}' in packet {} message {}",
-                  message_type, packet_num, message_seq_num);
-            None
-        },
+            }
+            if let iex_pcap_parser::IexDeepMessageImpl::TradeBreak(trade_break) = &message.body {
+                if config.apply_breaks && !c.trade_breaks.record_break(trade_break.trade_id) {
+                    warn!("Trade break for unknown trade {}", trade_break.trade_id);
+                }
+            }
+            c.tick_counter += 1;
+            let entry = c.tick_type_count.entry(message.message_type).or_insert(0);
+            (*entry) += 1;
+        }
+
+        c.packet_counter += 1;
+        if let Some(progress) = progress {
+            progress.update(c.packet_counter, bytes_read);
+        }
+        if config.stop_at_end_of_messages && c.parse_stats.end_of_messages {
+            info!("stopping at the end of messages in packet {}", c.packet_counter - 1);
+            break;
+        }
     }
+    if let Some(progress) = progress {
+        progress.bar.finish_and_clear();
+    }
+    collected
 }
 
-fn parse_body(bytes: &[u8], packet_num: u64, message_seq_num_start: u64) -> Vec<IexDeepMessage> {
-    let mut messages = Vec::new();
-    let mut offset = 0;
-    let mut message_seq_num = message_seq_num_start;
-    while 2 + offset < bytes.len() {
-        let message_length = bytes_u16!(bytes, offset);
-        offset += 2;
-        if message_length == 0 {
-            warn!("encountered 0-length message at offset {}. breaking", offset);
-            break;
+/// Writes the ticks of the packets to stdout as CSV, each as its packet comes due under `replay`,
+/// until the packets run out or `running` is cleared.
+fn replay_ticks<I>(config: &ParserConfig, replay: &iex_pcap_parser::Replay, packets: I, window: &TimeWindow,
+                   running: &AtomicBool) -> iex_pcap_parser::ParseStats
+    where I: Iterator<Item = Result<iex_pcap_parser::DeepPacket, ParseError>> {
+    let mut parse_stats = iex_pcap_parser::ParseStats::default();
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    if let Err(e) = writeln!(out, "symbol,{}", iex_pcap_parser::csv::HEADER) {
+        panic!("Failed to write replayed ticks: {}", e);
+    }
+    let packets = packets
+        .take_while(|_| running.load(Ordering::SeqCst))
+        .inspect(|packet| parse_stats.record(packet));
+    let replayed = replay.run(packets, |message| {
+        let tick = match message.to_serialized_tick() {
+            Some(_) if config.zero_trades.drops(message).is_some() => return,
+            Some(_) if config.price_range.excludes(message) => return,
+            Some(tick) if window.contains(tick.timestamp) => tick,
+            _ => return,
+        };
+        let symbol = message.normalized_symbol(&config.symbol_normalizer).expect("Ticks have a symbol");
+        // Stdout is line buffered, so each tick reaches the consumer as it's replayed.
+        if let Err(e) = writeln!(out, "{},{}", symbol.trim_end(), iex_pcap_parser::csv::tick_row(&tick)) {
+            panic!("Failed to write replayed ticks: {}", e);
         }
-        if let Some(response) = parse_message(&bytes[offset..], packet_num, message_seq_num) {
-            messages.push(response.parsed_message);
-            trace!("consumed bytes: {}", response.consumed_bytes);
-        } else {
-            warn!("Failed to parse message {} in packet {} at offset {}",
-                  message_seq_num, packet_num, offset);
+    });
+    info!("replayed {} packets", replayed);
+    parse_stats
+}
+
+/// Counts the messages of the input's captures by type without decoding them, and logs the counts.
+fn count_only(config: &ParserConfig, input: &str) -> iex_pcap_parser::ParseStats {
+    let captures = captures_for_input(input, config.recursive).unwrap_or_else(|e| panic!("{}", e));
+    let mut counts = iex_pcap_parser::MessageCounts::default();
+    for capture in &captures {
+        let mut packets = open_capture(capture, config, config.max_packets.map(|max| max - counts.packets));
+        counts.merge(&packets.count_messages());
+        if packets.limit_reached() {
+            warn!("Stopped at --max-packets {}", counts.packets);
+            break;
         }
-        offset += message_length as usize;
-        message_seq_num += 1;
     }
-    messages
+
+    info!("packets counted: {}", counts.packets);
+    info!("packets of other protocols, channels or sessions, or malformed, skipped: {}", counts.packets_skipped);
+    info!("messages counted: {}, {} truncated", counts.total(), counts.truncated);
+    for (message_type, count) in counts.iter() {
+        info!("message type: {} has {} count", message_type as char, count);
+    }
+    let mut parse_stats = iex_pcap_parser::ParseStats::default();
+    parse_stats.packets_total = counts.packets;
+    parse_stats.messages_parsed = counts.total();
+    parse_stats.messages_truncated = counts.truncated;
+    parse_stats
 }
 
-fn parse_header(bytes: &[u8]) -> Option<IexTpHeader> {
-    let iex_header_length = std::mem::size_of::<IexTpHeader>();
-    assert!(iex_header_length == 40);
-    if bytes.len() < iex_header_length {
-        return None;
+/// Like run, for --resume: each capture is written out as soon as it's read and recorded in the
+/// manifest, so a run that stops continues from the first unrecorded capture when run again.
+fn run_resumable(config: &ParserConfig, input: &str, running: &AtomicBool) -> iex_pcap_parser::ParseStats {
+    let captures = captures_for_input(input, config.recursive).unwrap_or_else(|e| panic!("{}", e));
+    let trade_date = trade_date_of_captures(&captures).unwrap_or_else(|e| panic!("{}", e));
+    let window = TimeWindow::new(config.start, config.end, &trade_date, config.dst)
+        .unwrap_or_else(|e| panic!("{}", e));
+    let output_path = config.name_template.render(&trade_date, config.filter.channel_id);
+    let manifest_path = ResumeManifest::path(&output_path);
+    let (file, mut manifest) = open_resumable_output(&output_path, &manifest_path, config);
+    let pending: Vec<&String> = captures.iter().filter(|c| !manifest.captures.contains(c)).collect();
+    if pending.len() < captures.len() {
+        info!("resuming {}: {} of {} captures already written", output_path, captures.len() - pending.len(),
+              captures.len());
     }
 
-    Some(IexTpHeader {
-        version: bytes[0],
-        reserved: bytes[1],
-        message_protocol_id: bytes_u16!(bytes, 2),
-        channel_id: bytes_u32!(bytes, 4),
-        session_id: bytes_u32!(bytes, 8),
-        payload_length: bytes_u16!(bytes, 12),
-        message_count: bytes_u16!(bytes, 14),
-        stream_offset: bytes_u64!(bytes, 16),
-        first_message_sequence_number: bytes_u64!(bytes, 24),
-        send_time: bytes_u64!(bytes, 32),
-    })
-}
+    let mut parse_stats = iex_pcap_parser::ParseStats::default();
+    for capture in pending {
+        info!("reading {}", capture);
+        let mut packets = open_capture(capture, config, None);
+        let collected = collect(config, &mut packets, &window, running, None, None);
+        abort_on_strict_errors(config, &collected.strict_errors);
+        parse_stats.merge(&collected.parse_stats);
+        if !running.load(Ordering::SeqCst) {
+            warn!("Interrupted reading {}; run again with --resume to continue from it", capture);
+            return parse_stats;
+        }
+        if collected.unreadable {
+            panic!("Failed to read all of {}; run again with --resume once it's replaced", capture);
+        }
+        info!("writing {} ticks of {}", collected.stonks_ticks.values().map(Vec::len).sum::<usize>(), capture);
+        write_resumed_capture(&file, config, &mut manifest, &manifest_path, capture, collected, &trade_date);
+        if config.stop_at_end_of_messages && parse_stats.end_of_messages {
+            break;
+        }
+    }
+    log_end_of_messages(config, &parse_stats);
 
-fn debug_header(iex_header: &IexTpHeader) {
-    info!("Version: {}", iex_header.version);
-    info!("Message Protocol ID: {}", iex_header.message_protocol_id);
-    info!("Channel ID: {}", iex_header.channel_id);
-    info!("Session ID: {}", iex_header.session_id);
-    info!("Payload length: {}", iex_header.payload_length);
-    info!("Message count: {}", iex_header.message_count);
-    info!("First msg seq num: {}", iex_header.first_message_sequence_number);
-    info!("Send time: {}", iex_header.send_time);
-    info!("");
+    finish_resumable_output(&file, &manifest, &manifest_path, &config.filter);
+    info!("wrote {} captures to {}", manifest.captures.len(), output_path);
+    parse_stats
 }
 
-#[derive(Debug)]
-enum LoadPcapError {
-    NoFileExtension,
-    WrongFileExtension,
-    FileError(io::Error),
-    DeflateError(io::Error),
-    PcapError(pcap::Error),
+/// The security directory's symbols without any trades kept, other than test securities being
+/// skipped, in name order.
+fn untraded_symbols(directory_symbols: &HashSet<String>, traded_symbols: &HashSet<String>,
+                    test_symbols: &HashSet<String>) -> Vec<String> {
+    let mut untraded: Vec<String> = directory_symbols.iter()
+        .filter(|symbol| !traded_symbols.contains(*symbol) && !test_symbols.contains(*symbol))
+        .cloned()
+        .collect();
+    untraded.sort();
+    untraded
 }
 
-fn load_capture_from_pcap<P: AsRef<path::Path>>(path: P) -> Result<pcap::Capture<pcap::Offline>, LoadPcapError> {
-    Capture::from_file(path).or_else(|e| Err(LoadPcapError::PcapError(e)))
+/// Logs whether the session's end of messages was read, as it is from a complete capture.
+fn log_end_of_messages(config: &ParserConfig, parse_stats: &iex_pcap_parser::ParseStats) {
+    if parse_stats.end_of_messages {
+        info!("end of messages: seen");
+    } else if !config.trades_only && !config.dump_headers {
+        warn!("end of messages: not seen; the capture is likely truncated");
+    }
 }
 
-fn load_capture_from_gz(path: &str) -> Result<pcap::Capture<pcap::Offline>, LoadPcapError> {
-    let f = match fs::File::open(path) {
-        Ok(f) => f,
-        Err(e) => {
-            return Err(LoadPcapError::FileError(e));
+/// Reads the input of `config` and writes its ticks out, until done or `running` is cleared.
+fn run(config: &ParserConfig, running: &AtomicBool) -> iex_pcap_parser::ParseStats {
+    let input = &config.input;
+    if let (true, Input::File(input)) = (config.count_only, input) {
+        return count_only(config, input);
+    }
+    if let (true, Input::File(input)) = (config.resume, input) {
+        return run_resumable(config, input, running);
+    }
+
+    let (mut packets, trade_date) = match input {
+        Input::File(input) => {
+            let captures = captures_for_input(input, config.recursive).unwrap_or_else(|e| panic!("{}", e));
+            let trade_date = trade_date_of_captures(&captures).unwrap_or_else(|e| panic!("{}", e));
+            if captures.len() > 1 {
+                info!("reading {} captures from {}", captures.len(), input);
+            }
+            let mut pending = VecDeque::from(captures);
+            let first = pending.pop_front().expect("at least one capture");
+            let packets = open_capture(&first, config, config.max_packets);
+            (Captures::new(config, packets, vec![first], pending), trade_date)
         },
-    };
-    let mut data = Vec::new();
-    if let Err(e) = flate2::read::GzDecoder::new(io::BufReader::new(f)).read_to_end(&mut data) {
-        return Err(LoadPcapError::DeflateError(e));
-    }
-    let temp_path = {
-        let p = path::Path::new(path);
-        let mut temp_dir = env::temp_dir();
-        temp_dir.push(p.file_stem().unwrap());
-        temp_dir
-    };
-    let temp_path2 = {
-        let p = path::Path::new(path);
-        let mut temp_dir = env::temp_dir();
-        temp_dir.push(p.file_stem().unwrap());
-        temp_dir
-    };
-    let mut pcap_file = match fs::File::create(temp_path) {
-        Ok(f) => f,
-        Err(e) => {
-            return Err(LoadPcapError::FileError(e));
+        #[cfg(not(feature = "native-pcap"))]
+        Input::Live(iface) => panic!("Capturing from {} needs libpcap; build with the native-pcap feature", iface),
+        #[cfg(feature = "native-pcap")]
+        Input::Live(iface) => {
+            let capture = match iex_pcap_parser::load_capture_from_device(iface) {
+                Ok(cap) => cap,
+                Err(e) => panic!("Failed to capture from {} with error: {:?}", iface, e),
+            };
+            let packets = iex_pcap_parser::Packets::new(capture.into())
+                .with_mode(parse_mode(config))
+                .with_filter(config.filter)
+                .with_trades_only(config.trades_only)
+                .with_skip_auctions(!config.include_auctions)
+                .with_headers_only(config.dump_headers);
+            // Today in New York, where IEX trades.
+            let today = chrono::Utc::now().with_timezone(&libdt::UsEastern).date_naive();
+            (Captures::new(config, packets, Vec::new(), VecDeque::new()), today)
         },
     };
-    if let Err(e) = pcap_file.write_all(&data) {
-        return Err(LoadPcapError::FileError(e));
+    let live = match input {
+        Input::Live(_) => true,
+        Input::File(_) => false,
+    };
+    let window = TimeWindow::new(config.start, config.end, &trade_date, config.dst)
+        .unwrap_or_else(|e| panic!("{}", e));
+    if let Some(replay) = &config.replay {
+        return replay_ticks(config, replay, packets, &window, running);
     }
-    info!("Decompressed gz to temp file {:?}", temp_path2);
-    load_capture_from_pcap(temp_path2)
-}
 
-// TODO(sherry): avoid uncompressing into temp pcap and read directly from gz
-fn load_capture_from_file(file: &str) -> Result<pcap::Capture<pcap::Offline>, LoadPcapError> {
-    let path = path::Path::new(file);
-    if let Some(extension) = path.extension() {
-        if extension == ffi::OsStr::new("pcap") {
-            load_capture_from_pcap(file)
-        } else if extension == ffi::OsStr::new("gz") {
-            load_capture_from_gz(file)
-        } else {
-            Err(LoadPcapError::WrongFileExtension)
+    let output_path = config.name_template.render(&trade_date, config.filter.channel_id);
+
+    // Checked upfront, so a refused run doesn't parse anything first. Dumping headers writes nothing.
+    if !config.overwrite && !config.dump_headers {
+        let existing = existing_outputs(path::Path::new("."), &trade_date, &output_path, config.split_by_symbol)
+            .unwrap_or_else(|e| panic!("Failed to look for existing output: {}", e));
+        if let Some(path) = existing.first() {
+            panic!("{} already exists; pass --overwrite to replace it", path.display());
         }
+    }
+
+    // In live mode the file is created upfront so ticks can be flushed to it as they come in.
+    let live_file = if live && !config.dump_headers {
+        Some(open_output(&output_path, &config.write_options, false))
+    } else {
+        None
+    };
+
+    let progress = if config.progress && io::stdout().is_terminal() {
+        Some(Progress::new(input))
     } else {
-        Err(LoadPcapError::NoFileExtension)
+        None
+    };
+
+    let Collected {
+        mut stonks_ticks,
+        mut official_prices,
+        mut auctions,
+        test_symbols,
+        directory_symbols,
+        traded_symbols,
+        tick_type_count,
+        packet_counter,
+        tick_counter,
+        tradeable_counter,
+        mut removed,
+        mut dataset_names,
+        symbol_names,
+        mut unwritable,
+        mut unwritable_ticks,
+        skipped_counter,
+        other_protocol_counter,
+        filtered_counter,
+        outside_window,
+        zero_size_trades,
+        zero_price_trades,
+        out_of_range_trades,
+        trade_breaks,
+        channels,
+        channel_summary,
+        stream_gaps,
+        parse_stats,
+        unreadable: _,
+        raw_messages,
+        strict_errors,
+    } = collect(config, &mut packets, &window, running, live_file.as_ref(), progress.as_ref());
+    abort_on_strict_errors(config, &strict_errors);
+
+    // Stopping is the normal way to end a live capture, but means a file was only partially read.
+    let interrupted = !live && !running.load(Ordering::SeqCst);
+    if interrupted {
+        warn!("Interrupted after {} packets; writing partial output", packet_counter);
     }
-}
+    let truncated = !interrupted && packets.limit_reached();
+    if truncated {
+        warn!("Stopped at --max-packets {}; writing partial output", packet_counter);
+    }
+    let interrupted = interrupted || truncated;
 
-fn main() {
-    pretty_env_logger::formatted_timed_builder()
-        .default_format_timestamp_nanos(true)
-        .init();
+    info!("packets processed: {}", packet_counter);
+    info!("ticks processed: {}", tick_counter);
+    info!("messages skipped: {}", skipped_counter);
+    info!("packets of other protocols skipped: {}", other_protocol_counter);
+    info!("packets of other channels or sessions skipped: {}", filtered_counter);
+    let channel_id = single_channel(&channels, &config.filter);
+    let channels: Vec<String> = channels.iter()
+        .map(|(channel, session)| format!("{}/{}", channel, session))
+        .collect();
+    info!("channel/session ids seen: {}", channels.join(", "));
+    if config.channel_summary {
+        for (channel_id, messages, symbols) in channel_summary.iter() {
+            info!("channel {}: {} messages of {} symbols", channel_id, messages, symbols);
+        }
+    }
+    info!("stream gaps: {} totalling {} missing bytes", stream_gaps.gaps, stream_gaps.missing_bytes);
+    if !symbol_names.collisions.is_empty() {
+        let collisions: Vec<String> = symbol_names.collisions.iter()
+            .map(|(symbol, name)| format!("{:?} as {:?}", symbol, name.trim_end()))
+            .collect();
+        info!("symbols normalizing like another: {}", collisions.join(", "));
+    }
+    info!("parse stats: {} packets, {} not IEX-TP, {} with malformed headers; {} messages parsed, \
+           {} of unknown type, {} with unknown enum bytes, {} truncated, {} of 0 length; {} message sequence gaps",
+          parse_stats.packets_total, parse_stats.packets_skipped_non_iex, parse_stats.headers_malformed,
+          parse_stats.messages_parsed, parse_stats.messages_unknown, parse_stats.messages_invalid_enum,
+          parse_stats.messages_truncated,
+          parse_stats.messages_zero_length, parse_stats.sequence_gaps);
+    if parse_stats.auctions_prefix_layout > 0 {
+        warn!("{} auction information messages had an older, shorter layout; their collars are written as 0",
+              parse_stats.auctions_prefix_layout);
+    }
+    if !interrupted && !live {
+        log_end_of_messages(config, &parse_stats);
+    }
+    if config.dump_headers {
+        return parse_stats;
+    }
 
-    let _ = hdf5::silence_errors();
+    if window != TimeWindow::default() {
+        info!("dropped {} ticks outside of --start/--end", outside_window);
+    }
+    removed += outside_window;
+    if config.zero_trades != iex_pcap_parser::ZeroTradeFilter::default() {
+        info!("dropped {} trades of 0 size and {} of 0 price", zero_size_trades, zero_price_trades);
+    }
+    if config.price_range != iex_pcap_parser::PriceRange::default() {
+        info!("dropped {} trades priced outside --min-price/--max-price", out_of_range_trades);
+    }
+    let untraded = untraded_symbols(&directory_symbols, &traded_symbols, &test_symbols);
+    if !directory_symbols.is_empty() {
+        info!("{} of {} security directory symbols had no trades", untraded.len(), directory_symbols.len());
+    }
 
-    let vargs: Vec<String> = env::args().collect();
-    if vargs.len() < 2 {
-        panic!("Needs at least 2 args");
+    let broken = trade_breaks.remove_broken(&mut stonks_ticks);
+    if config.apply_breaks {
+        info!("removed {} broken trades", broken);
     }
+    removed += broken;
 
-    let pcap = &vargs[1];
-    let mut capture = match load_capture_from_file(pcap) {
-        Ok(cap) => cap,
-        Err(e) => panic!("Failed to load {} with error: {:?}", pcap, e),
+    remove_test_securities(&mut official_prices, &test_symbols);
+    remove_test_securities(&mut auctions, &test_symbols);
+    let test_security_ticks = remove_test_securities(&mut stonks_ticks, &test_symbols);
+    removed += test_security_ticks;
+    if test_security_ticks > 0 {
+        info!("dropped {} ticks of {} test securities", test_security_ticks, test_symbols.len());
+    }
+    for (symbol, ticks) in remove_unwritable_symbols(&mut stonks_ticks, &mut dataset_names) {
+        warn!("Skipping {} ticks of unwritable symbol {:?}", ticks, symbol);
+        unwritable.insert(symbol);
+        unwritable_ticks += ticks;
+    }
+    if !unwritable.is_empty() {
+        info!("skipped {} ticks of {} unwritable symbols", unwritable_ticks, unwritable.len());
+    }
+    for (symbol, prices) in remove_unwritable_symbols(&mut official_prices, &mut dataset_names) {
+        warn!("Skipping {} official prices of unwritable symbol {:?}", prices, symbol);
+    }
+    info!("official prices: {} of {} symbols", official_prices.values().map(Vec::len).sum::<usize>(),
+          official_prices.len());
+    for (symbol, count) in remove_unwritable_symbols(&mut auctions, &mut dataset_names) {
+        warn!("Skipping {} auctions of unwritable symbol {:?}", count, symbol);
+    }
+    info!("auctions: {} of {} symbols", auctions.values().map(Vec::len).sum::<usize>(), auctions.len());
+    removed += unwritable_ticks;
+    let tick_yield = TickYield {
+        written: tradeable_counter - removed,
+        tradeable: tradeable_counter,
+        messages: tick_counter,
     };
+    info!("{}", tick_yield);
+    if tick_yield.written < tick_yield.tradeable {
+        info!("{:.2}% of tradeable messages were written", tick_yield.written_percent());
+    }
+
+    // Live captures are named after the local date, so only files are checked.
+    if !live {
+        let mismatches = ticks_off_trade_date(&stonks_ticks, &trade_date);
+        for (symbol, count, date) in &mismatches {
+            let date = date.map_or("an invalid date".to_string(), |d| d.to_string());
+            warn!("{} has {} ticks not on trade date {}, first on {}", symbol.trim_end(), count, trade_date, date);
+        }
+        if config.strict && !mismatches.is_empty() {
+            panic!("{} symbols have ticks not on trade date {}", mismatches.len(), trade_date);
+        }
+        let off_date = flag_auctions_off_trade_date(&mut auctions, &trade_date);
+        if off_date > 0 {
+            warn!("{} auctions are scheduled for a date other than trade date {}", off_date, trade_date);
+        }
+    }
 
-    // let mut system_ticks = Vec::new();
-    let mut stonks_ticks = HashMap::new();
-    let mut tick_type_count = HashMap::new();
+    if config.check_monotonic {
+        let out_of_order = timestamps_out_of_order(&stonks_ticks);
+        info!("ticks with a timestamp before the previous tick's: {}", out_of_order.len());
+        for (symbol, sequence_number, previous, timestamp) in out_of_order.iter().take(OUT_OF_ORDER_SHOWN) {
+            warn!("{} tick {} has timestamp {}, before the previous tick's {}", symbol.trim_end(), sequence_number,
+                  timestamp, previous);
+        }
+        if config.strict && !out_of_order.is_empty() {
+            panic!("{} ticks have a timestamp before the previous tick's", out_of_order.len());
+        }
+    }
 
-    let mut packet_counter = 0;
-    let mut tick_counter = 0;
-    while let Ok(raw_packet) = capture.next() {
-        let packet = match etherparse::SlicedPacket::from_ethernet(raw_packet.data) {
-            Err(value) => panic!("Failed to parse from ethernet: {:?}", value),
-            Ok(value) => value,
-        };
-        let iex_header = match parse_header(packet.payload) {
-            Some(hdr) => hdr,
-            None => panic!("Failed to parse header because it was too short"),
-        };
-        assert!(iex_header.version == 0x1);
-        assert!(iex_header.message_protocol_id == 0x8004);
+    if config.sort {
+        for ticks in stonks_ticks.values_mut() {
+            libh5::sort_ticks(ticks);
+        }
+    }
 
-        // dump_header(&iex_header);
+    let sources = packets.sources();
+    // Split output has a single dataset per file, so there's no symbol index to write.
+    let file = match live_file {
+        Some(file) => {
+            // Whatever is still buffered, however little.
+            flush_ticks(&file, &mut stonks_ticks, &config.write_options);
+            Some(file)
+        },
+        None if config.split_by_symbol => {
+            let output = OutputOptions {
+                write_options: &config.write_options,
+                sorted: config.sort,
+                index_by_time: config.index_by_time,
+                interrupted,
+                channel_id,
+                bars: config.bars,
+                sources: &sources,
+            };
+            let paths = write_split_by_symbol(path::Path::new("."), &trade_date, &stonks_ticks, &official_prices,
+                                              &auctions, &output);
+            info!("wrote {} files", paths.len());
+            None
+        },
+        None => {
+            let file = open_output(&output_path, &config.write_options, interrupted);
+            if config.combined {
+                let rows = combined_ticks(&stonks_ticks, config.sort);
+                info!("writing {} ticks of {} symbols combined", rows.len(), stonks_ticks.len());
+                match libh5::write_combined_ticks(&file, &rows, &config.write_options) {
+                    Ok(dataset) => if let Err(e) = libh5::write_attr(&dataset, libh5::SORTED_ATTR, &config.sort) {
+                        panic!("Failed to write sorted attribute for combined ticks: {}", e);
+                    },
+                    Err(e) => panic!("Failed to write combined ticks: {}", e),
+                }
+            }
+            for (symbol, ticks) in &stonks_ticks {
+                if !config.combined {
+                    write_dataset(&file, symbol, ticks, &config.write_options, config.sort, config.index_by_time);
+                }
+                if let Some(interval) = config.bars {
+                    write_bars(&file, symbol, ticks, interval);
+                }
+            }
+            Some(file)
+        },
+    };
 
-        let messages = parse_body(&packet.payload[std::mem::size_of::<IexTpHeader>()..], packet_counter, iex_header.first_message_sequence_number);
-        for message in messages {
-            if let Some(serialized_tick) = message.to_serialized_tick() {
-                let symbol = match message.symbol() {
-                    Some(symbol) => symbol,
-                    None => panic!("Trade tick needs to have a symbol"),
-                };
-                let entry = stonks_ticks.entry(symbol).or_insert(Vec::new());
-                (*entry).push(serialized_tick);
+    if let Some(file) = file {
+        write_channel_id(&file, channel_id);
+        write_source_files(&file, &sources);
+        for symbol in official_prices.keys() {
+            write_official_prices(&file, symbol, &official_prices);
+        }
+        for symbol in auctions.keys() {
+            write_auctions(&file, symbol, &auctions);
+        }
+        // The index counts the ticks of each symbol's dataset, which combined output doesn't have.
+        let symbols: Vec<&String> = stonks_ticks.keys().collect();
+        if !config.combined {
+            if let Err(e) = libh5::write_symbol_index(&file, &symbols) {
+                panic!("Failed to write symbol index: {}", e);
             }
-            tick_counter += 1;
-            let entry = tick_type_count.entry(message.message_type).or_insert(0);
-            (*entry) += 1;
         }
+        if config.untraded_symbols {
+            if let Err(e) = libh5::write_untraded_symbols(&file, &untraded) {
+                panic!("Failed to write untraded symbols: {}", e);
+            }
+        }
+        if config.capture_raw {
+            info!("writing {} raw messages", raw_messages.len());
+            if let Err(e) = libh5::write_raw_messages(&file, &raw_messages) {
+                panic!("Failed to write raw messages: {}", e);
+            }
+        }
+    }
 
-        packet_counter += 1;
+    if config.vwap {
+        for (symbol, snapshot) in symbol_vwaps(&stonks_ticks) {
+            info!("{:<8} vwap {:.4} volume {} trades {} last {}", symbol.trim_end(),
+                  snapshot.vwap.expect("symbols with trades have a vwap"), snapshot.volume, snapshot.trade_count,
+                  snapshot.last_price.expect("symbols with trades have a last price"));
+        }
     }
 
-    info!("packets processed: {}", packet_counter);
-    info!("ticks processed: {}", tick_counter);
+    for (tick_type, count) in &tick_type_count {
+        info!("tick type: {} has {} count", tick_type.clone() as char, count);
+    }
 
-    let trade_date = libiex::trade_date_from_deep_pcap(pcap)
-        .unwrap_or_else(|e| panic!("{:?}", e));
-    let file = match hdf5::file::File::open(format!("{}.h5", trade_date.format("%Y%m%d")), "w") {
-        Ok(f) => f,
-        Err(e) => panic!("Failed to open hdf5 handle: {}", e),
+    parse_stats
+}
+
+fn init_logger(log_level: log::LevelFilter) {
+    // An explicitly set RUST_LOG takes precedence over -v/-q. Logs go to stderr, keeping stdout for
+    // CSV written there by export and --replay.
+    let mut logger = pretty_env_logger::formatted_timed_builder();
+    logger.default_format_timestamp_nanos(true);
+    match env::var("RUST_LOG") {
+        Ok(filters) => logger.parse_filters(&filters),
+        Err(_) => logger.filter_level(log_level),
     };
+    logger.init();
+}
 
-    for (symbol, ticks) in &stonks_ticks {
-        info!("writing {} ticks for symbol {}", ticks.len(), symbol);
-        let dataset = match file.new_dataset::<libh5::Tick>().create(symbol, ticks.len()) {
-            Ok(x) => x,
-            Err(e) => panic!("Failed to create dataset for {}: {}", symbol, e),
+fn main() {
+    let vargs: Vec<String> = env::args().collect();
+    if vargs.get(1).map(String::as_str) == Some("export") {
+        let args = match parse_export_args(&vargs[2..]) {
+            Ok(args) => args,
+            Err(e) => panic!("{}\nUsage: {} export --in <file.h5> --symbol <symbol> --out <file.csv|->", e, vargs[0]),
         };
-        match dataset.write(&ticks) {
-            Ok(x) => {},
-            Err(e) => panic!("Failed to write ticks for {}: {}", symbol, e),
+        init_logger(log::LevelFilter::Info);
+        let _ = hdf5::silence_errors();
+        let stdout = io::stdout();
+        match export_csv(&args, stdout.lock()) {
+            Ok(count) if args.output == STDOUT_OUTPUT => info!("exported {} ticks for {} to stdout", count, args.symbol),
+            Ok(count) => info!("exported {} ticks for {} to {}", count, args.symbol, args.output),
+            Err(e) => panic!("Failed to write {}: {}", args.output, e),
+        }
+        return;
+    }
+    if vargs.get(1).map(String::as_str) == Some("merge") {
+        let args = match parse_merge_args(&vargs[2..]) {
+            Ok(args) => args,
+            Err(e) => panic!("{}\nUsage: {} merge --out <file.h5> [--concat] [--overwrite] <inputs.h5>...", e, vargs[0]),
         };
+        init_logger(log::LevelFilter::Info);
+        let _ = hdf5::silence_errors();
+        match merge_h5(&args) {
+            Ok(count) => info!("merged {} symbols into {}", count, args.output),
+            Err(e) => panic!("Failed to merge into {}: {}", args.output, e),
+        }
+        return;
     }
 
-    for (tick_type, count) in &tick_type_count {
-        info!("tick type: {} has {} count", tick_type.clone() as char, count);
+    if vargs.get(1).map(String::as_str) == Some("diff") {
+        let args = match parse_diff_args(&vargs[2..]) {
+            Ok(args) => args,
+            Err(e) => panic!("{}\nUsage: {} diff --a <old.h5> --b <new.h5> [--deep] [--limit <n>]", e, vargs[0]),
+        };
+        init_logger(log::LevelFilter::Info);
+        let _ = hdf5::silence_errors();
+        let diff = match diff_h5(&args) {
+            Ok(diff) => diff,
+            Err(e) => panic!("Failed to compare {} and {}: {}", args.a, args.b, e),
+        };
+        for difference in &diff.reported {
+            warn!("{}", difference);
+        }
+        if diff.count > 0 {
+            error!("{} differences between {} and {}", diff.count, args.a, args.b);
+            std::process::exit(1);
+        }
+        info!("no differences between {} and {}", args.a, args.b);
+        return;
+    }
+
+    if vargs.get(1).map(String::as_str) == Some("info") {
+        let args = match parse_info_args(&vargs[2..]) {
+            Ok(args) => args,
+            Err(e) => panic!("{}\nUsage: {} info --in <file.h5>", e, vargs[0]),
+        };
+        init_logger(log::LevelFilter::Info);
+        let _ = hdf5::silence_errors();
+        match h5_info(&args) {
+            Ok(lines) => {
+                for line in lines {
+                    println!("{}", line);
+                }
+            },
+            Err(e) => panic!("Failed to read {}: {}", args.input, e),
+        }
+        return;
+    }
+
+    if vargs.get(1).map(String::as_str) == Some("verify") {
+        let args = match parse_verify_args(&vargs[2..]) {
+            Ok(args) => args,
+            Err(e) => panic!("{}\nUsage: {} verify --in <file.h5>", e, vargs[0]),
+        };
+        init_logger(log::LevelFilter::Info);
+        let _ = hdf5::silence_errors();
+        let report = match verify_h5(&args) {
+            Ok(report) => report,
+            Err(e) => panic!("Failed to read {}: {}", args.input, e),
+        };
+        for (dataset, e) in &report.corrupt {
+            error!("{} is corrupt: {}", dataset, e);
+        }
+        if !report.corrupt.is_empty() {
+            error!("{} of {} datasets of {} are corrupt", report.corrupt.len(), report.datasets, args.input);
+            std::process::exit(1);
+        }
+        info!("read all {} datasets of {}, {} of them checksummed", report.datasets, args.input, report.checksummed);
+        return;
+    }
+
+    if vargs.iter().skip(1).any(|arg| arg == "--help" || arg == "-h") {
+        println!("{}", usage(&vargs[0]));
+        return;
+    }
+    let config = match parse_args(&vargs) {
+        Ok(config) => config,
+        Err(e) => panic!("{}\n{}", e, usage(&vargs[0])),
+    };
+
+    init_logger(config.log_level);
+
+    let _ = hdf5::silence_errors();
+
+    // Ctrl-C stops the main loop; whatever was parsed up to that point still gets written.
+    let running = Arc::new(AtomicBool::new(true));
+    let r = running.clone();
+    if let Err(e) = ctrlc::set_handler(move || r.store(false, Ordering::SeqCst)) {
+        panic!("Failed to install Ctrl-C handler: {}", e);
     }
 
+    run(&config, &running);
+
     info!("Hello, world!");
 }
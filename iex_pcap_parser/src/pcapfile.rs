@@ -0,0 +1,129 @@
+//! Minimal reader of classic pcap files, so captures can be read without libpcap. Only the packet
+//! data and timestamps of each record are extracted.
+
+use std::convert::TryInto;
+
+use crate::pcapng::{Packet, TruncatedBlock};
+
+#[cfg(test)]
+pub(crate) mod tests {
+    /// A capture of `frames` in `magic`'s byte order and timestamp precision, `subsec` apart.
+    pub(crate) fn capture(magic: u32, big_endian: bool, frames: &[Vec<u8>], subsec: u32) -> Vec<u8> {
+        let to_bytes = |field: u32| if big_endian { field.to_be_bytes() } else { field.to_le_bytes() };
+        let mut bytes = Vec::new();
+        // Magic, version 2.4, timezone, sigfigs, snaplen, ethernet link type.
+        for &field in &[magic, 0x0004_0002, 0, 0, 65535, 1] {
+            bytes.extend_from_slice(&to_bytes(field));
+        }
+        for (i, frame) in frames.iter().enumerate() {
+            // Seconds, microseconds or nanoseconds, captured length, original length.
+            for &field in &[1_562_160_000u32, i as u32 * subsec, frame.len() as u32, frame.len() as u32] {
+                bytes.extend_from_slice(&to_bytes(field));
+            }
+            bytes.extend_from_slice(frame);
+        }
+        bytes
+    }
+
+    #[test]
+    fn test_records() {
+        let frames = vec![vec![1, 2, 3], vec![4, 5]];
+        for &big_endian in &[false, true] {
+            let mut records = crate::pcapfile::Records::new(capture(crate::pcapfile::MAGIC, big_endian, &frames, 250)).unwrap();
+            let packet = records.next_packet().unwrap().unwrap();
+            assert_eq!((packet.data, packet.timestamp_ns.as_nanos()), (&[1u8, 2, 3][..], 1_562_160_000_000_000_000));
+            let packet = records.next_packet().unwrap().unwrap();
+            assert_eq!((packet.data, packet.timestamp_ns.as_nanos()), (&[4u8, 5][..], 1_562_160_000_000_250_000));
+            assert!(records.next_packet().is_none());
+        }
+
+        let mut records = crate::pcapfile::Records::new(capture(crate::pcapfile::NANOSECOND_MAGIC, false, &frames, 250)).unwrap();
+        records.next_packet();
+        assert_eq!(records.next_packet().unwrap().unwrap().timestamp_ns.as_nanos(), 1_562_160_000_000_000_250);
+    }
+
+    #[test]
+    fn test_truncated_record() {
+        let mut bytes = capture(crate::pcapfile::MAGIC, false, &[vec![1, 2, 3], vec![4, 5]], 1);
+        bytes.truncate(bytes.len() - 1);
+        let mut records = crate::pcapfile::Records::new(bytes).unwrap();
+        assert!(records.next_packet().unwrap().is_ok());
+        assert_eq!(records.next_packet().unwrap().err().unwrap().offset, 24 + 16 + 3);
+        assert!(records.next_packet().is_none());
+    }
+
+    #[test]
+    fn test_not_pcap() {
+        assert!(crate::pcapfile::Records::new(crate::pcapng::tests::capture(&[])).is_none());
+        assert!(crate::pcapfile::Records::new(crate::pcapfile::MAGIC.to_le_bytes().to_vec()).is_none());
+    }
+}
+
+pub(crate) const MAGIC: u32 = 0xa1b2_c3d4;
+/// Written instead of `MAGIC` when record timestamps count nanoseconds rather than microseconds.
+pub(crate) const NANOSECOND_MAGIC: u32 = 0xa1b2_3c4d;
+const FILE_HEADER_LENGTH: usize = 24;
+const RECORD_HEADER_LENGTH: usize = 16;
+
+pub struct Records {
+    data: Vec<u8>,
+    offset: usize,
+    big_endian: bool,
+    nanosecond: bool,
+}
+
+impl Records {
+    /// Returns `None` unless `data` starts with a pcap file header, in either byte order.
+    pub fn new(data: Vec<u8>) -> Option<Records> {
+        if data.len() < FILE_HEADER_LENGTH {
+            return None;
+        }
+        let magic: [u8; 4] = data[0..4].try_into().unwrap();
+        let (big_endian, nanosecond) = match (u32::from_le_bytes(magic), u32::from_be_bytes(magic)) {
+            (MAGIC, _) => (false, false),
+            (NANOSECOND_MAGIC, _) => (false, true),
+            (_, MAGIC) => (true, false),
+            (_, NANOSECOND_MAGIC) => (true, true),
+            _ => return None,
+        };
+        Some(Records {
+            data,
+            offset: FILE_HEADER_LENGTH,
+            big_endian,
+            nanosecond,
+        })
+    }
+
+    fn u32_at(&self, offset: usize) -> u32 {
+        if self.big_endian {
+            crate::bytes::read_u32_be(&self.data, offset)
+        } else {
+            crate::bytes::read_u32_le(&self.data, offset)
+        }
+    }
+
+    /// The next record. Reading stops at the first truncated record.
+    pub fn next_packet(&mut self) -> Option<Result<Packet<'_>, TruncatedBlock>> {
+        let start = self.offset;
+        if start >= self.data.len() {
+            return None;
+        }
+        let data_start = start + RECORD_HEADER_LENGTH;
+        let captured_length = if data_start <= self.data.len() { self.u32_at(start + 8) as usize } else { 0 };
+        if data_start > self.data.len() || captured_length > self.data.len() - data_start {
+            self.offset = self.data.len();
+            return Some(Err(TruncatedBlock { offset: start }));
+        }
+        self.offset = data_start + captured_length;
+        let (seconds, subsec) = (u64::from(self.u32_at(start)), u64::from(self.u32_at(start + 4)));
+        let timestamp_ns = if self.nanosecond {
+            libdt::UtcNs::from_nanos(seconds * libdt::NS_PER_SEC + subsec)
+        } else {
+            crate::capture_timestamp_ns(seconds as i64, subsec as i64)
+        };
+        Some(Ok(Packet {
+            timestamp_ns,
+            data: &self.data[data_start..self.offset],
+        }))
+    }
+}
@@ -0,0 +1,243 @@
+//! Minimal pcapng reader, used without libpcap or when it's too old to open pcapng captures. Only the
+//! packet data and timestamps of enhanced packet blocks are extracted; apart from the interface
+//! descriptions needed to interpret timestamps, every other block is skipped.
+
+#[cfg(test)]
+pub(crate) mod tests {
+    fn block(block_type: u32, body: &[u8]) -> Vec<u8> {
+        let total_length = (12 + body.len()) as u32;
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(&block_type.to_le_bytes());
+        bytes.extend_from_slice(&total_length.to_le_bytes());
+        bytes.extend_from_slice(body);
+        bytes.extend_from_slice(&total_length.to_le_bytes());
+        bytes
+    }
+
+    fn enhanced_packet(interface_id: u32, timestamp: u64, data: &[u8]) -> Vec<u8> {
+        let mut body = Vec::new();
+        let (high, low) = ((timestamp >> 32) as u32, timestamp as u32);
+        for field in &[interface_id, high, low, data.len() as u32, data.len() as u32] {
+            body.extend_from_slice(&field.to_le_bytes());
+        }
+        body.extend_from_slice(data);
+        body.resize((body.len() + 3) / 4 * 4, 0);
+        block(crate::pcapng::ENHANCED_PACKET_BLOCK, &body)
+    }
+
+    fn section_header() -> Vec<u8> {
+        let mut section_header = Vec::new();
+        section_header.extend_from_slice(&crate::pcapng::BYTE_ORDER_MAGIC.to_le_bytes());
+        section_header.extend_from_slice(&1u16.to_le_bytes());
+        section_header.extend_from_slice(&0u16.to_le_bytes());
+        section_header.extend_from_slice(&(-1i64).to_le_bytes());
+        block(crate::pcapng::SECTION_HEADER_BLOCK, &section_header)
+    }
+
+    /// A capture of `frames` on a single interface with microsecond timestamps.
+    pub(crate) fn capture(frames: &[Vec<u8>]) -> Vec<u8> {
+        let mut bytes = section_header();
+        bytes.extend(block(1, &[1, 0, 0, 0, 0, 0, 0, 0]));
+        for (i, frame) in frames.iter().enumerate() {
+            bytes.extend(enhanced_packet(0, 1_562_160_000_000_000 + i as u64, frame));
+        }
+        bytes
+    }
+
+    /// A section with an interface, two packets and a name resolution block in between.
+    fn fixture() -> Vec<u8> {
+        let mut bytes = section_header();
+        // Microsecond timestamps by default, then one interface with if_tsresol of nanoseconds.
+        bytes.extend(block(1, &[1, 0, 0, 0, 0, 0, 0, 0]));
+        bytes.extend(block(1, &[1, 0, 0, 0, 0, 0, 0, 0, 9, 0, 1, 0, 9, 0, 0, 0, 0, 0, 0, 0]));
+        bytes.extend(enhanced_packet(0, 1_562_160_000_123_456, &[1, 2, 3, 4, 5]));
+        bytes.extend(block(4, &[0, 0, 0, 0]));
+        bytes.extend(enhanced_packet(1, 1_562_160_000_123_456_789, &[6, 7, 8, 9]));
+        bytes
+    }
+
+    #[test]
+    fn test_enhanced_packets() {
+        let bytes = fixture();
+        assert!(crate::pcapng::is_pcapng(&bytes));
+        let mut packets = crate::pcapng::EnhancedPackets::new(bytes).unwrap();
+        let packet = packets.next_packet().unwrap().unwrap();
+        assert_eq!(packet.data, &[1, 2, 3, 4, 5]);
+        assert_eq!(packet.timestamp_ns.as_nanos(), 1_562_160_000_123_456_000);
+        let packet = packets.next_packet().unwrap().unwrap();
+        assert_eq!(packet.data, &[6, 7, 8, 9]);
+        assert_eq!(packet.timestamp_ns.as_nanos(), 1_562_160_000_123_456_789);
+        assert!(packets.next_packet().is_none());
+    }
+
+    #[test]
+    fn test_timestamp_ns() {
+        assert_eq!(crate::pcapng::timestamp_ns(1_500, 6).as_nanos(), 1_500_000);
+        assert_eq!(crate::pcapng::timestamp_ns(1_500, 9).as_nanos(), 1_500);
+        assert_eq!(crate::pcapng::timestamp_ns(1_500, 12).as_nanos(), 1);
+        assert_eq!(crate::pcapng::timestamp_ns(3 << 10, 0x80 | 10).as_nanos(), 3_000_000_000);
+        // A malformed if_tsresol of 10^-48 seconds or less.
+        assert_eq!(crate::pcapng::timestamp_ns(u64::MAX, 48).as_nanos(), 0);
+        assert_eq!(crate::pcapng::timestamp_ns(u64::MAX, 0x7f).as_nanos(), 0);
+    }
+
+    #[test]
+    fn test_truncated_block() {
+        let mut bytes = fixture();
+        bytes.truncate(bytes.len() - 6);
+        let mut packets = crate::pcapng::EnhancedPackets::new(bytes).unwrap();
+        assert!(packets.next_packet().unwrap().is_ok());
+        assert!(packets.next_packet().unwrap().is_err());
+        assert!(packets.next_packet().is_none());
+    }
+
+    #[test]
+    fn test_not_pcapng() {
+        assert!(!crate::pcapng::is_pcapng(&0xa1b2_c3d4u32.to_le_bytes()));
+        assert!(crate::pcapng::EnhancedPackets::new(vec![0; 32]).is_none());
+    }
+}
+
+pub(crate) const SECTION_HEADER_BLOCK: u32 = 0x0a0d_0d0a;
+const INTERFACE_DESCRIPTION_BLOCK: u32 = 1;
+pub(crate) const ENHANCED_PACKET_BLOCK: u32 = 6;
+/// Written in the section header's byte order, which tells readers how to decode the rest.
+pub(crate) const BYTE_ORDER_MAGIC: u32 = 0x1a2b_3c4d;
+const BLOCK_OVERHEAD: usize = 12;
+const ENHANCED_PACKET_HEADER_LENGTH: usize = 20;
+const INTERFACE_DESCRIPTION_HEADER_LENGTH: usize = 8;
+const OPTION_END: u16 = 0;
+const OPTION_IF_TSRESOL: u16 = 9;
+/// Interfaces without an if_tsresol option count in microseconds.
+const DEFAULT_TSRESOL: u8 = 6;
+
+/// The section header block type is a palindrome, so it reads the same in either byte order.
+pub fn is_pcapng(bytes: &[u8]) -> bool {
+    bytes.len() >= 4 && bytes[0..4] == SECTION_HEADER_BLOCK.to_le_bytes()
+}
+
+/// A block that runs past the end of the capture or declares an impossible length.
+#[derive(Debug)]
+pub struct TruncatedBlock {
+    pub offset: usize,
+}
+
+pub struct Packet<'a> {
+    pub timestamp_ns: libdt::UtcNs,
+    pub data: &'a [u8],
+}
+
+/// Converts a timestamp in units of `tsresol`: 10^-n seconds, or 2^-n seconds if the high bit is
+/// set.
+fn timestamp_ns(timestamp: u64, tsresol: u8) -> libdt::UtcNs {
+    let exponent = u32::from(tsresol & 0x7f);
+    let ns = if tsresol & 0x80 != 0 {
+        (u128::from(timestamp) * u128::from(libdt::NS_PER_SEC)) >> exponent
+    } else if exponent <= 9 {
+        u128::from(timestamp) * 10u128.pow(9 - exponent)
+    } else {
+        // Units too small for a u128 of nanoseconds make any u64 timestamp round down to 0.
+        10u128.checked_pow(exponent - 9).map_or(0, |units| u128::from(timestamp) / units)
+    };
+    libdt::UtcNs::from_nanos(ns as u64)
+}
+
+pub struct EnhancedPackets {
+    data: Vec<u8>,
+    offset: usize,
+    big_endian: bool,
+    /// if_tsresol of each interface described so far, indexed by interface id.
+    tsresols: Vec<u8>,
+}
+
+impl EnhancedPackets {
+    /// Returns `None` unless `data` starts with a pcapng section header.
+    pub fn new(data: Vec<u8>) -> Option<EnhancedPackets> {
+        if !is_pcapng(&data) || data.len() < BLOCK_OVERHEAD {
+            return None;
+        }
+        let big_endian = data[8..12] == BYTE_ORDER_MAGIC.to_be_bytes();
+        Some(EnhancedPackets {
+            data,
+            offset: 0,
+            big_endian,
+            tsresols: Vec::new(),
+        })
+    }
+
+    fn u16_at(&self, offset: usize) -> u16 {
+        if self.big_endian {
+            crate::bytes::read_u16_be(&self.data, offset)
+        } else {
+            crate::bytes::read_u16_le(&self.data, offset)
+        }
+    }
+
+    fn u32_at(&self, offset: usize) -> u32 {
+        if self.big_endian {
+            crate::bytes::read_u32_be(&self.data, offset)
+        } else {
+            crate::bytes::read_u32_le(&self.data, offset)
+        }
+    }
+
+    /// The if_tsresol option of an interface description block spanning `start..end`.
+    fn tsresol(&self, start: usize, end: usize) -> u8 {
+        let mut offset = start + 8 + INTERFACE_DESCRIPTION_HEADER_LENGTH;
+        while offset + 4 <= end {
+            let code = self.u16_at(offset);
+            let length = self.u16_at(offset + 2) as usize;
+            if code == OPTION_END || offset + 4 + length > end {
+                break;
+            }
+            if code == OPTION_IF_TSRESOL && length >= 1 {
+                return self.data[offset + 4];
+            }
+            offset += 4 + ((length + 3) & !3);
+        }
+        DEFAULT_TSRESOL
+    }
+
+    /// The next enhanced packet block. Reading stops at the first truncated block. Byte order
+    /// changes between sections aren't supported.
+    pub fn next_packet(&mut self) -> Option<Result<Packet<'_>, TruncatedBlock>> {
+        while self.offset < self.data.len() {
+            let start = self.offset;
+            let remaining = self.data.len() - start;
+            let block_length = if remaining >= 8 { self.u32_at(start + 4) as usize } else { 0 };
+            if block_length < BLOCK_OVERHEAD || block_length > remaining {
+                self.offset = self.data.len();
+                return Some(Err(TruncatedBlock { offset: start }));
+            }
+            self.offset += block_length;
+            let block_type = self.u32_at(start);
+            match block_type {
+                SECTION_HEADER_BLOCK => self.tsresols.clear(),
+                INTERFACE_DESCRIPTION_BLOCK => {
+                    let tsresol = self.tsresol(start, start + block_length - 4);
+                    self.tsresols.push(tsresol);
+                },
+                _ => {},
+            }
+            if block_type != ENHANCED_PACKET_BLOCK {
+                continue;
+            }
+            let body = start + 8;
+            if block_length < BLOCK_OVERHEAD + ENHANCED_PACKET_HEADER_LENGTH {
+                return Some(Err(TruncatedBlock { offset: start }));
+            }
+            let tsresol = self.tsresols.get(self.u32_at(body) as usize).cloned().unwrap_or(DEFAULT_TSRESOL);
+            let timestamp = u64::from(self.u32_at(body + 4)) << 32 | u64::from(self.u32_at(body + 8));
+            let captured_length = self.u32_at(body + 12) as usize;
+            let data_start = body + ENHANCED_PACKET_HEADER_LENGTH;
+            if data_start + captured_length > start + block_length - 4 {
+                return Some(Err(TruncatedBlock { offset: start }));
+            }
+            return Some(Ok(Packet {
+                timestamp_ns: timestamp_ns(timestamp, tsresol),
+                data: &self.data[data_start..data_start + captured_length],
+            }));
+        }
+        None
+    }
+}
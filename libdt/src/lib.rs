@@ -1,44 +1,513 @@
 extern crate chrono;
 
+use std::collections::BTreeSet;
+use std::convert::TryFrom;
+use std::fmt;
+use std::ops::{Add, AddAssign, Sub, SubAssign};
+use std::sync::OnceLock;
+use std::time::Duration;
+
 use chrono::prelude::*;
 
 #[cfg(test)]
 mod tests {
+    use chrono::Datelike;
+
+    use crate::UsEastern;
+
+    #[test]
+    fn test_eastern_dst_transitions() {
+        let at = |y, m, d, h, min, s| chrono::NaiveDate::from_ymd_opt(y, m, d).unwrap().and_hms_opt(h, min, s).unwrap();
+        let utc_ns = |ndt, policy| crate::utc_ns_for_naive_datetime_in(&ndt, &UsEastern, policy).map(|t| t.as_nanos() / crate::NS_PER_SEC);
+        // Clocks spring forward from 02:00 EST to 03:00 EDT at 07:00 UTC on 2019-03-10.
+        assert_eq!(utc_ns(at(2019, 3, 10, 1, 59, 59), crate::DstPolicy::Reject), Some(1552201199));
+        assert_eq!(utc_ns(at(2019, 3, 10, 3, 0, 0), crate::DstPolicy::Reject), Some(1552201200));
+        assert_eq!(utc_ns(at(2019, 3, 10, 2, 30, 0), crate::DstPolicy::Reject), None);
+        assert_eq!(utc_ns(at(2019, 3, 10, 2, 30, 0), crate::DstPolicy::Resolve), Some(1552203000));
+        // And fall back from 02:00 EDT to 01:00 EST at 06:00 UTC on 2019-11-03.
+        assert_eq!(utc_ns(at(2019, 11, 3, 1, 30, 0), crate::DstPolicy::Reject), None);
+        assert_eq!(utc_ns(at(2019, 11, 3, 1, 30, 0), crate::DstPolicy::Resolve), Some(1572759000));
+        assert_eq!(utc_ns(at(2019, 11, 3, 2, 30, 0), crate::DstPolicy::Reject), Some(1572766200));
+        // The open on 2019-07-03.
+        assert_eq!(utc_ns(at(2019, 7, 3, 9, 30, 0), crate::DstPolicy::Reject), Some(1562160600));
+
+        // 23:00 Eastern is already the next day in UTC.
+        assert_eq!(crate::naive_date_for_utc_ns_in(crate::UtcNs::from_nanos(1562209200 * crate::NS_PER_SEC), &UsEastern),
+                   chrono::NaiveDate::from_ymd_opt(2019, 7, 3));
+        assert_eq!(crate::naive_date_for_utc_ns_in(crate::UtcNs::from_nanos(1562209200 * crate::NS_PER_SEC), &chrono::Utc),
+                   chrono::NaiveDate::from_ymd_opt(2019, 7, 4));
+    }
+
+    #[test]
+    fn test_bucket_start() {
+        let bucket = |s: u64, interval| crate::bucket_start_in(crate::UtcNs::from_nanos(s * crate::NS_PER_SEC),
+                                                            std::time::Duration::from_secs(interval), &UsEastern)
+            .map(|t| t.as_nanos() / crate::NS_PER_SEC);
+        // 09:31:20 EDT on 2019-07-03.
+        assert_eq!(bucket(1562160680, 60), Some(1562160660));
+        assert_eq!(bucket(1562160680, 30 * 60), Some(1562160600));
+        // Buckets count from midnight, so 7 minute buckets start at 09:27 rather than on the UTC clock.
+        assert_eq!(bucket(1562160680, 7 * 60), Some(1562160420));
+        assert_eq!(bucket(1562160680, 24 * 60 * 60), Some(1562126400));
+        // 09:30 EDT on 2019-03-10 is 8.5 hours after midnight EST, and still starts a half hour.
+        assert_eq!(bucket(1552224600, 30 * 60), Some(1552224600));
+        assert_eq!(bucket(1552224600, 60 * 60), Some(1552222800));
+    }
+
+    fn eastern(y: i32, m: u32, d: u32) -> Option<crate::UtcNs> {
+        let ndt = chrono::NaiveDate::from_ymd_opt(y, m, d).unwrap().and_hms_opt(6, 30, 0).unwrap();
+        crate::utc_ns_for_naive_datetime_in(&ndt, &UsEastern, crate::DstPolicy::Reject)
+    }
+
     #[test]
     fn test_dst() {
-        assert_eq!(
-            crate::utc_ns_for_naive_datetime(
-                &chrono::NaiveDate::from_ymd(2018, 3, 12).and_hms(6, 30, 0)),
-            1520861400000000000);
-        assert_eq!(
-            crate::utc_ns_for_naive_datetime(
-                &chrono::NaiveDate::from_ymd(2018, 11, 2).and_hms(6, 30, 0)),
-            1541165400000000000);
+        // 06:30 EDT is 10:30 UTC.
+        assert_eq!(eastern(2018, 3, 12), Some(crate::UtcNs::from_nanos(1520850600000000000)));
+        assert_eq!(eastern(2018, 11, 2), Some(crate::UtcNs::from_nanos(1541154600000000000)));
     }
 
     #[test]
     fn test_no_dst() {
+        // 06:30 EST is 11:30 UTC.
+        assert_eq!(eastern(2018, 3, 9), Some(crate::UtcNs::from_nanos(1520595000000000000)));
+        assert_eq!(eastern(2018, 11, 5), Some(crate::UtcNs::from_nanos(1541417400000000000)));
+    }
+
+    #[test]
+    fn test_out_of_range() {
         assert_eq!(
             crate::utc_ns_for_naive_datetime(
-                &chrono::NaiveDate::from_ymd(2018, 3, 9).and_hms(6, 30, 0)),
-            1520605800000000000);
+                &chrono::NaiveDate::from_ymd_opt(1969, 7, 20).unwrap().and_hms_opt(20, 17, 0).unwrap()),
+            None);
         assert_eq!(
             crate::utc_ns_for_naive_datetime(
-                &chrono::NaiveDate::from_ymd(2018, 11, 5).and_hms(6, 30, 0)),
-            1541428200000000000);
+                &chrono::NaiveDate::from_ymd_opt(2300, 1, 1).unwrap().and_hms_opt(0, 0, 0).unwrap()),
+            None);
+    }
+
+    #[test]
+    fn test_dst_policy() {
+        let at = |y, m, d, h, min| chrono::NaiveDate::from_ymd_opt(y, m, d).unwrap().and_hms_opt(h, min, 0).unwrap();
+        let eastern = |ndt, policy| crate::utc_ns_for_naive_datetime_in(&ndt, &UsEastern, policy);
+        // 02:30 doesn't exist on 2019-03-10 in US Eastern time.
+        assert_eq!(eastern(at(2019, 3, 10, 2, 30), crate::DstPolicy::Reject), None);
+        assert_eq!(eastern(at(2019, 3, 10, 2, 30), crate::DstPolicy::Resolve),
+                   eastern(at(2019, 3, 10, 3, 30), crate::DstPolicy::Reject));
+        // 01:30 happens twice on 2019-11-03.
+        let ambiguous = at(2019, 11, 3, 1, 30);
+        assert_eq!(eastern(ambiguous, crate::DstPolicy::Reject), None);
+        let before = eastern(at(2019, 11, 3, 0, 30), crate::DstPolicy::Reject).unwrap();
+        assert_eq!(eastern(ambiguous, crate::DstPolicy::Resolve), Some(before + crate::duration_from_ns(crate::NS_PER_HOUR)));
+    }
+
+    #[test]
+    fn test_derived_constants() {
+        assert_eq!(crate::NS_PER_MIN, 60 * crate::NS_PER_SEC);
+        assert_eq!(crate::NS_PER_HOUR, 60 * crate::NS_PER_MIN);
+        assert_eq!(crate::NS_PER_DAY, 24 * crate::NS_PER_HOUR);
+        assert_eq!(crate::NS_PER_DAY, 86_400_000_000_000);
+        assert_eq!(crate::duration_from_ns(crate::NS_PER_MIN + 5), std::time::Duration::new(60, 5));
+    }
+
+    #[test]
+    fn test_utc_ns_arithmetic() {
+        let t = crate::UtcNs::from_nanos(1_562_169_600_000_000_000);
+        let later = t + std::time::Duration::from_millis(1500);
+        assert_eq!(later.as_nanos(), 1_562_169_601_500_000_000);
+        assert!(later > t);
+        assert_eq!(later - t, std::time::Duration::from_millis(1500));
+        assert_eq!(crate::UtcNs::from_nanos(1_562_160_600_000_000_123).to_rfc3339(), "2019-07-03T13:30:00.000000123Z");
+        assert_eq!(crate::UtcNs::from_nanos(u64::MAX).to_datetime().year(), 2554);
+        assert_eq!(later - std::time::Duration::from_millis(1500), t);
+        assert_eq!(t.to_string(), "1562169600000000000");
+    }
+
+    #[test]
+    fn test_trading_days() {
+        let date = |y, m, d| chrono::NaiveDate::from_ymd_opt(y, m, d).unwrap();
+        assert!(crate::is_trading_day(date(2019, 7, 3)));
+        assert!(!crate::is_trading_day(date(2019, 7, 4)));
+        assert!(!crate::is_trading_day(date(2019, 11, 28)));
+        assert!(!crate::is_trading_day(date(2019, 7, 6)));
+        // July 4 2020 was a Saturday, so markets closed on the Friday before.
+        assert!(!crate::is_trading_day(date(2020, 7, 3)));
+        // Early closes are still trading days.
+        let calendar = crate::HolidayCalendar::us_equities();
+        assert!(calendar.is_half_day(date(2019, 11, 29)) && crate::is_trading_day(date(2019, 11, 29)));
+        assert!(!calendar.is_half_day(date(2019, 11, 27)));
+
+        assert_eq!(crate::trading_days_in_range(date(2019, 7, 1), date(2019, 7, 8)),
+                   vec![date(2019, 7, 1), date(2019, 7, 2), date(2019, 7, 3), date(2019, 7, 5), date(2019, 7, 8)]);
+        assert!(crate::trading_days_in_range(date(2019, 7, 8), date(2019, 7, 1)).is_empty());
+        assert_eq!(crate::trading_days_in_range(date(2019, 1, 1), date(2019, 12, 31)).len(), 252);
+
+        // Closures the table doesn't know about, and overriding one it does.
+        let calendar = crate::HolidayCalendar::us_equities()
+            .with_holiday(date(2019, 7, 3))
+            .without_holiday(date(2019, 7, 4));
+        assert!(!calendar.is_trading_day(date(2019, 7, 3)));
+        assert!(calendar.is_trading_day(date(2019, 7, 4)));
+        assert!(!calendar.is_half_day(date(2019, 7, 3)));
+        assert!(crate::HolidayCalendar::new().is_trading_day(date(2019, 11, 28)));
+        assert!(!crate::HolidayCalendar::new().is_trading_day(date(2019, 11, 30)));
+    }
+
+    #[test]
+    fn test_naive_date_for_utc_ns() {
+        // Noon in New York, so the same date in UTC.
+        let noon = crate::UtcNs::from_nanos(1562169600000000000);
+        assert_eq!(crate::naive_date_for_utc_ns_in(noon, &UsEastern), chrono::NaiveDate::from_ymd_opt(2019, 7, 3));
+        assert_eq!(crate::naive_date_for_utc_ns_in(noon, &chrono::Utc), chrono::NaiveDate::from_ymd_opt(2019, 7, 3));
     }
 }
 
 pub const NS_PER_SEC: u64 = 1_000_000_000;
 pub const SEC_PER_MIN: u64 = 60;
+pub const MIN_PER_HOUR: u64 = 60;
+pub const HOUR_PER_DAY: u64 = 24;
+pub const NS_PER_MIN: u64 = NS_PER_SEC * SEC_PER_MIN;
+pub const NS_PER_HOUR: u64 = NS_PER_MIN * MIN_PER_HOUR;
+// Calendar days around DST changes are 23 or 25 hours long.
+pub const NS_PER_DAY: u64 = NS_PER_HOUR * HOUR_PER_DAY;
+
+pub fn duration_from_ns(ns: u64) -> std::time::Duration {
+    std::time::Duration::from_nanos(ns)
+}
+
+/// Nanoseconds since the Unix epoch, in UTC. Raw `u64` nanoseconds, like the timestamps stored in
+/// h5 files, are converted with `from_nanos` and `as_nanos`; there's deliberately no `From<u64>` so
+/// milliseconds or sequence numbers can't slip in unnoticed.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct UtcNs(u64);
+
+impl UtcNs {
+    pub const fn from_nanos(ns: u64) -> UtcNs {
+        UtcNs(ns)
+    }
+
+    pub const fn as_nanos(self) -> u64 {
+        self.0
+    }
+
+    /// Whole seconds since the Unix epoch, as some IEX fields are.
+    pub const fn from_secs(secs: u64) -> UtcNs {
+        UtcNs(secs * NS_PER_SEC)
+    }
+
+    /// The inverse of `utc_ns_for_naive_datetime`, in UTC. Every `UtcNs`, up to the year 2554, is
+    /// within chrono's range.
+    pub fn to_datetime(self) -> DateTime<Utc> {
+        let seconds = (self.0 / NS_PER_SEC) as i64;
+        let nanos = (self.0 % NS_PER_SEC) as u32;
+        Utc.timestamp_opt(seconds, nanos).single().expect("UtcNs is within chrono's range")
+    }
+
+    /// RFC 3339 in UTC with nanoseconds, e.g. 2019-07-03T13:30:00.000000123Z.
+    pub fn to_rfc3339(self) -> String {
+        self.to_datetime().to_rfc3339_opts(SecondsFormat::Nanos, true)
+    }
+}
+
+impl fmt::Display for UtcNs {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+fn duration_as_ns(duration: Duration) -> u64 {
+    u64::try_from(duration.as_nanos()).expect("duration overflows u64 nanoseconds")
+}
+
+impl Add<Duration> for UtcNs {
+    type Output = UtcNs;
 
-pub type UtcNs = u64;
+    fn add(self, rhs: Duration) -> UtcNs {
+        UtcNs(self.0.checked_add(duration_as_ns(rhs)).expect("overflow when adding duration to UtcNs"))
+    }
+}
+
+impl AddAssign<Duration> for UtcNs {
+    fn add_assign(&mut self, rhs: Duration) {
+        *self = *self + rhs;
+    }
+}
+
+impl Sub<Duration> for UtcNs {
+    type Output = UtcNs;
+
+    fn sub(self, rhs: Duration) -> UtcNs {
+        UtcNs(self.0.checked_sub(duration_as_ns(rhs)).expect("overflow when subtracting duration from UtcNs"))
+    }
+}
+
+impl SubAssign<Duration> for UtcNs {
+    fn sub_assign(&mut self, rhs: Duration) {
+        *self = *self - rhs;
+    }
+}
 
+/// Panics if `rhs` is later than `self`.
+impl Sub<UtcNs> for UtcNs {
+    type Output = Duration;
+
+    fn sub(self, rhs: UtcNs) -> Duration {
+        duration_from_ns(self.0.checked_sub(rhs.0).expect("subtracted a later UtcNs from an earlier one"))
+    }
+}
+
+/// `None` for ambiguous or nonexistent local times, and for datetimes outside what `UtcNs` can
+/// hold: anything before the Unix epoch, and anything whose i64 nanoseconds overflow, i.e. outside
+/// roughly 1677-09-21 to 2262-04-11.
 // XXX(sherry): careful that local time zone is what you expect!
 // XXX(sherry): what happens when the system time zone changes during the program's execution?
 pub fn utc_ns_for_naive_datetime(ndt: &chrono::prelude::NaiveDateTime) -> Option<UtcNs> {
-    match Local.from_local_datetime(ndt) {
-        chrono::LocalResult::Single(t) => Some(t.timestamp_nanos() as UtcNs),
-        _ => None,
+    utc_ns_for_naive_datetime_with(ndt, DstPolicy::Reject)
+}
+
+/// What to do with local times that DST changes make nonexistent (skipped when clocks spring
+/// forward) or ambiguous (repeated when they fall back).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DstPolicy {
+    /// Neither has a UTC time.
+    Reject,
+    /// Nonexistent times are moved forward by the length of the gap, like a clock that wasn't
+    /// changed yet, e.g. 02:30 becomes 03:30 daylight time. Ambiguous times take the earlier
+    /// instant.
+    Resolve,
+}
+
+fn utc_ns_for_datetime<Tz: TimeZone>(t: &DateTime<Tz>) -> Option<UtcNs> {
+    t.timestamp_nanos_opt().and_then(|ns| u64::try_from(ns).ok()).map(UtcNs)
+}
+
+// XXX(sherry): careful that local time zone is what you expect!
+pub fn utc_ns_for_naive_datetime_with(ndt: &chrono::prelude::NaiveDateTime, policy: DstPolicy) -> Option<UtcNs> {
+    utc_ns_for_naive_datetime_in(ndt, &Local, policy)
+}
+
+/// `ndt` in `tz` rather than the local time zone. For IEX, pass `UsEastern`, whose results don't
+/// depend on the host's time zone.
+pub fn utc_ns_for_naive_datetime_in<Tz: TimeZone>(ndt: &chrono::prelude::NaiveDateTime, tz: &Tz,
+                                                  policy: DstPolicy) -> Option<UtcNs> {
+    match (tz.from_local_datetime(ndt), policy) {
+        (chrono::LocalResult::Single(t), _) => utc_ns_for_datetime(&t),
+        (_, DstPolicy::Reject) => None,
+        // Local doesn't always list the two in order.
+        (chrono::LocalResult::Ambiguous(a, b), DstPolicy::Resolve) => utc_ns_for_datetime(&a.min(b)),
+        // DST gaps are an hour long in the US.
+        (chrono::LocalResult::None, DstPolicy::Resolve) => {
+            let shifted = *ndt + chrono::Duration::hours(1);
+            tz.from_local_datetime(&shifted).earliest().and_then(|t| utc_ns_for_datetime(&t))
+        },
+    }
+}
+
+// XXX(sherry): same caveat as above; this is the Eastern date only if the local time zone is.
+pub fn naive_date_for_utc_ns(utc_ns: UtcNs) -> Option<chrono::NaiveDate> {
+    naive_date_for_utc_ns_in(utc_ns, &Local)
+}
+
+// XXX(sherry): same caveat as above; these are Eastern buckets only if the local time zone is.
+pub fn bucket_start(utc_ns: UtcNs, interval: Duration) -> Option<UtcNs> {
+    bucket_start_in(utc_ns, interval, &Local)
+}
+
+/// The start of the `interval` long bucket that `utc_ns` falls in, counting buckets from midnight
+/// in `tz`, so e.g. 30 minute buckets start on the hour and half hour of `tz`'s clock. `None` if
+/// midnight doesn't exist in `tz` that day. Panics if `interval` is zero.
+pub fn bucket_start_in<Tz: TimeZone>(utc_ns: UtcNs, interval: Duration, tz: &Tz) -> Option<UtcNs> {
+    let date = naive_date_for_utc_ns_in(utc_ns, tz)?;
+    let midnight = tz.from_local_datetime(&date.and_hms_opt(0, 0, 0)?).earliest()
+        .and_then(|t| utc_ns_for_datetime(&t))?;
+    Some(UtcNs(utc_ns.0 - (utc_ns.0 - midnight.0) % duration_as_ns(interval)))
+}
+
+/// The date in `tz` at `utc_ns`; see `utc_ns_for_naive_datetime_in`.
+pub fn naive_date_for_utc_ns_in<Tz: TimeZone>(utc_ns: UtcNs, tz: &Tz) -> Option<chrono::NaiveDate> {
+    let seconds = (utc_ns.0 / NS_PER_SEC) as i64;
+    let nanos = (utc_ns.0 % NS_PER_SEC) as u32;
+    tz.timestamp_opt(seconds, nanos).single().map(|t| t.date_naive())
+}
+
+/// US Eastern time, where IEX trades, under the DST rules in force since 2007: daylight time from
+/// 02:00 on the second Sunday of March to 02:00 on the first Sunday of November. It's built in, so
+/// times converted in it don't depend on the host's time zone or zoneinfo. Dates before 2007 get
+/// the same rules, which they didn't have.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct UsEastern;
+
+fn est() -> FixedOffset {
+    FixedOffset::west_opt(5 * 3600).expect("EST is a valid offset")
+}
+
+fn edt() -> FixedOffset {
+    FixedOffset::west_opt(4 * 3600).expect("EDT is a valid offset")
+}
+
+impl TimeZone for UsEastern {
+    type Offset = FixedOffset;
+
+    fn from_offset(_offset: &FixedOffset) -> UsEastern {
+        UsEastern
+    }
+
+    fn offset_from_local_date(&self, local: &NaiveDate) -> chrono::LocalResult<FixedOffset> {
+        self.offset_from_local_datetime(&local.and_time(NaiveTime::MIN))
+    }
+
+    fn offset_from_local_datetime(&self, local: &NaiveDateTime) -> chrono::LocalResult<FixedOffset> {
+        let offsets: Vec<FixedOffset> = [edt(), est()].iter()
+            .filter(|offset| self.offset_from_utc_datetime(&(*local - **offset)) == **offset)
+            .cloned()
+            .collect();
+        match offsets[..] {
+            [offset] => chrono::LocalResult::Single(offset),
+            [a, b] => chrono::LocalResult::Ambiguous(a, b),
+            _ => chrono::LocalResult::None,
+        }
     }
+
+    fn offset_from_utc_date(&self, utc: &NaiveDate) -> FixedOffset {
+        self.offset_from_utc_datetime(&utc.and_time(NaiveTime::MIN))
+    }
+
+    /// Daylight time starts at 07:00 UTC on the second Sunday of March and ends at 06:00 UTC on
+    /// the first Sunday of November.
+    fn offset_from_utc_datetime(&self, utc: &NaiveDateTime) -> FixedOffset {
+        let sunday = |month, n, hour| NaiveDate::from_weekday_of_month_opt(utc.year(), month, Weekday::Sun, n)
+            .and_then(|date| date.and_hms_opt(hour, 0, 0));
+        match (sunday(3, 2, 7), sunday(11, 1, 6)) {
+            (Some(start), Some(end)) if start <= *utc && *utc < end => edt(),
+            _ => est(),
+        }
+    }
+}
+
+/// Full-day closures of the US equity markets, as published by NYSE. Needs a new year added once
+/// NYSE publishes it; past the last year listed only weekends are closed.
+const US_EQUITY_HOLIDAYS: &[(i32, u32, u32)] = &[
+    (2016, 1, 1), (2016, 1, 18), (2016, 2, 15), (2016, 3, 25), (2016, 5, 30), (2016, 7, 4), (2016, 9, 5),
+    (2016, 11, 24), (2016, 12, 26),
+    (2017, 1, 2), (2017, 1, 16), (2017, 2, 20), (2017, 4, 14), (2017, 5, 29), (2017, 7, 4), (2017, 9, 4),
+    (2017, 11, 23), (2017, 12, 25),
+    // Including the national day of mourning for George H. W. Bush.
+    (2018, 1, 1), (2018, 1, 15), (2018, 2, 19), (2018, 3, 30), (2018, 5, 28), (2018, 7, 4), (2018, 9, 3),
+    (2018, 11, 22), (2018, 12, 5), (2018, 12, 25),
+    (2019, 1, 1), (2019, 1, 21), (2019, 2, 18), (2019, 4, 19), (2019, 5, 27), (2019, 7, 4), (2019, 9, 2),
+    (2019, 11, 28), (2019, 12, 25),
+    (2020, 1, 1), (2020, 1, 20), (2020, 2, 17), (2020, 4, 10), (2020, 5, 25), (2020, 7, 3), (2020, 9, 7),
+    (2020, 11, 26), (2020, 12, 25),
+    (2021, 1, 1), (2021, 1, 18), (2021, 2, 15), (2021, 4, 2), (2021, 5, 31), (2021, 7, 5), (2021, 9, 6),
+    (2021, 11, 25), (2021, 12, 24),
+    // New Year's Day fell on a Saturday, which isn't observed on the Friday before.
+    (2022, 1, 17), (2022, 2, 21), (2022, 4, 15), (2022, 5, 30), (2022, 6, 20), (2022, 7, 4), (2022, 9, 5),
+    (2022, 11, 24), (2022, 12, 26),
+    (2023, 1, 2), (2023, 1, 16), (2023, 2, 20), (2023, 4, 7), (2023, 5, 29), (2023, 6, 19), (2023, 7, 4),
+    (2023, 9, 4), (2023, 11, 23), (2023, 12, 25),
+    (2024, 1, 1), (2024, 1, 15), (2024, 2, 19), (2024, 3, 29), (2024, 5, 27), (2024, 6, 19), (2024, 7, 4),
+    (2024, 9, 2), (2024, 11, 28), (2024, 12, 25),
+    // Including the national day of mourning for Jimmy Carter.
+    (2025, 1, 1), (2025, 1, 9), (2025, 1, 20), (2025, 2, 17), (2025, 4, 18), (2025, 5, 26), (2025, 6, 19),
+    (2025, 7, 4), (2025, 9, 1), (2025, 11, 27), (2025, 12, 25),
+    (2026, 1, 1), (2026, 1, 19), (2026, 2, 16), (2026, 4, 3), (2026, 5, 25), (2026, 6, 19), (2026, 7, 3),
+    (2026, 9, 7), (2026, 11, 26), (2026, 12, 25),
+    (2027, 1, 1), (2027, 1, 18), (2027, 2, 15), (2027, 3, 26), (2027, 5, 31), (2027, 6, 18), (2027, 7, 5),
+    (2027, 9, 6), (2027, 11, 25), (2027, 12, 24),
+];
+
+/// Days the US equity markets close early, at 13:00 Eastern, for the years of `US_EQUITY_HOLIDAYS`.
+const US_EQUITY_HALF_DAYS: &[(i32, u32, u32)] = &[
+    (2016, 11, 25),
+    (2017, 7, 3), (2017, 11, 24),
+    (2018, 7, 3), (2018, 11, 23), (2018, 12, 24),
+    (2019, 7, 3), (2019, 11, 29), (2019, 12, 24),
+    (2020, 11, 27), (2020, 12, 24),
+    (2021, 11, 26),
+    (2022, 11, 25),
+    (2023, 7, 3), (2023, 11, 24),
+    (2024, 7, 3), (2024, 11, 29), (2024, 12, 24),
+    (2025, 7, 3), (2025, 11, 28), (2025, 12, 24),
+    (2026, 11, 27), (2026, 12, 24),
+    (2027, 11, 26),
+];
+
+fn dates(table: &[(i32, u32, u32)]) -> BTreeSet<NaiveDate> {
+    table.iter()
+        .map(|&(y, m, d)| NaiveDate::from_ymd_opt(y, m, d).expect("holiday tables hold valid dates"))
+        .collect()
+}
+
+/// Which weekdays markets are closed or close early. Weekends are always closed.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct HolidayCalendar {
+    holidays: BTreeSet<NaiveDate>,
+    half_days: BTreeSet<NaiveDate>,
+}
+
+impl HolidayCalendar {
+    /// A calendar without holidays, open every weekday.
+    pub fn new() -> HolidayCalendar {
+        HolidayCalendar::default()
+    }
+
+    /// The US equity markets' holidays and early closes; see `US_EQUITY_HOLIDAYS` for the years
+    /// covered.
+    pub fn us_equities() -> HolidayCalendar {
+        HolidayCalendar {
+            holidays: dates(US_EQUITY_HOLIDAYS),
+            half_days: dates(US_EQUITY_HALF_DAYS),
+        }
+    }
+
+    /// Closes markets on `date`, e.g. for an unscheduled closure the table doesn't have yet.
+    pub fn with_holiday(mut self, date: NaiveDate) -> HolidayCalendar {
+        self.half_days.remove(&date);
+        self.holidays.insert(date);
+        self
+    }
+
+    pub fn without_holiday(mut self, date: NaiveDate) -> HolidayCalendar {
+        self.holidays.remove(&date);
+        self
+    }
+
+    pub fn with_half_day(mut self, date: NaiveDate) -> HolidayCalendar {
+        self.holidays.remove(&date);
+        self.half_days.insert(date);
+        self
+    }
+
+    pub fn is_trading_day(&self, date: NaiveDate) -> bool {
+        let weekend = matches!(date.weekday(), Weekday::Sat | Weekday::Sun);
+        !weekend && !self.holidays.contains(&date)
+    }
+
+    /// Whether `date` is a trading day on which markets close early.
+    pub fn is_half_day(&self, date: NaiveDate) -> bool {
+        self.is_trading_day(date) && self.half_days.contains(&date)
+    }
+
+    /// Trading days from `start` to `end`, both included, in order.
+    pub fn trading_days_in_range(&self, start: NaiveDate, end: NaiveDate) -> Vec<NaiveDate> {
+        start.iter_days()
+            .take_while(|date| *date <= end)
+            .filter(|date| self.is_trading_day(*date))
+            .collect()
+    }
+}
+
+/// `HolidayCalendar::us_equities`, built on first use.
+fn us_equities() -> &'static HolidayCalendar {
+    static CALENDAR: OnceLock<HolidayCalendar> = OnceLock::new();
+    CALENDAR.get_or_init(HolidayCalendar::us_equities)
+}
+
+/// Whether the US equity markets are open on `date`, if only for half a day.
+pub fn is_trading_day(date: NaiveDate) -> bool {
+    us_equities().is_trading_day(date)
+}
+
+/// The US equity markets' trading days from `start` to `end`, both included.
+pub fn trading_days_in_range(start: NaiveDate, end: NaiveDate) -> Vec<NaiveDate> {
+    us_equities().trading_days_in_range(start, end)
 }
@@ -1,8 +1,13 @@
 extern crate chrono;
 
+use std::collections::HashSet;
 use std::ffi;
+use std::fs;
+use std::io;
 use std::path;
 
+use chrono::Datelike;
+
 #[cfg(test)]
 mod tests {
     #[test]
@@ -33,6 +38,46 @@ mod tests {
         assert_eq!(crate::yyyymmdd_prefix_from_stem("20180228"),
                    Ok(chrono::NaiveDate::from_ymd(2018, 2, 28)));
     }
+
+    #[test]
+    fn test_symbol_and_date_from_h5() {
+        let trade_date = chrono::NaiveDate::from_ymd(2019, 7, 3);
+        assert_eq!(crate::h5_for_symbol(&trade_date, "AAPL    "), "20190703_AAPL.h5");
+        assert_eq!(crate::symbol_and_date_from_h5("out/20190703_AAPL.h5"),
+                   Ok(("AAPL".to_string(), trade_date)));
+        assert_eq!(crate::symbol_and_date_from_h5("20190703_BRK.A.h5"),
+                   Ok(("BRK.A".to_string(), trade_date)));
+        assert_eq!(crate::symbol_and_date_from_h5("20190703.h5"),
+                   Err(crate::TradeDateFromFileErr::NoSymbol));
+        assert_eq!(crate::symbol_and_date_from_h5("20190732_AAPL.h5"),
+                   Err(crate::TradeDateFromFileErr::InvalidDate));
+    }
+
+    fn date(year: i32, month: u32, day: u32) -> chrono::NaiveDate {
+        chrono::NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    #[test]
+    fn test_trade_dates_in_range() {
+        // Thursday 2019-07-04 through Tuesday 2019-07-09.
+        assert_eq!(crate::trade_dates_in_range(date(2019, 7, 4), date(2019, 7, 9)),
+                   vec![date(2019, 7, 4), date(2019, 7, 5), date(2019, 7, 8), date(2019, 7, 9)]);
+        assert_eq!(crate::trade_dates_in_range(date(2019, 7, 6), date(2019, 7, 7)), vec![]);
+        assert_eq!(crate::trade_dates_in_range(date(2019, 7, 9), date(2019, 7, 4)), vec![]);
+    }
+
+    #[test]
+    fn test_missing_trade_dates() {
+        let mut dir = std::env::temp_dir();
+        dir.push(format!("libfs_missing_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        for name in &["20190705.h5", "20190708_AAPL.h5", "20190709.txt"] {
+            std::fs::write(dir.join(name), b"").unwrap();
+        }
+        assert_eq!(crate::missing_trade_dates(&dir, date(2019, 7, 4), date(2019, 7, 9)).unwrap(),
+                   vec![date(2019, 7, 4), date(2019, 7, 9)]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
 }
 
 pub type H5RawPath = str;
@@ -45,6 +90,7 @@ pub enum TradeDateFromFileErr {
     InvalidUnicode,
     // TODO(sherry): make reason more precise, year | month | date
     InvalidDate,
+    NoSymbol,
 }
 
 pub fn yyyymmdd_prefix_from_stem(stem: &str)
@@ -69,3 +115,57 @@ pub fn trade_date_from_h5(h5_path: &H5RawPath)
         .and_then(|stem| stem.to_str().ok_or_else(|| TradeDateFromFileErr::InvalidUnicode))
         .and_then(yyyymmdd_prefix_from_stem)
 }
+
+/// Name of the file holding a single symbol's ticks, e.g. `20190703_AAPL.h5`. Symbols are trimmed
+/// of the space padding they have in DEEP messages.
+pub fn h5_for_symbol(trade_date: &chrono::NaiveDate, symbol: &str) -> String {
+    format!("{}_{}.h5", trade_date.format("%Y%m%d"), symbol.trim_end())
+}
+
+pub fn symbol_and_date_from_h5(h5_path: &H5RawPath)
+    -> Result<(String, chrono::NaiveDate), TradeDateFromFileErr> {
+    let path = path::Path::new(h5_path);
+    if path.extension() != Some(ffi::OsStr::new("h5")) {
+        return Err(TradeDateFromFileErr::WrongFileExtension);
+    }
+
+    let stem = path.file_stem()
+        .ok_or(TradeDateFromFileErr::NoStem)?
+        .to_str()
+        .ok_or(TradeDateFromFileErr::InvalidUnicode)?;
+    match stem.find('_') {
+        Some(i) if i + 1 < stem.len() => {
+            yyyymmdd_prefix_from_stem(&stem[..i]).map(|date| (stem[i + 1..].to_string(), date))
+        },
+        _ => Err(TradeDateFromFileErr::NoSymbol),
+    }
+}
+
+/// Weekdays from `start` through `end`, inclusive. Market holidays aren't known here, so they're
+/// included too.
+pub fn trade_dates_in_range(start: chrono::NaiveDate, end: chrono::NaiveDate) -> Vec<chrono::NaiveDate> {
+    start.iter_days()
+        .take_while(|date| *date <= end)
+        .filter(|date| date.weekday().number_from_monday() <= 5)
+        .collect()
+}
+
+/// Trade dates from `start` through `end` without an h5 file in `dir`, either a whole day's file or
+/// one symbol's. Other files are ignored.
+pub fn missing_trade_dates<P: AsRef<path::Path>>(dir: P, start: chrono::NaiveDate, end: chrono::NaiveDate)
+    -> io::Result<Vec<chrono::NaiveDate>> {
+    let mut present = HashSet::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let path = match path.to_str() {
+            Some(path) => path,
+            None => continue,
+        };
+        let date = trade_date_from_h5(path)
+            .or_else(|_| symbol_and_date_from_h5(path).map(|(_, date)| date));
+        if let Ok(date) = date {
+            present.insert(date);
+        }
+    }
+    Ok(trade_dates_in_range(start, end).into_iter().filter(|date| !present.contains(date)).collect())
+}
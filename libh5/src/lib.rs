@@ -1,6 +1,811 @@
+//! Everything that reads or writes h5 files needs the `hdf5` feature, which is on by default and
+//! links against the native HDF5 library. Without it, only `Tick` and the functions operating on
+//! slices of ticks are available. The `serde` feature derives `Serialize` and `Deserialize` for
+//! `Tick`.
+
+#[cfg(feature = "hdf5")]
 extern crate hdf5;
+#[cfg(feature = "hdf5")]
+extern crate hdf5_sys;
+#[cfg(feature = "hdf5")]
+#[macro_use]
+extern crate ndarray;
+
+#[cfg(feature = "hdf5")]
+use std::cmp::Reverse;
+#[cfg(feature = "hdf5")]
+use std::collections::{BTreeMap, BinaryHeap, VecDeque};
+#[cfg(feature = "hdf5")]
+use std::ffi;
+#[cfg(feature = "hdf5")]
+use std::mem;
+
+#[cfg(feature = "hdf5")]
+use hdf5_sys::h5;
+#[cfg(feature = "hdf5")]
+use hdf5_sys::h5a;
+#[cfg(feature = "hdf5")]
+use hdf5_sys::h5d;
+#[cfg(feature = "hdf5")]
+use hdf5_sys::h5g;
+#[cfg(feature = "hdf5")]
+use hdf5_sys::h5l;
+#[cfg(feature = "hdf5")]
+use hdf5_sys::h5p::H5P_DEFAULT;
+#[cfg(feature = "hdf5")]
+use hdf5_sys::h5s;
+#[cfg(feature = "hdf5")]
+use hdf5_sys::h5t;
+#[cfg(feature = "hdf5")]
+use hdf5_sys::h5i::hid_t;
+#[cfg(feature = "hdf5")]
+use hdf5_sys::h5::hsize_t;
+
+#[cfg(test)]
+mod tests {
+    #[cfg(feature = "hdf5")]
+    fn temp_h5(name: &str) -> String {
+        let mut path = std::env::temp_dir();
+        path.push(format!("libh5_{}_{}.h5", name, std::process::id()));
+        path.to_str().unwrap().to_string()
+    }
+
+    fn tick(timestamp: u64) -> crate::Tick {
+        crate::Tick {
+            message_type: b'T',
+            message_subtype: 0,
+            timestamp,
+            size: 100,
+            price: 1234500,
+            price_multiplier: 10000,
+            packet_number: 0,
+            message_sequence_number: timestamp,
+            capture_timestamp: timestamp,
+        }
+    }
+
+    #[test]
+    fn test_sort_ticks() {
+        let mut ticks = vec![tick(3), tick(1), tick(2)];
+        ticks[2].message_sequence_number = 9;
+        ticks.push(crate::Tick { message_sequence_number: 5, ..tick(2) });
+        crate::sort_ticks(&mut ticks);
+        let keys: Vec<(u64, u64)> = ticks.iter()
+            .map(|t| (t.timestamp, t.message_sequence_number))
+            .collect();
+        assert_eq!(keys, vec![(1, 1), (2, 5), (2, 9), (3, 3)]);
+    }
+
+    #[test]
+    fn test_format_price() {
+        assert_eq!(crate::format_price(1_234_500, 10000), "123.4500");
+        assert_eq!(crate::format_price(1_990_000, 10000), "199.0000");
+        assert_eq!(crate::format_price(123, 10000), "0.0123");
+        assert_eq!(crate::format_price(0, 10000), "0.0000");
+        assert_eq!(crate::format_price(42, 1), "42");
+        assert_eq!(crate::format_price(42, 10), "4.2");
+        assert_eq!(crate::format_price(5, 4), "1.25");
+        assert_eq!(crate::format_price(4, 4), "1.00");
+        assert_eq!(crate::format_price(1, 8), "0.125");
+        assert_eq!(crate::format_price(1, 3), "0.3");
+        assert_eq!(crate::format_price(2, 3), "0.7");
+        assert_eq!(crate::format_price(1, 12), "0.08");
+        assert_eq!(crate::format_price(u64::MAX, 10000), "1844674407370955.1615");
+        assert_eq!(crate::format_price(1, 0), "inf");
+        assert_eq!(crate::Decimal::from_price(5, 4), Some(crate::Decimal { units: 125, decimals: 2 }));
+        assert_eq!(crate::Decimal::from_price(1, 1 << 60), None);
+        assert_eq!(tick(1).price_decimal().map(|d| d.to_string()), Some("123.4500".to_string()));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_round_trip() {
+        let json = serde_json::to_string(&tick(7)).unwrap();
+        assert!(json.contains("\"price_multiplier\":10000"));
+        assert!(serde_json::from_str::<crate::Tick>(&json).unwrap() == tick(7));
+    }
+
+    #[test]
+    fn test_time_index_matches_linear_scan() {
+        let minute = 60_000_000_000;
+        let timestamps = [5, 10, minute - 1, minute, 3 * minute + 7, 3 * minute + 8, 7 * minute];
+        let ticks: Vec<crate::Tick> = timestamps.iter().map(|t| tick(*t)).collect();
+        let index = crate::time_index(&ticks, minute);
+        let buckets: Vec<u64> = index.iter().map(|e| e.bucket_start).collect();
+        assert_eq!(buckets, vec![0, minute, 3 * minute, 7 * minute]);
+        for entry in &index {
+            let first = ticks.iter().position(|t| t.timestamp >= entry.bucket_start).unwrap();
+            assert_eq!(entry.offset, first as u64);
+        }
+
+        for &(start, end) in &[(0, 1), (7, minute), (minute - 1, 3 * minute + 8), (2 * minute, 9 * minute), (8 * minute, 9 * minute)] {
+            let (lo, hi) = crate::time_index_rows(&index, ticks.len(), start, end);
+            let expected: Vec<u64> = timestamps.iter().cloned().filter(|t| *t >= start && *t < end).collect();
+            let found: Vec<u64> = ticks[lo..hi].iter().map(|t| t.timestamp).filter(|t| *t >= start && *t < end).collect();
+            assert_eq!(found, expected);
+        }
+    }
+
+    #[cfg(feature = "hdf5")]
+    #[test]
+    fn test_verify_datasets() {
+        let path = temp_h5("verify");
+        let price = 0x0123_4567_89ab_cdef;
+        let ticks: Vec<crate::Tick> = (0..10).map(|i| crate::Tick { price, ..tick(i) }).collect();
+        let options = crate::WriteOptions { checksum: true, ..crate::WriteOptions::default() };
+        {
+            let file = hdf5::File::open(&path, "w").unwrap();
+            let dataset = crate::write_ticks(&file, "AAPL", &ticks, &options).unwrap();
+            assert!(dataset.filters().get_fletcher32());
+            crate::append_ticks_with_options(&file, "QQQ", &ticks, &options).unwrap();
+            crate::append_ticks(&file, "SPY", &[tick(1)]).unwrap();
+            crate::write_official_prices(&file, "SPY", &[tick(2)]).unwrap();
+            crate::write_symbol_index(&file, &["AAPL", "QQQ", "SPY"]).unwrap();
+        }
+        let verify = || crate::verify_datasets(&hdf5::File::open(&path, "r").unwrap()).unwrap();
+        assert_eq!(verify(), crate::VerifyReport { datasets: 5, checksummed: 2, corrupt: Vec::new() });
+
+        // Flipping a bit of a price is caught by the checksum of its chunk.
+        let mut bytes = std::fs::read(&path).unwrap();
+        let at = bytes.windows(8).position(|window| window == price.to_le_bytes()).unwrap();
+        bytes[at] ^= 1;
+        std::fs::write(&path, &bytes).unwrap();
+        let report = verify();
+        assert_eq!((report.datasets, report.corrupt.len()), (5, 1));
+        assert!(report.corrupt[0].0 == "AAPL" || report.corrupt[0].0 == "QQQ");
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[cfg(feature = "hdf5")]
+    #[test]
+    fn test_merged_time_ordered_iter() {
+        let path = temp_h5("time_ordered");
+        let aapl: Vec<crate::Tick> = [1, 4, 5, 9, 9].iter().map(|&t| tick(t)).collect();
+        let spy: Vec<crate::Tick> = [2, 3, 6, 7, 8, 9].iter().map(|&t| tick(t)).collect();
+        {
+            let file = hdf5::File::open(&path, "w").unwrap();
+            crate::append_ticks(&file, "AAPL", &aapl).unwrap();
+            crate::append_ticks(&file, "SPY", &spy).unwrap();
+            crate::append_ticks(&file, "QQQ", &[]).unwrap();
+        }
+
+        let file = hdf5::File::open(&path, "r").unwrap();
+        assert_eq!(crate::read_attr::<bool>(&file.dataset("AAPL").unwrap(), crate::SORTED_ATTR).unwrap(), Some(false));
+        // Buffers smaller than the datasets are refilled as they run out.
+        for merged in [crate::merged_time_ordered_iter(&file).unwrap(), crate::time_ordered_ticks(&file, 2).unwrap()] {
+            let merged: Vec<(String, u64)> = merged.map(|tick| tick.unwrap())
+                .map(|(symbol, tick)| (symbol, tick.timestamp))
+                .collect();
+            let timestamps: Vec<u64> = merged.iter().map(|(_, timestamp)| *timestamp).collect();
+            assert_eq!(timestamps, vec![1, 2, 3, 4, 5, 6, 7, 8, 9, 9, 9]);
+            let symbols: Vec<&str> = merged.iter().map(|(symbol, _)| symbol.as_str()).collect();
+            assert_eq!(symbols, vec!["AAPL", "SPY", "SPY", "AAPL", "AAPL", "SPY", "SPY", "SPY", "AAPL", "AAPL", "SPY"]);
+        }
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[cfg(feature = "hdf5")]
+    #[test]
+    fn test_load_ticks_in_range() {
+        let path = temp_h5("in_range");
+        let minute = 60_000_000_000;
+        let ticks: Vec<crate::Tick> = (0..10).map(|i| tick(i * minute / 2)).collect();
+        {
+            let file = hdf5::File::open(&path, "w").unwrap();
+            crate::append_ticks(&file, "AAPL", &ticks).unwrap();
+            crate::append_ticks(&file, "SPY", &ticks).unwrap();
+            crate::write_time_index(&file, "AAPL", &ticks).unwrap();
+        }
+        for symbol in &["AAPL", "SPY"] {
+            let found = crate::load_ticks_in_range(&path, symbol, minute, 3 * minute).unwrap();
+            let timestamps: Vec<u64> = found.iter().map(|t| t.timestamp).collect();
+            assert_eq!(timestamps, [2u64, 3, 4, 5].iter().map(|i| i * minute / 2).collect::<Vec<u64>>());
+        }
+        let file = hdf5::File::open(&path, "r").unwrap();
+        assert_eq!(crate::symbols(&file).unwrap(), vec!["AAPL", "SPY"]);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[cfg(feature = "hdf5")]
+    #[test]
+    fn test_tick_reader_load_range() {
+        let path = temp_h5("tick_reader_range");
+        let minute = 60_000_000_000;
+        let ticks: Vec<crate::Tick> = (0..10).map(|i| tick(i * minute / 2)).collect();
+        {
+            let file = hdf5::File::open(&path, "w").unwrap();
+            crate::append_ticks(&file, "AAPL", &ticks).unwrap();
+            crate::write_time_index(&file, "AAPL", &ticks).unwrap();
+        }
+        let timestamps = |found: Vec<crate::Tick>| -> Vec<u64> { found.iter().map(|t| t.timestamp / (minute / 2)).collect() };
+        for reader in &[crate::TickReader::open(&path).unwrap(), crate::TickReader::open_in_memory(&path).unwrap()] {
+            assert_eq!(timestamps(reader.load_range("AAPL", minute, 3 * minute).unwrap()), vec![2, 3, 4, 5]);
+            assert_eq!(timestamps(reader.load_range("AAPL", 4 * minute, 10 * minute).unwrap()), vec![8, 9]);
+            assert!(reader.load_range("SPY", 0, minute).is_err());
+        }
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[cfg(feature = "hdf5")]
+    #[test]
+    fn test_tick_reader_queries() {
+        let path = temp_h5("tick_reader_queries");
+        let trade = |timestamp, price| crate::Tick { price, ..tick(timestamp) };
+        let quote = crate::Tick { message_type: b'8', ..tick(3) };
+        {
+            let file = hdf5::File::open(&path, "w").unwrap();
+            crate::append_ticks(&file, "AAPL    ", &[trade(1, 1_000_000), trade(2, 1_100_000)]).unwrap();
+            crate::append_ticks(&file, "SPY     ", &[quote, trade(4, 3_000_000)]).unwrap();
+            crate::append_ticks(&file, "BAD     ", &[crate::Tick { price_multiplier: 0, ..tick(5) }]).unwrap();
+            crate::write_symbol_index(&file, &["AAPL    ", "BAD     ", "SPY     "]).unwrap();
+        }
+        let reader = crate::TickReader::open(&path).unwrap();
+        assert_eq!(reader.symbols().unwrap(), vec!["AAPL    ", "BAD     ", "SPY     "]);
+        assert_eq!(reader.load("AAPL    ").unwrap().len(), 2);
+        assert_eq!(reader.load("SPY     ").unwrap().iter().map(|t| t.timestamp).collect::<Vec<u64>>(), vec![3, 4]);
+        let summary = reader.summary("AAPL    ").unwrap();
+        assert_eq!((summary.tick_count, summary.trade_count, summary.last_price), (2, 2, Some(110.0)));
+        assert_eq!(reader.summary("SPY     ").unwrap().trade_count, 1);
+        assert!(matches!(reader.load("BAD     "), Err(crate::LoadTicksError::InvalidPriceMultiplier(_))));
+        assert!(matches!(reader.summary("BAD     "), Err(crate::LoadTicksError::InvalidPriceMultiplier(_))));
+        assert!(matches!(reader.load("QQQ     "), Err(crate::LoadTicksError::Hdf5(_))));
+        // Earlier failures leave the reader usable.
+        assert_eq!(reader.load_range("AAPL    ", 2, 3).unwrap().len(), 1);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[cfg(feature = "hdf5")]
+    #[test]
+    fn test_combined_ticks_round_trip() {
+        let path = temp_h5("combined");
+        let rows = vec![
+            crate::TickWithSymbol::new("AAPL    ", &tick(1)).unwrap(),
+            crate::TickWithSymbol::new("SPY     ", &crate::Tick { price: 2_950_000, ..tick(2) }).unwrap(),
+            crate::TickWithSymbol::new("AAPL    ", &tick(3)).unwrap(),
+        ];
+        assert!(crate::TickWithSymbol::new("TOOLONGSYM", &tick(4)).is_err());
+        {
+            let file = hdf5::File::open(&path, "w").unwrap();
+            crate::write_combined_ticks(&file, &rows, &crate::WriteOptions::default()).unwrap();
+            let fixed_point = crate::WriteOptions { fixed_point: true, ..crate::WriteOptions::default() };
+            assert!(crate::write_combined_ticks(&file, &rows, &fixed_point).is_err());
+        }
+
+        let file = hdf5::File::open(&path, "r").unwrap();
+        let read = crate::read_combined_ticks(&file).unwrap();
+        assert_eq!(read, rows);
+        let symbols: Vec<&str> = read.iter().map(crate::TickWithSymbol::symbol).collect();
+        assert_eq!(symbols, vec!["AAPL    ", "SPY     ", "AAPL    "]);
+        assert!(read[1].to_tick() == crate::Tick { price: 2_950_000, ..tick(2) });
+        assert!(crate::symbols(&file).unwrap().is_empty());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_write_options_validate() {
+        assert!(crate::WriteOptions::default().validate().is_ok());
+        let options = crate::WriteOptions { chunk_size: 0, ..crate::WriteOptions::default() };
+        assert!(options.validate().is_err());
+    }
+
+    #[cfg(feature = "hdf5")]
+    #[test]
+    fn test_write_ticks_chunk_sizes() {
+        let path = temp_h5("chunk_sizes");
+        let ticks: Vec<crate::Tick> = (0..100).map(tick).collect();
+        let layouts = [
+            crate::WriteOptions::default(),
+            crate::WriteOptions { chunk_size: 1, layout: crate::Layout::Chunked, ..crate::WriteOptions::default() },
+            crate::WriteOptions { chunk_size: 7, layout: crate::Layout::Chunked, ..crate::WriteOptions::default() },
+            crate::WriteOptions { chunk_size: 1000, layout: crate::Layout::Chunked, ..crate::WriteOptions::default() },
+            crate::WriteOptions { fixed_point: true, ..crate::WriteOptions::default() },
+            crate::WriteOptions { chunk_size: 7, layout: crate::Layout::Chunked, fixed_point: true, ..crate::WriteOptions::default() },
+        ];
+        {
+            let file = hdf5::File::open(&path, "w").unwrap();
+            for (i, options) in layouts.iter().enumerate() {
+                crate::write_ticks(&file, &format!("S{}", i), &ticks, options).unwrap();
+                crate::append_ticks_with_options(&file, &format!("A{}", i), &ticks[..50], options).unwrap();
+                crate::append_ticks_with_options(&file, &format!("A{}", i), &ticks[50..], options).unwrap();
+            }
+        }
+        for i in 0..layouts.len() {
+            assert!(crate::load_ticks_from_file(&format!("S{}", i), &path) == ticks);
+            assert!(crate::load_ticks_from_file(&format!("A{}", i), &path) == ticks);
+        }
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    fn summary_ticks() -> Vec<crate::Tick> {
+        let trade = |price, size| crate::Tick { price, size, ..tick(1) };
+        vec![
+            trade(1_000_000, 100),
+            crate::Tick { message_type: b'8', price: 5_000_000, ..tick(2) },
+            trade(900_000, 50),
+            trade(1_200_000, 10),
+            trade(1_100_000, 1),
+        ]
+    }
+
+    fn expected_summary(symbol: &str) -> crate::SymbolSummary {
+        crate::SymbolSummary {
+            symbol: symbol.to_string(),
+            tick_count: 5,
+            trade_count: 4,
+            volume: 161,
+            first_price: Some(100.0),
+            last_price: Some(110.0),
+            min_price: Some(90.0),
+            max_price: Some(120.0),
+        }
+    }
+
+    #[cfg(feature = "hdf5")]
+    #[test]
+    fn test_fixed_point_tick() {
+        let tick = crate::Tick { price: 1_234_500, ..tick(1) };
+        let fixed_point = crate::FixedPointTick::from_tick(&tick);
+        assert_eq!(fixed_point.price, 1_234_500);
+        assert!(fixed_point.to_tick(tick.price_multiplier) == tick);
+        assert_eq!(crate::format_price(fixed_point.price, 10000), "123.4500");
+    }
+
+    #[cfg(feature = "hdf5")]
+    #[test]
+    fn test_fixed_point_round_trip() {
+        let path = temp_h5("fixed_point");
+        let ticks: Vec<crate::Tick> = (0..10).map(|i| crate::Tick { price: 1_990_000 + i * 100, ..tick(i) }).collect();
+        let options = crate::WriteOptions { fixed_point: true, ..crate::WriteOptions::default() };
+        {
+            let file = hdf5::File::open(&path, "w").unwrap();
+            crate::write_versions(&file, "0.1.0", &options).unwrap();
+            let dataset = crate::write_ticks(&file, "AAPL", &ticks, &options).unwrap();
+            assert_eq!(crate::read_attr::<u64>(&dataset, crate::PRICE_MULTIPLIER_ATTR).unwrap(), Some(10000));
+            assert!(dataset.dtype().unwrap().to_descriptor().unwrap() != <crate::Tick as hdf5::H5Type>::type_descriptor());
+            crate::append_ticks_with_options(&file, "SPY", &ticks[..5], &options).unwrap();
+            crate::append_ticks_with_options(&file, "SPY", &ticks[5..], &options).unwrap();
+            let other = [crate::Tick { price_multiplier: 100, ..tick(11) }];
+            assert!(crate::append_ticks_with_options(&file, "SPY", &other, &options).is_err());
+            let mixed = [tick(12), crate::Tick { price_multiplier: 100, ..tick(13) }];
+            assert!(crate::write_ticks(&file, "MSFT", &mixed, &options).is_err());
+            assert_eq!(crate::read_attr::<u32>(&file, crate::SCHEMA_VERSION_ATTR).unwrap(),
+                       Some(crate::FIXED_POINT_SCHEMA_VERSION));
+        }
+        for symbol in &["AAPL", "SPY"] {
+            let loaded = crate::load_ticks_validated(symbol, &path).unwrap();
+            assert!(loaded == ticks);
+            let prices: Vec<String> = loaded.iter().map(|t| crate::format_price(t.price, t.price_multiplier)).collect();
+            assert_eq!(prices[..2], ["199.0000".to_string(), "199.0100".to_string()]);
+        }
+        let found = crate::load_ticks_in_range(&path, "AAPL", 2, 4).unwrap();
+        assert!(found == ticks[2..4]);
+        assert_eq!(crate::symbol_summary(&path, "AAPL").unwrap().first_price, Some(199.0));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_symbol_summary_add() {
+        let mut summary = crate::SymbolSummary::new("AAPL");
+        for tick in &summary_ticks() {
+            summary.add(tick);
+        }
+        assert_eq!(summary, expected_summary("AAPL"));
+        assert_eq!(summary.to_string(), "AAPL     ticks 5 trades 4 volume 161 first 100 last 110 min 90 max 120");
+        assert_eq!(crate::SymbolSummary::new("SPY").to_string(),
+                   "SPY      ticks 0 trades 0 volume 0 first - last - min - max -");
+    }
+
+    #[cfg(feature = "hdf5")]
+    #[test]
+    fn test_file_summary() {
+        let path = temp_h5("summary");
+        {
+            let file = hdf5::File::open(&path, "w").unwrap();
+            crate::append_ticks(&file, "SPY", &summary_ticks()).unwrap();
+            crate::append_ticks(&file, "AAPL", &summary_ticks()).unwrap();
+            crate::append_ticks(&file, "BAD", &[crate::Tick { price_multiplier: 0, ..tick(1) }]).unwrap();
+        }
+        assert_eq!(crate::symbol_summary(&path, "SPY").unwrap(), expected_summary("SPY"));
+        assert!(crate::symbol_summary(&path, "BAD").is_err());
+        assert!(crate::file_summary(&path).is_err());
+        {
+            let file = hdf5::File::open(&path, "r+").unwrap();
+            file.unlink("BAD").unwrap();
+        }
+        assert_eq!(crate::file_summary(&path).unwrap(), vec![expected_summary("AAPL"), expected_summary("SPY")]);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_validate_price_multipliers() {
+        let mut ticks = vec![tick(1), tick(2), tick(3), tick(4)];
+        assert_eq!(crate::validate_price_multipliers(&ticks), Ok(()));
+        ticks[1].price_multiplier = 0;
+        ticks[3].price_multiplier = 100;
+        assert_eq!(crate::validate_price_multipliers(&ticks), Err(crate::PriceMultiplierError {
+            zero: vec![1],
+            inconsistent: vec![3],
+        }));
+    }
+
+    #[cfg(feature = "hdf5")]
+    #[test]
+    fn test_load_ticks_validated() {
+        let path = temp_h5("validated");
+        {
+            let file = hdf5::File::open(&path, "w").unwrap();
+            crate::append_ticks(&file, "AAPL", &[tick(1), tick(2)]).unwrap();
+            crate::append_ticks(&file, "SPY", &[tick(3), crate::Tick { price_multiplier: 0, ..tick(4) }]).unwrap();
+        }
+        assert_eq!(crate::load_ticks_validated("AAPL", &path).ok().map(|t| t.len()), Some(2));
+        match crate::load_ticks_validated("SPY", &path) {
+            Err(crate::LoadTicksError::InvalidPriceMultiplier(e)) => assert_eq!(e.zero, vec![1]),
+            _ => panic!("expected an invalid price multiplier"),
+        }
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[cfg(feature = "hdf5")]
+    #[test]
+    fn test_source_files() {
+        let path = temp_h5("source_files");
+        {
+            let file = hdf5::File::open(&path, "w").unwrap();
+            assert!(crate::read_source_files(&file).unwrap().is_empty());
+            crate::write_source_files(&file, &["20190703_IEXTP1_DEEP1.0.pcap.gz"]).unwrap();
+            assert_eq!(crate::read_source_files(&file).unwrap(), vec!["20190703_IEXTP1_DEEP1.0.pcap.gz"]);
+            // Rewriting replaces the list.
+            crate::write_source_files(&file, &["20190703_IEXTP1_DEEP1.0_1.pcap", "20190703_IEXTP1_DEEP1.0_2.pcap"]).unwrap();
+        }
+        let file = hdf5::File::open(&path, "r").unwrap();
+        assert_eq!(crate::read_source_files(&file).unwrap(),
+                   vec!["20190703_IEXTP1_DEEP1.0_1.pcap", "20190703_IEXTP1_DEEP1.0_2.pcap"]);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[cfg(feature = "hdf5")]
+    #[test]
+    fn test_schema_version() {
+        let path = temp_h5("schema_version");
+        {
+            let file = hdf5::File::open(&path, "w").unwrap();
+            crate::write_versions(&file, "0.1.0", &crate::WriteOptions::default()).unwrap();
+            crate::append_ticks(&file, "AAPL", &[tick(1)]).unwrap();
+        }
+        assert_eq!(crate::load_ticks_validated("AAPL", &path).ok().map(|t| t.len()), Some(1));
+        {
+            let file = hdf5::File::open(&path, "r+").unwrap();
+            assert_eq!(crate::read_attr::<hdf5::types::VarLenUnicode>(&file, crate::PARSER_VERSION_ATTR).unwrap()
+                           .map(|v| v.as_str().to_string()), Some("0.1.0".to_string()));
+            crate::write_attr(&file, crate::SCHEMA_VERSION_ATTR, &0u32).unwrap();
+        }
+        match crate::load_ticks_validated("AAPL", &path) {
+            Err(crate::LoadTicksError::Hdf5(e)) => {
+                let message = e.to_string();
+                assert!(message.contains("schema version 0 (written by parser 0.1.0)"), "{}", message);
+                assert!(message.contains("reads versions 1 and 2 (fixed point)"), "{}", message);
+            },
+            _ => panic!("expected a schema version mismatch"),
+        }
+        assert!(crate::symbol_summary(&path, "AAPL").is_err());
+        assert!(crate::load_ticks_in_range(&path, "AAPL", 0, 10).is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[cfg(feature = "hdf5")]
+    #[test]
+    fn test_tick_layout_mismatches() {
+        use hdf5::types::{CompoundField, CompoundType, IntSize, TypeDescriptor};
+        use hdf5::H5Type;
+
+        assert!(crate::tick_layout_mismatches(&crate::Tick::type_descriptor()).is_empty());
+        assert_eq!(crate::tick_layout_mismatches(&u64::type_descriptor()),
+                   vec!["not a compound type but Unsigned(U8)".to_string()]);
+
+        let mut compound = match crate::Tick::type_descriptor() {
+            TypeDescriptor::Compound(compound) => compound,
+            _ => unreachable!(),
+        };
+        let size = compound.size;
+        compound.fields.retain(|f| f.name != "capture_timestamp");
+        compound.fields.iter_mut().find(|f| f.name == "size").unwrap().ty = TypeDescriptor::Unsigned(IntSize::U8);
+        compound.fields.push(CompoundField::new("trade_id", TypeDescriptor::Unsigned(IntSize::U8), size, 9));
+        compound.size = size + 8;
+        assert_eq!(crate::tick_layout_mismatches(&TypeDescriptor::Compound(compound)), vec![
+            "field size is Unsigned(U8) instead of Unsigned(U4)".to_string(),
+            "no field capture_timestamp".to_string(),
+            "unknown field trade_id".to_string(),
+            format!("size is {} bytes instead of {}", size + 8, size),
+        ]);
+        let empty = CompoundType { fields: Vec::new(), size: 0 };
+        assert_eq!(crate::tick_layout_mismatches(&TypeDescriptor::Compound(empty)).len(), 10);
+    }
+
+    /// `Tick` before `capture_timestamp` was added.
+    #[cfg(feature = "hdf5")]
+    #[derive(hdf5::H5Type, Clone)]
+    #[repr(C)]
+    struct OldTick {
+        message_type: u8,
+        message_subtype: u8,
+        timestamp: u64,
+        size: u32,
+        price: u64,
+        price_multiplier: u64,
+        packet_number: u64,
+        message_sequence_number: u64,
+    }
+
+    #[cfg(feature = "hdf5")]
+    #[test]
+    fn test_mismatched_tick_datatype() {
+        let path = temp_h5("mismatched_datatype");
+        {
+            let file = hdf5::File::open(&path, "w").unwrap();
+            let old = OldTick { message_type: b'T', message_subtype: 0, timestamp: 1, size: 100, price: 1234500,
+                                price_multiplier: 10000, packet_number: 0, message_sequence_number: 1 };
+            file.new_dataset::<OldTick>().create("AAPL", 1).unwrap().write(&[old]).unwrap();
+        }
+        match crate::load_ticks_validated("AAPL", &path) {
+            Err(crate::LoadTicksError::Hdf5(e)) => {
+                assert!(e.to_string().contains("/AAPL doesn't hold ticks of this libh5's layout: no field capture_timestamp"),
+                        "{}", e);
+            },
+            _ => panic!("expected a datatype mismatch"),
+        }
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[cfg(feature = "hdf5")]
+    #[test]
+    fn test_symbol_index_matches_datasets() {
+        let path = temp_h5("symbol_index");
+        {
+            let file = hdf5::File::open(&path, "w").unwrap();
+            crate::append_ticks(&file, "AAPL", &[tick(1), tick(2), tick(3)]).unwrap();
+            crate::append_ticks(&file, "SPY", &[tick(4)]).unwrap();
+            crate::write_symbol_index(&file, &["SPY", "AAPL"]).unwrap();
+            assert!(crate::read_untraded_symbols(&file).unwrap().is_empty());
+            crate::write_untraded_symbols(&file, &["QQQ", "IWM"]).unwrap();
+        }
+
+        let index = crate::load_symbol_index(&path).unwrap();
+        assert_eq!(index, vec![("AAPL".to_string(), 3), ("SPY".to_string(), 1)]);
+        for (symbol, tick_count) in &index {
+            assert_eq!(crate::load_ticks_from_file(symbol, &path).len() as u64, *tick_count);
+        }
+        let file = hdf5::File::open(&path, "r").unwrap();
+        assert_eq!(crate::symbols(&file).unwrap(), vec!["AAPL", "SPY"]);
+        assert_eq!(crate::read_untraded_symbols(&file).unwrap(), vec!["IWM", "QQQ"]);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[cfg(feature = "hdf5")]
+    #[test]
+    fn test_symbol_grouping() {
+        let ticks: Vec<crate::Tick> = (0..6).map(tick).collect();
+        for (grouping, aapl) in &[(crate::SymbolGrouping::FirstLetter, "A/AAPL    "),
+                                  (crate::SymbolGrouping::FirstTwoLetters, "AA/AAPL    ")] {
+            let path = temp_h5(&format!("symbol_grouping_{}", grouping.prefix_len()));
+            let options = crate::WriteOptions { symbol_grouping: *grouping, ..crate::WriteOptions::default() };
+            {
+                let file = hdf5::File::open(&path, "w").unwrap();
+                crate::write_versions(&file, "0.1.0", &options).unwrap();
+                crate::write_ticks(&file, "AAPL    ", &ticks, &options).unwrap();
+                crate::append_ticks(&file, "AMZN    ", &ticks[..2]).unwrap();
+                crate::append_ticks(&file, "AMZN    ", &ticks[2..3]).unwrap();
+                crate::write_ticks(&file, "A       ", &ticks[..1], &options).unwrap();
+                crate::write_ticks(&file, "SPY     ", &ticks[..4], &options).unwrap();
+                crate::write_symbol_index(&file, &crate::symbols(&file).unwrap()).unwrap();
+                assert!(file.link_exists(aapl));
+                assert_eq!(crate::read_symbol_grouping(&file).unwrap(), *grouping);
+            }
+
+            let file = hdf5::File::open(&path, "r").unwrap();
+            assert_eq!(crate::symbols(&file).unwrap(), vec!["A       ", "AAPL    ", "AMZN    ", "SPY     "]);
+            assert!(crate::read_ticks(&crate::tick_dataset(&file, "AAPL    ").unwrap()).unwrap() == ticks);
+            assert!(crate::load_ticks_from_file("AMZN    ", &path) == ticks[..3]);
+            let index: Vec<(String, u64)> = crate::load_symbol_index(&path).unwrap().into_iter()
+                .map(|(symbol, count)| (symbol.trim_end().to_string(), count))
+                .collect();
+            assert_eq!(index, vec![("A".to_string(), 1), ("AAPL".to_string(), 6), ("AMZN".to_string(), 3),
+                                   ("SPY".to_string(), 4)]);
+            std::fs::remove_file(&path).unwrap();
+        }
+    }
+
+    #[cfg(feature = "hdf5")]
+    #[test]
+    fn test_timestamp_range() {
+        let path = temp_h5("timestamp_range");
+        {
+            let file = hdf5::File::open(&path, "w").unwrap();
+            crate::write_ticks(&file, "AAPL", &[tick(5), tick(2), tick(9)], &crate::WriteOptions::default()).unwrap();
+            crate::append_ticks(&file, "SPY", &[tick(4), tick(6)]).unwrap();
+            crate::append_ticks(&file, "SPY", &[tick(1)]).unwrap();
+            crate::append_ticks(&file, "SPY", &[tick(3), tick(8)]).unwrap();
+            let options = crate::WriteOptions { fixed_point: true, ..crate::WriteOptions::default() };
+            crate::write_ticks(&file, "MSFT", &[tick(7)], &options).unwrap();
+            crate::write_ticks(&file, "QQQ", &[], &crate::WriteOptions::default()).unwrap();
+        }
+        let file = hdf5::File::open(&path, "r").unwrap();
+        for symbol in &["AAPL", "SPY", "MSFT"] {
+            let dataset = file.dataset(symbol).unwrap();
+            let timestamps: Vec<u64> = crate::read_ticks(&dataset).unwrap().iter().map(|t| t.timestamp).collect();
+            let expected = (*timestamps.iter().min().unwrap(), *timestamps.iter().max().unwrap());
+            assert_eq!(crate::read_timestamp_range(&dataset).unwrap(), Some(expected));
+        }
+        assert_eq!(crate::read_timestamp_range(&file.dataset("SPY").unwrap()).unwrap(), Some((1, 8)));
+        assert_eq!(crate::read_timestamp_range(&file.dataset("QQQ").unwrap()).unwrap(), None);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[cfg(feature = "hdf5")]
+    #[test]
+    fn test_official_prices_round_trip() {
+        let path = temp_h5("official_prices");
+        let official_price = |timestamp, price_type| crate::Tick {
+            message_type: crate::OFFICIAL_PRICE,
+            message_subtype: price_type,
+            size: 0,
+            ..tick(timestamp)
+        };
+        let prices = vec![official_price(1, crate::OFFICIAL_OPENING_PRICE), official_price(2, crate::OFFICIAL_CLOSING_PRICE)];
+        {
+            let file = hdf5::File::open(&path, "w").unwrap();
+            crate::append_ticks(&file, "SPY", &[tick(1)]).unwrap();
+            crate::write_official_prices(&file, "SPY", &prices).unwrap();
+        }
+
+        let file = hdf5::File::open(&path, "r").unwrap();
+        assert!(crate::read_official_prices(&file, "SPY").unwrap() == prices);
+        assert!(crate::read_official_prices(&file, "AAPL").unwrap().is_empty());
+        assert_eq!(crate::symbols(&file).unwrap(), vec!["SPY"]);
+        std::fs::remove_file(&path).unwrap();
+    }
 
-#[derive(hdf5::H5Type, Clone, PartialEq)]
+    #[cfg(feature = "hdf5")]
+    #[test]
+    fn test_append_to_groups() {
+        let path = temp_h5("append_to_groups");
+        {
+            let file = hdf5::File::open(&path, "w").unwrap();
+            crate::append_official_prices(&file, "AAPL", &[tick(1)]).unwrap();
+            crate::append_official_prices(&file, "AAPL", &[tick(2), tick(3)]).unwrap();
+            crate::append_official_prices(&file, "SPY", &[tick(4)]).unwrap();
+        }
+        let file = hdf5::File::open(&path, "r").unwrap();
+        let timestamps: Vec<u64> = crate::read_official_prices(&file, "AAPL").unwrap().iter().map(|t| t.timestamp).collect();
+        assert_eq!(timestamps, vec![1, 2, 3]);
+        assert_eq!(crate::group_symbols(&file, crate::OFFICIAL_PRICES_GROUP).unwrap(), vec!["AAPL", "SPY"]);
+        assert!(crate::group_symbols(&file, crate::AUCTIONS_GROUP).unwrap().is_empty());
+        assert!(crate::symbols(&file).unwrap().is_empty());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[cfg(feature = "hdf5")]
+    #[test]
+    fn test_auctions_round_trip() {
+        let path = temp_h5("auctions");
+        let auction = crate::Auction {
+            timestamp: 1_562_183_940_000_000_000,
+            auction_type: b'C',
+            paired_shares: 1_000,
+            reference_price: 2_967_500,
+            indicative_clearing_price: 2_967_000,
+            imbalance_shares: 200,
+            imbalance_side: b'B',
+            extension_number: 0,
+            scheduled_auction_time: 1_562_184_000_000_000_000,
+            scheduled_off_trade_date: false,
+            auction_book_clearing_price: 2_967_000,
+            collar_reference_price: 2_967_500,
+            lower_auction_collar: 2_670_700,
+            upper_auction_collar: 3_264_200,
+            price_multiplier: 10000,
+            packet_number: 3,
+            message_sequence_number: 4,
+        };
+        {
+            let file = hdf5::File::open(&path, "w").unwrap();
+            crate::append_ticks(&file, "SPY", &[tick(1)]).unwrap();
+            crate::write_auctions(&file, "SPY", std::slice::from_ref(&auction)).unwrap();
+        }
+
+        let file = hdf5::File::open(&path, "r").unwrap();
+        assert_eq!(crate::read_auctions(&file, "SPY").unwrap(), vec![auction]);
+        assert!(crate::read_auctions(&file, "AAPL").unwrap().is_empty());
+        assert_eq!(crate::symbols(&file).unwrap(), vec!["SPY"]);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_bars() {
+        let trade = |timestamp, price, size| crate::Tick { price, size, ..tick(timestamp) };
+        let minute = |timestamp| timestamp - timestamp % 60;
+        let ticks = vec![
+            trade(61, 1010000, 100),
+            // Out of order, so it opens the bar.
+            trade(60, 1000000, 100),
+            crate::Tick { message_type: b'8', ..trade(62, 2000000, 500) },
+            trade(65, 990000, 200),
+            trade(119, 1005000, 100),
+            // Nothing in the minute from 120.
+            trade(185, 1020000, 300),
+        ];
+        let bars = crate::bars(&ticks, minute);
+
+        assert_eq!(bars.len(), 2);
+        assert_eq!((bars[0].timestamp, bars[0].open, bars[0].high, bars[0].low, bars[0].close),
+                   (60, 1000000, 1010000, 990000, 1005000));
+        assert_eq!((bars[0].volume, bars[0].trade_count, bars[0].price_multiplier), (500, 4, 10000));
+        assert!((bars[0].vwap - 99.9).abs() < 1e-9);
+        assert_eq!((bars[1].timestamp, bars[1].open, bars[1].close, bars[1].volume), (180, 1020000, 1020000, 300));
+        assert!((bars[1].vwap - 102.0).abs() < 1e-9);
+        assert!(crate::bars(&[], minute).is_empty());
+    }
+
+    #[test]
+    fn test_accumulator() {
+        let trade = |price, size| crate::Tick { price, size, ..tick(1) };
+        let mut accumulator = crate::Accumulator::default();
+        assert_eq!(accumulator.snapshot(), crate::AccumulatorSnapshot::default());
+
+        accumulator.update(&trade(100000, 100));
+        accumulator.update(&trade(105000, 200));
+        // Quotes don't count.
+        accumulator.update(&crate::Tick { message_type: b'8', ..trade(200000, 1000) });
+        accumulator.update(&trade(97500, 100));
+        // (10.00 * 100 + 10.50 * 200 + 9.75 * 100) / 400
+        let snapshot = accumulator.snapshot();
+        assert!((snapshot.vwap.unwrap() - 10.1875).abs() < 1e-9);
+        assert_eq!((snapshot.volume, snapshot.trade_count, snapshot.last_price), (400, 3, Some(9.75)));
+    }
+
+    #[cfg(feature = "hdf5")]
+    #[test]
+    fn test_bars_round_trip() {
+        let path = temp_h5("bars");
+        let bars = crate::bars(&[tick(60), tick(61), tick(125)], |timestamp| timestamp - timestamp % 60);
+        {
+            let file = hdf5::File::open(&path, "w").unwrap();
+            crate::append_ticks(&file, "SPY", &[tick(60)]).unwrap();
+            crate::write_bars(&file, "SPY", &bars, 60).unwrap();
+        }
+
+        let file = hdf5::File::open(&path, "r").unwrap();
+        assert_eq!(crate::read_bars(&file, "SPY").unwrap(), Some((bars, 60)));
+        assert_eq!(crate::read_bars(&file, "AAPL").unwrap(), None);
+        assert_eq!(crate::symbols(&file).unwrap(), vec!["SPY"]);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[cfg(feature = "hdf5")]
+    #[test]
+    fn test_raw_messages_round_trip() {
+        let path = temp_h5("raw_messages");
+        let raw = vec![
+            crate::RawMessage::new(3, 17, &[b'Z', 0, 1, 2]),
+            crate::RawMessage::new(3, 18, &[]),
+            crate::RawMessage::new(9, 40, &[b'T'; 37]),
+        ];
+        {
+            let file = hdf5::File::open(&path, "w").unwrap();
+            crate::append_ticks(&file, "AAPL", &[tick(1)]).unwrap();
+            crate::write_raw_messages(&file, &raw).unwrap();
+        }
+
+        let file = hdf5::File::open(&path, "r").unwrap();
+        assert_eq!(crate::read_raw_messages(&file).unwrap(), raw);
+        assert_eq!(crate::symbols(&file).unwrap(), vec!["AAPL"]);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
+
+#[cfg_attr(feature = "hdf5", derive(hdf5::H5Type))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+// Bump SCHEMA_VERSION when changing the fields.
+#[derive(Clone, PartialEq)]
 #[repr(C)]
 pub struct Tick {
     pub message_type: u8,
@@ -13,20 +818,1623 @@ pub struct Tick {
     pub price_multiplier: u64,
     pub packet_number: u64,
     pub message_sequence_number: u64,
+    // unit is nanoseconds; when the packet was captured, to compare against timestamp
+    pub capture_timestamp: u64,
+}
+
+impl Tick {
+    /// NaN or infinite if `price_multiplier` is zero, which `validate_price_multipliers` catches.
+    pub fn price_f64(&self) -> f64 {
+        self.price as f64 / self.price_multiplier as f64
+    }
+
+    pub fn price_decimal(&self) -> Option<Decimal> {
+        Decimal::from_price(self.price, self.price_multiplier)
+    }
+}
+
+/// A `Tick` without `price_multiplier`, for datasets written with `WriteOptions::fixed_point`
+/// that hold it once in their `PRICE_MULTIPLIER_ATTR` instead.
+#[cfg(feature = "hdf5")]
+#[derive(hdf5::H5Type, Clone, Debug, PartialEq)]
+#[repr(C)]
+struct FixedPointTick {
+    message_type: u8,
+    message_subtype: u8,
+    timestamp: u64,
+    size: u32,
+    price: u64,
+    packet_number: u64,
+    message_sequence_number: u64,
+    capture_timestamp: u64,
+}
+
+#[cfg(feature = "hdf5")]
+impl FixedPointTick {
+    fn from_tick(tick: &Tick) -> FixedPointTick {
+        FixedPointTick {
+            message_type: tick.message_type,
+            message_subtype: tick.message_subtype,
+            timestamp: tick.timestamp,
+            size: tick.size,
+            price: tick.price,
+            packet_number: tick.packet_number,
+            message_sequence_number: tick.message_sequence_number,
+            capture_timestamp: tick.capture_timestamp,
+        }
+    }
+
+    fn to_tick(&self, price_multiplier: u64) -> Tick {
+        Tick {
+            message_type: self.message_type,
+            message_subtype: self.message_subtype,
+            timestamp: self.timestamp,
+            size: self.size,
+            price: self.price,
+            price_multiplier,
+            packet_number: self.packet_number,
+            message_sequence_number: self.message_sequence_number,
+            capture_timestamp: self.capture_timestamp,
+        }
+    }
+}
+
+/// `units / 10^decimals`, for printing prices without going through floats.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Decimal {
+    pub units: u128,
+    pub decimals: u32,
+}
+
+impl Decimal {
+    /// `price / price_multiplier` with as many decimals as the multiplier calls for: exactly
+    /// enough for multipliers of the form 2^a * 5^b (so 4 decimals for 10000, 2 for 4), or else
+    /// as many as the multiplier has digits, rounding half up. `None` if the multiplier is zero or
+    /// the scaled price doesn't fit.
+    pub fn from_price(price: u64, price_multiplier: u64) -> Option<Decimal> {
+        if price_multiplier == 0 {
+            return None;
+        }
+        let (mut twos, mut fives, mut rest) = (0, 0, price_multiplier);
+        while rest % 2 == 0 {
+            rest /= 2;
+            twos += 1;
+        }
+        while rest % 5 == 0 {
+            rest /= 5;
+            fives += 1;
+        }
+        let decimals = if rest == 1 {
+            u32::max(twos, fives)
+        } else {
+            price_multiplier.to_string().len() as u32
+        };
+        let multiplier = u128::from(price_multiplier);
+        let scaled = u128::from(price).checked_mul(10u128.checked_pow(decimals)?)?;
+        Some(Decimal { units: (scaled + multiplier / 2) / multiplier, decimals })
+    }
+}
+
+impl std::fmt::Display for Decimal {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        if self.decimals == 0 {
+            return write!(f, "{}", self.units);
+        }
+        let power = 10u128.pow(self.decimals);
+        write!(f, "{}.{:0width$}", self.units / power, self.units % power, width = self.decimals as usize)
+    }
+}
+
+/// `price` divided by `price_multiplier` as `Decimal` formats it, which keeps trailing zeros, so
+/// every price of a dataset has the same number of decimals. Falls back to `price_f64`'s
+/// formatting if there's no `Decimal`, e.g. "NaN" for a zero multiplier.
+pub fn format_price(price: u64, price_multiplier: u64) -> String {
+    match Decimal::from_price(price, price_multiplier) {
+        Some(decimal) => decimal.to_string(),
+        None => (price as f64 / price_multiplier as f64).to_string(),
+    }
+}
+
+/// Root attribute set on files whose run was interrupted before the whole capture was read.
+pub const INCOMPLETE_ATTR: &str = "incomplete";
+
+/// Root attribute holding the IEX-TP channel id of every tick in the file, for files whose ticks
+/// all came from a single channel.
+pub const CHANNEL_ID_ATTR: &str = "channel_id";
+
+#[cfg(feature = "hdf5")]
+fn last_h5_error(what: &str) -> hdf5::Error {
+    hdf5::Error::query().unwrap_or_else(|| hdf5::Error::from(what))
+}
+
+#[cfg(feature = "hdf5")]
+fn attr_name(name: &str) -> hdf5::Result<ffi::CString> {
+    ffi::CString::new(name).map_err(|_| hdf5::Error::from(format!("invalid attribute name '{}'", name)))
+}
+
+// The hdf5 crate doesn't wrap attributes yet, so these go through hdf5-sys directly. Like the
+// rest of this crate, they assume hdf5 isn't used concurrently from multiple threads.
+
+/// Writes a scalar attribute on a file, group or dataset, replacing any existing value.
+#[cfg(feature = "hdf5")]
+pub fn write_attr<T: hdf5::H5Type>(loc: &hdf5::Location, name: &str, value: &T) -> hdf5::Result<()> {
+    let space = unsafe { h5s::H5Screate(h5s::H5S_class_t::H5S_SCALAR) };
+    write_attr_in(loc, name, space, value as *const T)
+}
+
+/// Like `write_attr`, for a one-dimensional attribute of `values`.
+#[cfg(feature = "hdf5")]
+pub fn write_attr_list<T: hdf5::H5Type>(loc: &hdf5::Location, name: &str, values: &[T]) -> hdf5::Result<()> {
+    let dims = [values.len() as hsize_t];
+    let space = unsafe { h5s::H5Screate_simple(1, dims.as_ptr(), std::ptr::null()) };
+    write_attr_in(loc, name, space, values.as_ptr())
+}
+
+/// Creates the attribute over `space`, which it closes, from the values at `values`.
+#[cfg(feature = "hdf5")]
+fn write_attr_in<T: hdf5::H5Type>(loc: &hdf5::Location, name: &str, space: hid_t, values: *const T)
+    -> hdf5::Result<()> {
+    if space < 0 {
+        return Err(last_h5_error("H5Screate"));
+    }
+    let created = (|| {
+        let datatype = hdf5::Datatype::from_type::<T>()?;
+        let name = attr_name(name)?;
+        unsafe {
+            if h5a::H5Aexists(loc.id(), name.as_ptr()) > 0 && h5a::H5Adelete(loc.id(), name.as_ptr()) < 0 {
+                return Err(last_h5_error("H5Adelete"));
+            }
+            let attr = h5a::H5Acreate2(loc.id(), name.as_ptr(), datatype.id(), space, H5P_DEFAULT, H5P_DEFAULT);
+            if attr < 0 {
+                return Err(last_h5_error("H5Acreate2"));
+            }
+            let status = h5a::H5Awrite(attr, datatype.id(), values as *const _);
+            h5a::H5Aclose(attr);
+            if status < 0 {
+                return Err(last_h5_error("H5Awrite"));
+            }
+        }
+        Ok(())
+    })();
+    unsafe {
+        h5s::H5Sclose(space);
+    }
+    created
+}
+
+/// Reads a scalar attribute, returning `None` if it isn't set.
+#[cfg(feature = "hdf5")]
+pub fn read_attr<T: hdf5::H5Type>(loc: &hdf5::Location, name: &str) -> hdf5::Result<Option<T>> {
+    let datatype = hdf5::Datatype::from_type::<T>()?;
+    let name = attr_name(name)?;
+    unsafe {
+        match h5a::H5Aexists(loc.id(), name.as_ptr()) {
+            0 => return Ok(None),
+            e if e < 0 => return Err(last_h5_error("H5Aexists")),
+            _ => {},
+        }
+        let attr = h5a::H5Aopen(loc.id(), name.as_ptr(), H5P_DEFAULT);
+        if attr < 0 {
+            return Err(last_h5_error("H5Aopen"));
+        }
+        let mut value = mem::MaybeUninit::<T>::uninit();
+        let status = h5a::H5Aread(attr, datatype.id(), value.as_mut_ptr() as *mut _);
+        h5a::H5Aclose(attr);
+        if status < 0 {
+            return Err(last_h5_error("H5Aread"));
+        }
+        Ok(Some(value.assume_init()))
+    }
+}
+
+/// Reads a one-dimensional attribute written by `write_attr_list`, returning `None` if it isn't set.
+#[cfg(feature = "hdf5")]
+pub fn read_attr_list<T: hdf5::H5Type>(loc: &hdf5::Location, name: &str) -> hdf5::Result<Option<Vec<T>>> {
+    let datatype = hdf5::Datatype::from_type::<T>()?;
+    let name = attr_name(name)?;
+    unsafe {
+        match h5a::H5Aexists(loc.id(), name.as_ptr()) {
+            0 => return Ok(None),
+            e if e < 0 => return Err(last_h5_error("H5Aexists")),
+            _ => {},
+        }
+        let attr = h5a::H5Aopen(loc.id(), name.as_ptr(), H5P_DEFAULT);
+        if attr < 0 {
+            return Err(last_h5_error("H5Aopen"));
+        }
+        let space = h5a::H5Aget_space(attr);
+        let len = if space < 0 { -1 } else { h5s::H5Sget_simple_extent_npoints(space) };
+        if space >= 0 {
+            h5s::H5Sclose(space);
+        }
+        if len < 0 {
+            h5a::H5Aclose(attr);
+            return Err(last_h5_error("H5Aget_space"));
+        }
+        let mut values = Vec::<T>::with_capacity(len as usize);
+        let status = h5a::H5Aread(attr, datatype.id(), values.as_mut_ptr() as *mut _);
+        h5a::H5Aclose(attr);
+        if status < 0 {
+            return Err(last_h5_error("H5Aread"));
+        }
+        values.set_len(len as usize);
+        Ok(Some(values))
+    }
+}
+
+/// Root attribute listing the file names, without their directories, of the captures a file was
+/// parsed from, in the order they were read.
+pub const SOURCE_FILES_ATTR: &str = "source_files";
+
+#[cfg(feature = "hdf5")]
+pub fn write_source_files<S: AsRef<str>>(file: &hdf5::File, sources: &[S]) -> hdf5::Result<()> {
+    let sources = sources.iter()
+        .map(|source| source.as_ref().parse::<hdf5::types::VarLenUnicode>()
+            .map_err(|e| hdf5::Error::from(format!("invalid source file name '{}': {}", source.as_ref(), e))))
+        .collect::<hdf5::Result<Vec<_>>>()?;
+    write_attr_list(file, SOURCE_FILES_ATTR, &sources)
+}
+
+/// The captures a file was parsed from, or none for files written without `SOURCE_FILES_ATTR`.
+#[cfg(feature = "hdf5")]
+pub fn read_source_files(file: &hdf5::File) -> hdf5::Result<Vec<String>> {
+    let sources = read_attr_list::<hdf5::types::VarLenUnicode>(file, SOURCE_FILES_ATTR)?;
+    Ok(sources.unwrap_or_default().iter().map(|source| source.as_str().to_string()).collect())
+}
+
+/// Version of the `Tick` layout that this crate reads and writes.
+pub const SCHEMA_VERSION: u32 = 1;
+/// Version of files written with `WriteOptions::fixed_point`, so that a libh5 from before
+/// fixed-point datasets refuses them instead of misreading them.
+pub const FIXED_POINT_SCHEMA_VERSION: u32 = 2;
+/// Root attribute holding the file's `SCHEMA_VERSION`.
+pub const SCHEMA_VERSION_ATTR: &str = "schema_version";
+/// Root attribute holding the version of the program that wrote the file.
+pub const PARSER_VERSION_ATTR: &str = "parser_version";
+
+/// Marks a new file as written by `parser_version`, with `SCHEMA_VERSION`, or
+/// `FIXED_POINT_SCHEMA_VERSION` if its ticks are written with `options`' `fixed_point`, and
+/// records `options`' `symbol_grouping` unless it's flat.
+#[cfg(feature = "hdf5")]
+pub fn write_versions(file: &hdf5::File, parser_version: &str, options: &WriteOptions) -> hdf5::Result<()> {
+    let parser_version: hdf5::types::VarLenUnicode = parser_version.parse()
+        .map_err(|e| hdf5::Error::from(format!("invalid parser version: {}", e)))?;
+    let schema_version = if options.fixed_point { FIXED_POINT_SCHEMA_VERSION } else { SCHEMA_VERSION };
+    write_attr(file, SCHEMA_VERSION_ATTR, &schema_version)?;
+    if options.symbol_grouping != SymbolGrouping::Flat {
+        write_attr(file, SYMBOL_GROUPING_ATTR, &options.symbol_grouping.prefix_len())?;
+    }
+    write_attr(file, PARSER_VERSION_ATTR, &parser_version)
+}
+
+/// Fails unless the file was written with the current `SCHEMA_VERSION` or
+/// `FIXED_POINT_SCHEMA_VERSION`, since reading ticks of another layout gives garbage instead of an
+/// error. Files from before versioning have no `schema_version` and aren't checked.
+#[cfg(feature = "hdf5")]
+pub fn check_schema_version(file: &hdf5::File) -> hdf5::Result<()> {
+    match read_attr::<u32>(file, SCHEMA_VERSION_ATTR)? {
+        Some(version) if version != SCHEMA_VERSION && version != FIXED_POINT_SCHEMA_VERSION => {
+            let parser_version = read_attr::<hdf5::types::VarLenUnicode>(file, PARSER_VERSION_ATTR)?
+                .map_or("an unknown parser".to_string(), |v| format!("parser {}", v));
+            Err(hdf5::Error::from(format!(
+                "{} has tick schema version {} (written by {}), but this libh5 reads versions {} and {} \
+                 (fixed point); re-parse the capture or read it with a matching libh5",
+                file.filename(), version, parser_version, SCHEMA_VERSION, FIXED_POINT_SCHEMA_VERSION)))
+        },
+        _ => Ok(()),
+    }
+}
+
+/// Differences between the layout of a dataset's datatype and `Tick`'s, e.g. from a file written
+/// by a build with other fields. Fields are matched by name.
+#[cfg(feature = "hdf5")]
+fn tick_layout_mismatches(found: &hdf5::types::TypeDescriptor) -> Vec<String> {
+    layout_mismatches::<Tick>(found)
+}
+
+#[cfg(feature = "hdf5")]
+fn layout_mismatches<T: hdf5::H5Type>(found: &hdf5::types::TypeDescriptor) -> Vec<String> {
+    use hdf5::types::TypeDescriptor;
+
+    let expected = match T::type_descriptor() {
+        TypeDescriptor::Compound(compound) => compound,
+        _ => unreachable!("ticks are structs"),
+    };
+    let found = match found {
+        TypeDescriptor::Compound(compound) => compound,
+        other => return vec![format!("not a compound type but {:?}", other)],
+    };
+    let mut mismatches = Vec::new();
+    for field in &expected.fields {
+        match found.fields.iter().find(|f| f.name == field.name) {
+            None => mismatches.push(format!("no field {}", field.name)),
+            Some(f) if f.ty != field.ty => {
+                mismatches.push(format!("field {} is {:?} instead of {:?}", field.name, f.ty, field.ty));
+            },
+            Some(f) if f.offset != field.offset => {
+                mismatches.push(format!("field {} is at offset {} instead of {}", field.name, f.offset, field.offset));
+            },
+            Some(_) => {},
+        }
+    }
+    for field in &found.fields {
+        if !expected.fields.iter().any(|f| f.name == field.name) {
+            mismatches.push(format!("unknown field {}", field.name));
+        }
+    }
+    if found.size != expected.size {
+        mismatches.push(format!("size is {} bytes instead of {}", found.size, expected.size));
+    }
+    mismatches
+}
+
+/// Fails unless `dataset`'s datatype has `Tick`'s layout, or that of fixed-point ticks if it has a
+/// `PRICE_MULTIPLIER_ATTR`, since `read_raw` would otherwise reinterpret rows of another layout
+/// as ticks.
+#[cfg(feature = "hdf5")]
+pub fn check_tick_datatype(dataset: &hdf5::Dataset) -> hdf5::Result<()> {
+    let found = dataset.dtype()?.to_descriptor()?;
+    let mismatches = match read_attr::<u64>(dataset, PRICE_MULTIPLIER_ATTR)? {
+        Some(_) => layout_mismatches::<FixedPointTick>(&found),
+        None => tick_layout_mismatches(&found),
+    };
+    if mismatches.is_empty() {
+        Ok(())
+    } else {
+        Err(hdf5::Error::from(format!("{} doesn't hold ticks of this libh5's layout: {}",
+                                      dataset.name(), mismatches.join(", "))))
+    }
+}
+
+/// Opens `symbol`'s dataset after checking `check_schema_version` and `check_tick_datatype`. Its
+/// rows are read with `read_ticks` or `read_tick_rows`, which handle both layouts.
+#[cfg(feature = "hdf5")]
+pub fn tick_dataset(file: &hdf5::File, symbol: &str) -> hdf5::Result<hdf5::Dataset> {
+    check_schema_version(file)?;
+    let dataset = file.dataset(&symbol_dataset_path(file, symbol)?)?;
+    check_tick_datatype(&dataset)?;
+    Ok(dataset)
+}
+
+/// Attribute of fixed-point datasets holding the price multiplier of all their ticks. Datasets
+/// without it store `price_multiplier` in every tick.
+pub const PRICE_MULTIPLIER_ATTR: &str = "price_multiplier";
+
+/// Every tick of a dataset from `tick_dataset`.
+#[cfg(feature = "hdf5")]
+pub fn read_ticks(dataset: &hdf5::Dataset) -> hdf5::Result<Vec<Tick>> {
+    read_tick_rows(dataset, 0, dataset.size())
+}
+
+/// The ticks in rows `start..end` of a dataset from `tick_dataset`.
+#[cfg(feature = "hdf5")]
+pub fn read_tick_rows(dataset: &hdf5::Dataset, start: usize, end: usize) -> hdf5::Result<Vec<Tick>> {
+    if start >= end {
+        return Ok(Vec::new());
+    }
+    match read_attr::<u64>(dataset, PRICE_MULTIPLIER_ATTR)? {
+        Some(price_multiplier) => Ok(dataset.read_slice_1d::<FixedPointTick, _>(s![start..end])?
+            .iter()
+            .map(|t| t.to_tick(price_multiplier))
+            .collect()),
+        None => Ok(dataset.read_slice_1d::<Tick, _>(s![start..end])?.to_vec()),
+    }
+}
+
+/// The one price multiplier of ticks written to a fixed-point dataset, or 0 if there are none.
+#[cfg(feature = "hdf5")]
+fn fixed_point_price_multiplier(ticks: &[Tick]) -> hdf5::Result<u64> {
+    validate_price_multipliers(ticks).map_err(|e| hdf5::Error::from(format!(
+        "fixed-point ticks need a single nonzero price multiplier, but {} are zero and {} differ",
+        e.zero.len(), e.inconsistent.len())))?;
+    Ok(ticks.first().map_or(0, |t| t.price_multiplier))
+}
+
+/// Dataset attributes holding the earliest and latest tick timestamps, in nanoseconds since the
+/// epoch, so a symbol's active time window is known without reading its ticks. Empty datasets and
+/// those written before these existed don't have them.
+pub const FIRST_TIMESTAMP_ATTR: &str = "first_timestamp";
+pub const LAST_TIMESTAMP_ATTR: &str = "last_timestamp";
+
+/// The dataset's `FIRST_TIMESTAMP_ATTR` and `LAST_TIMESTAMP_ATTR`, if set.
+#[cfg(feature = "hdf5")]
+pub fn read_timestamp_range(dataset: &hdf5::Dataset) -> hdf5::Result<Option<(u64, u64)>> {
+    match (read_attr::<u64>(dataset, FIRST_TIMESTAMP_ATTR)?, read_attr::<u64>(dataset, LAST_TIMESTAMP_ATTR)?) {
+        (Some(first), Some(last)) => Ok(Some((first, last))),
+        _ => Ok(None),
+    }
+}
+
+/// Widens the dataset's timestamp range to cover `ticks`, which needn't be sorted.
+#[cfg(feature = "hdf5")]
+fn write_timestamp_range(dataset: &hdf5::Dataset, ticks: &[Tick]) -> hdf5::Result<()> {
+    let (first, last) = match (ticks.iter().map(|t| t.timestamp).min(), ticks.iter().map(|t| t.timestamp).max()) {
+        (Some(first), Some(last)) => (first, last),
+        _ => return Ok(()),
+    };
+    let (first, last) = match read_timestamp_range(dataset)? {
+        Some((existing_first, existing_last)) => (first.min(existing_first), last.max(existing_last)),
+        None => (first, last),
+    };
+    write_attr(dataset, FIRST_TIMESTAMP_ATTR, &first)?;
+    write_attr(dataset, LAST_TIMESTAMP_ATTR, &last)
+}
+
+/// Dataset attribute recording whether the ticks were sorted with `sort_ticks` before writing.
+/// Datasets created by `append_ticks` are marked unsorted, since ticks appended over a run are only
+/// in order within each append; a writer that knows better overwrites it.
+pub const SORTED_ATTR: &str = "sorted";
+
+/// Sorts by timestamp, breaking ties by sequence number. Packet order is usually already timestamp
+/// order, but that isn't guaranteed across channels or retransmits.
+pub fn sort_ticks(ticks: &mut [Tick]) {
+    ticks.sort_by_key(|t| (t.timestamp, t.message_sequence_number));
+}
+
+/// Number of ticks per chunk for datasets that grow via `append_ticks`.
+pub const APPEND_CHUNK_SIZE: usize = 4096;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Layout {
+    Contiguous,
+    Chunked,
+}
+
+/// How tick datasets are stored. Chunks are read whole, so smaller ones make reads of a few ticks
+/// cheaper while larger ones keep files smaller.
+#[derive(Clone, Debug, PartialEq)]
+pub struct WriteOptions {
+    /// Ticks per chunk, `APPEND_CHUNK_SIZE` by default.
+    pub chunk_size: usize,
+    /// Contiguous by default. Datasets written by `append_ticks` are always chunked, since only
+    /// chunked datasets can grow.
+    pub layout: Layout,
+    /// Stores the price multiplier once per dataset, in `PRICE_MULTIPLIER_ATTR`, instead of in
+    /// every tick. All ticks of a dataset must then share it. Off by default.
+    pub fixed_point: bool,
+    /// Flat by default. Recorded in new files by `write_versions`.
+    pub symbol_grouping: SymbolGrouping,
+    /// Stores a Fletcher32 checksum with every chunk of tick datasets, so that reads of a
+    /// corrupted chunk fail instead of returning bad ticks, and `verify_datasets` finds it.
+    /// Datasets are then chunked whatever the layout. Off by default.
+    pub checksum: bool,
+}
+
+impl Default for WriteOptions {
+    fn default() -> WriteOptions {
+        WriteOptions {
+            chunk_size: APPEND_CHUNK_SIZE,
+            layout: Layout::Contiguous,
+            fixed_point: false,
+            symbol_grouping: SymbolGrouping::Flat,
+            checksum: false,
+        }
+    }
+}
+
+impl WriteOptions {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.chunk_size == 0 {
+            return Err("chunk size must be nonzero".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// Where tick datasets are in a file. A root group with thousands of members is slow to open in
+/// some HDF5 tools, so they can be nested in groups named after the first letters of their
+/// symbols, e.g. `/A/AAPL` or `/AA/AAPL`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SymbolGrouping {
+    Flat,
+    FirstLetter,
+    FirstTwoLetters,
+}
+
+impl SymbolGrouping {
+    /// The number of leading symbol characters groups are named by, as stored in
+    /// `SYMBOL_GROUPING_ATTR`.
+    pub fn prefix_len(self) -> u32 {
+        match self {
+            SymbolGrouping::Flat => 0,
+            SymbolGrouping::FirstLetter => 1,
+            SymbolGrouping::FirstTwoLetters => 2,
+        }
+    }
+
+    pub fn from_prefix_len(prefix_len: u32) -> Option<SymbolGrouping> {
+        match prefix_len {
+            0 => Some(SymbolGrouping::Flat),
+            1 => Some(SymbolGrouping::FirstLetter),
+            2 => Some(SymbolGrouping::FirstTwoLetters),
+            _ => None,
+        }
+    }
+}
+
+/// Root attribute holding the file's `SymbolGrouping::prefix_len`. Files without it are flat.
+pub const SYMBOL_GROUPING_ATTR: &str = "symbol_grouping";
+
+#[cfg(feature = "hdf5")]
+pub fn read_symbol_grouping(file: &hdf5::File) -> hdf5::Result<SymbolGrouping> {
+    match read_attr::<u32>(file, SYMBOL_GROUPING_ATTR)? {
+        None => Ok(SymbolGrouping::Flat),
+        Some(prefix_len) => SymbolGrouping::from_prefix_len(prefix_len)
+            .ok_or_else(|| hdf5::Error::from(format!("unknown symbol grouping {}", prefix_len))),
+    }
+}
+
+/// The path of `symbol`'s tick dataset in `file`: the symbol itself in flat files, and otherwise
+/// the symbol in the group named after its first letters.
+#[cfg(feature = "hdf5")]
+pub fn symbol_dataset_path(file: &hdf5::File, symbol: &str) -> hdf5::Result<String> {
+    let prefix_len = read_symbol_grouping(file)?.prefix_len() as usize;
+    if prefix_len == 0 {
+        return Ok(symbol.to_string());
+    }
+    let prefix: String = symbol.trim_end().chars().take(prefix_len).collect();
+    if prefix.is_empty() {
+        return Err(hdf5::Error::from(format!("can't group the blank symbol '{}'", symbol)));
+    }
+    Ok(format!("{}/{}", prefix, symbol))
+}
+
+/// Like `symbol_dataset_path`, creating the symbol's group if it doesn't exist yet.
+#[cfg(feature = "hdf5")]
+fn create_symbol_dataset_path(file: &hdf5::File, symbol: &str) -> hdf5::Result<String> {
+    let path = symbol_dataset_path(file, symbol)?;
+    if let Some(slash) = path.find('/') {
+        if !file.link_exists(&path[..slash]) {
+            file.create_group(&path[..slash])?;
+        }
+    }
+    Ok(path)
+}
+
+/// Writes `ticks` as a new dataset for the symbol.
+#[cfg(feature = "hdf5")]
+pub fn write_ticks(file: &hdf5::File, symbol: &str, ticks: &[Tick], options: &WriteOptions)
+    -> hdf5::Result<hdf5::Dataset> {
+    options.validate()?;
+    let path = create_symbol_dataset_path(file, symbol)?;
+    // Chunks can't be larger than a fixed-size dataset.
+    let chunk_size = options.chunk_size.min(ticks.len()).max(1);
+    if options.fixed_point {
+        let price_multiplier = fixed_point_price_multiplier(ticks)?;
+        let mut builder = file.new_dataset::<FixedPointTick>();
+        if options.layout == Layout::Chunked || options.checksum {
+            builder.chunk(chunk_size).fletcher32(options.checksum);
+        }
+        let dataset = builder.create(&path, ticks.len())?;
+        dataset.write(&ticks.iter().map(FixedPointTick::from_tick).collect::<Vec<_>>())?;
+        write_attr(&dataset, PRICE_MULTIPLIER_ATTR, &price_multiplier)?;
+        write_timestamp_range(&dataset, ticks)?;
+        return Ok(dataset);
+    }
+    let mut builder = file.new_dataset::<Tick>();
+    if options.layout == Layout::Chunked || options.checksum {
+        builder.chunk(chunk_size).fletcher32(options.checksum);
+    }
+    let dataset = builder.create(&path, ticks.len())?;
+    dataset.write(ticks)?;
+    write_timestamp_range(&dataset, ticks)?;
+    Ok(dataset)
+}
+
+/// Appends `ticks` to the symbol's dataset, creating it as a resizable dataset if it doesn't exist
+/// yet. Used when ticks are flushed to disk periodically instead of once at the end of a run.
+#[cfg(feature = "hdf5")]
+pub fn append_ticks(file: &hdf5::File, symbol: &str, ticks: &[Tick]) -> hdf5::Result<()> {
+    append_ticks_with_options(file, symbol, ticks, &WriteOptions::default())
+}
+
+/// Like `append_ticks`, with the chunk size of new datasets taken from `options`.
+#[cfg(feature = "hdf5")]
+pub fn append_ticks_with_options(file: &hdf5::File, symbol: &str, ticks: &[Tick], options: &WriteOptions)
+    -> hdf5::Result<()> {
+    options.validate()?;
+    let path = create_symbol_dataset_path(file, symbol)?;
+    if options.fixed_point {
+        return append_fixed_point_ticks(file, symbol, &path, ticks, options);
+    }
+    let dataset = if file.link_exists(&path) {
+        file.dataset(&path)?
+    } else {
+        let dataset = file.new_dataset::<Tick>()
+            .chunk(options.chunk_size)
+            .fletcher32(options.checksum)
+            .resizable(true)
+            .create(&path, 0)?;
+        write_attr(&dataset, SORTED_ATTR, &false)?;
+        dataset
+    };
+    let start = dataset.size();
+    let end = start + ticks.len();
+    dataset.resize(end)?;
+    dataset.write_slice(ticks, s![start..end])?;
+    write_timestamp_range(&dataset, ticks)
+}
+
+/// Appended ticks must have the price multiplier of those already in the dataset.
+#[cfg(feature = "hdf5")]
+fn append_fixed_point_ticks(file: &hdf5::File, symbol: &str, path: &str, ticks: &[Tick], options: &WriteOptions)
+    -> hdf5::Result<()> {
+    let price_multiplier = fixed_point_price_multiplier(ticks)?;
+    let dataset = if file.link_exists(path) {
+        let dataset = file.dataset(path)?;
+        match read_attr::<u64>(&dataset, PRICE_MULTIPLIER_ATTR)? {
+            None => return Err(hdf5::Error::from(format!("{} isn't a fixed-point dataset", symbol))),
+            Some(existing) if existing != 0 && price_multiplier != 0 && existing != price_multiplier => {
+                return Err(hdf5::Error::from(format!(
+                    "{} has price multiplier {}, but the appended ticks have {}", symbol, existing, price_multiplier)));
+            },
+            Some(_) => {},
+        }
+        dataset
+    } else {
+        let dataset = file.new_dataset::<FixedPointTick>()
+            .chunk(options.chunk_size)
+            .fletcher32(options.checksum)
+            .resizable(true)
+            .create(path, 0)?;
+        write_attr(&dataset, SORTED_ATTR, &false)?;
+        dataset
+    };
+    if price_multiplier != 0 {
+        write_attr(&dataset, PRICE_MULTIPLIER_ATTR, &price_multiplier)?;
+    } else if read_attr::<u64>(&dataset, PRICE_MULTIPLIER_ATTR)?.is_none() {
+        write_attr(&dataset, PRICE_MULTIPLIER_ATTR, &0u64)?;
+    }
+    let start = dataset.size();
+    let end = start + ticks.len();
+    dataset.resize(end)?;
+    dataset.write_slice(&ticks.iter().map(FixedPointTick::from_tick).collect::<Vec<_>>(), s![start..end])?;
+    write_timestamp_range(&dataset, ticks)
+}
+
+/// Root dataset listing every symbol in the file along with its tick count, so readers can discover
+/// a file's contents in one read.
+pub const SYMBOL_INDEX_DATASET: &str = "_symbol_index";
+
+#[cfg(feature = "hdf5")]
+#[derive(hdf5::H5Type, Clone, PartialEq, Debug)]
+#[repr(C)]
+pub struct SymbolIndexEntry {
+    // IEX symbols are at most 8 characters.
+    pub symbol: hdf5::types::FixedAscii<[u8; 8]>,
+    pub tick_count: u64,
+}
+
+/// Writes the symbol index for the given symbols, sorted by symbol. Counts are taken from the
+/// datasets themselves so the index can't disagree with them.
+#[cfg(feature = "hdf5")]
+pub fn write_symbol_index<S: AsRef<str>>(file: &hdf5::File, symbols: &[S]) -> hdf5::Result<()> {
+    let mut symbols: Vec<&str> = symbols.iter().map(AsRef::as_ref).collect();
+    symbols.sort();
+    let mut entries = Vec::with_capacity(symbols.len());
+    for symbol in symbols {
+        entries.push(SymbolIndexEntry {
+            symbol: fixed_symbol(symbol)?,
+            tick_count: file.dataset(&symbol_dataset_path(file, symbol)?)?.size() as u64,
+        });
+    }
+    let dataset = file.new_dataset::<SymbolIndexEntry>().create(SYMBOL_INDEX_DATASET, entries.len())?;
+    dataset.write(&entries)
+}
+
+#[cfg(feature = "hdf5")]
+fn fixed_symbol(symbol: &str) -> hdf5::Result<hdf5::types::FixedAscii<[u8; 8]>> {
+    hdf5::types::FixedAscii::from_ascii(symbol.as_bytes())
+        .map_err(|e| hdf5::Error::from(format!("Invalid symbol '{}': {}", symbol, e)))
+}
+
+/// Root dataset listing the symbols of the day's security directory that had no trades, for
+/// building a universe of symbols. They have no tick datasets of trades, though they may have
+/// price level updates.
+pub const UNTRADED_SYMBOLS_DATASET: &str = "_untraded_symbols";
+
+/// Writes `symbols` as the untraded symbols, sorted.
+#[cfg(feature = "hdf5")]
+pub fn write_untraded_symbols<S: AsRef<str>>(file: &hdf5::File, symbols: &[S]) -> hdf5::Result<()> {
+    let mut symbols: Vec<&str> = symbols.iter().map(AsRef::as_ref).collect();
+    symbols.sort();
+    let symbols = symbols.into_iter().map(fixed_symbol).collect::<hdf5::Result<Vec<_>>>()?;
+    let dataset = file.new_dataset::<hdf5::types::FixedAscii<[u8; 8]>>()
+        .create(UNTRADED_SYMBOLS_DATASET, symbols.len())?;
+    dataset.write(&symbols)
+}
+
+/// The untraded symbols, or none for files written without them.
+#[cfg(feature = "hdf5")]
+pub fn read_untraded_symbols(file: &hdf5::File) -> hdf5::Result<Vec<String>> {
+    if !file.link_exists(UNTRADED_SYMBOLS_DATASET) {
+        return Ok(Vec::new());
+    }
+    let symbols = file.dataset(UNTRADED_SYMBOLS_DATASET)?.read_raw::<hdf5::types::FixedAscii<[u8; 8]>>()?;
+    Ok(symbols.iter().map(|symbol| symbol.as_str().to_string()).collect())
+}
+
+/// Root dataset holding every symbol's ticks as `TickWithSymbol` rows, written by
+/// `write_combined_ticks` in place of a dataset per symbol.
+pub const COMBINED_TICKS_DATASET: &str = "_ticks";
+
+/// A `Tick` with its symbol, padded like dataset names, as a row of `COMBINED_TICKS_DATASET` for
+/// loaders that flatten a day's ticks into columns.
+#[cfg(feature = "hdf5")]
+#[derive(hdf5::H5Type, Clone, PartialEq, Debug)]
+#[repr(C)]
+pub struct TickWithSymbol {
+    pub symbol: hdf5::types::FixedAscii<[u8; 8]>,
+    pub message_type: u8,
+    pub message_subtype: u8,
+    pub timestamp: u64,
+    pub size: u32,
+    pub price: u64,
+    pub price_multiplier: u64,
+    pub packet_number: u64,
+    pub message_sequence_number: u64,
+    pub capture_timestamp: u64,
+}
+
+#[cfg(feature = "hdf5")]
+impl TickWithSymbol {
+    /// Fails for symbols longer than 8 characters or that aren't ASCII.
+    pub fn new(symbol: &str, tick: &Tick) -> hdf5::Result<TickWithSymbol> {
+        Ok(TickWithSymbol {
+            symbol: fixed_symbol(symbol)?,
+            message_type: tick.message_type,
+            message_subtype: tick.message_subtype,
+            timestamp: tick.timestamp,
+            size: tick.size,
+            price: tick.price,
+            price_multiplier: tick.price_multiplier,
+            packet_number: tick.packet_number,
+            message_sequence_number: tick.message_sequence_number,
+            capture_timestamp: tick.capture_timestamp,
+        })
+    }
+
+    pub fn symbol(&self) -> &str {
+        self.symbol.as_str()
+    }
+
+    pub fn to_tick(&self) -> Tick {
+        Tick {
+            message_type: self.message_type,
+            message_subtype: self.message_subtype,
+            timestamp: self.timestamp,
+            size: self.size,
+            price: self.price,
+            price_multiplier: self.price_multiplier,
+            packet_number: self.packet_number,
+            message_sequence_number: self.message_sequence_number,
+            capture_timestamp: self.capture_timestamp,
+        }
+    }
+}
+
+/// Writes `ticks` as the file's `COMBINED_TICKS_DATASET`, in their order, laid out like
+/// `write_ticks`. Fixed-point prices aren't supported, as rows of many symbols can have different
+/// price multipliers.
+#[cfg(feature = "hdf5")]
+pub fn write_combined_ticks(file: &hdf5::File, ticks: &[TickWithSymbol], options: &WriteOptions)
+    -> hdf5::Result<hdf5::Dataset> {
+    options.validate()?;
+    if options.fixed_point {
+        return Err(hdf5::Error::from("combined ticks can't be written with fixed-point prices"));
+    }
+    let mut builder = file.new_dataset::<TickWithSymbol>();
+    if options.layout == Layout::Chunked || options.checksum {
+        builder.chunk(options.chunk_size.min(ticks.len()).max(1)).fletcher32(options.checksum);
+    }
+    let dataset = builder.create(COMBINED_TICKS_DATASET, ticks.len())?;
+    dataset.write(ticks)?;
+    Ok(dataset)
+}
+
+/// The rows of the file's `COMBINED_TICKS_DATASET`, or none for files with a dataset per symbol.
+#[cfg(feature = "hdf5")]
+pub fn read_combined_ticks(file: &hdf5::File) -> hdf5::Result<Vec<TickWithSymbol>> {
+    if !file.link_exists(COMBINED_TICKS_DATASET) {
+        return Ok(Vec::new());
+    }
+    file.dataset(COMBINED_TICKS_DATASET)?.read_raw::<TickWithSymbol>()
+}
+
+/// The rows of the file's `COMBINED_TICKS_DATASET` by symbol, padded like dataset names, each in
+/// row order. Files with a dataset per symbol have none.
+#[cfg(feature = "hdf5")]
+pub fn read_combined_symbol_ticks(file: &hdf5::File) -> hdf5::Result<BTreeMap<String, Vec<Tick>>> {
+    let mut ticks: BTreeMap<String, Vec<Tick>> = BTreeMap::new();
+    for row in read_combined_ticks(file)? {
+        ticks.entry(row.symbol().to_string()).or_default().push(row.to_tick());
+    }
+    Ok(ticks)
+}
+
+/// Symbols of the tick datasets in `file`, in name order, whether they're in the root or in
+/// groups of its `SymbolGrouping`. Unlike `load_symbol_index`, this works for files written
+/// without an index.
+#[cfg(feature = "hdf5")]
+pub fn symbols(file: &hdf5::File) -> hdf5::Result<Vec<String>> {
+    let members: Vec<String> = link_names(file, ".")?.into_iter()
+        .filter(|name| name != SYMBOL_INDEX_DATASET && name != TIME_INDEX_GROUP && name != RAW_MESSAGES_DATASET
+                && name != OFFICIAL_PRICES_GROUP && name != BARS_GROUP && name != AUCTIONS_GROUP
+                && name != UNTRADED_SYMBOLS_DATASET && name != COMBINED_TICKS_DATASET)
+        .collect();
+    if read_symbol_grouping(file)? == SymbolGrouping::Flat {
+        return Ok(members);
+    }
+    let mut symbols = Vec::new();
+    for group in members {
+        symbols.extend(link_names(file, &group)?);
+    }
+    symbols.sort();
+    Ok(symbols)
+}
+
+/// Names of the datasets in one of the root groups holding a dataset per symbol, such as
+/// `OFFICIAL_PRICES_GROUP`, in name order. Files without the group have none.
+#[cfg(feature = "hdf5")]
+pub fn group_symbols(file: &hdf5::File, group: &str) -> hdf5::Result<Vec<String>> {
+    if !file.link_exists(group) {
+        return Ok(Vec::new());
+    }
+    link_names(file, group)
+}
+
+#[cfg(feature = "hdf5")]
+fn link_names(file: &hdf5::File, group: &str) -> hdf5::Result<Vec<String>> {
+    let group = ffi::CString::new(group).map_err(|_| hdf5::Error::from(format!("invalid group name '{}'", group)))?;
+    let mut names = Vec::new();
+    unsafe {
+        let mut info = h5g::H5G_info_t::default();
+        if h5g::H5Gget_info_by_name(file.id(), group.as_ptr(), &mut info, H5P_DEFAULT) < 0 {
+            return Err(last_h5_error("H5Gget_info_by_name"));
+        }
+        for i in 0..info.nlinks {
+            let len = h5l::H5Lget_name_by_idx(file.id(), group.as_ptr(), h5::H5_INDEX_NAME, h5::H5_ITER_INC,
+                                              i, std::ptr::null_mut(), 0, H5P_DEFAULT);
+            if len < 0 {
+                return Err(last_h5_error("H5Lget_name_by_idx"));
+            }
+            let mut name = vec![0u8; len as usize + 1];
+            h5l::H5Lget_name_by_idx(file.id(), group.as_ptr(), h5::H5_INDEX_NAME, h5::H5_ITER_INC,
+                                    i, name.as_mut_ptr() as *mut _, name.len(), H5P_DEFAULT);
+            name.truncate(len as usize);
+            names.push(String::from_utf8(name).map_err(|_| hdf5::Error::from("dataset name isn't valid UTF-8"))?);
+        }
+    }
+    Ok(names)
+}
+
+/// Returns `(symbol, tick_count)` pairs sorted by symbol.
+#[cfg(feature = "hdf5")]
+pub fn load_symbol_index(file: &str) -> hdf5::Result<Vec<(String, u64)>> {
+    let file = hdf5::File::open(file, "r")?;
+    let entries = file.dataset(SYMBOL_INDEX_DATASET)?.read_raw::<SymbolIndexEntry>()?;
+    Ok(entries.iter().map(|e| (e.symbol.as_str().to_string(), e.tick_count)).collect())
+}
+
+/// Root group holding the official opening and closing prices of each symbol, as ticks of type
+/// `OFFICIAL_PRICE`, in a dataset named after the symbol.
+pub const OFFICIAL_PRICES_GROUP: &str = "_official_prices";
+
+#[cfg(feature = "hdf5")]
+pub fn write_official_prices(file: &hdf5::File, symbol: &str, ticks: &[Tick]) -> hdf5::Result<()> {
+    let group = if file.link_exists(OFFICIAL_PRICES_GROUP) {
+        file.group(OFFICIAL_PRICES_GROUP)?
+    } else {
+        file.create_group(OFFICIAL_PRICES_GROUP)?
+    };
+    group.new_dataset::<Tick>().create(symbol, ticks.len())?.write(ticks)
+}
+
+/// Appends to the symbol's official prices, creating them as a resizable dataset if there are none
+/// yet, for output written a part at a time.
+#[cfg(feature = "hdf5")]
+pub fn append_official_prices(file: &hdf5::File, symbol: &str, ticks: &[Tick]) -> hdf5::Result<()> {
+    append_to_group(file, OFFICIAL_PRICES_GROUP, symbol, ticks)
+}
+
+/// Appends `rows` to the dataset `name` of the root group `group`, creating either if need be.
+#[cfg(feature = "hdf5")]
+fn append_to_group<T: hdf5::H5Type>(file: &hdf5::File, group: &str, name: &str, rows: &[T]) -> hdf5::Result<()> {
+    let group = if file.link_exists(group) {
+        file.group(group)?
+    } else {
+        file.create_group(group)?
+    };
+    let dataset = if group.link_exists(name) {
+        group.dataset(name)?
+    } else {
+        group.new_dataset::<T>().chunk(APPEND_CHUNK_SIZE).resizable(true).create(name, 0)?
+    };
+    let start = dataset.size();
+    let end = start + rows.len();
+    dataset.resize(end)?;
+    dataset.write_slice(rows, s![start..end])
+}
+
+/// The symbol's official prices, or none if the file has none for it.
+#[cfg(feature = "hdf5")]
+pub fn read_official_prices(file: &hdf5::File, symbol: &str) -> hdf5::Result<Vec<Tick>> {
+    if !file.link_exists(OFFICIAL_PRICES_GROUP) || !file.group(OFFICIAL_PRICES_GROUP)?.link_exists(symbol) {
+        return Ok(Vec::new());
+    }
+    read_ticks(&file.group(OFFICIAL_PRICES_GROUP)?.dataset(symbol)?)
+}
+
+/// Root group holding the auction information messages of each symbol, in a dataset named after
+/// the symbol.
+pub const AUCTIONS_GROUP: &str = "_auctions";
+
+/// An auction information message, with its prices in units of `price_multiplier`.
+#[cfg_attr(feature = "hdf5", derive(hdf5::H5Type))]
+#[derive(Clone, Debug, PartialEq)]
+#[repr(C)]
+pub struct Auction {
+    // unit is nanoseconds
+    pub timestamp: u64,
+    // 'O'pening, 'C'losing, 'I'PO, 'H'alt or 'V'olatility
+    pub auction_type: u8,
+    pub paired_shares: u32,
+    pub reference_price: u64,
+    pub indicative_clearing_price: u64,
+    pub imbalance_shares: u32,
+    // 'B'uy, 'S'ell or 'N'o imbalance
+    pub imbalance_side: u8,
+    pub extension_number: u8,
+    // unit is nanoseconds since the epoch like timestamp, although IEX sends whole seconds
+    pub scheduled_auction_time: u64,
+    // set when scheduled_auction_time isn't on the trade date of the capture
+    pub scheduled_off_trade_date: bool,
+    pub auction_book_clearing_price: u64,
+    pub collar_reference_price: u64,
+    pub lower_auction_collar: u64,
+    pub upper_auction_collar: u64,
+    pub price_multiplier: u64,
+    pub packet_number: u64,
+    pub message_sequence_number: u64,
+}
+
+#[cfg(feature = "hdf5")]
+pub fn write_auctions(file: &hdf5::File, symbol: &str, auctions: &[Auction]) -> hdf5::Result<()> {
+    let group = if file.link_exists(AUCTIONS_GROUP) {
+        file.group(AUCTIONS_GROUP)?
+    } else {
+        file.create_group(AUCTIONS_GROUP)?
+    };
+    group.new_dataset::<Auction>().create(symbol, auctions.len())?.write(auctions)
+}
+
+/// Like `append_official_prices`, for auction information messages.
+#[cfg(feature = "hdf5")]
+pub fn append_auctions(file: &hdf5::File, symbol: &str, auctions: &[Auction]) -> hdf5::Result<()> {
+    append_to_group(file, AUCTIONS_GROUP, symbol, auctions)
+}
+
+/// The symbol's auction information messages, or none if the file has none for it.
+#[cfg(feature = "hdf5")]
+pub fn read_auctions(file: &hdf5::File, symbol: &str) -> hdf5::Result<Vec<Auction>> {
+    if !file.link_exists(AUCTIONS_GROUP) || !file.group(AUCTIONS_GROUP)?.link_exists(symbol) {
+        return Ok(Vec::new());
+    }
+    file.group(AUCTIONS_GROUP)?.dataset(symbol)?.read_raw::<Auction>()
+}
+
+/// Root dataset holding the undecoded bytes of messages, so they can be decoded again offline.
+pub const RAW_MESSAGES_DATASET: &str = "_raw_messages";
+
+/// A message's bytes as they were in the capture, without its length prefix.
+#[cfg(feature = "hdf5")]
+#[derive(hdf5::H5Type, Clone, PartialEq, Debug)]
+#[repr(C)]
+pub struct RawMessage {
+    /// Number of the packet the message arrived in, from 0 in capture order.
+    pub packet_number: u64,
+    pub message_sequence_number: u64,
+    pub bytes: hdf5::types::VarLenArray<u8>,
+}
+
+#[cfg(feature = "hdf5")]
+impl RawMessage {
+    pub fn new(packet_number: u64, message_sequence_number: u64, bytes: &[u8]) -> RawMessage {
+        RawMessage {
+            packet_number,
+            message_sequence_number,
+            bytes: hdf5::types::VarLenArray::from_slice(bytes),
+        }
+    }
+}
+
+#[cfg(feature = "hdf5")]
+pub fn write_raw_messages(file: &hdf5::File, messages: &[RawMessage]) -> hdf5::Result<()> {
+    file.new_dataset::<RawMessage>().create(RAW_MESSAGES_DATASET, messages.len())?.write(messages)
+}
+
+/// The file's raw messages, or none if it was written without them.
+#[cfg(feature = "hdf5")]
+pub fn read_raw_messages(file: &hdf5::File) -> hdf5::Result<Vec<RawMessage>> {
+    if !file.link_exists(RAW_MESSAGES_DATASET) {
+        return Ok(Vec::new());
+    }
+    file.dataset(RAW_MESSAGES_DATASET)?.read_raw::<RawMessage>()
+}
+
+/// Root group holding the bars of each symbol, in a dataset named after the symbol with the bar
+/// interval in its `BAR_INTERVAL_ATTR`.
+pub const BARS_GROUP: &str = "_bars";
+
+/// Length of the bars of a dataset in `BARS_GROUP`, in nanoseconds.
+pub const BAR_INTERVAL_ATTR: &str = "interval_ns";
+
+/// Open, high, low and close prices, volume and VWAP of the trades in an interval.
+#[cfg_attr(feature = "hdf5", derive(hdf5::H5Type))]
+#[derive(Clone, Debug, PartialEq)]
+#[repr(C)]
+pub struct Bar {
+    // unit is nanoseconds; when the interval starts
+    pub timestamp: u64,
+    pub open: u64,
+    pub high: u64,
+    pub low: u64,
+    pub close: u64,
+    pub price_multiplier: u64,
+    pub volume: u64,
+    // volume-weighted average price, already divided by price_multiplier
+    pub vwap: f64,
+    pub trade_count: u64,
+}
+
+/// Aggregates the `TRADE_REPORT` ticks of a symbol into a bar per interval, in time order.
+/// `bucket_start` maps a timestamp to the start of its interval. Intervals without trades have no
+/// bar, rather than one with no volume repeating the previous close.
+pub fn bars<F: Fn(u64) -> u64>(ticks: &[Tick], bucket_start: F) -> Vec<Bar> {
+    let mut trades: Vec<Tick> = ticks.iter().filter(|t| t.message_type == TRADE_REPORT).cloned().collect();
+    sort_ticks(&mut trades);
+    let mut bars: Vec<Bar> = Vec::new();
+    let mut accumulators: Vec<Accumulator> = Vec::new();
+    for trade in &trades {
+        let timestamp = bucket_start(trade.timestamp);
+        match bars.last_mut() {
+            Some(bar) if bar.timestamp == timestamp => {
+                bar.high = bar.high.max(trade.price);
+                bar.low = bar.low.min(trade.price);
+                bar.close = trade.price;
+                bar.volume += u64::from(trade.size);
+                bar.trade_count += 1;
+            },
+            _ => {
+                bars.push(Bar {
+                    timestamp,
+                    open: trade.price,
+                    high: trade.price,
+                    low: trade.price,
+                    close: trade.price,
+                    price_multiplier: trade.price_multiplier,
+                    volume: u64::from(trade.size),
+                    vwap: 0.0,
+                    trade_count: 1,
+                });
+                accumulators.push(Accumulator::default());
+            },
+        }
+        accumulators.last_mut().expect("an accumulator per bar").update(trade);
+    }
+    for (bar, accumulator) in bars.iter_mut().zip(accumulators) {
+        bar.vwap = accumulator.snapshot().vwap.unwrap_or(f64::NAN);
+    }
+    bars
+}
+
+#[cfg(feature = "hdf5")]
+pub fn write_bars(file: &hdf5::File, symbol: &str, bars: &[Bar], interval_ns: u64) -> hdf5::Result<()> {
+    let group = if file.link_exists(BARS_GROUP) {
+        file.group(BARS_GROUP)?
+    } else {
+        file.create_group(BARS_GROUP)?
+    };
+    let dataset = group.new_dataset::<Bar>().create(symbol, bars.len())?;
+    dataset.write(bars)?;
+    write_attr(&dataset, BAR_INTERVAL_ATTR, &interval_ns)
+}
+
+/// The symbol's bars and their interval in nanoseconds, or `None` if the file has no bars for it.
+#[cfg(feature = "hdf5")]
+pub fn read_bars(file: &hdf5::File, symbol: &str) -> hdf5::Result<Option<(Vec<Bar>, u64)>> {
+    if !file.link_exists(BARS_GROUP) || !file.group(BARS_GROUP)?.link_exists(symbol) {
+        return Ok(None);
+    }
+    let dataset = file.group(BARS_GROUP)?.dataset(symbol)?;
+    let interval_ns = read_attr::<u64>(&dataset, BAR_INTERVAL_ATTR)?
+        .ok_or_else(|| hdf5::Error::from("bars without an interval"))?;
+    Ok(Some((dataset.read_raw::<Bar>()?, interval_ns)))
+}
+
+/// Root group holding a time index dataset per symbol, named after the symbol, for symbols whose
+/// ticks were sorted before writing.
+pub const TIME_INDEX_GROUP: &str = "_time_index";
+
+/// Width of the buckets in time indexes written by `write_time_index`: one minute.
+pub const TIME_INDEX_BUCKET_NS: u64 = 60_000_000_000;
+
+/// The first tick at or after `bucket_start`, which is a multiple of the bucket width. Buckets
+/// without ticks have no entry.
+#[cfg_attr(feature = "hdf5", derive(hdf5::H5Type))]
+#[derive(Clone, PartialEq, Debug)]
+#[repr(C)]
+pub struct TimeIndexEntry {
+    pub bucket_start: u64,
+    pub offset: u64,
+}
+
+/// Index of `ticks`, which must be sorted by timestamp, in buckets of `bucket_ns`.
+pub fn time_index(ticks: &[Tick], bucket_ns: u64) -> Vec<TimeIndexEntry> {
+    let mut index: Vec<TimeIndexEntry> = Vec::new();
+    for (i, tick) in ticks.iter().enumerate() {
+        let bucket_start = tick.timestamp - tick.timestamp % bucket_ns;
+        if index.last().map(|e| e.bucket_start) != Some(bucket_start) {
+            index.push(TimeIndexEntry { bucket_start, offset: i as u64 });
+        }
+    }
+    index
+}
+
+/// Rows of a dataset of `tick_count` sorted ticks that hold every tick in `start..end`. The rows
+/// can also hold ticks just outside of it, from the buckets `start` and `end` fall in.
+pub fn time_index_rows(index: &[TimeIndexEntry], tick_count: usize, start: u64, end: u64) -> (usize, usize) {
+    let lo = match index.iter().rposition(|e| e.bucket_start <= start) {
+        Some(i) => index[i].offset as usize,
+        None => 0,
+    };
+    let hi = match index.iter().position(|e| e.bucket_start >= end) {
+        Some(i) => index[i].offset as usize,
+        None => tick_count,
+    };
+    (lo, hi.max(lo))
+}
+
+/// Writes the time index of a symbol's ticks, which must be sorted by timestamp.
+#[cfg(feature = "hdf5")]
+pub fn write_time_index(file: &hdf5::File, symbol: &str, ticks: &[Tick]) -> hdf5::Result<()> {
+    let group = if file.link_exists(TIME_INDEX_GROUP) {
+        file.group(TIME_INDEX_GROUP)?
+    } else {
+        file.create_group(TIME_INDEX_GROUP)?
+    };
+    let index = time_index(ticks, TIME_INDEX_BUCKET_NS);
+    group.new_dataset::<TimeIndexEntry>().create(symbol, index.len())?.write(&index)
+}
+
+/// The symbol's ticks with timestamps in `start..end`. Only the rows covered by the symbol's time
+/// index are read if it has one; otherwise every tick is.
+#[cfg(feature = "hdf5")]
+pub fn load_ticks_in_range(file: &str, symbol: &str, start: u64, end: u64) -> hdf5::Result<Vec<Tick>> {
+    ticks_in_range(&hdf5::File::open(file, "r")?, symbol, start, end)
+}
+
+#[cfg(feature = "hdf5")]
+fn ticks_in_range(file: &hdf5::File, symbol: &str, start: u64, end: u64) -> hdf5::Result<Vec<Tick>> {
+    let dataset = tick_dataset(file, symbol)?;
+    let ticks = if file.link_exists(TIME_INDEX_GROUP) && file.group(TIME_INDEX_GROUP)?.link_exists(symbol) {
+        let index = file.group(TIME_INDEX_GROUP)?.dataset(symbol)?.read_raw::<TimeIndexEntry>()?;
+        let (lo, hi) = time_index_rows(&index, dataset.size(), start, end);
+        read_tick_rows(&dataset, lo, hi)?
+    } else {
+        read_ticks(&dataset)?
+    };
+    Ok(ticks.into_iter().filter(|t| t.timestamp >= start && t.timestamp < end).collect())
+}
+
+/// A file opened once to answer many queries, as a server does, where the free functions taking a
+/// path open it again for each. The file stays open until the reader is dropped. The HDF5 library
+/// runs one call at a time behind a global lock, so a reader can be shared between threads, but
+/// their queries take turns.
+#[cfg(feature = "hdf5")]
+pub struct TickReader {
+    file: hdf5::File,
+}
+
+#[cfg(feature = "hdf5")]
+impl TickReader {
+    pub fn open(path: &str) -> Result<TickReader, LoadTicksError> {
+        Ok(TickReader { file: hdf5::File::open(path, "r")? })
+    }
+
+    /// Reads the whole file into memory with HDF5's core driver, so queries don't go back to disk.
+    /// Ticks are still copied out of it by each query, as the bindings have no zero-copy reads.
+    pub fn open_in_memory(path: &str) -> Result<TickReader, LoadTicksError> {
+        Ok(TickReader { file: hdf5::File::with_options().driver("core").mode("r").open(path)? })
+    }
+
+    /// The file's symbols, like `symbols`.
+    pub fn symbols(&self) -> Result<Vec<String>, LoadTicksError> {
+        Ok(symbols(&self.file)?)
+    }
+
+    /// Like `load_ticks_validated`.
+    pub fn load(&self, symbol: &str) -> Result<Vec<Tick>, LoadTicksError> {
+        validated_ticks(&self.file, symbol)
+    }
+
+    /// Like `load_ticks_in_range`.
+    pub fn load_range(&self, symbol: &str, start: u64, end: u64) -> Result<Vec<Tick>, LoadTicksError> {
+        Ok(ticks_in_range(&self.file, symbol, start, end)?)
+    }
+
+    /// Like `symbol_summary`.
+    pub fn summary(&self, symbol: &str) -> Result<SymbolSummary, LoadTicksError> {
+        symbol_summary_in(&self.file, symbol)
+    }
+}
+
+/// Ticks read ahead of `TimeOrderedTicks` per symbol.
+#[cfg(feature = "hdf5")]
+const TIME_ORDERED_BUFFER_TICKS: usize = APPEND_CHUNK_SIZE;
+
+/// The ticks of every symbol in a file, with their symbols, in timestamp order. See
+/// `merged_time_ordered_iter`.
+#[cfg(feature = "hdf5")]
+pub struct TimeOrderedTicks {
+    cursors: Vec<SymbolCursor>,
+    // The next tick of each symbol with any left, by timestamp, then sequence number, then the
+    // symbol's position in `cursors`.
+    heads: BinaryHeap<Reverse<(u64, u64, usize)>>,
+    buffer_ticks: usize,
+    failed: bool,
+}
+
+#[cfg(feature = "hdf5")]
+struct SymbolCursor {
+    symbol: String,
+    dataset: hdf5::Dataset,
+    buffer: VecDeque<Tick>,
+    next_row: usize,
+}
+
+#[cfg(feature = "hdf5")]
+impl SymbolCursor {
+    /// Reads the next rows once the buffered ones run out.
+    fn refill(&mut self, buffer_ticks: usize) -> hdf5::Result<()> {
+        if self.buffer.is_empty() {
+            let end = (self.next_row + buffer_ticks).min(self.dataset.size());
+            self.buffer.extend(read_tick_rows(&self.dataset, self.next_row, end)?);
+            self.next_row = end;
+        }
+        Ok(())
+    }
+
+    fn head(&self, position: usize) -> Option<Reverse<(u64, u64, usize)>> {
+        self.buffer.front().map(|tick| Reverse((tick.timestamp, tick.message_sequence_number, position)))
+    }
+}
+
+/// Merges the ticks of all symbols in `file` into one stream in timestamp order, for replaying a
+/// whole day across symbols. Each symbol's dataset must already be sorted, as with `--sort`. Only a
+/// chunk of each symbol's ticks is held at a time. Ticks of the same timestamp come in sequence
+/// number order, and then by symbol.
+#[cfg(feature = "hdf5")]
+pub fn merged_time_ordered_iter(file: &hdf5::File) -> hdf5::Result<TimeOrderedTicks> {
+    time_ordered_ticks(file, TIME_ORDERED_BUFFER_TICKS)
+}
+
+#[cfg(feature = "hdf5")]
+fn time_ordered_ticks(file: &hdf5::File, buffer_ticks: usize) -> hdf5::Result<TimeOrderedTicks> {
+    let mut cursors = Vec::new();
+    let mut heads = BinaryHeap::new();
+    for symbol in symbols(file)? {
+        let mut cursor = SymbolCursor {
+            dataset: tick_dataset(file, &symbol)?,
+            symbol,
+            buffer: VecDeque::new(),
+            next_row: 0,
+        };
+        cursor.refill(buffer_ticks)?;
+        heads.extend(cursor.head(cursors.len()));
+        cursors.push(cursor);
+    }
+    Ok(TimeOrderedTicks { cursors, heads, buffer_ticks, failed: false })
+}
+
+#[cfg(feature = "hdf5")]
+impl Iterator for TimeOrderedTicks {
+    /// A read error ends the stream.
+    type Item = hdf5::Result<(String, Tick)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+        let Reverse((_, _, position)) = self.heads.pop()?;
+        let cursor = &mut self.cursors[position];
+        let tick = cursor.buffer.pop_front().expect("symbols in the heap have a buffered tick");
+        if let Err(e) = cursor.refill(self.buffer_ticks) {
+            self.failed = true;
+            return Some(Err(e));
+        }
+        self.heads.extend(cursor.head(position));
+        Some(Ok((cursor.symbol.clone(), tick)))
+    }
+}
+
+/// What `verify_datasets` found reading every dataset of a file.
+#[derive(Debug, Default, PartialEq)]
+pub struct VerifyReport {
+    pub datasets: usize,
+    /// Datasets with Fletcher32 checksums, whose data is checked. Reading others only catches
+    /// corruption of the file's structure.
+    pub checksummed: usize,
+    /// The path of every dataset that couldn't be read, with the error.
+    pub corrupt: Vec<(String, String)>,
+}
+
+/// Reads every dataset of `file`, in the root and in groups, for an archive's integrity check.
+/// Checksummed datasets, written with `WriteOptions::checksum`, fail to read if any of their
+/// chunks was corrupted. Fails only if the file's groups can't be listed.
+#[cfg(feature = "hdf5")]
+pub fn verify_datasets(file: &hdf5::File) -> hdf5::Result<VerifyReport> {
+    let mut report = VerifyReport::default();
+    let mut groups = vec![".".to_string()];
+    while let Some(group) = groups.pop() {
+        for name in link_names(file, &group)? {
+            let path = if group == "." { name } else { format!("{}/{}", group, name) };
+            let dataset = match file.dataset(&path) {
+                Ok(dataset) => dataset,
+                Err(_) if file.group(&path).is_ok() => {
+                    groups.push(path);
+                    continue;
+                },
+                Err(e) => {
+                    report.corrupt.push((path, e.to_string()));
+                    continue;
+                },
+            };
+            report.datasets += 1;
+            if dataset.filters().get_fletcher32() {
+                report.checksummed += 1;
+            }
+            if let Err(e) = read_dataset_bytes(&dataset) {
+                report.corrupt.push((path, e.to_string()));
+            }
+        }
+    }
+    report.corrupt.sort();
+    Ok(report)
+}
+
+/// Reads all of a dataset of a fixed-size datatype as stored, without converting it.
+#[cfg(feature = "hdf5")]
+fn read_dataset_bytes(dataset: &hdf5::Dataset) -> hdf5::Result<Vec<u8>> {
+    unsafe {
+        let datatype = h5d::H5Dget_type(dataset.id());
+        if datatype < 0 {
+            return Err(last_h5_error("H5Dget_type"));
+        }
+        let mut bytes = vec![0u8; dataset.size() * h5t::H5Tget_size(datatype)];
+        let status = h5d::H5Dread(dataset.id(), datatype, h5s::H5S_ALL, h5s::H5S_ALL, H5P_DEFAULT,
+                                  bytes.as_mut_ptr() as *mut _);
+        h5t::H5Tclose(datatype);
+        if status < 0 {
+            return Err(last_h5_error("H5Dread"));
+        }
+        Ok(bytes)
+    }
+}
+
+/// Indices of ticks whose `price_multiplier` is zero, or differs from the dataset's first nonzero one.
+#[derive(Debug, PartialEq)]
+pub struct PriceMultiplierError {
+    pub zero: Vec<usize>,
+    pub inconsistent: Vec<usize>,
+}
+
+/// Prices can't be decoded without a nonzero multiplier, and a dataset is always written with one.
+pub fn validate_price_multipliers(ticks: &[Tick]) -> Result<(), PriceMultiplierError> {
+    let expected = ticks.iter().map(|t| t.price_multiplier).find(|m| *m != 0);
+    let mut error = PriceMultiplierError { zero: Vec::new(), inconsistent: Vec::new() };
+    for (i, tick) in ticks.iter().enumerate() {
+        if tick.price_multiplier == 0 {
+            error.zero.push(i);
+        } else if Some(tick.price_multiplier) != expected {
+            error.inconsistent.push(i);
+        }
+    }
+    if error.zero.is_empty() && error.inconsistent.is_empty() {
+        Ok(())
+    } else {
+        Err(error)
+    }
+}
+
+#[cfg(feature = "hdf5")]
+#[derive(Debug)]
+pub enum LoadTicksError {
+    Hdf5(hdf5::Error),
+    InvalidPriceMultiplier(PriceMultiplierError),
+}
+
+#[cfg(feature = "hdf5")]
+impl From<hdf5::Error> for LoadTicksError {
+    fn from(e: hdf5::Error) -> LoadTicksError {
+        LoadTicksError::Hdf5(e)
+    }
+}
+
+/// Like `load_ticks_from_file`, but returns errors instead of panicking, and checks the ticks'
+/// price multipliers with `validate_price_multipliers`.
+#[cfg(feature = "hdf5")]
+pub fn load_ticks_validated(symbol: &str, file: &str) -> Result<Vec<Tick>, LoadTicksError> {
+    validated_ticks(&hdf5::File::open(file, "r")?, symbol)
+}
+
+#[cfg(feature = "hdf5")]
+fn validated_ticks(file: &hdf5::File, symbol: &str) -> Result<Vec<Tick>, LoadTicksError> {
+    let ticks = read_ticks(&tick_dataset(file, symbol)?)?;
+    validate_price_multipliers(&ticks).map_err(LoadTicksError::InvalidPriceMultiplier)?;
+    Ok(ticks)
+}
+
+/// Per-symbol figures for a quick look at a file. Prices and volume are of trades only, since price
+/// level updates are quotes.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SymbolSummary {
+    pub symbol: String,
+    pub tick_count: u64,
+    pub trade_count: u64,
+    pub volume: u64,
+    pub first_price: Option<f64>,
+    pub last_price: Option<f64>,
+    pub min_price: Option<f64>,
+    pub max_price: Option<f64>,
+}
+
+/// Message type of trade report ticks.
+pub const TRADE_REPORT: u8 = b'T';
+
+/// Message type of official price ticks, whose subtype is one of the two below.
+pub const OFFICIAL_PRICE: u8 = b'X';
+pub const OFFICIAL_OPENING_PRICE: u8 = b'Q';
+pub const OFFICIAL_CLOSING_PRICE: u8 = b'M';
+
+impl SymbolSummary {
+    pub fn new(symbol: &str) -> SymbolSummary {
+        SymbolSummary { symbol: symbol.to_string(), ..SymbolSummary::default() }
+    }
+
+    /// Adds the next tick in dataset order.
+    pub fn add(&mut self, tick: &Tick) {
+        self.tick_count += 1;
+        if tick.message_type != TRADE_REPORT {
+            return;
+        }
+        let price = tick.price_f64();
+        self.trade_count += 1;
+        self.volume += u64::from(tick.size);
+        self.first_price = self.first_price.or(Some(price));
+        self.last_price = Some(price);
+        self.min_price = Some(self.min_price.map_or(price, |p| p.min(price)));
+        self.max_price = Some(self.max_price.map_or(price, |p| p.max(price)));
+    }
+}
+
+impl std::fmt::Display for SymbolSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let price = |p: Option<f64>| p.map_or("-".to_string(), |p| p.to_string());
+        write!(f, "{:<8} ticks {} trades {} volume {} first {} last {} min {} max {}",
+               self.symbol.trim_end(), self.tick_count, self.trade_count, self.volume,
+               price(self.first_price), price(self.last_price), price(self.min_price), price(self.max_price))
+    }
+}
+
+/// Running VWAP and volume of a symbol's trades, updated a tick at a time. Ticks other than trade
+/// reports are skipped, like in `SymbolSummary`.
+#[derive(Clone, Debug, Default)]
+pub struct Accumulator {
+    notional: f64,
+    volume: u64,
+    trade_count: u64,
+    last_price: Option<f64>,
+}
+
+/// An `Accumulator`'s figures so far. `vwap` is `None` until a trade with a size comes in.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct AccumulatorSnapshot {
+    pub vwap: Option<f64>,
+    pub volume: u64,
+    pub trade_count: u64,
+    pub last_price: Option<f64>,
+}
+
+impl Accumulator {
+    pub fn update(&mut self, tick: &Tick) {
+        if tick.message_type != TRADE_REPORT {
+            return;
+        }
+        let price = tick.price_f64();
+        self.notional += price * f64::from(tick.size);
+        self.volume += u64::from(tick.size);
+        self.trade_count += 1;
+        self.last_price = Some(price);
+    }
+
+    pub fn snapshot(&self) -> AccumulatorSnapshot {
+        AccumulatorSnapshot {
+            vwap: if self.volume > 0 { Some(self.notional / self.volume as f64) } else { None },
+            volume: self.volume,
+            trade_count: self.trade_count,
+            last_price: self.last_price,
+        }
+    }
+}
+
+/// Summarizes a symbol's ticks, reading `APPEND_CHUNK_SIZE` of them at a time. Fails like
+/// `load_ticks_validated` if any price multiplier is invalid.
+#[cfg(feature = "hdf5")]
+pub fn symbol_summary(file: &str, symbol: &str) -> Result<SymbolSummary, LoadTicksError> {
+    let file = hdf5::File::open(file, "r")?;
+    symbol_summary_in(&file, symbol)
+}
+
+#[cfg(feature = "hdf5")]
+fn symbol_summary_in(file: &hdf5::File, symbol: &str) -> Result<SymbolSummary, LoadTicksError> {
+    let dataset = tick_dataset(file, symbol)?;
+    let size = dataset.size();
+    let mut summary = SymbolSummary::new(symbol);
+    let mut price_multiplier = None;
+    let mut error = PriceMultiplierError { zero: Vec::new(), inconsistent: Vec::new() };
+    for start in (0..size).step_by(APPEND_CHUNK_SIZE) {
+        let end = (start + APPEND_CHUNK_SIZE).min(size);
+        for (i, tick) in read_tick_rows(&dataset, start, end)?.iter().enumerate() {
+            if tick.price_multiplier == 0 {
+                error.zero.push(start + i);
+                continue;
+            }
+            if *price_multiplier.get_or_insert(tick.price_multiplier) != tick.price_multiplier {
+                error.inconsistent.push(start + i);
+            }
+            summary.add(tick);
+        }
+    }
+    if !error.zero.is_empty() || !error.inconsistent.is_empty() {
+        return Err(LoadTicksError::InvalidPriceMultiplier(error));
+    }
+    Ok(summary)
+}
+
+/// `symbol_summary` of every symbol in the file, in symbol order.
+#[cfg(feature = "hdf5")]
+pub fn file_summary(file: &str) -> Result<Vec<SymbolSummary>, LoadTicksError> {
+    let file = hdf5::File::open(file, "r")?;
+    let mut summaries = Vec::new();
+    for symbol in symbols(&file)? {
+        summaries.push(symbol_summary_in(&file, &symbol)?);
+    }
+    Ok(summaries)
 }
 
 // TODO(sherry): return Result<Vec<libh5::Tick>>
+#[cfg(feature = "hdf5")]
 pub fn load_ticks_from_file(symbol: &str, file: &str) -> Vec<Tick> {
     let file = match hdf5::file::File::open(file, "r") {
         Ok(f) => f,
         Err(e) => panic!("Failed to open {}: {}", file, e),
     };
 
-    let dataset = match file.dataset(symbol) {
+    // Combined files hold every symbol's ticks in a single dataset.
+    if file.link_exists(COMBINED_TICKS_DATASET) {
+        return match read_combined_symbol_ticks(&file) {
+            Ok(mut ticks) => ticks.remove(symbol)
+                .unwrap_or_else(|| panic!("No ticks of '{}' in {}", symbol, COMBINED_TICKS_DATASET)),
+            Err(e) => panic!("Failed to read data: {}", e),
+        };
+    }
+
+    let dataset = match tick_dataset(&file, symbol) {
         Ok(d) => d,
         Err(e) => panic!("Failed to load dataset '{}': {}", symbol, e),
     };
-    let ticks = match dataset.read_raw::<Tick>() {
+    let ticks = match read_ticks(&dataset) {
         Ok(d) => d,
         Err(e) => panic!("Failed to read data: {}", e),
     };
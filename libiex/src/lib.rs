@@ -2,6 +2,8 @@ extern crate chrono;
 extern crate libfs;
 
 use std::ffi;
+use std::fs;
+use std::io;
 use std::path;
 
 #[cfg(test)]
@@ -12,14 +14,56 @@ mod tests {
                    Ok(chrono::NaiveDate::from_ymd(2019, 7, 3)));
         assert_eq!(crate::trade_date_from_deep_pcap("../../data/iex/20190703_IEXTP1_DEEP1.0.pcap"),
                    Ok(chrono::NaiveDate::from_ymd(2019, 7, 3)));
+        assert_eq!(crate::trade_date_from_deep_pcap("20190703_IEXTP1_DEEP1.0.pcap.zst"),
+                   Ok(chrono::NaiveDate::from_ymd(2019, 7, 3)));
+        assert_eq!(crate::trade_date_from_deep_pcap("20190703_IEXTP1_DEEP1.0.pcap.bz2"),
+                   Ok(chrono::NaiveDate::from_ymd(2019, 7, 3)));
+        assert_eq!(crate::trade_date_from_deep_pcap("20190703_IEXTP1_DEEP1.0.pcapng"),
+                   Ok(chrono::NaiveDate::from_ymd(2019, 7, 3)));
+        assert_eq!(crate::trade_date_from_deep_pcap("notes.gz"), Err(libfs::TradeDateFromFileErr::InvalidDate));
+        // Stems shorter than a date, or whose 8th byte is inside a multibyte char, aren't sliced.
+        assert_eq!(crate::trade_date_from_deep_pcap("abc.pcap"), Err(libfs::TradeDateFromFileErr::InvalidDate));
+        assert_eq!(crate::trade_date_from_deep_pcap("2019070é_IEXTP1_DEEP1.0.pcap"),
+                   Err(libfs::TradeDateFromFileErr::InvalidDate));
+        assert_eq!(crate::trade_date_from_deep_pcap("é2019070_IEXTP1_DEEP1.0.pcap"),
+                   Err(libfs::TradeDateFromFileErr::InvalidDate));
+        assert_eq!(crate::trade_date_from_deep_pcap(".pcap"), Err(libfs::TradeDateFromFileErr::WrongFileExtension));
+    }
+
+    #[test]
+    fn test_deep_pcaps_in_dir() {
+        let mut dir = std::env::temp_dir();
+        dir.push(format!("libiex_pcaps_{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("late")).unwrap();
+        for name in &["20190703_IEXTP1_DEEP1.0_2.pcap", "20190703_IEXTP1_DEEP1.0_1.pcap.gz", "notes.gz",
+                      "20190703.h5", "late/20190703_IEXTP1_DEEP1.0_3.pcap.zst",
+                      "late/20190703_IEXTP1_DEEP1.0_10.pcap", "late/20190702_IEXTP1_DEEP1.0_11.pcap"] {
+            std::fs::write(dir.join(name), b"").unwrap();
+        }
+        assert_eq!(crate::deep_pcaps_in_dir(&dir, false).unwrap(), vec![
+            dir.join("20190703_IEXTP1_DEEP1.0_1.pcap.gz"),
+            dir.join("20190703_IEXTP1_DEEP1.0_2.pcap"),
+        ]);
+        // By number rather than character, so _10 comes after _3.
+        assert_eq!(crate::deep_pcaps_in_dir(&dir, true).unwrap(), vec![
+            dir.join("late/20190702_IEXTP1_DEEP1.0_11.pcap"),
+            dir.join("20190703_IEXTP1_DEEP1.0_1.pcap.gz"),
+            dir.join("20190703_IEXTP1_DEEP1.0_2.pcap"),
+            dir.join("late/20190703_IEXTP1_DEEP1.0_3.pcap.zst"),
+            dir.join("late/20190703_IEXTP1_DEEP1.0_10.pcap"),
+        ]);
+        std::fs::remove_dir_all(&dir).unwrap();
     }
 }
 
+/// Extensions of captures, plain or compressed.
+const DEEP_PCAP_EXTENSIONS: [&str; 5] = ["pcap", "pcapng", "gz", "bz2", "zst"];
+
 pub fn trade_date_from_deep_pcap(deep_pcap: &str)
     -> Result<chrono::NaiveDate, libfs::TradeDateFromFileErr> {
     let path = path::Path::new(deep_pcap);
     if let Some(extension) = path.extension() {
-        if !extension.eq(ffi::OsStr::new("pcap")) && !extension.eq(ffi::OsStr::new("gz")) {
+        if !DEEP_PCAP_EXTENSIONS.iter().any(|e| extension.eq(ffi::OsStr::new(e))) {
             return Err(libfs::TradeDateFromFileErr::WrongFileExtension);
         }
     } else {
@@ -30,5 +74,41 @@ pub fn trade_date_from_deep_pcap(deep_pcap: &str)
     path.file_stem()
         .ok_or_else(|| libfs::TradeDateFromFileErr::NoStem)
         .and_then(|stem| stem.to_str().ok_or_else(|| libfs::TradeDateFromFileErr::InvalidUnicode))
-        .and_then(|stem| libfs::yyyymmdd_prefix_from_stem(&stem[0..8]))
+        .and_then(|stem| stem.get(0..8).ok_or(libfs::TradeDateFromFileErr::InvalidDate))
+        .and_then(libfs::yyyymmdd_prefix_from_stem)
+}
+
+/// The number after the last `_` of a capture's file name less its extensions, e.g. 10 for
+/// `20190703_IEXTP1_DEEP1.0_10.pcap.gz`, which orders the files a day is split over.
+fn capture_sequence(path: &path::Path) -> Option<u64> {
+    let mut name = path.file_name()?.to_str()?;
+    while let Some((stem, extension)) = name.rsplit_once('.') {
+        if !DEEP_PCAP_EXTENSIONS.contains(&extension) {
+            break;
+        }
+        name = stem;
+    }
+    name.rsplit_once('_')?.1.parse().ok()
+}
+
+/// Captures in `dir` that `trade_date_from_deep_pcap` finds a date for, in the order they're read:
+/// by trade date, then by `capture_sequence` so that `_2` comes before `_10`, then by path.
+/// Subdirectories are only walked if `recursive`.
+pub fn deep_pcaps_in_dir<P: AsRef<path::Path>>(dir: P, recursive: bool) -> io::Result<Vec<path::PathBuf>> {
+    let mut pcaps = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            if recursive {
+                pcaps.extend(deep_pcaps_in_dir(&path, recursive)?);
+            }
+        } else if path.to_str().is_some_and(|p| trade_date_from_deep_pcap(p).is_ok()) {
+            pcaps.push(path);
+        }
+    }
+    pcaps.sort_by_cached_key(|path| {
+        let trade_date = path.to_str().and_then(|p| trade_date_from_deep_pcap(p).ok());
+        (trade_date, capture_sequence(path), path.clone())
+    });
+    Ok(pcaps)
 }